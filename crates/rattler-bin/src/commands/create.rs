@@ -20,7 +20,7 @@ use rattler::{
 };
 use rattler_conda_types::{
     Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, Matches, PackageName,
-    ParseStrictness, Platform, PrefixRecord, RepoDataRecord, Version,
+    ParseStrictness, Platform, PrefixRecord, RepoDataRecord,
 };
 use rattler_networking::{AuthenticationMiddleware, AuthenticationStorage};
 use rattler_repodata_gateway::{Gateway, RepoData, SourceConfig};
@@ -49,6 +49,11 @@ pub struct Opt {
     #[clap(long)]
     virtual_package: Option<Vec<String>>,
 
+    /// Ignore the `CONDA_OVERRIDE_*` environment variables when detecting
+    /// virtual packages.
+    #[clap(long)]
+    no_virtual_package_overrides: bool,
+
     #[clap(long)]
     solver: Option<Solver>,
 
@@ -79,6 +84,10 @@ pub enum SolveStrategy {
     /// Resolve the lowest compatible version for direct dependencies but the
     /// highest compatible for transitive dependencies.
     LowestDirect,
+
+    /// Keep the installed packages if possible and otherwise resolve the
+    /// versions closest to the installed versions.
+    MinimalUpdate,
 }
 
 #[derive(Default, Debug, Clone, Copy, ValueEnum)]
@@ -94,6 +103,7 @@ impl From<SolveStrategy> for rattler_solve::SolveStrategy {
             SolveStrategy::Highest => rattler_solve::SolveStrategy::Highest,
             SolveStrategy::Lowest => rattler_solve::SolveStrategy::LowestVersion,
             SolveStrategy::LowestDirect => rattler_solve::SolveStrategy::LowestVersionDirect,
+            SolveStrategy::MinimalUpdate => rattler_solve::SolveStrategy::MinimalUpdate,
         }
     }
 }
@@ -159,16 +169,25 @@ pub async fn create(opt: Opt) -> miette::Result<()> {
         .build()
         .expect("failed to create client");
 
-    let download_client = reqwest_middleware::ClientBuilder::new(download_client)
+    let download_client = reqwest_middleware::ClientBuilder::new(download_client.clone())
         .with_arc(Arc::new(
             AuthenticationMiddleware::from_env_and_defaults().into_diagnostic()?,
         ))
-        .with(rattler_networking::OciMiddleware)
+        .with(
+            rattler_networking::OciMiddleware::new()
+                .with_authentication_storage(
+                    AuthenticationStorage::from_env_and_defaults().into_diagnostic()?,
+                )
+                .with_client(download_client.clone()),
+        )
         .with(rattler_networking::S3Middleware::new(
             HashMap::new(),
             AuthenticationStorage::from_env_and_defaults().into_diagnostic()?,
         ))
         .with(rattler_networking::GCSMiddleware)
+        .with(rattler_networking::AzureMiddleware::new(
+            AuthenticationStorage::from_env_and_defaults().into_diagnostic()?,
+        ))
         .build();
 
     // Get the package names from the matchspecs so we can only load the package
@@ -218,29 +237,22 @@ pub async fn create(opt: Opt) -> miette::Result<()> {
         if let Some(virtual_packages) = opt.virtual_package {
             Ok(virtual_packages
                 .iter()
-                .map(|virt_pkg| {
-                    let elems = virt_pkg.split('=').collect::<Vec<&str>>();
-                    Ok(GenericVirtualPackage {
-                        name: elems[0].try_into().into_diagnostic()?,
-                        version: elems
-                            .get(1)
-                            .map_or(Version::from_str("0"), |s| Version::from_str(s))
-                            .expect("Could not parse virtual package version"),
-                        build_string: (*elems.get(2).unwrap_or(&"")).to_string(),
-                    })
-                })
+                .map(|virt_pkg| GenericVirtualPackage::from_str(virt_pkg).into_diagnostic())
                 .collect::<miette::Result<Vec<_>>>()?)
         } else {
-            rattler_virtual_packages::VirtualPackage::detect(
-                &rattler_virtual_packages::VirtualPackageOverrides::default(),
-            )
-            .map(|vpkgs| {
-                vpkgs
-                    .iter()
-                    .map(|vpkg| GenericVirtualPackage::from(vpkg.clone()))
-                    .collect::<Vec<_>>()
-            })
-            .into_diagnostic()
+            let overrides = if opt.no_virtual_package_overrides {
+                rattler_virtual_packages::VirtualPackageOverrides::none()
+            } else {
+                rattler_virtual_packages::VirtualPackageOverrides::from_env()
+            };
+            rattler_virtual_packages::VirtualPackage::detect(&overrides)
+                .map(|vpkgs| {
+                    vpkgs
+                        .iter()
+                        .map(|vpkg| GenericVirtualPackage::from(vpkg.clone()))
+                        .collect::<Vec<_>>()
+                })
+                .into_diagnostic()
         }
     })?;
 
@@ -3,12 +3,21 @@ use rattler_conda_types::GenericVirtualPackage;
 use rattler_virtual_packages::VirtualPackageOverrides;
 
 #[derive(Debug, clap::Parser)]
-pub struct Opt {}
+pub struct Opt {
+    /// Ignore the `CONDA_OVERRIDE_*` environment variables and only detect
+    /// the virtual packages of the host system.
+    #[clap(long)]
+    no_overrides: bool,
+}
 
-pub fn virtual_packages(_opt: Opt) -> miette::Result<()> {
+pub fn virtual_packages(opt: Opt) -> miette::Result<()> {
+    let overrides = if opt.no_overrides {
+        VirtualPackageOverrides::none()
+    } else {
+        VirtualPackageOverrides::from_env()
+    };
     let virtual_packages =
-        rattler_virtual_packages::VirtualPackage::detect(&VirtualPackageOverrides::default())
-            .into_diagnostic()?;
+        rattler_virtual_packages::VirtualPackage::detect(&overrides).into_diagnostic()?;
     for package in virtual_packages {
         println!("{}", GenericVirtualPackage::from(package.clone()));
     }
@@ -14,7 +14,8 @@ use url::Url;
 /// Command line arguments that contain authentication data
 #[derive(Parser, Debug)]
 struct LoginArgs {
-    /// The host to authenticate with (e.g. prefix.dev)
+    /// The host to authenticate with (e.g. prefix.dev). Credentials can be
+    /// scoped to a path, e.g. `*.internal.corp/channels/team-*`
     host: String,
 
     /// The token to use (for authentication with prefix.dev)
@@ -44,6 +45,10 @@ struct LoginArgs {
     /// The S3 session token
     #[clap(long, requires_all = ["s3_access_key_id"])]
     s3_session_token: Option<String>,
+
+    /// The Azure shared access signature (SAS) token
+    #[clap(long, conflicts_with_all = ["token", "username", "password", "conda_token", "s3_access_key_id"])]
+    azure_sas_token: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -95,6 +100,10 @@ pub enum AuthenticationCLIError {
     #[error("Authentication with S3 requires a S3 access key ID and a secret access key. Use `--s3-access-key-id` and `--s3-secret-access-key` to provide them")]
     S3BadMethod,
 
+    /// Bad authentication method when using Azure Blob Storage
+    #[error("Authentication with Azure Blob Storage requires a SAS token. Use `--azure-sas-token` to provide one")]
+    AzureBadMethod,
+
     // TODO: rework this
     /// Wrapper for errors that are generated from the underlying storage system
     /// (keyring or file system)
@@ -120,21 +129,34 @@ pub enum AuthenticationCLIError {
 }
 
 fn get_url(url: &str) -> Result<String, AuthenticationCLIError> {
-    // parse as url and extract host without scheme or port
-    let host = if url.contains("://") {
-        url::Url::parse(url)?.host_str().unwrap().to_string()
+    // parse as url and extract host without scheme or port, http(s) URLs keep
+    // their path so credentials can be scoped to a channel
+    let key = if url.contains("://") {
+        let url = url::Url::parse(url)?;
+        let host = url.host_str().unwrap();
+        match url.scheme() {
+            "http" | "https" => format!("{host}{}", url.path().trim_end_matches('/')),
+            _ => host.to_string(),
+        }
     } else {
-        url.to_string()
+        url.trim_end_matches('/').to_string()
     };
 
+    let (host, path) = match key.split_once('/') {
+        Some((host, path)) => (host, Some(path)),
+        None => (key.as_str(), None),
+    };
     let host = if host.matches('.').count() == 1 {
         // use wildcard for top-level domains
         format!("*.{host}")
     } else {
-        host
+        host.to_string()
     };
 
-    Ok(host)
+    Ok(match path {
+        Some(path) => format!("{host}/{path}"),
+        None => host,
+    })
 }
 
 /// Result of prefix.dev token validation
@@ -175,6 +197,8 @@ async fn login(
             secret_access_key,
             session_token,
         }
+    } else if let Some(sas_token) = args.azure_sas_token {
+        Authentication::AzureSasToken(sas_token)
     } else {
         return Err(AuthenticationCLIError::NoAuthenticationMethod);
     };
@@ -193,6 +217,12 @@ async fn login(
         return Err(AuthenticationCLIError::S3BadMethod);
     }
 
+    if args.host.contains("az://") && !matches!(auth, Authentication::AzureSasToken(_))
+        || matches!(auth, Authentication::AzureSasToken(_)) && !args.host.contains("az://")
+    {
+        return Err(AuthenticationCLIError::AzureBadMethod);
+    }
+
     let host = get_url(&args.host)?;
     eprintln!("Authenticating with {host} using {} method", auth.method());
 
@@ -334,6 +364,7 @@ mod tests {
             s3_access_key_id: None,
             s3_secret_access_key: None,
             s3_session_token: None,
+            azure_sas_token: None,
         }
     }
 
@@ -522,4 +553,22 @@ mod tests {
         let result = login(args, storage).await;
         assert!(matches!(result, Err(AuthenticationCLIError::S3BadMethod)));
     }
+
+    #[test]
+    fn test_get_url() {
+        assert_eq!(get_url("prefix.dev").unwrap(), "*.prefix.dev");
+        assert_eq!(
+            get_url("https://repo.prefix.dev/").unwrap(),
+            "repo.prefix.dev"
+        );
+        assert_eq!(
+            get_url("https://conda.internal.corp/channels/team-a/").unwrap(),
+            "conda.internal.corp/channels/team-a"
+        );
+        assert_eq!(
+            get_url("*.internal.corp/channels/team-*").unwrap(),
+            "*.internal.corp/channels/team-*"
+        );
+        assert_eq!(get_url("corp.dev/channels").unwrap(), "*.corp.dev/channels");
+    }
 }
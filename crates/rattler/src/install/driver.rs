@@ -3,6 +3,7 @@ use std::{
     collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
 };
 
 use indexmap::IndexSet;
@@ -14,9 +15,12 @@ use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
 
 use super::{
     clobber_registry::{ClobberError, ClobberRegistry, ClobberedPath},
-    link_script::{PrePostLinkError, PrePostLinkResult},
+    link_script::{
+        LinkScriptEnvInheritance, LinkScriptFilter, LinkScriptOptions, LinkScriptTimeoutPolicy,
+        PrePostLinkError, PrePostLinkResult,
+    },
     unlink::{recursively_remove_empty_directories, UnlinkError},
-    Transaction, TransactionOperation,
+    Reporter, Transaction, TransactionOperation,
 };
 use crate::install::link_script::LinkScriptError;
 
@@ -31,6 +35,8 @@ pub struct InstallDriver {
     io_concurrency_semaphore: Option<Arc<Semaphore>>,
     pub(crate) clobber_registry: Arc<Mutex<ClobberRegistry>>,
     execute_link_scripts: bool,
+    pub(crate) link_script_options: LinkScriptOptions,
+    pub(crate) reporter: Option<Arc<dyn Reporter>>,
 }
 
 impl Default for InstallDriver {
@@ -43,11 +49,24 @@ impl Default for InstallDriver {
 }
 
 /// A builder to configure a new `InstallDriver`.
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct InstallDriverBuilder {
     io_concurrency_semaphore: Option<Arc<Semaphore>>,
     clobber_registry: Option<ClobberRegistry>,
     execute_link_scripts: bool,
+    link_script_options: LinkScriptOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+}
+
+impl std::fmt::Debug for InstallDriverBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstallDriverBuilder")
+            .field("io_concurrency_semaphore", &self.io_concurrency_semaphore)
+            .field("clobber_registry", &self.clobber_registry)
+            .field("execute_link_scripts", &self.execute_link_scripts)
+            .field("link_script_options", &self.link_script_options)
+            .finish_non_exhaustive()
+    }
 }
 
 /// The result of the post-processing step.
@@ -113,6 +132,82 @@ impl InstallDriverBuilder {
         }
     }
 
+    /// Sets the maximum duration a single link script is allowed to run. If a
+    /// script exceeds this duration it is killed together with all of its
+    /// child processes. By default, scripts may run indefinitely.
+    pub fn with_link_script_timeout(self, timeout: Duration) -> Self {
+        Self {
+            link_script_options: LinkScriptOptions {
+                timeout: Some(timeout),
+                ..self.link_script_options
+            },
+            ..self
+        }
+    }
+
+    /// Sets what should happen when a link script exceeds its timeout. By
+    /// default, the remaining scripts are still executed.
+    pub fn with_link_script_timeout_policy(self, policy: LinkScriptTimeoutPolicy) -> Self {
+        Self {
+            link_script_options: LinkScriptOptions {
+                timeout_policy: policy,
+                ..self.link_script_options
+            },
+            ..self
+        }
+    }
+
+    /// Sets the packages whose link scripts are allowed to run. Scripts of
+    /// packages that are not allowed by the filter are skipped. This does not
+    /// enable the execution of link scripts, use
+    /// [`Self::execute_link_scripts`] for that.
+    pub fn with_link_script_filter(self, filter: LinkScriptFilter) -> Self {
+        Self {
+            link_script_options: LinkScriptOptions {
+                filter,
+                ..self.link_script_options
+            },
+            ..self
+        }
+    }
+
+    /// Sets additional environment variables that are passed to link scripts.
+    /// This can be used to pass proxy settings, license servers or
+    /// `CONDA_OVERRIDE_*` values to scripts that need them.
+    pub fn with_link_script_env_vars(
+        self,
+        env_vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        Self {
+            link_script_options: LinkScriptOptions {
+                env_vars: env_vars.into_iter().collect(),
+                ..self.link_script_options
+            },
+            ..self
+        }
+    }
+
+    /// Sets which environment variables of the current process are inherited
+    /// by link scripts. By default, all variables are inherited.
+    pub fn with_link_script_env_inheritance(self, inheritance: LinkScriptEnvInheritance) -> Self {
+        Self {
+            link_script_options: LinkScriptOptions {
+                env_inheritance: inheritance,
+                ..self.link_script_options
+            },
+            ..self
+        }
+    }
+
+    /// Sets a reporter that receives the output of link scripts while they
+    /// are running.
+    pub fn with_reporter(self, reporter: Arc<dyn Reporter>) -> Self {
+        Self {
+            reporter: Some(reporter),
+            ..self
+        }
+    }
+
     pub fn finish(self) -> InstallDriver {
         InstallDriver {
             io_concurrency_semaphore: self.io_concurrency_semaphore,
@@ -122,6 +217,8 @@ impl InstallDriverBuilder {
                 .map(Arc::new)
                 .unwrap_or_default(),
             execute_link_scripts: self.execute_link_scripts,
+            link_script_options: self.link_script_options,
+            reporter: self.reporter,
         }
     }
 }
@@ -162,6 +259,9 @@ impl InstallDriver {
                 Ok(res) => {
                     result = Some(res);
                 }
+                Err(e @ LinkScriptError::TimedOut(..)) => {
+                    return Err(PrePostLinkError::LinkScriptAborted(e));
+                }
                 Err(e) => {
                     tracing::error!("Error running pre-unlink scripts: {:?}", e);
                 }
@@ -1,38 +1,163 @@
-use crate::install::PythonInfo;
+//! Generates launchers for Python entry points.
+//!
+//! An entry point is a command that calls a Python function, e.g. `jupyter-lab
+//! = jupyterlab.labapp:main`. On unix the launcher is a Python script with a
+//! shebang that points to the interpreter of the prefix. On Windows the
+//! launcher consists of the Python script and a small executable that runs
+//! it.
+//!
+//! The launchers are generated by [`python_entry_point_launcher`] without
+//! touching the file system, so besides the installer they can also be used
+//! by tools that build packages.
+
+use std::{
+    borrow::Cow,
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
 use digest::Output;
 use rattler_conda_types::{
     package::EntryPoint,
     prefix_record::{PathType, PathsEntry},
     Platform,
 };
-use rattler_digest::HashingWriter;
-use rattler_digest::Sha256;
-use std::{fs::File, io, io::Write, path::Path};
+use rattler_digest::{HashingWriter, Sha256};
 
 use super::Prefix;
+use crate::install::PythonInfo;
 
 /// Get the bytes of the windows launcher executable.
 pub fn get_windows_launcher(platform: &Platform) -> &'static [u8] {
-    match platform {
+    windows_launcher(platform).unwrap_or_else(|| match platform {
         Platform::Win32 => unimplemented!("32 bit windows is not supported for entry points"),
-        Platform::Win64 => include_bytes!("../../resources/launcher64.exe"),
         Platform::WinArm64 => unimplemented!("arm64 windows is not supported for entry points"),
         _ => panic!("unsupported platform"),
+    })
+}
+
+/// Returns the bytes of the windows launcher executable, or `None` if there
+/// is no launcher for the platform.
+fn windows_launcher(platform: &Platform) -> Option<&'static [u8]> {
+    match platform {
+        Platform::Win64 => Some(include_bytes!("../../resources/launcher64.exe")),
+        _ => None,
+    }
+}
+
+/// A file that is part of the launcher of an entry point.
+#[derive(Debug, Clone)]
+pub struct LauncherFile {
+    /// The path of the file relative to the prefix.
+    pub relative_path: PathBuf,
+
+    /// The contents of the file.
+    pub contents: Cow<'static, [u8]>,
+
+    /// The type of the file as it is recorded in the prefix record.
+    pub path_type: PathType,
+}
+
+impl LauncherFile {
+    /// Returns true if the file must be made executable.
+    pub fn is_executable(&self) -> bool {
+        matches!(
+            self.path_type,
+            PathType::UnixPythonEntryPoint | PathType::WindowsPythonEntryPointExe
+        )
     }
+
+    /// Writes the file to `target_dir`, which is usually the root of a prefix,
+    /// and returns the [`PathsEntry`] that describes it.
+    pub fn write(&self, target_dir: &Path) -> io::Result<PathsEntry> {
+        let path = target_dir.join(&self.relative_path);
+        fs_err::create_dir_all(
+            path.parent()
+                .expect("since we joined with target_dir there must be a parent"),
+        )?;
+        let (hash, size) = write_and_hash(&path, &self.contents)?;
+
+        // Make the script executable. This is only supported on Unix based filesystems.
+        #[cfg(unix)]
+        if self.is_executable() {
+            fs_err::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o775))?;
+        }
+
+        Ok(PathsEntry {
+            relative_path: self.relative_path.clone(),
+            // todo: clobbering of entry points not handled yet
+            original_path: None,
+            path_type: self.path_type,
+            no_link: false,
+            sha256: Some(hash),
+            sha256_in_prefix: None,
+            size_in_bytes: Some(size as _),
+            prefix_placeholder: None,
+            file_mode: None,
+        })
+    }
+}
+
+/// Generates the files of the launcher for a Python entry point that is
+/// installed in the prefix at `target_prefix`, without writing them.
+///
+/// On unix a single executable script is generated, see [`python_shebang`]
+/// for how prefixes with spaces or long paths are handled. On windows a
+/// script called `<command>-script.py` and an executable called
+/// `<command>.exe` are generated. The executable starts a Python interpreter
+/// which runs the script next to it. The source code for the executable can be
+/// found here:
+/// <https://github.com/conda/conda-build/tree/master/conda_build/launcher_sources>.
+///
+/// Returns an error if there is no launcher executable for the windows
+/// platform of `python_info`.
+pub fn python_entry_point_launcher(
+    target_prefix: &str,
+    entry_point: &EntryPoint,
+    python_info: &PythonInfo,
+) -> io::Result<Vec<LauncherFile>> {
+    let platform = python_info.platform;
+    if !platform.is_windows() {
+        let script = python_entry_point_template(target_prefix, false, entry_point, python_info);
+        return Ok(vec![LauncherFile {
+            relative_path: python_info.bin_dir.join(&entry_point.command),
+            contents: Cow::Owned(script.into_bytes()),
+            path_type: PathType::UnixPythonEntryPoint,
+        }]);
+    }
+
+    let launcher = windows_launcher(&platform).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("entry points are not supported on {platform}"),
+        )
+    })?;
+    let script = python_entry_point_template(target_prefix, true, entry_point, python_info);
+    Ok(vec![
+        LauncherFile {
+            relative_path: python_info
+                .bin_dir
+                .join(format!("{}-script.py", &entry_point.command)),
+            contents: Cow::Owned(script.into_bytes()),
+            path_type: PathType::WindowsPythonEntryPointScript,
+        },
+        LauncherFile {
+            relative_path: python_info
+                .bin_dir
+                .join(format!("{}.exe", &entry_point.command)),
+            contents: Cow::Borrowed(launcher),
+            path_type: PathType::WindowsPythonEntryPointExe,
+        },
+    ])
 }
 
 /// Creates an "entry point" on disk for a Python entrypoint. Entrypoints are executable files that
 /// directly call a certain Python function.
 ///
-/// On unix this is pretty trivial through the use of an executable shell script that invokes the
-/// python compiler which in turn invokes the correct Python function. On windows however, this
-/// mechanism doesnt exists. Instead a special executable is copied that starts a Python interpreter
-/// which executes a file that is named the same as the executable but with the `.py` file
-/// extension. So if there is an entry point file called `foo.py` an executable is created called
-/// `foo.exe` that will automatically invoke `foo.py`.
-///
-/// The special executable is embedded in the library. The source code for the launcher can be found
-/// here: <https://github.com/conda/conda-build/tree/master/conda_build/launcher_sources>.
+/// On windows a special executable is copied that starts a Python interpreter which executes a
+/// file that is named the same as the executable but with the `-script.py` suffix. See
+/// [`python_entry_point_launcher`] for details.
 ///
 /// See [`create_unix_python_entry_point`] for the unix variant of this function.
 pub fn create_windows_python_entry_point(
@@ -42,63 +167,17 @@ pub fn create_windows_python_entry_point(
     python_info: &PythonInfo,
     target_platform: &Platform,
 ) -> Result<[PathsEntry; 2], std::io::Error> {
-    // Construct the path to where we will be creating the python entry point script.
-    let relative_path_script_py = python_info
-        .bin_dir
-        .join(format!("{}-script.py", &entry_point.command));
-
-    // Write the contents of the launcher script to disk
-    let script_path = target_dir.path().join(&relative_path_script_py);
-    std::fs::create_dir_all(
-        script_path
-            .parent()
-            .expect("since we joined with target_dir there must be a parent"),
-    )?;
-    let script_contents =
-        python_entry_point_template(target_prefix, true, entry_point, python_info);
-    let (hash, size) = write_and_hash(&script_path, script_contents)?;
-
-    // Construct a path to where we will create the python launcher executable.
-    let relative_path_script_exe = python_info
-        .bin_dir
-        .join(format!("{}.exe", &entry_point.command));
-
-    // Include the bytes of the launcher directly in the binary so we can write it to disk.
-    let launcher_bytes = get_windows_launcher(target_platform);
-    std::fs::write(
-        target_dir.path().join(&relative_path_script_exe),
-        launcher_bytes,
-    )?;
-
-    let fixed_launcher_digest = rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
-        "28b001bb9a72ae7a24242bfab248d767a1ac5dec981c672a3944f7a072375e9a",
-    )
-    .unwrap();
-
+    let python_info = PythonInfo {
+        platform: *target_platform,
+        ..python_info.clone()
+    };
+    let [script, exe]: [LauncherFile; 2] =
+        python_entry_point_launcher(target_prefix, entry_point, &python_info)?
+            .try_into()
+            .expect("windows launchers consist of two files");
     Ok([
-        PathsEntry {
-            relative_path: relative_path_script_py,
-            // todo: clobbering of entry points not handled yet
-            original_path: None,
-            path_type: PathType::WindowsPythonEntryPointScript,
-            no_link: false,
-            sha256: Some(hash),
-            sha256_in_prefix: None,
-            size_in_bytes: Some(size as _),
-            prefix_placeholder: None,
-            file_mode: None,
-        },
-        PathsEntry {
-            relative_path: relative_path_script_exe,
-            original_path: None,
-            path_type: PathType::WindowsPythonEntryPointExe,
-            no_link: false,
-            sha256: Some(fixed_launcher_digest),
-            sha256_in_prefix: None,
-            size_in_bytes: Some(launcher_bytes.len() as u64),
-            prefix_placeholder: None,
-            file_mode: None,
-        },
+        script.write(target_dir.path())?,
+        exe.write(target_dir.path())?,
     ])
 }
 
@@ -115,39 +194,27 @@ pub fn create_unix_python_entry_point(
     entry_point: &EntryPoint,
     python_info: &PythonInfo,
 ) -> Result<PathsEntry, std::io::Error> {
-    // Construct the path to where we will be creating the python entry point script.
-    let relative_path = python_info.bin_dir.join(&entry_point.command);
-
-    // Write the contents of the launcher script to disk
-    let script_path = target_dir.path().join(&relative_path);
-    std::fs::create_dir_all(
-        script_path
-            .parent()
-            .expect("since we joined with target_dir there must be a parent"),
-    )?;
-    let script_contents =
-        python_entry_point_template(target_prefix, false, entry_point, python_info);
-    let (hash, size) = write_and_hash(&script_path, script_contents)?;
-
-    // Make the script executable. This is only supported on Unix based filesystems.
-    #[cfg(unix)]
-    std::fs::set_permissions(
-        script_path,
-        std::os::unix::fs::PermissionsExt::from_mode(0o775),
-    )?;
-
-    Ok(PathsEntry {
-        relative_path,
-        // todo: clobbering of entry points not handled yet
-        original_path: None,
-        path_type: PathType::UnixPythonEntryPoint,
-        no_link: false,
-        sha256: Some(hash),
-        sha256_in_prefix: None,
-        size_in_bytes: Some(size as _),
-        prefix_placeholder: None,
-        file_mode: None,
-    })
+    let [script]: [LauncherFile; 1] =
+        python_entry_point_launcher(target_prefix, entry_point, python_info)?
+            .try_into()
+            .expect("unix launchers consist of a single file");
+    script.write(target_dir.path())
+}
+
+/// Returns a shebang that runs the rest of a script with the Python
+/// interpreter at `interpreter`.
+///
+/// Shebangs cannot be longer than 127 characters and executables with spaces
+/// are problematic. In those cases a shebang is returned that starts the
+/// script with `/bin/sh`, which in turn executes the interpreter with the
+/// script. This works because the second line is valid in both shell and
+/// Python.
+pub fn python_shebang(interpreter: &str) -> String {
+    if interpreter.len() > 127 - 2 || interpreter.contains(' ') {
+        format!("#!/bin/sh\n'''exec' \"{interpreter}\" \"$0\" \"$@\" #'''")
+    } else {
+        format!("#!{interpreter}")
+    }
 }
 
 /// Returns Python code that, when placed in an executable file, invokes the specified
@@ -191,7 +258,7 @@ pub fn python_entry_point_template(
 /// Writes the given bytes to a file and records the hash, as well as the size of the file.
 fn write_and_hash(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<(Output<Sha256>, usize)> {
     let bytes = contents.as_ref();
-    let mut writer = HashingWriter::<_, Sha256>::new(File::create(path)?);
+    let mut writer = HashingWriter::<_, Sha256>::new(fs_err::File::create(path)?);
     writer.write_all(bytes)?;
     let (_, hash) = writer.finalize();
     Ok((hash, bytes.len()))
@@ -201,8 +268,8 @@ fn write_and_hash(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<(Output
 mod test {
     use crate::install::PythonInfo;
     use rattler_conda_types::package::EntryPoint;
-    use rattler_conda_types::{Platform, Version};
-    use std::str::FromStr;
+    use rattler_conda_types::{prefix_record::PathType, Platform, Version};
+    use std::{path::Path, str::FromStr};
 
     #[test]
     fn test_entry_point_script() {
@@ -232,4 +299,58 @@ mod test {
         );
         insta::assert_snapshot!("windows", script);
     }
+
+    #[test]
+    fn test_python_entry_point_launcher() {
+        let entry_point = EntryPoint::from_str("jupyter-lab = jupyterlab.labapp:main").unwrap();
+        let version = Version::from_str("3.11.0").unwrap();
+
+        let python_info = PythonInfo::from_version(&version, None, Platform::Linux64).unwrap();
+        let files =
+            super::python_entry_point_launcher("/prefix", &entry_point, &python_info).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].relative_path, Path::new("bin/jupyter-lab"));
+        assert_eq!(files[0].path_type, PathType::UnixPythonEntryPoint);
+        assert!(files[0].contents.starts_with(b"#!/prefix/bin/python3.11\n"));
+
+        let python_info = PythonInfo::from_version(&version, None, Platform::Win64).unwrap();
+        let files =
+            super::python_entry_point_launcher("C:\\prefix", &entry_point, &python_info).unwrap();
+        assert_eq!(
+            files
+                .iter()
+                .map(|file| (file.relative_path.as_path(), file.path_type))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    Path::new("Scripts/jupyter-lab-script.py"),
+                    PathType::WindowsPythonEntryPointScript
+                ),
+                (
+                    Path::new("Scripts/jupyter-lab.exe"),
+                    PathType::WindowsPythonEntryPointExe
+                ),
+            ]
+        );
+
+        let python_info = PythonInfo::from_version(&version, None, Platform::WinArm64).unwrap();
+        assert!(
+            super::python_entry_point_launcher("C:\\prefix", &entry_point, &python_info).is_err()
+        );
+    }
+
+    #[test]
+    fn test_python_shebang() {
+        assert_eq!(
+            super::python_shebang("/prefix/bin/python"),
+            "#!/prefix/bin/python"
+        );
+        assert_eq!(
+            super::python_shebang("/pre fix/bin/python"),
+            "#!/bin/sh\n'''exec' \"/pre fix/bin/python\" \"$0\" \"$@\" #'''"
+        );
+
+        let long = format!("/{}/bin/python", "a".repeat(200));
+        assert!(super::python_shebang(&long).starts_with("#!/bin/sh\n"));
+    }
 }
@@ -1,3 +1,4 @@
+use rattler_package_streaming::ExtractError;
 use simple_spawn_blocking::Cancelled;
 use std::path::PathBuf;
 
@@ -24,6 +25,27 @@ pub enum InstallerError {
     #[error("failed to fetch {0}")]
     FailedToFetch(String, #[source] PackageCacheError),
 
+    /// Failed to stream a package into the prefix
+    #[error("failed to stream {0}")]
+    FailedToStream(String, #[source] Box<ExtractError>),
+
+    /// A package does not have a hash while the [`HashPolicy`] requires one.
+    ///
+    /// [`HashPolicy`]: super::HashPolicy
+    #[error("{0} does not have a sha256 or md5 hash")]
+    MissingHash(String),
+
+    /// A downloaded package does not match the hash of its record.
+    #[error("hash mismatch for {file_name}: expected {expected}, got {actual}")]
+    HashMismatch {
+        /// The file name of the package.
+        file_name: String,
+        /// The expected hash.
+        expected: String,
+        /// The hash of the downloaded package.
+        actual: String,
+    },
+
     /// Failed to link a certain package
     #[error("failed to link {0}")]
     LinkError(String, #[source] InstallError),
@@ -52,6 +74,11 @@ pub enum InstallerError {
     #[error("the operation was cancelled")]
     Cancelled,
 
+    /// The pypi install handler failed
+    #[cfg(feature = "lock")]
+    #[error("failed to install the PyPI packages")]
+    PypiInstallFailed(#[source] Box<dyn std::error::Error + Send + Sync>),
+
     /// Failed to create the prefix
     #[error("failed to create the prefix")]
     FailedToCreatePrefix(PathBuf, #[source] std::io::Error),
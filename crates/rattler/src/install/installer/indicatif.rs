@@ -8,7 +8,8 @@ use std::{
 
 use indicatif::{HumanBytes, MultiProgress, ProgressFinish, ProgressStyle};
 use parking_lot::Mutex;
-use rattler_conda_types::{PrefixRecord, RepoDataRecord};
+use rattler_conda_types::{PackageName, PrefixRecord, RepoDataRecord};
+use rattler_networking::retry_policies::RetryAttempt;
 
 use crate::install::{Reporter, Transaction, TransactionOperation};
 
@@ -624,6 +625,16 @@ impl<F: ProgressFormatter + Send> Reporter for IndicatifReporter<F> {
         inner.update_download_message();
     }
 
+    fn on_download_retry(&self, cache_entry: usize, attempt: &RetryAttempt) {
+        let inner = self.inner.lock();
+        if let Some(download_progress) = &inner.download_progress {
+            download_progress.set_message(format!(
+                "retrying {} ({attempt})",
+                inner.package_names[cache_entry]
+            ));
+        }
+    }
+
     fn on_populate_cache_complete(&self, _cache_entry: usize) {
         let mut inner = self.inner.lock();
 
@@ -746,6 +757,13 @@ impl<F: ProgressFormatter + Send> Reporter for IndicatifReporter<F> {
         inner.update_linking_message();
     }
 
+    fn on_link_script_output(&self, package: &PackageName, line: &str) {
+        let inner = self.inner.lock();
+        let _ = inner
+            .multi_progress
+            .println(format!("{}: {line}", package.as_normalized()));
+    }
+
     fn on_transaction_operation_complete(&self, _operation: usize) {}
 
     fn on_transaction_complete(&self) {
@@ -1,6 +1,8 @@
 mod error;
 #[cfg(feature = "indicatif")]
 mod indicatif;
+#[cfg(feature = "lock")]
+mod pypi;
 mod reporter;
 pub(crate) mod result_record;
 
@@ -9,7 +11,8 @@ use std::{
     future::ready,
     io,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, OnceLock},
+    time::Duration,
 };
 
 use super::{
@@ -20,9 +23,11 @@ use crate::{
     default_cache_dir,
     install::{
         clobber_registry::ClobberedPath,
-        link_script::{LinkScriptError, PrePostLinkResult},
+        link_script::{
+            LinkScriptError, LinkScriptFilter, LinkScriptTimeoutPolicy, PrePostLinkResult,
+        },
     },
-    package_cache::PackageCache,
+    package_cache::{PackageCache, PackageCacheError},
 };
 pub use error::InstallerError;
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt, TryFutureExt};
@@ -32,16 +37,20 @@ pub use indicatif::{
     ProgressFormatter,
 };
 use itertools::Itertools;
+#[cfg(feature = "lock")]
+pub use pypi::{PypiInstallContext, PypiInstallHandler};
 use rattler_cache::package_cache::{CacheLock, CacheReporter};
 use rattler_conda_types::{
     prefix_record::{Link, LinkType},
     MatchSpec, PackageName, Platform, PrefixRecord, RepoDataRecord,
 };
-use rattler_networking::retry_policies::default_retry_policy;
+use rattler_networking::retry_policies::{default_retry_policy, RetryAttempt};
 use rattler_networking::LazyClient;
+use rattler_package_streaming::{DownloadReporter, ExtractError};
 use rayon::prelude::*;
 pub use reporter::Reporter;
 use simple_spawn_blocking::tokio::run_blocking_task;
+use tempfile::TempDir;
 use tokio::{sync::Semaphore, task::JoinError};
 
 #[derive(Default)]
@@ -58,7 +67,11 @@ pub struct Installer {
     package_cache: Option<PackageCache>,
     downloader: Option<LazyClient>,
     execute_link_scripts: bool,
+    link_script_timeout: Option<Duration>,
+    link_script_timeout_policy: LinkScriptTimeoutPolicy,
+    link_script_filter: Option<LinkScriptFilter>,
     io_semaphore: Option<Arc<Semaphore>>,
+    download_semaphore: Option<Arc<Semaphore>>,
     reporter: Option<Arc<dyn Reporter>>,
     target_platform: Option<Platform>,
     apple_code_sign_behavior: AppleCodeSignBehavior,
@@ -68,6 +81,49 @@ pub struct Installer {
     requested_specs: Option<Vec<MatchSpec>>,
     // TODO: Determine upfront if these are possible.
     link_options: LinkOptions,
+    streaming_install: bool,
+    hash_policy: HashPolicy,
+    #[cfg(feature = "lock")]
+    pypi: Option<pypi::PypiInstallation>,
+}
+
+/// Determines how the hashes of downloaded packages are verified against the
+/// hashes of the records that are installed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HashPolicy {
+    /// Do not verify the hashes of downloaded packages.
+    Ignore,
+
+    /// Verify the hash of a downloaded package if the record has a sha256 or
+    /// md5 hash.
+    #[default]
+    VerifyIfPresent,
+
+    /// Every record must have a sha256 or md5 hash and downloaded packages
+    /// must match it. This is recommended when installing records from a
+    /// lock-file.
+    Require,
+}
+
+impl HashPolicy {
+    /// Returns the record that should be used to fetch the package. The hashes
+    /// are removed if they should not be verified.
+    fn record_to_fetch(self, record: &RepoDataRecord) -> Result<RepoDataRecord, InstallerError> {
+        let mut record = record.clone();
+        match self {
+            HashPolicy::Ignore => {
+                record.package_record.sha256 = None;
+                record.package_record.md5 = None;
+            }
+            HashPolicy::VerifyIfPresent => {}
+            HashPolicy::Require => {
+                if record.package_record.sha256.is_none() && record.package_record.md5.is_none() {
+                    return Err(InstallerError::MissingHash(record.file_name));
+                }
+            }
+        }
+        Ok(record)
+    }
 }
 
 #[derive(Debug)]
@@ -135,6 +191,57 @@ impl Installer {
         self
     }
 
+    /// Sets an optional download concurrency limit. This limits the number of
+    /// packages that are fetched into the cache at the same time,
+    /// independently of the IO concurrency limit used for linking.
+    ///
+    /// The optimal number of parallel HTTP transfers is bounded by the
+    /// available bandwidth and the limits of the server, which usually differs
+    /// a lot from the optimal number of parallel filesystem operations. By
+    /// default, the number of concurrent downloads is not limited.
+    #[must_use]
+    pub fn with_download_concurrency_limit(self, limit: usize) -> Self {
+        Self {
+            download_semaphore: Some(Arc::new(Semaphore::new(limit))),
+            ..self
+        }
+    }
+
+    /// Sets an optional download concurrency limit.
+    ///
+    /// This function is similar to [`Self::with_download_concurrency_limit`],
+    /// but modifies an existing instance.
+    pub fn set_download_concurrency_limit(&mut self, limit: usize) -> &mut Self {
+        self.download_semaphore = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+
+    /// Sets an optional download concurrency semaphore. This allows sharing
+    /// a download limit between multiple installers.
+    #[must_use]
+    pub fn with_download_concurrency_semaphore(
+        self,
+        download_concurrency_semaphore: Arc<Semaphore>,
+    ) -> Self {
+        Self {
+            download_semaphore: Some(download_concurrency_semaphore),
+            ..self
+        }
+    }
+
+    /// Sets an optional download concurrency semaphore.
+    ///
+    /// This function is similar to
+    /// [`Self::with_download_concurrency_semaphore`], but modifies an existing
+    /// instance.
+    pub fn set_download_concurrency_semaphore(
+        &mut self,
+        download_concurrency_semaphore: Arc<Semaphore>,
+    ) -> &mut Self {
+        self.download_semaphore = Some(download_concurrency_semaphore);
+        self
+    }
+
     /// Sets whether to execute link scripts or not.
     ///
     /// By default, link scripts are not executed. Link scripts can run
@@ -158,6 +265,67 @@ impl Installer {
         self
     }
 
+    /// Sets the packages whose link scripts are allowed to run.
+    ///
+    /// Setting a filter enables the execution of link scripts, but only for
+    /// the packages that are allowed by the filter. This makes it possible to
+    /// enable scripts for the few packages that need them while keeping them
+    /// disabled for everything else.
+    #[must_use]
+    pub fn with_link_script_filter(self, filter: LinkScriptFilter) -> Self {
+        Self {
+            link_script_filter: Some(filter),
+            ..self
+        }
+    }
+
+    /// Sets the packages whose link scripts are allowed to run.
+    ///
+    /// This function is similar to [`Self::with_link_script_filter`], but
+    /// modifies an existing instance.
+    pub fn set_link_script_filter(&mut self, filter: LinkScriptFilter) -> &mut Self {
+        self.link_script_filter = Some(filter);
+        self
+    }
+
+    /// Sets the maximum duration a single link script is allowed to run. If a
+    /// script exceeds this duration it is killed together with all of its
+    /// child processes.
+    #[must_use]
+    pub fn with_link_script_timeout(self, timeout: Duration) -> Self {
+        Self {
+            link_script_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets the maximum duration a single link script is allowed to run.
+    ///
+    /// This function is similar to [`Self::with_link_script_timeout`], but
+    /// modifies an existing instance.
+    pub fn set_link_script_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.link_script_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets what should happen when a link script exceeds its timeout.
+    #[must_use]
+    pub fn with_link_script_timeout_policy(self, policy: LinkScriptTimeoutPolicy) -> Self {
+        Self {
+            link_script_timeout_policy: policy,
+            ..self
+        }
+    }
+
+    /// Sets what should happen when a link script exceeds its timeout.
+    ///
+    /// This function is similar to [`Self::with_link_script_timeout_policy`],
+    /// but modifies an existing instance.
+    pub fn set_link_script_timeout_policy(&mut self, policy: LinkScriptTimeoutPolicy) -> &mut Self {
+        self.link_script_timeout_policy = policy;
+        self
+    }
+
     /// Sets the package cache to use.
     #[must_use]
     pub fn with_package_cache(self, package_cache: PackageCache) -> Self {
@@ -312,6 +480,83 @@ impl Installer {
         self
     }
 
+    /// Enables or disables streaming installs.
+    ///
+    /// When enabled, packages are not stored in the package cache. Instead,
+    /// each archive is streamed into a temporary staging directory inside the
+    /// target prefix and linked from there, after which the staging directory
+    /// is removed. Because the staging directory lives on the same filesystem
+    /// as the prefix, files can be hard linked instead of copied, which
+    /// roughly halves the IO required compared to populating the cache first.
+    ///
+    /// This is useful for one-shot environments (e.g. container builds) where
+    /// the package cache would be thrown away anyway. The package cache set
+    /// with [`Self::with_package_cache`] is ignored in this mode.
+    #[must_use]
+    pub fn with_streaming_install(self, streaming_install: bool) -> Self {
+        Self {
+            streaming_install,
+            ..self
+        }
+    }
+
+    /// Enables or disables streaming installs.
+    ///
+    /// This function is similar to [`Self::with_streaming_install`], but
+    /// modifies an existing instance.
+    pub fn set_streaming_install(&mut self, streaming_install: bool) -> &mut Self {
+        self.streaming_install = streaming_install;
+        self
+    }
+
+    /// Sets how the hashes of downloaded packages are verified. Defaults to
+    /// [`HashPolicy::VerifyIfPresent`].
+    ///
+    /// If a downloaded package does not match the hash of its record,
+    /// installation fails with [`InstallerError::HashMismatch`].
+    #[must_use]
+    pub fn with_hash_policy(self, hash_policy: HashPolicy) -> Self {
+        Self {
+            hash_policy,
+            ..self
+        }
+    }
+
+    /// Sets how the hashes of downloaded packages are verified.
+    ///
+    /// This function is similar to [`Self::with_hash_policy`], but modifies an
+    /// existing instance.
+    pub fn set_hash_policy(&mut self, hash_policy: HashPolicy) -> &mut Self {
+        self.hash_policy = hash_policy;
+        self
+    }
+
+    /// Sets the pypi packages of a lock-file and the handler that installs
+    /// them.
+    ///
+    /// After all conda packages have been linked and the post-link scripts
+    /// have run, the prefix is activated and the handler is invoked with the
+    /// `packages`. This allows installing environments that contain both
+    /// conda and pypi packages in one call.
+    #[cfg(feature = "lock")]
+    #[must_use]
+    pub fn with_pypi_packages(
+        self,
+        packages: Vec<(
+            rattler_lock::PypiPackageData,
+            rattler_lock::PypiPackageEnvironmentData,
+        )>,
+        handler: impl PypiInstallHandler + 'static,
+    ) -> Self {
+        Self {
+            pypi: Some(pypi::PypiInstallation {
+                packages,
+                handler: Arc::new(handler),
+            }),
+            ..self
+        }
+    }
+
     /// Sets the link options for the installer.
     pub fn with_link_options(self, options: LinkOptions) -> Self {
         Self {
@@ -427,8 +672,16 @@ impl Installer {
             update_existing_records(transaction.unchanged_packages(), spec_mapping, &prefix)?;
         }
 
+        #[cfg(feature = "lock")]
+        let pypi = self.pypi;
+
         // If the transaction is empty we can short-circuit the installation
         if transaction.operations.is_empty() {
+            #[cfg(feature = "lock")]
+            if let Some(pypi) = pypi {
+                pypi.run(prefix.clone(), target_platform).await?;
+            }
+
             return Ok(InstallationResult {
                 transaction,
                 pre_link_script_result: None,
@@ -451,9 +704,21 @@ impl Installer {
             )
         });
 
-        // Construct a driver.
-        let driver = InstallDriver::builder()
-            .execute_link_scripts(self.execute_link_scripts)
+        // Construct a driver. Setting a link script filter implies that the
+        // scripts allowed by it are executed.
+        let mut driver = InstallDriver::builder()
+            .execute_link_scripts(self.execute_link_scripts || self.link_script_filter.is_some())
+            .with_link_script_timeout_policy(self.link_script_timeout_policy);
+        if let Some(timeout) = self.link_script_timeout {
+            driver = driver.with_link_script_timeout(timeout);
+        }
+        if let Some(filter) = self.link_script_filter {
+            driver = driver.with_link_script_filter(filter);
+        }
+        if let Some(reporter) = self.reporter.clone() {
+            driver = driver.with_reporter(reporter);
+        }
+        let driver = driver
             .with_io_concurrency_semaphore(
                 self.io_semaphore.unwrap_or(Arc::new(Semaphore::new(100))),
             )
@@ -534,6 +799,9 @@ impl Installer {
         {
             let downloader = &downloader;
             let package_cache = &package_cache;
+            let streaming_install = self.streaming_install;
+            let hash_policy = self.hash_policy;
+            let download_semaphore = self.download_semaphore.clone();
             let reporter = self.reporter.clone();
             let base_install_options = &base_install_options;
             let driver = &driver;
@@ -552,22 +820,50 @@ impl Installer {
                     let downloader = downloader.clone();
                     let reporter = reporter.clone();
                     let package_cache = package_cache.clone();
+                    let prefix = prefix.clone();
                     tokio::spawn(async move {
                         let populate_cache_report = reporter.clone().map(|r| {
                             let cache_index = r.on_populate_cache_start(operation_idx, &record);
                             (r, cache_index)
                         });
-                        let cache_lock = populate_cache(
-                            &record,
-                            downloader,
-                            &package_cache,
-                            populate_cache_report.clone(),
-                        )
-                        .await?;
+                        // Limit the number of packages that are fetched at the same time.
+                        let download_permit = match &download_semaphore {
+                            Some(semaphore) => Some(
+                                semaphore
+                                    .clone()
+                                    .acquire_owned()
+                                    .await
+                                    .map_err(|_err| InstallerError::Cancelled)?,
+                            ),
+                            None => None,
+                        };
+                        let fetch_record = hash_policy.record_to_fetch(&record)?;
+                        let package = if streaming_install {
+                            FetchedPackage::Staged(
+                                stream_to_staging_dir(
+                                    &fetch_record,
+                                    downloader,
+                                    &prefix,
+                                    populate_cache_report.clone(),
+                                )
+                                .await?,
+                            )
+                        } else {
+                            FetchedPackage::Cached(
+                                populate_cache(
+                                    &fetch_record,
+                                    downloader,
+                                    &package_cache,
+                                    populate_cache_report.clone(),
+                                )
+                                .await?,
+                            )
+                        };
+                        drop(download_permit);
                         if let Some((reporter, index)) = populate_cache_report {
                             reporter.on_populate_cache_complete(index);
                         }
-                        Ok((cache_lock, record))
+                        Ok((package, record))
                     })
                     .map_err(JoinError::try_into_panic)
                     .map(|res| match res {
@@ -582,7 +878,7 @@ impl Installer {
                 };
 
                 // Install the package if it was fetched.
-                if let Some((cache_lock, record)) = package_to_install.await? {
+                if let Some((package, record)) = package_to_install.await? {
                     let reporter = reporter
                         .as_deref()
                         .map(|r| (r, r.on_link_start(operation_idx, &record)));
@@ -592,7 +888,7 @@ impl Installer {
                     link_package(
                         &record,
                         prefix,
-                        cache_lock.path(),
+                        &package,
                         base_install_options.clone(),
                         driver,
                         requested_spec,
@@ -641,6 +937,11 @@ impl Installer {
             reporter.on_transaction_complete();
         }
 
+        #[cfg(feature = "lock")]
+        if let Some(pypi) = pypi {
+            pypi.run(prefix.clone(), target_platform).await?;
+        }
+
         let transaction = transaction.into_installation_result_record();
 
         Ok(InstallationResult {
@@ -652,17 +953,48 @@ impl Installer {
     }
 }
 
+/// The extracted contents of a package that is about to be linked into a
+/// prefix.
+enum FetchedPackage {
+    /// The package was extracted into the package cache.
+    Cached(CacheLock),
+
+    /// The package was streamed into a temporary staging directory inside the
+    /// target prefix. The directory is removed when this value is dropped.
+    Staged(TempDir),
+}
+
+impl FetchedPackage {
+    /// Returns the directory that contains the extracted package.
+    fn path(&self) -> &Path {
+        match self {
+            FetchedPackage::Cached(lock) => lock.path(),
+            FetchedPackage::Staged(dir) => dir.path(),
+        }
+    }
+
+    /// Returns the cache directory the package was extracted to, or `None` if
+    /// the package was streamed directly into the prefix.
+    fn cached_package_dir(&self) -> Option<&Path> {
+        match self {
+            FetchedPackage::Cached(lock) => Some(lock.path()),
+            FetchedPackage::Staged(_) => None,
+        }
+    }
+}
+
 async fn link_package(
     record: &RepoDataRecord,
     target_prefix: &Prefix,
-    cached_package_dir: &Path,
+    package: &FetchedPackage,
     install_options: InstallOptions,
     driver: &InstallDriver,
     requested_specs: Vec<String>,
 ) -> Result<(), InstallerError> {
     let record = record.clone();
     let target_prefix = target_prefix.clone();
-    let cached_package_dir = cached_package_dir.to_path_buf();
+    let package_dir = package.path().to_path_buf();
+    let cached_package_dir = package.cached_package_dir().map(Path::to_path_buf);
     let clobber_registry = driver.clobber_registry.clone();
 
     let (tx, rx) = tokio::sync::oneshot::channel();
@@ -671,7 +1003,7 @@ async fn link_package(
         let inner = move || {
             // Link the contents of the package into the prefix.
             let paths = crate::install::link_package_sync(
-                &cached_package_dir,
+                &package_dir,
                 &target_prefix,
                 clobber_registry,
                 install_options,
@@ -680,9 +1012,9 @@ async fn link_package(
 
             // Construct a PrefixRecord for the package
             let prefix_record = PrefixRecord {
-                extracted_package_dir: Some(cached_package_dir.clone()),
-                link: Some(Link {
-                    source: cached_package_dir,
+                extracted_package_dir: cached_package_dir.clone(),
+                link: cached_package_dir.map(|source| Link {
+                    source,
                     // TODO: compute the right value here based on the options and `can_hard_link`
                     // ...
                     link_type: Some(LinkType::HardLink),
@@ -754,6 +1086,10 @@ async fn populate_cache(
         fn on_download_completed(&self, index: usize) {
             self.reporter.on_download_completed(index);
         }
+
+        fn on_download_retry(&self, attempt: &RetryAttempt) {
+            self.reporter.on_download_retry(self.cache_index, attempt);
+        }
     }
 
     cache
@@ -770,7 +1106,119 @@ async fn populate_cache(
             }),
         )
         .await
-        .map_err(|e| InstallerError::FailedToFetch(record.file_name.clone(), e))
+        .map_err(|e| {
+            let mismatch = match &e {
+                PackageCacheError::FetchError(err) => err
+                    .downcast_ref::<ExtractError>()
+                    .and_then(|err| hash_mismatch_error(record, err)),
+                _ => None,
+            };
+            mismatch.unwrap_or_else(|| InstallerError::FailedToFetch(record.file_name.clone(), e))
+        })
+}
+
+/// Converts an [`ExtractError::HashMismatch`] into an
+/// [`InstallerError::HashMismatch`].
+fn hash_mismatch_error(record: &RepoDataRecord, err: &ExtractError) -> Option<InstallerError> {
+    match err {
+        ExtractError::HashMismatch {
+            expected, actual, ..
+        } => Some(InstallerError::HashMismatch {
+            file_name: record.file_name.clone(),
+            expected: expected.clone(),
+            actual: actual.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// Streams the archive of a package into a temporary staging directory inside
+/// the target prefix, bypassing the package cache.
+async fn stream_to_staging_dir(
+    record: &RepoDataRecord,
+    downloader: LazyClient,
+    prefix: &Prefix,
+    reporter: Option<(Arc<dyn Reporter>, usize)>,
+) -> Result<TempDir, InstallerError> {
+    struct DownloadReporterBridge {
+        reporter: Arc<dyn Reporter>,
+        cache_index: usize,
+        download_index: OnceLock<usize>,
+    }
+
+    impl DownloadReporter for DownloadReporterBridge {
+        fn on_download_start(&self) {
+            self.download_index
+                .get_or_init(|| self.reporter.on_download_start(self.cache_index));
+        }
+
+        fn on_download_progress(&self, bytes_downloaded: u64, total_bytes: Option<u64>) {
+            if let Some(&index) = self.download_index.get() {
+                self.reporter
+                    .on_download_progress(index, bytes_downloaded, total_bytes);
+            }
+        }
+
+        fn on_download_complete(&self) {
+            if let Some(&index) = self.download_index.get() {
+                self.reporter.on_download_completed(index);
+            }
+        }
+    }
+
+    // Stage the package inside the prefix so files can be hard linked.
+    let staging_dir = tempfile::Builder::new()
+        .prefix(".rattler-staging-")
+        .tempdir_in(prefix.path())
+        .map_err(|e| {
+            InstallerError::IoError(
+                format!(
+                    "failed to create staging directory for {}",
+                    record.file_name
+                ),
+                e,
+            )
+        })?;
+
+    let result = rattler_package_streaming::reqwest::tokio::extract(
+        downloader.client().clone(),
+        record.url.clone(),
+        staging_dir.path(),
+        record.package_record.sha256,
+        reporter.map(|(reporter, cache_index)| {
+            Arc::new(DownloadReporterBridge {
+                reporter,
+                cache_index,
+                download_index: OnceLock::new(),
+            }) as _
+        }),
+    )
+    .await
+    .map_err(|e| {
+        hash_mismatch_error(record, &e).unwrap_or_else(|| {
+            InstallerError::FailedToStream(record.file_name.clone(), Box::new(e))
+        })
+    })?;
+
+    // Like the package cache, only verify one hash, preferring sha256.
+    let mismatch = match (record.package_record.sha256, record.package_record.md5) {
+        (Some(sha256), _) if sha256 != result.sha256 => {
+            Some((format!("{sha256:x}"), format!("{:x}", result.sha256)))
+        }
+        (None, Some(md5)) if md5 != result.md5 => {
+            Some((format!("{md5:x}"), format!("{:x}", result.md5)))
+        }
+        _ => None,
+    };
+    if let Some((expected, actual)) = mismatch {
+        return Err(InstallerError::HashMismatch {
+            file_name: record.file_name.clone(),
+            expected,
+            actual,
+        });
+    }
+
+    Ok(staging_dir)
 }
 
 /// Updates only the `requested_specs` fields in a conda-meta JSON file.
@@ -1355,4 +1803,161 @@ mod tests {
             "Migrated specs should match the original spec"
         );
     }
+
+    #[tokio::test]
+    async fn test_install_with_download_concurrency_limit() {
+        let (_temp_dir, target_prefix) = create_test_environment();
+        let repo_record = create_dummy_repo_record();
+
+        let semaphore = Arc::new(Semaphore::new(1));
+        let installer = Installer::new().with_download_concurrency_semaphore(semaphore.clone());
+        install_and_verify_success(installer, &target_prefix, repo_record.clone()).await;
+
+        // All permits should have been returned.
+        assert_eq!(semaphore.available_permits(), 1);
+        assert!(get_meta_file_path(&target_prefix, &repo_record).exists());
+    }
+
+    #[tokio::test]
+    async fn test_streaming_install_e2e() {
+        let (temp_dir, target_prefix) = create_test_environment();
+        let cache_dir = TempDir::new().unwrap();
+        let repo_record = create_dummy_repo_record();
+
+        let installer = Installer::new()
+            .with_package_cache(PackageCache::new(cache_dir.path()))
+            .with_streaming_install(true);
+        install_and_verify_success(installer, &target_prefix, repo_record.clone()).await;
+
+        // The package should be installed without referencing a cache directory.
+        let meta_file_path = get_meta_file_path(&target_prefix, &repo_record);
+        let record = read_prefix_record(&meta_file_path);
+        assert!(record.extracted_package_dir.is_none());
+        assert!(record.link.is_none());
+
+        // Neither the package cache nor the prefix should contain extracted
+        // package directories.
+        assert_eq!(std::fs::read_dir(cache_dir.path()).unwrap().count(), 0);
+        assert!(std::fs::read_dir(temp_dir.path()).unwrap().all(|entry| {
+            !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".rattler-staging-")
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_hash_policy_require_missing_hash() {
+        let (_temp_dir, target_prefix) = create_test_environment();
+        let repo_record = create_dummy_repo_record();
+
+        let result = Installer::new()
+            .with_hash_policy(HashPolicy::Require)
+            .install(&target_prefix, vec![repo_record])
+            .await;
+        assert!(
+            matches!(result, Err(InstallerError::MissingHash(_))),
+            "expected a missing hash error, got {result:#?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_mismatch() {
+        let mut repo_record = create_dummy_repo_record();
+        repo_record.package_record.sha256 = Some(
+            rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
+                "0000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap(),
+        );
+
+        for streaming_install in [false, true] {
+            let (_temp_dir, target_prefix) = create_test_environment();
+            let cache_dir = TempDir::new().unwrap();
+            let result = Installer::new()
+                .with_package_cache(PackageCache::new(cache_dir.path()))
+                .with_streaming_install(streaming_install)
+                .with_hash_policy(HashPolicy::Require)
+                .install(&target_prefix, vec![repo_record.clone()])
+                .await;
+            assert!(
+                matches!(result, Err(InstallerError::HashMismatch { .. })),
+                "expected a hash mismatch, got {result:#?}"
+            );
+            assert!(!get_meta_file_path(&target_prefix, &repo_record).exists());
+        }
+
+        // Ignoring the hash installs the package anyway.
+        let (_temp_dir, target_prefix) = create_test_environment();
+        let cache_dir = TempDir::new().unwrap();
+        let installer = Installer::new()
+            .with_package_cache(PackageCache::new(cache_dir.path()))
+            .with_hash_policy(HashPolicy::Ignore);
+        install_and_verify_success(installer, &target_prefix, repo_record.clone()).await;
+    }
+
+    #[cfg(feature = "lock")]
+    #[tokio::test]
+    async fn test_pypi_install_handler() {
+        use std::sync::Mutex;
+
+        use futures::future::BoxFuture;
+        use rattler_lock::{PypiPackageData, PypiPackageEnvironmentData, UrlOrPath};
+
+        /// The names of the packages and whether the prefix was activated.
+        type Invocation = (Vec<String>, bool);
+
+        #[derive(Default)]
+        struct RecordingHandler(Arc<Mutex<Vec<Invocation>>>);
+
+        impl PypiInstallHandler for RecordingHandler {
+            fn install<'a>(
+                &'a self,
+                context: PypiInstallContext<'a>,
+            ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+                Box::pin(async move {
+                    // The conda packages must already be linked.
+                    assert!(context.prefix.path().join("conda-meta").is_dir());
+                    self.0.lock().unwrap().push((
+                        context
+                            .packages
+                            .iter()
+                            .map(|(p, _)| p.name.to_string())
+                            .collect(),
+                        context.activation_env.contains_key("CONDA_PREFIX"),
+                    ));
+                    Ok(())
+                })
+            }
+        }
+
+        let (_temp_dir, target_prefix) = create_test_environment();
+        let repo_record = create_dummy_repo_record();
+        let pypi_package = PypiPackageData {
+            name: "requests".parse().unwrap(),
+            version: "2.32.3".parse().unwrap(),
+            location: UrlOrPath::Url(
+                Url::parse("https://files.pythonhosted.org/requests-2.32.3-py3-none-any.whl")
+                    .unwrap(),
+            ),
+            hash: None,
+            requires_dist: Vec::new(),
+            requires_python: None,
+            editable: false,
+        };
+
+        let handler = RecordingHandler::default();
+        let calls = handler.0.clone();
+        let installer = Installer::new().with_pypi_packages(
+            vec![(pypi_package, PypiPackageEnvironmentData::default())],
+            handler,
+        );
+        install_and_verify_success(installer, &target_prefix, repo_record).await;
+
+        assert_eq!(
+            calls.lock().unwrap().as_slice(),
+            &[(vec!["requests".to_string()], true)]
+        );
+    }
 }
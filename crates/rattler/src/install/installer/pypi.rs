@@ -0,0 +1,81 @@
+//! An extension point to install the pypi packages of a lock-file after the
+//! conda packages have been linked.
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures::future::BoxFuture;
+use rattler_conda_types::{prefix::Prefix, Platform};
+use rattler_lock::{PypiPackageData, PypiPackageEnvironmentData};
+use simple_spawn_blocking::tokio::run_blocking_task;
+
+use super::InstallerError;
+use crate::install::link_script::{activated_environment, script_shell, LinkScriptEnvInheritance};
+
+/// The information that is passed to a [`PypiInstallHandler`].
+#[derive(Debug)]
+pub struct PypiInstallContext<'a> {
+    /// The prefix that the conda packages were installed into.
+    pub prefix: &'a Prefix,
+
+    /// The platform of the prefix.
+    pub platform: Platform,
+
+    /// The environment variables of the activated prefix. This can be used to
+    /// run the python interpreter of the prefix.
+    pub activation_env: &'a HashMap<String, String>,
+
+    /// The pypi packages that should be installed.
+    pub packages: &'a [(PypiPackageData, PypiPackageEnvironmentData)],
+}
+
+/// A handler that installs the pypi packages of a lock-file into a prefix.
+///
+/// Rattler does not install pypi packages itself. Implementations of this trait
+/// can be passed to [`super::Installer::with_pypi_packages`] and are invoked
+/// after all conda packages have been linked and the post-link scripts have
+/// run.
+pub trait PypiInstallHandler: Send + Sync {
+    /// Installs the pypi packages into the prefix.
+    ///
+    /// This is called on every installation, even if the set of packages is
+    /// empty, so the handler can also remove packages that are no longer
+    /// part of the environment.
+    fn install<'a>(
+        &'a self,
+        context: PypiInstallContext<'a>,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+/// The pypi packages and the handler that installs them.
+pub(super) struct PypiInstallation {
+    pub packages: Vec<(PypiPackageData, PypiPackageEnvironmentData)>,
+    pub handler: Arc<dyn PypiInstallHandler>,
+}
+
+impl PypiInstallation {
+    /// Activates the prefix and invokes the handler.
+    pub async fn run(self, prefix: Prefix, platform: Platform) -> Result<(), InstallerError> {
+        let activation_env = {
+            let prefix = prefix.clone();
+            run_blocking_task(move || {
+                Ok::<_, InstallerError>(activated_environment(
+                    prefix.path(),
+                    &script_shell(&platform),
+                    &platform,
+                    &LinkScriptEnvInheritance::All,
+                ))
+            })
+            .await?
+        };
+
+        self.handler
+            .install(PypiInstallContext {
+                prefix: &prefix,
+                platform,
+                activation_env: &activation_env,
+                packages: &self.packages,
+            })
+            .await
+            .map_err(InstallerError::PypiInstallFailed)
+    }
+}
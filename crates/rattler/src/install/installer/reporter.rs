@@ -1,4 +1,5 @@
-use rattler_conda_types::{PrefixRecord, RepoDataRecord};
+use rattler_conda_types::{PackageName, PrefixRecord, RepoDataRecord};
+use rattler_networking::retry_policies::RetryAttempt;
 
 use crate::install::Transaction;
 
@@ -59,6 +60,12 @@ pub trait Reporter: Send + Sync {
     /// corresponding download.
     fn on_download_completed(&self, download_idx: usize);
 
+    /// Called when downloading the package of the cache entry failed and is
+    /// about to be retried. The `cache_entry` is the value returned by
+    /// [`Reporter::on_populate_cache_start`], which can be used to show a
+    /// message like "retrying numpy (attempt 2/5, waiting 8s)".
+    fn on_download_retry(&self, _cache_entry: usize, _attempt: &RetryAttempt) {}
+
     /// Called when the cache for a package was populated
     ///
     /// The `cache_entry` is the value return by `on_populate_cache_start` for
@@ -89,6 +96,13 @@ pub trait Reporter: Send + Sync {
     /// package.
     fn on_link_complete(&self, index: usize);
 
+    /// Called for every line of output that a pre-unlink or post-link script
+    /// of `package` writes to stdout or stderr. Lines are reported while the
+    /// script is still running and without the trailing newline.
+    ///
+    /// This is only called when link scripts are executed.
+    fn on_link_script_output(&self, _package: &PackageName, _line: &str) {}
+
     /// Called when a transaction operation finishes.
     fn on_transaction_operation_complete(&self, operation: usize);
 
@@ -2,15 +2,28 @@
 use std::{
     borrow::Borrow,
     collections::{HashMap, HashSet},
+    ffi::OsStr,
     fmt::{Display, Formatter},
-    path::Path,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use rattler_conda_types::{PackageName, PackageRecord, Platform, PrefixRecord};
-use rattler_shell::shell::{Bash, CmdExe, ShellEnum};
+use rattler_conda_types::{
+    package::PathsJson, Channel, ChannelConfig, ChannelUrl, PackageName, PackageRecord,
+    ParseChannelError, Platform, PrefixRecord, RepoDataRecord,
+};
+use rattler_shell::{
+    activation::{prefix_path_entries, ActivationVariables, Activator, PathModificationBehavior},
+    shell::{Bash, CmdExe, Shell, ShellEnum},
+};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use url::Url;
 
-use super::{InstallDriver, Transaction};
+use super::{InstallDriver, Reporter, Transaction};
 
 /// Error type for link script errors
 #[derive(Debug, thiserror::Error)]
@@ -18,9 +31,200 @@ pub enum LinkScriptError {
     /// An error occurred while reading the message file
     #[error("{0}")]
     IoError(String, #[source] std::io::Error),
+
+    /// A link script did not finish within the configured timeout and the
+    /// [`LinkScriptTimeoutPolicy`] requested to abort.
+    #[error("the {1} script of '{name}' did not finish within {2:?}", name = .0.as_normalized())]
+    TimedOut(PackageName, LinkScriptType, Duration),
+}
+
+/// Determines what happens when a link script exceeds its timeout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LinkScriptTimeoutPolicy {
+    /// Kill the script, record it as timed out and continue with the
+    /// remaining scripts.
+    #[default]
+    Continue,
+
+    /// Kill the script and stop running any further link scripts.
+    Abort,
+}
+
+/// Selects the packages whose link scripts are allowed to run.
+///
+/// By default, the scripts of all packages are allowed to run. Use
+/// [`LinkScriptFilter::allow_packages`] to only enable the scripts of a few
+/// packages that genuinely need them.
+#[derive(Debug, Default, Clone)]
+pub struct LinkScriptFilter {
+    /// If set, only packages in this set are allowed to run link scripts.
+    pub allowed_packages: Option<HashSet<PackageName>>,
+
+    /// Packages that are never allowed to run link scripts. This takes
+    /// precedence over `allowed_packages`.
+    pub denied_packages: HashSet<PackageName>,
+
+    /// If set, only packages that originate from one of these channels are
+    /// allowed to run link scripts. Channels are compared by their full,
+    /// normalized base URL.
+    pub allowed_channels: Option<HashSet<ChannelUrl>>,
+}
+
+impl LinkScriptFilter {
+    /// Only allow the link scripts of the specified packages to run.
+    pub fn allow_packages(packages: impl IntoIterator<Item = PackageName>) -> Self {
+        Self {
+            allowed_packages: Some(packages.into_iter().collect()),
+            ..Self::default()
+        }
+    }
+
+    /// Never allow the link scripts of the specified packages to run.
+    #[must_use]
+    pub fn with_denied_packages(self, packages: impl IntoIterator<Item = PackageName>) -> Self {
+        Self {
+            denied_packages: packages.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Only allow the link scripts of packages from the specified channels to
+    /// run.
+    #[must_use]
+    pub fn with_allowed_channels(self, channels: impl IntoIterator<Item = Channel>) -> Self {
+        Self {
+            allowed_channels: Some(
+                channels
+                    .into_iter()
+                    .map(|channel| channel.base_url)
+                    .collect(),
+            ),
+            ..self
+        }
+    }
+
+    /// Only allow the link scripts of packages from the specified channels to
+    /// run. Channels can be specified by name (e.g. `conda-forge`) or by URL
+    /// and are resolved to their base URL using the `channel_config`.
+    pub fn with_allowed_channel_names(
+        self,
+        channels: impl IntoIterator<Item = impl AsRef<str>>,
+        channel_config: &ChannelConfig,
+    ) -> Result<Self, ParseChannelError> {
+        let channels = channels
+            .into_iter()
+            .map(|channel| Channel::from_str(channel, channel_config))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.with_allowed_channels(channels))
+    }
+
+    /// Returns true if the link scripts of the given record are allowed to run.
+    pub fn is_allowed(&self, record: &RepoDataRecord) -> bool {
+        let name = &record.package_record.name;
+        if self.denied_packages.contains(name) {
+            return false;
+        }
+
+        if let Some(allowed_packages) = &self.allowed_packages {
+            if !allowed_packages.contains(name) {
+                return false;
+            }
+        }
+
+        if let Some(allowed_channels) = &self.allowed_channels {
+            // Records without a channel URL cannot be attributed to a channel
+            // and are therefore never allowed.
+            let Some(channel) = record
+                .channel
+                .as_deref()
+                .and_then(|channel| Url::parse(channel).ok())
+            else {
+                return false;
+            };
+            return allowed_channels.contains(&ChannelUrl::from(channel));
+        }
+
+        true
+    }
+}
+
+/// Determines which environment variables of the current process are
+/// inherited by link scripts.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum LinkScriptEnvInheritance {
+    /// Inherit all environment variables of the current process.
+    #[default]
+    All,
+
+    /// Only inherit the specified environment variables.
+    Only(HashSet<String>),
+
+    /// Do not inherit any environment variables. Note that on Windows many
+    /// programs fail to run without variables like `SystemRoot`.
+    None,
+}
+
+impl LinkScriptEnvInheritance {
+    /// Returns the environment variables of the current process that are
+    /// inherited according to these rules.
+    pub(crate) fn inherited_env(&self) -> HashMap<String, String> {
+        let vars = std::env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)));
+        match self {
+            LinkScriptEnvInheritance::All => vars.collect(),
+            LinkScriptEnvInheritance::Only(keys) => {
+                vars.filter(|(key, _)| keys.contains(key)).collect()
+            }
+            LinkScriptEnvInheritance::None => HashMap::new(),
+        }
+    }
+
+    /// Applies the inheritance rules to the given command.
+    fn apply(&self, command: &mut std::process::Command) {
+        match self {
+            LinkScriptEnvInheritance::All => {}
+            LinkScriptEnvInheritance::Only(keys) => {
+                command.env_clear();
+                for key in keys {
+                    if let Some(value) = std::env::var_os(key) {
+                        command.env(key, value);
+                    }
+                }
+            }
+            LinkScriptEnvInheritance::None => {
+                command.env_clear();
+            }
+        }
+    }
+}
+
+/// Options that control how link scripts are executed.
+#[derive(Debug, Default, Clone)]
+pub struct LinkScriptOptions {
+    /// The maximum duration a single link script is allowed to run. When the
+    /// timeout expires the script and all of its child processes are killed.
+    /// `None` means scripts may run indefinitely.
+    pub timeout: Option<Duration>,
+
+    /// What to do when a script exceeds the timeout.
+    pub timeout_policy: LinkScriptTimeoutPolicy,
+
+    /// Selects the packages whose link scripts are allowed to run.
+    pub filter: LinkScriptFilter,
+
+    /// Additional environment variables that are set for link scripts. The
+    /// variables that describe the package (`PREFIX`, `PKG_NAME`,
+    /// `PKG_VERSION` and `PKG_BUILDNUM`) cannot be overwritten.
+    pub env_vars: HashMap<String, String>,
+
+    /// Determines which environment variables of the current process are
+    /// inherited by link scripts.
+    pub env_inheritance: LinkScriptEnvInheritance,
 }
 
 /// The type of link script to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum LinkScriptType {
     /// The pre-unlink script (run before the package is unlinked)
     /// This is stored in the environment as `bin/.{name}-pre-unlink.sh` or
@@ -67,6 +271,147 @@ impl Display for LinkScriptType {
     }
 }
 
+/// A link script that would be executed when a [`Transaction`] is applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedLinkScript {
+    /// The package that contains the script.
+    pub package: PackageName,
+
+    /// The path of the script relative to the target prefix.
+    pub path: PathBuf,
+
+    /// Whether this is a pre-unlink or post-link script.
+    pub link_script_type: LinkScriptType,
+}
+
+/// The name of the directory inside `conda-meta` that stores the
+/// [`LinkScriptRecord`]s of the installed packages.
+pub const LINK_SCRIPT_RECORDS_DIR: &str = "link-scripts";
+
+/// The maximum number of bytes of stdout and stderr that are stored in a
+/// [`LinkScriptRecord`]. Only the last bytes of the output are kept.
+const MAX_RECORDED_OUTPUT: usize = 8 * 1024;
+
+/// The result of the last link script that was executed for a package. These
+/// records are stored next to the `PrefixRecord`s in
+/// `conda-meta/link-scripts/<name>.json` so it can later be determined whether
+/// a script ran successfully.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkScriptRecord {
+    /// The name of the package that contains the script.
+    pub package: PackageName,
+
+    /// The version of the package.
+    pub version: String,
+
+    /// The build string of the package.
+    pub build: String,
+
+    /// The type of script that was executed.
+    pub link_script_type: LinkScriptType,
+
+    /// The exit code of the script. `None` if the script could not be started,
+    /// was killed or terminated by a signal.
+    pub exit_code: Option<i32>,
+
+    /// True if the script was killed because it exceeded its timeout.
+    #[serde(default)]
+    pub timed_out: bool,
+
+    /// How long the script ran in milliseconds.
+    pub duration_ms: u64,
+
+    /// The last part of the output the script wrote to stdout.
+    #[serde(default)]
+    pub stdout: String,
+
+    /// The last part of the output the script wrote to stderr.
+    #[serde(default)]
+    pub stderr: String,
+}
+
+impl LinkScriptRecord {
+    fn new(
+        package_record: &PackageRecord,
+        link_script_type: LinkScriptType,
+        output: &Result<ScriptOutput, std::io::Error>,
+        duration: Duration,
+    ) -> Self {
+        let (exit_code, timed_out, stdout, stderr) = match output {
+            Ok(ScriptOutput::Completed(status, stdout, stderr)) => (
+                status.code(),
+                false,
+                truncate_output(stdout),
+                truncate_output(stderr),
+            ),
+            Ok(ScriptOutput::TimedOut(stdout, stderr)) => {
+                (None, true, truncate_output(stdout), truncate_output(stderr))
+            }
+            Err(err) => (None, false, String::new(), err.to_string()),
+        };
+
+        Self {
+            package: package_record.name.clone(),
+            version: package_record.version.to_string(),
+            build: package_record.build.clone(),
+            link_script_type,
+            exit_code,
+            timed_out,
+            duration_ms: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+            stdout,
+            stderr,
+        }
+    }
+
+    /// Returns true if the script finished successfully.
+    pub fn is_success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Returns the path of the record for the given package in a prefix.
+    pub fn path_in_prefix(prefix: &Path, package: &PackageName) -> PathBuf {
+        prefix
+            .join("conda-meta")
+            .join(LINK_SCRIPT_RECORDS_DIR)
+            .join(format!("{}.json", package.as_normalized()))
+    }
+
+    /// Reads the record of the given package from a prefix. Returns `None` if
+    /// no link script was recorded for the package.
+    pub fn from_prefix(
+        prefix: &Path,
+        package: &PackageName,
+    ) -> Result<Option<Self>, std::io::Error> {
+        let path = Self::path_in_prefix(prefix, package);
+        let contents = match fs_err::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+
+    /// Writes the record to the prefix, replacing any previous record of the
+    /// same package.
+    pub fn write_to_prefix(&self, prefix: &Path) -> Result<(), std::io::Error> {
+        let path = Self::path_in_prefix(prefix, &self.package);
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        fs_err::write(path, contents)
+    }
+}
+
+/// Returns the last [`MAX_RECORDED_OUTPUT`] bytes of the output as a string.
+fn truncate_output(output: &[u8]) -> String {
+    let start = output.len().saturating_sub(MAX_RECORDED_OUTPUT);
+    String::from_utf8_lossy(&output[start..]).into_owned()
+}
+
 /// Records the results of running pre/post link scripts
 #[derive(Debug, Clone)]
 pub struct PrePostLinkResult {
@@ -75,6 +420,15 @@ pub struct PrePostLinkResult {
 
     /// Packages that failed to run the link scripts
     pub failed_packages: Vec<PackageName>,
+
+    /// Packages whose link script was killed because it exceeded the
+    /// configured timeout. These packages are also part of
+    /// `failed_packages`.
+    pub timed_out_packages: Vec<PackageName>,
+
+    /// Packages that have a link script that was not executed because the
+    /// package is not allowed by the [`LinkScriptFilter`].
+    pub skipped_packages: Vec<PackageName>,
 }
 
 /// An error that can occur during pre-, post-link script execution.
@@ -83,16 +437,25 @@ pub enum PrePostLinkError {
     /// Failed to determine the currently installed packages.
     #[error("failed to determine the installed packages")]
     FailedToDetectInstalledPackages(#[source] std::io::Error),
+
+    /// Running the link scripts was aborted.
+    #[error("running the link scripts was aborted")]
+    LinkScriptAborted(#[source] LinkScriptError),
 }
 
 /// Run the link scripts for a given package
+///
+/// If a `reporter` is specified, the output of the scripts is streamed to
+/// [`Reporter::on_link_script_output`] while the scripts are running.
 pub fn run_link_scripts<'a>(
     link_script_type: LinkScriptType,
     prefix_records: impl Iterator<Item = &'a PrefixRecord>,
     target_prefix: &Path,
     platform: &Platform,
+    options: &LinkScriptOptions,
+    reporter: Option<Arc<dyn Reporter>>,
 ) -> Result<PrePostLinkResult, LinkScriptError> {
-    let mut env = HashMap::new();
+    let mut env = options.env_vars.clone();
     env.insert(
         "PREFIX".to_string(),
         target_prefix.to_string_lossy().to_string(),
@@ -100,13 +463,31 @@ pub fn run_link_scripts<'a>(
 
     // prefix records are topologically sorted, so we can be sure that all
     // dependencies are installed before the package itself.
+    let shell = script_shell(platform);
+
+    // The environment of the activated prefix is computed once, the first time
+    // a script is actually executed.
+    let mut activated_env: Option<HashMap<String, String>> = None;
+
     let mut failed_packages = Vec::new();
+    let mut timed_out_packages = Vec::new();
+    let mut skipped_packages = Vec::new();
     let mut messages = HashMap::<PackageName, String>::new();
     for record in prefix_records {
         let prec = &record.repodata_record.package_record;
         let link_file = target_prefix.join(link_script_type.get_path(prec, platform));
 
         if link_file.exists() {
+            if !options.filter.is_allowed(&record.repodata_record) {
+                tracing::info!(
+                    "Skipping {} script for {} because it is not allowed",
+                    link_script_type,
+                    prec.name.as_normalized()
+                );
+                skipped_packages.push(prec.name.clone());
+                continue;
+            }
+
             env.insert(
                 "PKG_NAME".to_string(),
                 prec.name.as_normalized().to_string(),
@@ -114,25 +495,70 @@ pub fn run_link_scripts<'a>(
             env.insert("PKG_VERSION".to_string(), prec.version.to_string());
             env.insert("PKG_BUILDNUM".to_string(), prec.build_number.to_string());
 
-            let shell = if platform.is_windows() {
-                ShellEnum::CmdExe(CmdExe)
-            } else {
-                ShellEnum::Bash(Bash)
-            };
-
             tracing::info!(
                 "Running {} script for {}",
                 link_script_type.to_string(),
                 prec.name.as_normalized()
             );
 
-            match rattler_shell::run_in_environment(target_prefix, &link_file, shell, &env) {
-                Ok(o) if o.status.success() => {}
-                Ok(o) => {
+            let on_line = reporter.clone().map(|reporter| {
+                let package = prec.name.clone();
+                Arc::new(move |line: &str| reporter.on_link_script_output(&package, line))
+                    as OutputCallback
+            });
+
+            let activated_env = activated_env.get_or_insert_with(|| {
+                activated_environment(target_prefix, &shell, platform, &options.env_inheritance)
+            });
+
+            let command = link_script_command(
+                &shell,
+                &link_file,
+                &options.env_inheritance,
+                activated_env,
+                &env,
+            );
+
+            let started = Instant::now();
+            let output = run_script(command, options.timeout, on_line);
+            let script_record =
+                LinkScriptRecord::new(prec, link_script_type, &output, started.elapsed());
+            if let Err(err) = script_record.write_to_prefix(target_prefix) {
+                tracing::warn!(
+                    "Failed to record the result of the {} script of {}: {}",
+                    link_script_type,
+                    prec.name.as_normalized(),
+                    err
+                );
+            }
+
+            match output {
+                Ok(ScriptOutput::Completed(status, _, _)) if status.success() => {}
+                Ok(ScriptOutput::Completed(status, stdout, stderr)) => {
                     failed_packages.push(prec.name.clone());
-                    tracing::warn!("Error running post-link script. Status: {:?}", o.status);
-                    tracing::warn!("  stdout: {}", String::from_utf8_lossy(&o.stdout));
-                    tracing::warn!("  stderr: {}", String::from_utf8_lossy(&o.stderr));
+                    tracing::warn!("Error running post-link script. Status: {:?}", status);
+                    tracing::warn!("  stdout: {}", String::from_utf8_lossy(&stdout));
+                    tracing::warn!("  stderr: {}", String::from_utf8_lossy(&stderr));
+                }
+                Ok(ScriptOutput::TimedOut(stdout, stderr)) => {
+                    let timeout = options.timeout.unwrap_or_default();
+                    tracing::warn!(
+                        "The {} script of {} did not finish within {:?} and was killed",
+                        link_script_type,
+                        prec.name.as_normalized(),
+                        timeout
+                    );
+                    tracing::warn!("  stdout: {}", String::from_utf8_lossy(&stdout));
+                    tracing::warn!("  stderr: {}", String::from_utf8_lossy(&stderr));
+                    if options.timeout_policy == LinkScriptTimeoutPolicy::Abort {
+                        return Err(LinkScriptError::TimedOut(
+                            prec.name.clone(),
+                            link_script_type,
+                            timeout,
+                        ));
+                    }
+                    failed_packages.push(prec.name.clone());
+                    timed_out_packages.push(prec.name.clone());
                 }
                 Err(e) => {
                     failed_packages.push(prec.name.clone());
@@ -177,9 +603,301 @@ pub fn run_link_scripts<'a>(
     Ok(PrePostLinkResult {
         messages,
         failed_packages,
+        timed_out_packages,
+        skipped_packages,
     })
 }
 
+/// Constructs the command that runs the link script at `link_file`. The
+/// environment of the script consists of the inherited variables of the
+/// current process, overlaid with the variables of the activated prefix and
+/// finally the variables that describe the package.
+fn link_script_command(
+    shell: &ShellEnum,
+    link_file: &Path,
+    inheritance: &LinkScriptEnvInheritance,
+    activated_env: &HashMap<String, String>,
+    env: &HashMap<String, String>,
+) -> std::process::Command {
+    let mut command = shell.create_run_script_command(link_file);
+    inheritance.apply(&mut command);
+    command.envs(activated_env.iter()).envs(env.iter());
+    command
+}
+
+/// Returns the shell that is used to run scripts on `platform`.
+pub(crate) fn script_shell(platform: &Platform) -> ShellEnum {
+    if platform.is_windows() {
+        ShellEnum::CmdExe(CmdExe)
+    } else {
+        ShellEnum::Bash(Bash)
+    }
+}
+
+/// Computes the environment variables that need to be set to run a script in
+/// the activated `target_prefix`. This includes `PATH`, `CONDA_PREFIX` and all
+/// changes made by the `activate.d` scripts and the environment variables of
+/// the prefix.
+///
+/// The activation starts from the environment variables of the current
+/// process that are inherited according to `inheritance`, so variables that
+/// are not inherited don't leak into the result (e.g. through `PATH`).
+///
+/// If activation fails, a minimal environment that only sets `CONDA_PREFIX`
+/// and prepends the prefix to the inherited `PATH` is returned instead.
+pub(crate) fn activated_environment(
+    target_prefix: &Path,
+    shell: &ShellEnum,
+    platform: &Platform,
+    inheritance: &LinkScriptEnvInheritance,
+) -> HashMap<String, String> {
+    let inherited_env = inheritance.inherited_env();
+    let path_var = shell.path_var(platform).to_string();
+    let inherited_path = inherited_env.get(&path_var).cloned();
+
+    let activation =
+        Activator::from_path(target_prefix, shell.clone(), *platform).and_then(|activator| {
+            // Only run the activation in a cleared environment if variables
+            // are actually filtered.
+            let environment = (*inheritance != LinkScriptEnvInheritance::All).then(|| {
+                inherited_env
+                    .iter()
+                    .map(|(key, value)| (OsStr::new(key), OsStr::new(value)))
+                    .collect()
+            });
+            let variables = ActivationVariables {
+                conda_prefix: inherited_env.get("CONDA_PREFIX").map(PathBuf::from),
+                path: None,
+                path_modification_behavior: PathModificationBehavior::Prepend,
+                current_env: inherited_env.clone(),
+            };
+            activator.run_activation(variables, environment)
+        });
+
+    match activation {
+        Ok(env) => env,
+        Err(err) => {
+            tracing::warn!(
+                "Failed to activate {} to run link scripts, falling back to a minimal environment: {}",
+                target_prefix.display(),
+                err
+            );
+
+            let mut paths = prefix_path_entries(target_prefix, platform);
+            if let Some(path) = inherited_path {
+                paths.extend(std::env::split_paths(&path));
+            }
+
+            let mut env = HashMap::new();
+            env.insert(
+                "CONDA_PREFIX".to_string(),
+                target_prefix.to_string_lossy().to_string(),
+            );
+            if let Ok(path) = std::env::join_paths(paths) {
+                env.insert(path_var, path.to_string_lossy().to_string());
+            }
+            env
+        }
+    }
+}
+
+/// A callback that is invoked with every line a link script writes.
+type OutputCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// The outcome of running a single link script.
+enum ScriptOutput {
+    /// The script finished, contains the exit status, stdout and stderr.
+    Completed(ExitStatus, Vec<u8>, Vec<u8>),
+
+    /// The script was killed because it exceeded its timeout. Contains the
+    /// output that was captured up to that point.
+    TimedOut(Vec<u8>, Vec<u8>),
+}
+
+/// Spawns the command and waits for it to finish. If the command does not
+/// finish within `timeout` the process and all of its children are killed.
+///
+/// Every line written to stdout or stderr is passed to `on_line` as soon as it
+/// is read.
+fn run_script(
+    mut command: std::process::Command,
+    timeout: Option<Duration>,
+    on_line: Option<OutputCallback>,
+) -> Result<ScriptOutput, std::io::Error> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // Spawn the script in its own process group so we can kill the entire
+    // process tree if it times out.
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let mut child = command.spawn()?;
+    let (stdout, stdout_reader) = capture_output(child.stdout.take(), on_line.clone());
+    let (stderr, stderr_reader) = capture_output(child.stderr.take(), on_line);
+
+    let status = match timeout {
+        None => Some(child.wait()?),
+        Some(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break Some(status);
+                }
+                if Instant::now() >= deadline {
+                    kill_process_tree(&mut child);
+                    let _ = child.wait();
+                    break None;
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+    };
+
+    let take_output = |output: Arc<Mutex<Vec<u8>>>| {
+        let mut output = output.lock().unwrap();
+        std::mem::take(&mut *output)
+    };
+    match status {
+        Some(status) => {
+            // The process exited so the pipes are closed, wait for the readers
+            // to consume everything that is left.
+            let _ = stdout_reader.join();
+            let _ = stderr_reader.join();
+            Ok(ScriptOutput::Completed(
+                status,
+                take_output(stdout),
+                take_output(stderr),
+            ))
+        }
+        // Don't wait for the readers here, a process that escaped the process
+        // tree might still hold on to the pipes.
+        None => Ok(ScriptOutput::TimedOut(
+            take_output(stdout),
+            take_output(stderr),
+        )),
+    }
+}
+
+/// Reads the output of a pipe line by line on a background thread.
+fn capture_output(
+    pipe: Option<impl Read + Send + 'static>,
+    on_line: Option<OutputCallback>,
+) -> (Arc<Mutex<Vec<u8>>>, std::thread::JoinHandle<()>) {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let reader = std::thread::spawn({
+        let output = output.clone();
+        move || {
+            let Some(pipe) = pipe else {
+                return;
+            };
+            let mut reader = BufReader::new(pipe);
+            let mut line = Vec::new();
+            while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                if let Some(on_line) = &on_line {
+                    let text = String::from_utf8_lossy(&line);
+                    on_line(text.trim_end_matches(['\r', '\n']));
+                }
+                output.lock().unwrap().extend_from_slice(&line);
+                line.clear();
+            }
+        }
+    });
+    (output, reader)
+}
+
+/// Kills a process and all of its descendants.
+#[cfg(unix)]
+fn kill_process_tree(child: &mut Child) {
+    // The script was spawned as the leader of its own process group, signal the
+    // whole group.
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: `kill` has no memory safety requirements.
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+}
+
+/// Kills a process and all of its descendants.
+#[cfg(not(unix))]
+fn kill_process_tree(child: &mut Child) {
+    let _ = std::process::Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &child.id().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    let _ = child.kill();
+}
+
+impl<Old, New> Transaction<Old, New>
+where
+    Old: Borrow<PrefixRecord>,
+    New: AsRef<PackageRecord>,
+{
+    /// Returns the link scripts that would be executed when this transaction
+    /// is applied, without executing anything. This allows callers to inspect
+    /// the scripts before deciding whether to enable link scripts.
+    ///
+    /// Pre-unlink scripts are determined from the files that are recorded for
+    /// the packages that are removed. Post-link scripts are part of the
+    /// package archives, `package_paths` is called for every package that is
+    /// installed to retrieve the `paths.json` of the package (e.g. from an
+    /// extracted package in the cache). Packages for which `package_paths`
+    /// returns `None` are not included.
+    pub fn planned_link_scripts(
+        &self,
+        mut package_paths: impl FnMut(&New) -> Option<PathsJson>,
+    ) -> Vec<PlannedLinkScript> {
+        let mut scripts = Vec::new();
+
+        for record in self.removed_packages() {
+            let record: &PrefixRecord = record.borrow();
+            let prec = &record.repodata_record.package_record;
+            let script_path = LinkScriptType::PreUnlink.get_path(prec, &self.platform);
+            if record
+                .paths_data
+                .paths
+                .iter()
+                .any(|entry| entry.relative_path == Path::new(&script_path))
+            {
+                scripts.push(PlannedLinkScript {
+                    package: prec.name.clone(),
+                    path: PathBuf::from(script_path),
+                    link_script_type: LinkScriptType::PreUnlink,
+                });
+            }
+        }
+
+        for record in self.installed_packages() {
+            let prec = record.as_ref();
+            let script_path = LinkScriptType::PostLink.get_path(prec, &self.platform);
+            let Some(paths) = package_paths(record) else {
+                continue;
+            };
+            if paths
+                .paths
+                .iter()
+                .any(|entry| entry.relative_path == Path::new(&script_path))
+            {
+                scripts.push(PlannedLinkScript {
+                    package: prec.name.clone(),
+                    path: PathBuf::from(script_path),
+                    link_script_type: LinkScriptType::PostLink,
+                });
+            }
+        }
+
+        scripts
+    }
+}
+
 impl InstallDriver {
     /// Run any post-link scripts that are part of the packages that are being
     /// installed.
@@ -208,6 +926,8 @@ impl InstallDriver {
             filter_iter,
             target_prefix,
             &transaction.platform,
+            &self.link_script_options,
+            self.reporter.clone(),
         )
     }
 
@@ -226,12 +946,28 @@ impl InstallDriver {
             transaction.removed_packages().map(Borrow::borrow),
             target_prefix,
             &transaction.platform,
+            &self.link_script_options,
+            self.reporter.clone(),
         )
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use rattler_conda_types::ChannelConfig;
+    use rattler_shell::shell::{Bash, ShellEnum};
+
+    use super::{
+        activated_environment, link_script_command, run_script, truncate_output,
+        LinkScriptEnvInheritance, LinkScriptFilter, LinkScriptRecord, LinkScriptType,
+        OutputCallback, PlannedLinkScript, ScriptOutput, MAX_RECORDED_OUTPUT,
+    };
     use crate::{
         get_repodata_record, get_test_data_dir,
         install::{
@@ -240,7 +976,11 @@ mod tests {
         },
         package_cache::PackageCache,
     };
-    use rattler_conda_types::{prefix::Prefix, Platform, PrefixRecord, RepoDataRecord};
+    use rattler_conda_types::{
+        package::{PackageFile, PathsJson},
+        prefix::Prefix,
+        PackageName, Platform, PrefixRecord, RepoDataRecord,
+    };
     use rattler_networking::LazyClient;
 
     fn test_operations() -> Vec<TransactionOperation<PrefixRecord, RepoDataRecord>> {
@@ -307,4 +1047,320 @@ mod tests {
         // check that the pre-unlink script was run
         assert!(!target_prefix.path().join("i-was-post-linked").exists());
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_script_timeout_kills_process_tree() {
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg("echo started; sleep 30 & wait");
+
+        let start = Instant::now();
+        let output = run_script(command, Some(Duration::from_millis(500)), None).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let ScriptOutput::TimedOut(stdout, _) = output else {
+            panic!("expected the script to time out");
+        };
+        assert_eq!(String::from_utf8_lossy(&stdout).trim(), "started");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_script_without_timeout() {
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg("echo done; exit 3");
+
+        let ScriptOutput::Completed(status, stdout, _) = run_script(command, None, None).unwrap()
+        else {
+            panic!("expected the script to complete");
+        };
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&stdout).trim(), "done");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_script_streams_lines() {
+        let mut command = std::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg("echo first; echo second >&2; echo third");
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let on_line = {
+            let lines = lines.clone();
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_string()))
+                as OutputCallback
+        };
+        run_script(command, None, Some(on_line)).unwrap();
+
+        let mut lines = lines.lock().unwrap().clone();
+        lines.sort();
+        assert_eq!(lines, vec!["first", "second", "third"]);
+    }
+
+    #[test]
+    fn test_link_script_filter() {
+        let mut record = get_repodata_record(
+            get_test_data_dir().join("link-scripts/link-scripts-0.1.0-h4616a5c_0.conda"),
+        );
+        record.channel = Some("https://conda.anaconda.org/conda-forge/".to_string());
+        let name = record.package_record.name.clone();
+        let other = PackageName::new_unchecked("other");
+
+        assert!(LinkScriptFilter::default().is_allowed(&record));
+        assert!(LinkScriptFilter::allow_packages([name.clone()]).is_allowed(&record));
+        assert!(!LinkScriptFilter::allow_packages([other.clone()]).is_allowed(&record));
+        assert!(!LinkScriptFilter::default()
+            .with_denied_packages([name.clone()])
+            .is_allowed(&record));
+        assert!(LinkScriptFilter::default()
+            .with_denied_packages([other])
+            .is_allowed(&record));
+
+        let channel_config = ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap());
+        let allow_channels = |channels: &[&str]| {
+            LinkScriptFilter::default()
+                .with_allowed_channel_names(channels, &channel_config)
+                .unwrap()
+        };
+        assert!(LinkScriptFilter::allow_packages([name.clone()])
+            .with_allowed_channel_names(["conda-forge"], &channel_config)
+            .unwrap()
+            .is_allowed(&record));
+        assert!(allow_channels(&["https://conda.anaconda.org/conda-forge"]).is_allowed(&record));
+        assert!(allow_channels(&["https://CONDA.anaconda.org/conda-forge/"]).is_allowed(&record));
+        assert!(!allow_channels(&["bioconda"]).is_allowed(&record));
+
+        // A channel on a foreign host with the same last path segment must not
+        // be confused with the allowed channel.
+        record.channel = Some("https://evil.example/conda-forge/".to_string());
+        assert!(!allow_channels(&["conda-forge"]).is_allowed(&record));
+
+        // Records that only carry a channel name cannot be verified.
+        record.channel = Some("conda-forge".to_string());
+        assert!(!allow_channels(&["conda-forge"]).is_allowed(&record));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_env_inheritance() {
+        let run_with = |inheritance: LinkScriptEnvInheritance| {
+            let mut command = std::process::Command::new("/bin/sh");
+            command
+                .arg("-c")
+                .arg("echo \"$RATTLER_TEST_INHERITED\"")
+                .env("RATTLER_TEST_INHERITED", "inherited");
+            inheritance.apply(&mut command);
+            let ScriptOutput::Completed(_, stdout, _) = run_script(command, None, None).unwrap()
+            else {
+                panic!("expected the script to complete");
+            };
+            String::from_utf8_lossy(&stdout).trim().to_string()
+        };
+
+        assert_eq!(run_with(LinkScriptEnvInheritance::All), "inherited");
+        assert_eq!(run_with(LinkScriptEnvInheritance::None), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_activated_environment() {
+        let target_prefix = tempfile::tempdir().unwrap();
+        let activate_dir = target_prefix.path().join("etc/conda/activate.d");
+        fs_err::create_dir_all(&activate_dir).unwrap();
+        fs_err::write(
+            activate_dir.join("activate-test.sh"),
+            "export RATTLER_TEST_ACTIVATED=yes\n",
+        )
+        .unwrap();
+
+        let env = activated_environment(
+            target_prefix.path(),
+            &ShellEnum::Bash(Bash),
+            &Platform::current(),
+            &LinkScriptEnvInheritance::All,
+        );
+
+        assert_eq!(
+            env.get("CONDA_PREFIX").map(String::as_str),
+            Some(target_prefix.path().to_str().unwrap())
+        );
+        assert_eq!(
+            env.get("RATTLER_TEST_ACTIVATED").map(String::as_str),
+            Some("yes")
+        );
+        let bin_dir = target_prefix.path().join("bin");
+        assert!(std::env::split_paths(&env["PATH"]).any(|p| p == bin_dir));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_activated_environment_inheritance() {
+        std::env::set_var("RATTLER_TEST_LINK_SCRIPT_SENTINEL", "leaked");
+
+        // The activation script copies the sentinel so we can observe whether
+        // it was visible during activation.
+        let target_prefix = tempfile::tempdir().unwrap();
+        let activate_dir = target_prefix.path().join("etc/conda/activate.d");
+        fs_err::create_dir_all(&activate_dir).unwrap();
+        fs_err::write(
+            activate_dir.join("activate-test.sh"),
+            "export RATTLER_TEST_SEEN=\"seen:$RATTLER_TEST_LINK_SCRIPT_SENTINEL\"\n",
+        )
+        .unwrap();
+
+        let activate = |inheritance: LinkScriptEnvInheritance| {
+            activated_environment(
+                target_prefix.path(),
+                &ShellEnum::Bash(Bash),
+                &Platform::current(),
+                &inheritance,
+            )
+        };
+
+        let env = activate(LinkScriptEnvInheritance::All);
+        assert_eq!(env["RATTLER_TEST_SEEN"], "seen:leaked");
+
+        // Bash falls back to a builtin default `PATH` when it starts without
+        // one, so only entries outside of that default would have leaked.
+        let bash_default_path = std::process::Command::new("bash")
+            .env_clear()
+            .args(["-c", "echo -n \"$PATH\""])
+            .output()
+            .unwrap()
+            .stdout;
+        let bash_default_path = String::from_utf8(bash_default_path).unwrap();
+        let only_prefix_or_default = |path: &str| {
+            std::env::split_paths(path).all(|p| {
+                p.starts_with(target_prefix.path())
+                    || std::env::split_paths(&bash_default_path).any(|d| d == p)
+            })
+        };
+
+        // Neither the sentinel nor the `PATH` of the parent are used.
+        let env = activate(LinkScriptEnvInheritance::None);
+        assert_eq!(env["RATTLER_TEST_SEEN"], "seen:");
+        assert!(only_prefix_or_default(&env["PATH"]));
+
+        let env = activate(LinkScriptEnvInheritance::Only(HashSet::from([
+            "HOME".to_string()
+        ])));
+        assert_eq!(env["RATTLER_TEST_SEEN"], "seen:");
+        assert!(only_prefix_or_default(&env["PATH"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_script_environment() {
+        std::env::set_var("RATTLER_TEST_LINK_SCRIPT_SENTINEL", "leaked");
+
+        let target_prefix = tempfile::tempdir().unwrap();
+        let link_file = target_prefix.path().join("print-env.sh");
+        fs_err::write(&link_file, "env\n").unwrap();
+
+        let shell = ShellEnum::Bash(Bash);
+        let inheritance = LinkScriptEnvInheritance::None;
+        let activated_env = activated_environment(
+            target_prefix.path(),
+            &shell,
+            &Platform::current(),
+            &inheritance,
+        );
+        let env = HashMap::from([
+            (
+                "PREFIX".to_string(),
+                target_prefix.path().to_string_lossy().to_string(),
+            ),
+            ("PKG_NAME".to_string(), "foo".to_string()),
+        ]);
+
+        let command = link_script_command(&shell, &link_file, &inheritance, &activated_env, &env);
+        let ScriptOutput::Completed(status, stdout, _) = run_script(command, None, None).unwrap()
+        else {
+            panic!("expected the script to complete");
+        };
+        assert!(status.success());
+
+        // The script only sees the activated environment, the package
+        // variables and the variables that bash sets itself.
+        let mut script_env: HashMap<_, _> = String::from_utf8_lossy(&stdout)
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let mut expected = activated_env.clone();
+        expected.extend(env);
+        for key in ["PWD", "SHLVL", "_"] {
+            script_env.remove(key);
+            expected.remove(key);
+        }
+        assert_eq!(script_env, expected);
+        assert!(!script_env.contains_key("RATTLER_TEST_LINK_SCRIPT_SENTINEL"));
+    }
+
+    #[test]
+    fn test_planned_link_scripts() {
+        let package_path =
+            get_test_data_dir().join("link-scripts/link-scripts-0.1.0-h4616a5c_0.conda");
+        let extracted = tempfile::tempdir().unwrap();
+        rattler_package_streaming::fs::extract(&package_path, extracted.path()).unwrap();
+        let paths = PathsJson::from_package_directory(extracted.path()).unwrap();
+
+        let transaction = transaction::Transaction::<PrefixRecord, RepoDataRecord> {
+            operations: test_operations(),
+            python_info: None,
+            current_python_info: None,
+            platform: Platform::Linux64,
+            unchanged: Vec::new(),
+        };
+
+        assert!(transaction.planned_link_scripts(|_| None).is_empty());
+
+        let planned = transaction.planned_link_scripts(|_| Some(paths.clone()));
+        assert_eq!(
+            planned,
+            vec![PlannedLinkScript {
+                package: PackageName::new_unchecked("link-scripts"),
+                path: "bin/.link-scripts-post-link.sh".into(),
+                link_script_type: LinkScriptType::PostLink,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_link_script_record_roundtrip() {
+        let prefix = tempfile::tempdir().unwrap();
+        let package = PackageName::new_unchecked("ffmpeg");
+        assert_eq!(
+            LinkScriptRecord::from_prefix(prefix.path(), &package).unwrap(),
+            None
+        );
+
+        let record = LinkScriptRecord {
+            package: package.clone(),
+            version: "6.1.1".to_string(),
+            build: "h1234_0".to_string(),
+            link_script_type: LinkScriptType::PostLink,
+            exit_code: Some(0),
+            timed_out: false,
+            duration_ms: 42,
+            stdout: "done".to_string(),
+            stderr: String::new(),
+        };
+        record.write_to_prefix(prefix.path()).unwrap();
+
+        let read = LinkScriptRecord::from_prefix(prefix.path(), &package)
+            .unwrap()
+            .unwrap();
+        assert!(read.is_success());
+        assert_eq!(read, record);
+    }
+
+    #[test]
+    fn test_truncate_output() {
+        let output = vec![b'a'; MAX_RECORDED_OUTPUT + 10];
+        assert_eq!(truncate_output(&output).len(), MAX_RECORDED_OUTPUT);
+        assert_eq!(truncate_output(b"short"), "short");
+    }
 }
@@ -18,9 +18,11 @@
 pub mod apple_codesign;
 mod clobber_registry;
 mod driver;
-mod entry_point;
+pub mod entry_point;
 pub mod link;
 pub mod link_script;
+#[cfg(feature = "solve")]
+mod plan;
 mod python;
 mod transaction;
 pub mod unlink;
@@ -43,14 +45,20 @@ pub use apple_codesign::AppleCodeSignBehavior;
 pub use driver::InstallDriver;
 use fs_err::tokio as tokio_fs;
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
-pub use installer::{result_record::InstallationResultRecord, Installer, InstallerError, Reporter};
+pub use installer::{
+    result_record::InstallationResultRecord, HashPolicy, Installer, InstallerError, Reporter,
+};
 #[cfg(feature = "indicatif")]
 pub use installer::{
     DefaultProgressFormatter, IndicatifReporter, IndicatifReporterBuilder, Placement,
     ProgressFormatter,
 };
+#[cfg(feature = "lock")]
+pub use installer::{PypiInstallContext, PypiInstallHandler};
 use itertools::Itertools;
 pub use link::{link_file, LinkFileError, LinkMethod};
+#[cfg(feature = "solve")]
+pub use plan::{solve_and_plan, SolveAndPlanError, SolveAndPlanResult};
 pub use python::PythonInfo;
 use rattler_conda_types::{
     package::{IndexJson, LinkJson, NoArchLinks, PackageFile, PathsEntry, PathsJson},
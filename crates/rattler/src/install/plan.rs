@@ -0,0 +1,206 @@
+//! Solving an environment and planning the changes to a prefix in one go.
+
+use std::collections::HashSet;
+
+use rattler_conda_types::{PackageName, Platform, PrefixRecord, RepoDataRecord, SolverResult};
+use rattler_solve::{IntoRepoData, SolveError, SolverImpl, SolverTask};
+
+use super::{Transaction, TransactionError};
+
+/// Error that occurred during [`solve_and_plan`].
+#[derive(Debug, thiserror::Error)]
+pub enum SolveAndPlanError {
+    /// The environment could not be solved.
+    #[error(transparent)]
+    SolveError(#[from] SolveError),
+
+    /// The transaction could not be constructed.
+    #[error(transparent)]
+    TransactionError(#[from] TransactionError),
+}
+
+/// The result of [`solve_and_plan`].
+#[derive(Debug)]
+pub struct SolveAndPlanResult {
+    /// The result of the solver.
+    pub solver_result: SolverResult,
+
+    /// The operations that transform the installed packages into the solved
+    /// packages.
+    pub transaction: Transaction<PrefixRecord, RepoDataRecord>,
+}
+
+/// Solves the task and constructs the [`Transaction`] that updates the
+/// `installed_packages` of a prefix to the solution.
+///
+/// If `task` does not specify any locked packages, its `locked_packages` are
+/// overwritten with the installed packages, so that the solver only changes
+/// them if required. Packages in `reinstall` are reinstalled even if they did
+/// not change. `platform` must be the platform of the prefix, it is used to
+/// determine whether noarch python packages have to be relinked.
+pub fn solve_and_plan<'a, S, R, TAvailablePackagesIterator>(
+    solver: &mut S,
+    mut task: SolverTask<TAvailablePackagesIterator>,
+    installed_packages: Vec<PrefixRecord>,
+    reinstall: Option<&HashSet<PackageName>>,
+    platform: Platform,
+) -> Result<SolveAndPlanResult, SolveAndPlanError>
+where
+    S: SolverImpl,
+    R: IntoRepoData<'a, S::RepoData<'a>>,
+    TAvailablePackagesIterator: IntoIterator<Item = R>,
+{
+    if task.locked_packages.is_empty() {
+        task.locked_packages = installed_packages
+            .iter()
+            .map(|record| record.repodata_record.clone())
+            .collect();
+    }
+
+    let solver_result = solver.solve(task)?;
+    let transaction = Transaction::from_current_and_desired(
+        installed_packages,
+        solver_result.records.iter().cloned(),
+        reinstall,
+        None,
+        platform,
+    )?;
+
+    Ok(SolveAndPlanResult {
+        solver_result,
+        transaction,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use rattler_conda_types::{
+        MatchSpec, PackageName, ParseStrictness, Platform, PrefixRecord, RepoDataRecord,
+    };
+    use rattler_solve::{resolvo::Solver, SolverTask};
+    use serde_json::json;
+
+    use super::solve_and_plan;
+    use crate::install::TransactionOperation;
+
+    fn record(name: &str, version: &str, depends: &[&str], noarch: bool) -> RepoDataRecord {
+        let subdir = if noarch { "noarch" } else { "win-64" };
+        let file_name = format!("{name}-{version}-0.conda");
+        serde_json::from_value(json!({
+            "name": name,
+            "version": version,
+            "build": "0",
+            "build_number": 0,
+            "subdir": subdir,
+            "depends": depends,
+            "noarch": noarch.then_some("python"),
+            "url": format!("https://prefix.dev/example/{subdir}/{file_name}"),
+            "fn": file_name,
+            "channel": "https://prefix.dev/example/",
+        }))
+        .unwrap()
+    }
+
+    fn specs(specs: &[&str]) -> Vec<MatchSpec> {
+        specs
+            .iter()
+            .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Strict).unwrap())
+            .collect()
+    }
+
+    fn describe(operation: &TransactionOperation<PrefixRecord, RepoDataRecord>) -> (&str, &str) {
+        let (kind, record) = match operation {
+            TransactionOperation::Install(new) => ("install", new),
+            TransactionOperation::Change { new, .. } => ("change", new),
+            TransactionOperation::Reinstall { new, .. } => ("reinstall", new),
+            TransactionOperation::Remove(_) => unreachable!("nothing is removed"),
+        };
+        (kind, record.package_record.name.as_normalized())
+    }
+
+    #[test]
+    fn test_solve_and_plan() {
+        let prefix = tempfile::tempdir().unwrap();
+        let conda_meta = prefix.path().join("conda-meta");
+        fs_err::create_dir_all(&conda_meta).unwrap();
+        for record in [
+            record("python", "3.11.0", &[], false),
+            record("foo", "1.0", &["python"], true),
+            record("bar", "1.0", &[], false),
+        ] {
+            let prefix_record = PrefixRecord::from_repodata_record(record, Vec::new());
+            prefix_record
+                .write_to_path(conda_meta.join(prefix_record.file_name()), true)
+                .unwrap();
+        }
+        let installed = PrefixRecord::collect_from_prefix::<PrefixRecord>(prefix.path()).unwrap();
+
+        let available = [
+            record("python", "3.11.0", &[], false),
+            record("python", "3.12.0", &[], false),
+            record("foo", "1.0", &["python"], true),
+            record("foo", "2.0", &["python"], true),
+            record("bar", "1.0", &[], false),
+        ];
+        let reinstall = HashSet::from([PackageName::new_unchecked("bar")]);
+        let result = solve_and_plan(
+            &mut Solver,
+            SolverTask {
+                specs: specs(&["python 3.12.*", "foo", "bar"]),
+                ..SolverTask::from_iter([&available])
+            },
+            installed.clone(),
+            Some(&reinstall),
+            Platform::Win64,
+        )
+        .unwrap();
+
+        // `foo` is locked to the installed version, but is relinked because
+        // python changed. `bar` did not change but is reinstalled on request.
+        let mut operations = result
+            .transaction
+            .operations
+            .iter()
+            .map(describe)
+            .collect::<Vec<_>>();
+        operations.sort_unstable();
+        assert_eq!(
+            operations,
+            [
+                ("change", "bar"),
+                ("change", "python"),
+                ("reinstall", "foo")
+            ]
+        );
+
+        // The python paths are derived from the given platform.
+        assert_eq!(result.transaction.platform, Platform::Win64);
+        let python_info = result.transaction.python_info.unwrap();
+        assert_eq!(python_info.short_version, (3, 12));
+        assert_eq!(python_info.path, std::path::Path::new("python.exe"));
+
+        // Explicitly locked packages replace the installed packages, so `foo`
+        // is updated.
+        let result = solve_and_plan(
+            &mut Solver,
+            SolverTask {
+                specs: specs(&["foo"]),
+                locked_packages: vec![available[0].clone()],
+                ..SolverTask::from_iter([&available])
+            },
+            installed,
+            None,
+            Platform::Win64,
+        )
+        .unwrap();
+        let foo = result
+            .solver_result
+            .records
+            .iter()
+            .find(|record| record.package_record.name.as_normalized() == "foo")
+            .unwrap();
+        assert_eq!(foo.package_record.version.as_str(), "2.0");
+    }
+}
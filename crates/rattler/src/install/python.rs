@@ -102,17 +102,7 @@ impl PythonInfo {
     pub fn shebang(&self, target_prefix: &str) -> String {
         let target_path = Path::new(target_prefix).join(self.path());
         let target_path = target_path.as_os_str().to_string_lossy().replace('\\', "/");
-
-        // Shebangs cannot be larger than 127 characters and executables with spaces are
-        // problematic.
-        if target_path.len() > 127 - 2 || target_path.contains(' ') {
-            format!(
-                "#!/bin/sh\n'''exec' \"{}\" \"$0\" \"$@\" #'''",
-                &target_path
-            )
-        } else {
-            format!("#!{}", &target_path)
-        }
+        super::entry_point::python_shebang(&target_path)
     }
 
     /// Returns the target location of a file in a noarch python package given
@@ -11,6 +11,8 @@ use fs_err::tokio as tokio_fs;
 use rattler_conda_types::{prefix::Prefix, prefix_record::PrefixRecord};
 use uuid::Uuid;
 
+use super::link_script::LinkScriptRecord;
+
 /// Error that can occur while unlinking a package.
 #[derive(Debug, thiserror::Error)]
 pub enum UnlinkError {
@@ -210,6 +212,22 @@ pub async fn unlink_package(
         UnlinkError::FailedToDeleteFile(conda_meta_path.to_string_lossy().to_string(), e)
     })?;
 
+    // Remove the recorded link script result, if any
+    let link_script_record_path = LinkScriptRecord::path_in_prefix(
+        target_prefix.path(),
+        &prefix_record.repodata_record.package_record.name,
+    );
+    match tokio_fs::remove_file(&link_script_record_path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(UnlinkError::FailedToDeleteFile(
+                link_script_record_path.to_string_lossy().to_string(),
+                e,
+            ));
+        }
+    }
+
     Ok(())
 }
 
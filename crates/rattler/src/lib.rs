@@ -18,6 +18,9 @@ use std::path::PathBuf;
 #[cfg(feature = "cli-tools")]
 pub mod cli;
 pub mod install;
+#[cfg(feature = "lock")]
+pub mod lock;
+pub mod sbom;
 pub use rattler_cache::{package_cache, validation};
 
 /// A helper function that returns a [`Channel`] instance that points to an
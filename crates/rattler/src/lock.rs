@@ -0,0 +1,462 @@
+//! Resolving a set of specs for multiple platforms and storing the result in a
+//! lock-file.
+//!
+//! The [`LockBuilder`] fetches the repodata for every platform through a
+//! [`Gateway`], solves the specs and stores the resulting packages, including
+//! their URLs and hashes, in a [`LockFile`]. An existing environment can be
+//! updated partially with [`LockBuilder::with_locked_environment`].
+
+use std::collections::{HashMap, HashSet};
+
+use futures::future::try_join_all;
+use rattler_conda_types::{
+    Channel, GenericVirtualPackage, MatchSpec, PackageName, PackageRecord, ParseStrictness,
+    Platform, RepoDataRecord,
+};
+use rattler_lock::{
+    CondaBinaryData, CondaPackageData, ConversionError, Environment, LockFile, SolveOptions,
+    DEFAULT_ENVIRONMENT_NAME,
+};
+use rattler_repodata_gateway::{Gateway, GatewayError};
+use rattler_solve::{resolvo, SolveError, SolverImpl, SolverTask};
+use simple_spawn_blocking::{tokio::run_blocking_task, Cancelled};
+
+/// Error that occurred while creating a lock-file with a [`LockBuilder`].
+#[derive(Debug, thiserror::Error)]
+pub enum LockError {
+    /// The repodata for a platform could not be fetched.
+    #[error("failed to fetch the repodata for {0}")]
+    GatewayError(Platform, #[source] GatewayError),
+
+    /// The specs could not be solved for a platform.
+    #[error("failed to solve the environment for {0}")]
+    SolveError(Platform, #[source] SolveError),
+
+    /// A previously locked package could not be converted to a repodata
+    /// record.
+    #[error("failed to read the locked package {0}")]
+    ConversionError(String, #[source] ConversionError),
+
+    /// The operation was cancelled.
+    #[error("the operation was cancelled")]
+    Cancelled,
+}
+
+impl From<Cancelled> for LockError {
+    fn from(_: Cancelled) -> Self {
+        LockError::Cancelled
+    }
+}
+
+/// Solves a set of specs for multiple platforms and stores the solutions in a
+/// [`LockFile`].
+///
+/// For every platform the repodata of the platform and `noarch` subdirectories
+/// of the channels is fetched through a [`Gateway`], after which the specs are
+/// solved. The platforms are solved concurrently.
+///
+/// ```no_run
+/// # async fn example(gateway: rattler_repodata_gateway::Gateway, channel: rattler_conda_types::Channel) {
+/// use rattler::lock::LockBuilder;
+/// use rattler_conda_types::{MatchSpec, ParseStrictness, Platform};
+///
+/// let lock_file = LockBuilder::new(
+///     [channel],
+///     [Platform::Linux64, Platform::OsxArm64],
+///     [MatchSpec::from_str("python 3.12.*", ParseStrictness::Strict).unwrap()],
+/// )
+/// .lock(&gateway)
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LockBuilder {
+    channels: Vec<Channel>,
+    platforms: Vec<Platform>,
+    specs: Vec<MatchSpec>,
+    virtual_packages: HashMap<Platform, Vec<GenericVirtualPackage>>,
+    options: SolveOptions,
+    environment: String,
+    locked_packages: HashMap<Platform, Vec<CondaBinaryData>>,
+    update_packages: Option<HashSet<PackageName>>,
+}
+
+impl LockBuilder {
+    /// Constructs a new builder that solves `specs` for each of the
+    /// `platforms` using the packages from `channels`.
+    pub fn new(
+        channels: impl IntoIterator<Item = Channel>,
+        platforms: impl IntoIterator<Item = Platform>,
+        specs: impl IntoIterator<Item = MatchSpec>,
+    ) -> Self {
+        Self {
+            channels: channels.into_iter().collect(),
+            platforms: platforms.into_iter().collect(),
+            specs: specs.into_iter().collect(),
+            virtual_packages: HashMap::new(),
+            options: SolveOptions::default(),
+            environment: DEFAULT_ENVIRONMENT_NAME.to_string(),
+            locked_packages: HashMap::new(),
+            update_packages: None,
+        }
+    }
+
+    /// Sets a previously locked environment that should be updated instead of
+    /// locking from scratch.
+    ///
+    /// The packages of the environment are passed to the solver as locked
+    /// packages, so they are only changed if the specs require it. Packages
+    /// that are kept are stored exactly as they were in the previous
+    /// lock-file. Use [`Self::with_update_packages`] to re-lock specific
+    /// packages.
+    #[must_use]
+    pub fn with_locked_environment(mut self, environment: Environment<'_>) -> Self {
+        self.locked_packages = environment
+            .conda_packages_by_platform()
+            .map(|(platform, packages)| {
+                (
+                    platform,
+                    packages
+                        .filter_map(CondaPackageData::as_binary)
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .collect();
+        self
+    }
+
+    /// Sets the packages that should be updated when a locked environment is
+    /// set with [`Self::with_locked_environment`].
+    ///
+    /// The locked versions of these packages and of the packages that directly
+    /// depend on them are not passed to the solver, so they are updated to
+    /// the best matching version. All other packages remain locked.
+    #[must_use]
+    pub fn with_update_packages(mut self, packages: impl IntoIterator<Item = PackageName>) -> Self {
+        self.update_packages = Some(packages.into_iter().collect());
+        self
+    }
+
+    /// Sets the virtual packages that are assumed to be available when solving
+    /// for `platform`. By default no virtual packages are available.
+    #[must_use]
+    pub fn with_virtual_packages(
+        mut self,
+        platform: Platform,
+        virtual_packages: impl IntoIterator<Item = GenericVirtualPackage>,
+    ) -> Self {
+        self.virtual_packages
+            .insert(platform, virtual_packages.into_iter().collect());
+        self
+    }
+
+    /// Sets the options that are used to solve the environment. The options
+    /// are also stored in the lock-file.
+    #[must_use]
+    pub fn with_options(mut self, options: SolveOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the name of the environment in the lock-file. Defaults to
+    /// [`DEFAULT_ENVIRONMENT_NAME`].
+    #[must_use]
+    pub fn with_environment_name(mut self, name: impl Into<String>) -> Self {
+        self.environment = name.into();
+        self
+    }
+
+    /// Fetches the repodata, solves the environment for all platforms and
+    /// returns the resulting lock-file.
+    pub async fn lock(self, gateway: &Gateway) -> Result<LockFile, LockError> {
+        let solved = try_join_all(
+            self.platforms
+                .iter()
+                .map(|&platform| self.solve_platform(gateway, platform)),
+        )
+        .await?;
+
+        let mut builder = LockFile::builder();
+        builder
+            .set_channels(
+                &self.environment,
+                self.channels
+                    .iter()
+                    .map(|channel| channel.base_url.to_string()),
+            )
+            .set_options(&self.environment, self.options.clone());
+        for (platform, packages) in solved {
+            for package in packages {
+                builder.add_conda_package(&self.environment, platform, package);
+            }
+        }
+
+        Ok(builder.finish())
+    }
+
+    async fn solve_platform(
+        &self,
+        gateway: &Gateway,
+        platform: Platform,
+    ) -> Result<(Platform, Vec<CondaPackageData>), LockError> {
+        let repo_data = gateway
+            .query(
+                self.channels.iter().cloned(),
+                [platform, Platform::NoArch],
+                self.specs.iter().cloned(),
+            )
+            .recursive(true)
+            .await
+            .map_err(|err| LockError::GatewayError(platform, err))?;
+
+        let kept_packages = self.kept_packages(platform);
+        let locked_packages = kept_packages
+            .iter()
+            .map(|package| {
+                RepoDataRecord::try_from(*package)
+                    .map_err(|err| LockError::ConversionError(package.file_name.clone(), err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let specs = self.specs.clone();
+        let virtual_packages = self
+            .virtual_packages
+            .get(&platform)
+            .cloned()
+            .unwrap_or_default();
+        let SolveOptions {
+            strategy,
+            channel_priority,
+            exclude_newer,
+        } = self.options.clone();
+
+        let records = run_blocking_task(move || {
+            let task = SolverTask {
+                locked_packages,
+                specs,
+                virtual_packages,
+                strategy,
+                channel_priority,
+                exclude_newer,
+                ..SolverTask::from_iter(&repo_data)
+            };
+            resolvo::Solver
+                .solve(task)
+                .map(|result| result.records)
+                .map_err(|err| LockError::SolveError(platform, err))
+        })
+        .await?;
+
+        // Store the packages that did not change exactly as they were locked
+        // before.
+        let packages = records
+            .into_iter()
+            .map(|record| {
+                kept_packages
+                    .iter()
+                    .find(|package| {
+                        package.file_name == record.file_name
+                            && package.location.as_url() == Some(&record.url)
+                    })
+                    .map_or_else(
+                        || CondaPackageData::from(record),
+                        |package| CondaPackageData::Binary((*package).clone()),
+                    )
+            })
+            .collect();
+
+        Ok((platform, packages))
+    }
+
+    /// Returns the previously locked packages for `platform` that should not
+    /// be updated.
+    fn kept_packages(&self, platform: Platform) -> Vec<&CondaBinaryData> {
+        let Some(packages) = self.locked_packages.get(&platform) else {
+            return Vec::new();
+        };
+        let Some(update) = &self.update_packages else {
+            return packages.iter().collect();
+        };
+
+        let updated = updated_packages(
+            packages.iter().map(|package| &package.package_record),
+            update,
+        );
+        packages
+            .iter()
+            .filter(|package| !updated.contains(&package.package_record.name))
+            .collect()
+    }
+}
+
+/// Returns the names of the packages in `update` together with the names of
+/// all the records that (transitively) depend on them.
+fn updated_packages<'a>(
+    records: impl IntoIterator<Item = &'a PackageRecord>,
+    update: &'a HashSet<PackageName>,
+) -> HashSet<&'a PackageName> {
+    let dependencies: HashMap<&PackageName, Vec<PackageName>> = records
+        .into_iter()
+        .map(|record| (&record.name, dependency_names(record)))
+        .collect();
+
+    // Add the packages that depend on an updated package until nothing
+    // changes anymore.
+    let mut updated: HashSet<&PackageName> = update.iter().collect();
+    loop {
+        let dependents: Vec<_> = dependencies
+            .iter()
+            .filter(|(name, depends)| {
+                !updated.contains(*name) && depends.iter().any(|dep| updated.contains(&dep))
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        updated.extend(dependents);
+    }
+    updated
+}
+
+/// Returns the names of the dependencies of a record.
+fn dependency_names(record: &PackageRecord) -> Vec<PackageName> {
+    record
+        .depends
+        .iter()
+        .filter_map(|spec| {
+            MatchSpec::from_str(spec, ParseStrictness::Lenient)
+                .ok()?
+                .name
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, path::Path};
+
+    use rattler_conda_types::{
+        Channel, MatchSpec, PackageName, PackageRecord, ParseStrictness, Platform, Version,
+    };
+    use rattler_lock::DEFAULT_ENVIRONMENT_NAME;
+    use rattler_repodata_gateway::Gateway;
+
+    use super::LockBuilder;
+
+    #[tokio::test]
+    async fn test_lock_builder() {
+        let channel = Channel::from_directory(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/channels/dummy-optional-dependencies"),
+        );
+
+        let lock_file = LockBuilder::new(
+            [channel],
+            [Platform::Linux64, Platform::OsxArm64],
+            [MatchSpec::from_str("bar", ParseStrictness::Strict).unwrap()],
+        )
+        .lock(&Gateway::new())
+        .await
+        .unwrap();
+
+        let environment = lock_file.environment(DEFAULT_ENVIRONMENT_NAME).unwrap();
+        assert_eq!(environment.channels().len(), 1);
+        for platform in [Platform::Linux64, Platform::OsxArm64] {
+            let file_names = environment
+                .conda_repodata_records(platform)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|record| record.file_name)
+                .collect::<Vec<_>>();
+            assert_eq!(file_names, vec!["bar-2-xxx.tar.bz2"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_update() {
+        let channel = Channel::from_directory(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/channels/dummy-optional-dependencies"),
+        );
+        let gateway = Gateway::new();
+        let file_names = |lock_file: &rattler_lock::LockFile| {
+            lock_file
+                .default_environment()
+                .unwrap()
+                .conda_repodata_records(Platform::Linux64)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|record| record.file_name)
+                .collect::<Vec<_>>()
+        };
+
+        let previous = LockBuilder::new(
+            [channel.clone()],
+            [Platform::Linux64],
+            [MatchSpec::from_str("bar ==1", ParseStrictness::Strict).unwrap()],
+        )
+        .lock(&gateway)
+        .await
+        .unwrap();
+        assert_eq!(file_names(&previous), vec!["bar-1-xxx.tar.bz2"]);
+
+        // Relaxing the spec keeps the locked package.
+        let spec = MatchSpec::from_str("bar", ParseStrictness::Strict).unwrap();
+        let relocked = LockBuilder::new([channel.clone()], [Platform::Linux64], [spec.clone()])
+            .with_locked_environment(previous.default_environment().unwrap())
+            .lock(&gateway)
+            .await
+            .unwrap();
+        assert_eq!(file_names(&relocked), vec!["bar-1-xxx.tar.bz2"]);
+
+        // Explicitly updating the package selects the newest version.
+        let updated = LockBuilder::new([channel], [Platform::Linux64], [spec])
+            .with_locked_environment(previous.default_environment().unwrap())
+            .with_update_packages(["bar".parse().unwrap()])
+            .lock(&gateway)
+            .await
+            .unwrap();
+        assert_eq!(file_names(&updated), vec!["bar-2-xxx.tar.bz2"]);
+    }
+
+    #[test]
+    fn test_updated_packages() {
+        let record = |name: &str, depends: &[&str]| {
+            let mut record = PackageRecord::new(
+                name.parse().unwrap(),
+                "1".parse::<Version>().unwrap(),
+                String::from("0"),
+            );
+            record.depends = depends.iter().map(ToString::to_string).collect();
+            record
+        };
+        let records = [
+            record("python", &[]),
+            record("numpy", &["python >=3.8"]),
+            record("scipy", &["numpy>=1.20", "python"]),
+            record("pandas", &["numpy[version='>=1.22']"]),
+            record("zlib", &[]),
+        ];
+
+        let update: HashSet<PackageName> = ["python".parse().unwrap()].into_iter().collect();
+        let mut updated = super::updated_packages(&records, &update)
+            .into_iter()
+            .map(PackageName::as_normalized)
+            .collect::<Vec<_>>();
+        updated.sort_unstable();
+        assert_eq!(updated, ["numpy", "pandas", "python", "scipy"]);
+
+        let update: HashSet<PackageName> = ["numpy".parse().unwrap()].into_iter().collect();
+        let mut updated = super::updated_packages(&records, &update)
+            .into_iter()
+            .map(PackageName::as_normalized)
+            .collect::<Vec<_>>();
+        updated.sort_unstable();
+        assert_eq!(updated, ["numpy", "pandas", "scipy"]);
+    }
+}
@@ -0,0 +1,630 @@
+//! Generates a software bill of materials (SBOM) for a conda environment.
+//!
+//! An [`Sbom`] can be created from the records of the packages installed in a
+//! prefix or from the packages locked for an environment and can be rendered
+//! as an [SPDX](https://spdx.dev) or [CycloneDX](https://cyclonedx.org) JSON
+//! document.
+
+use std::{collections::HashMap, time::SystemTime};
+
+use rattler_conda_types::{
+    package::{AboutJson, PackageFile},
+    MatchSpec, ParseStrictness, PrefixRecord, RepoDataRecord,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+/// A package that is described by an [`Sbom`].
+#[derive(Debug, Clone)]
+pub struct SbomPackage {
+    /// The record of the package.
+    pub record: RepoDataRecord,
+
+    /// The `about.json` of the package if it is available. This provides
+    /// additional metadata like the homepage and the license of the package.
+    pub about: Option<AboutJson>,
+}
+
+impl From<RepoDataRecord> for SbomPackage {
+    fn from(record: RepoDataRecord) -> Self {
+        Self {
+            record,
+            about: None,
+        }
+    }
+}
+
+/// A software bill of materials that describes the packages of an
+/// environment.
+#[derive(Debug, Clone)]
+pub struct Sbom {
+    name: String,
+    packages: Vec<SbomPackage>,
+    created: SystemTime,
+    serial_number: Uuid,
+}
+
+impl Sbom {
+    /// Constructs a new SBOM with the given name that describes `packages`.
+    pub fn new(name: impl Into<String>, packages: impl IntoIterator<Item = SbomPackage>) -> Self {
+        let mut packages = packages.into_iter().collect::<Vec<_>>();
+        packages.sort_by(|a, b| {
+            a.record
+                .package_record
+                .name
+                .cmp(&b.record.package_record.name)
+        });
+        Self {
+            name: name.into(),
+            packages,
+            created: SystemTime::now(),
+            serial_number: Uuid::new_v4(),
+        }
+    }
+
+    /// Constructs a new SBOM from a set of records, e.g. the result of a
+    /// solve.
+    pub fn from_records(
+        name: impl Into<String>,
+        records: impl IntoIterator<Item = RepoDataRecord>,
+    ) -> Self {
+        Self::new(name, records.into_iter().map(SbomPackage::from))
+    }
+
+    /// Constructs a new SBOM from the records of the packages installed in a
+    /// prefix.
+    ///
+    /// If the extracted package directory of a record is known the
+    /// `about.json` of the package is read to provide additional metadata.
+    pub fn from_prefix_records<'a>(
+        name: impl Into<String>,
+        records: impl IntoIterator<Item = &'a PrefixRecord>,
+    ) -> Self {
+        let packages = records.into_iter().map(|record| {
+            let about = record.extracted_package_dir.as_ref().and_then(|dir| {
+                AboutJson::from_package_directory(dir)
+                    .map_err(|err| {
+                        tracing::debug!(
+                            "failed to read about.json of {}: {err}",
+                            record.repodata_record.file_name
+                        );
+                    })
+                    .ok()
+            });
+            SbomPackage {
+                record: record.repodata_record.clone(),
+                about,
+            }
+        });
+        Self::new(name, packages)
+    }
+
+    /// Constructs a new SBOM from the conda packages locked for a platform of
+    /// a lock-file environment. Returns `None` if the platform is not defined
+    /// for the environment.
+    ///
+    /// Only binary conda packages are included, source and pypi packages are
+    /// omitted.
+    #[cfg(feature = "lock")]
+    pub fn from_lock_environment(
+        name: impl Into<String>,
+        environment: &rattler_lock::Environment<'_>,
+        platform: rattler_conda_types::Platform,
+    ) -> Result<Option<Self>, rattler_lock::ConversionError> {
+        Ok(environment
+            .conda_repodata_records(platform)?
+            .map(|records| Self::from_records(name, records)))
+    }
+
+    /// Sets the time at which the SBOM was created. Defaults to the time the
+    /// SBOM was constructed.
+    pub fn with_creation_time(self, created: SystemTime) -> Self {
+        Self { created, ..self }
+    }
+
+    /// Sets the unique identifier of the SBOM. This is used as the serial
+    /// number of a `CycloneDX` document and as part of the namespace of an SPDX
+    /// document. Defaults to a random identifier.
+    pub fn with_serial_number(self, serial_number: Uuid) -> Self {
+        Self {
+            serial_number,
+            ..self
+        }
+    }
+
+    /// Returns the packages described by the SBOM.
+    pub fn packages(&self) -> &[SbomPackage] {
+        &self.packages
+    }
+
+    /// Renders the SBOM as an SPDX 2.3 JSON document.
+    pub fn to_spdx_json(&self) -> Result<String, serde_json::Error> {
+        let ids = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(idx, package)| {
+                let name = package.record.package_record.name.as_normalized();
+                let name = name
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                    .collect::<String>();
+                format!("SPDXRef-Package-{idx}-{name}")
+            })
+            .collect::<Vec<_>>();
+
+        let packages = self
+            .packages
+            .iter()
+            .zip(&ids)
+            .map(|(package, id)| {
+                let record = &package.record.package_record;
+                let mut checksums = Vec::new();
+                if let Some(md5) = &record.md5 {
+                    checksums
+                        .push(json!({ "algorithm": "MD5", "checksumValue": format!("{md5:x}") }));
+                }
+                if let Some(sha256) = &record.sha256 {
+                    checksums.push(
+                        json!({ "algorithm": "SHA256", "checksumValue": format!("{sha256:x}") }),
+                    );
+                }
+
+                let external_refs = std::iter::once(conda_purl(&package.record))
+                    .chain(purls(&package.record))
+                    .map(|purl| {
+                        json!({
+                            "referenceCategory": "PACKAGE-MANAGER",
+                            "referenceType": "purl",
+                            "referenceLocator": purl,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut value = json!({
+                    "SPDXID": id,
+                    "name": record.name.as_normalized(),
+                    "versionInfo": record.version.to_string(),
+                    "downloadLocation": package.record.url.as_str(),
+                    "filesAnalyzed": false,
+                    "checksums": checksums,
+                    "licenseConcluded": "NOASSERTION",
+                    "licenseDeclared": license(package)
+                        .filter(|license| is_spdx_expression(license))
+                        .unwrap_or("NOASSERTION"),
+                    "copyrightText": "NOASSERTION",
+                    "externalRefs": external_refs,
+                });
+                if let Some(channel) = &package.record.channel {
+                    value["sourceInfo"] = json!(format!("conda channel: {channel}"));
+                }
+                if let Some(about) = &package.about {
+                    if let Some(home) = about.home.first() {
+                        value["homepage"] = json!(home.as_str());
+                    }
+                    if let Some(summary) = &about.summary {
+                        value["summary"] = json!(summary);
+                    }
+                    if let Some(description) = &about.description {
+                        value["description"] = json!(description);
+                    }
+                }
+                value
+            })
+            .collect::<Vec<_>>();
+
+        let relationships = ids
+            .iter()
+            .map(|id| {
+                json!({
+                    "spdxElementId": "SPDXRef-DOCUMENT",
+                    "relationshipType": "DESCRIBES",
+                    "relatedSpdxElement": id,
+                })
+            })
+            .chain(self.dependencies().map(|(from, to)| {
+                json!({
+                    "spdxElementId": &ids[from],
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": &ids[to],
+                })
+            }))
+            .collect::<Vec<_>>();
+
+        let document = json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": self.name,
+            "documentNamespace": format!(
+                "https://spdx.org/spdxdocs/{}-{}",
+                percent_encode(&self.name),
+                self.serial_number
+            ),
+            "creationInfo": {
+                "created": self.timestamp(),
+                "creators": [format!("Tool: rattler-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        });
+        serde_json::to_string_pretty(&document)
+    }
+
+    /// Renders the SBOM as a `CycloneDX` 1.5 JSON document.
+    pub fn to_cyclonedx_json(&self) -> Result<String, serde_json::Error> {
+        let refs = self
+            .packages
+            .iter()
+            .map(|package| package.record.url.to_string())
+            .collect::<Vec<_>>();
+
+        let components = self
+            .packages
+            .iter()
+            .zip(&refs)
+            .map(|(package, bom_ref)| {
+                let record = &package.record.package_record;
+                let mut hashes = Vec::new();
+                if let Some(md5) = &record.md5 {
+                    hashes.push(json!({ "alg": "MD5", "content": format!("{md5:x}") }));
+                }
+                if let Some(sha256) = &record.sha256 {
+                    hashes.push(json!({ "alg": "SHA-256", "content": format!("{sha256:x}") }));
+                }
+
+                let mut external_references =
+                    vec![json!({ "type": "distribution", "url": package.record.url.as_str() })];
+                let mut properties = vec![
+                    json!({ "name": "conda:build", "value": record.build }),
+                    json!({ "name": "conda:subdir", "value": record.subdir }),
+                ];
+                if let Some(channel) = &package.record.channel {
+                    properties.push(json!({ "name": "conda:channel", "value": channel }));
+                }
+                properties.extend(
+                    purls(&package.record)
+                        .map(|purl| json!({ "name": "conda:purl", "value": purl })),
+                );
+
+                let mut value = json!({
+                    "type": "library",
+                    "bom-ref": bom_ref,
+                    "name": record.name.as_normalized(),
+                    "version": record.version.to_string(),
+                    "purl": conda_purl(&package.record),
+                    "hashes": hashes,
+                });
+                if let Some(license) = license(package) {
+                    value["licenses"] = if is_spdx_expression(license) {
+                        json!([{ "expression": license }])
+                    } else {
+                        json!([{ "license": { "name": license } }])
+                    };
+                }
+                if let Some(about) = &package.about {
+                    if let Some(summary) = &about.summary {
+                        value["description"] = json!(summary);
+                    }
+                    for (kind, urls) in [
+                        ("website", &about.home),
+                        ("vcs", &about.dev_url),
+                        ("documentation", &about.doc_url),
+                    ] {
+                        external_references.extend(
+                            urls.iter()
+                                .map(|url| json!({ "type": kind, "url": url.as_str() })),
+                        );
+                    }
+                }
+                value["externalReferences"] = json!(external_references);
+                value["properties"] = json!(properties);
+                value
+            })
+            .collect::<Vec<_>>();
+
+        let mut depends_on = vec![Vec::new(); self.packages.len()];
+        for (from, to) in self.dependencies() {
+            depends_on[from].push(refs[to].as_str());
+        }
+        let dependencies = refs
+            .iter()
+            .zip(depends_on)
+            .map(|(bom_ref, depends_on)| json!({ "ref": bom_ref, "dependsOn": depends_on }))
+            .collect::<Vec<_>>();
+
+        let document = json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "serialNumber": self.serial_number.urn().to_string(),
+            "version": 1,
+            "metadata": {
+                "timestamp": self.timestamp(),
+                "tools": {
+                    "components": [{
+                        "type": "application",
+                        "name": "rattler",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    }],
+                },
+                "component": {
+                    "type": "application",
+                    "name": self.name,
+                },
+            },
+            "components": components,
+            "dependencies": dependencies,
+        });
+        serde_json::to_string_pretty(&document)
+    }
+
+    fn timestamp(&self) -> String {
+        humantime::format_rfc3339_seconds(self.created).to_string()
+    }
+
+    /// Returns the dependencies between the packages as pairs of indices into
+    /// `self.packages`. Dependencies on packages that are not part of the SBOM
+    /// (e.g. virtual packages) are ignored.
+    fn dependencies(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let by_name = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(idx, package)| (package.record.package_record.name.as_normalized(), idx))
+            .collect::<HashMap<_, _>>();
+
+        self.packages
+            .iter()
+            .enumerate()
+            .flat_map(move |(from, package)| {
+                let mut targets = package
+                    .record
+                    .package_record
+                    .depends
+                    .iter()
+                    .filter_map(|dep| {
+                        MatchSpec::from_str(dep, ParseStrictness::Lenient)
+                            .ok()?
+                            .name
+                    })
+                    .filter_map(|name| by_name.get(name.as_normalized()).copied())
+                    .collect::<Vec<_>>();
+                targets.sort_unstable();
+                targets.dedup();
+                targets.into_iter().map(move |to| (from, to))
+            })
+    }
+}
+
+/// Returns the license of a package, preferring the license from the record.
+fn license(package: &SbomPackage) -> Option<&str> {
+    package
+        .record
+        .package_record
+        .license
+        .as_deref()
+        .or_else(|| package.about.as_ref()?.license.as_deref())
+        .filter(|license| !license.trim().is_empty())
+}
+
+/// Returns true if the license is a valid SPDX license expression. Other
+/// licenses cannot be used where the formats require an SPDX expression.
+fn is_spdx_expression(license: &str) -> bool {
+    spdx::Expression::parse(license).is_ok()
+}
+
+/// Returns the package urls that are associated with the record, e.g. the purl
+/// of the pypi package that a conda package repackages.
+fn purls(record: &RepoDataRecord) -> impl Iterator<Item = String> + '_ {
+    record
+        .package_record
+        .purls
+        .iter()
+        .flatten()
+        .map(ToString::to_string)
+}
+
+/// Constructs the `pkg:conda` package url of a record.
+fn conda_purl(record: &RepoDataRecord) -> String {
+    let package_record = &record.package_record;
+    let package_type = if record.file_name.ends_with(".tar.bz2") {
+        "tar.bz2"
+    } else {
+        "conda"
+    };
+
+    let mut purl = format!(
+        "pkg:conda/{}@{}?build={}",
+        percent_encode(package_record.name.as_normalized()),
+        percent_encode(&package_record.version.to_string()),
+        percent_encode(&package_record.build),
+    );
+    if let Some(channel) = &record.channel {
+        purl.push_str(&format!("&channel={}", percent_encode(channel)));
+    }
+    purl.push_str(&format!(
+        "&subdir={}&type={package_type}",
+        percent_encode(&package_record.subdir)
+    ));
+    purl
+}
+
+/// Percent-encodes all characters of a purl component or URL path segment
+/// except for the unreserved characters.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use rattler_conda_types::{package::AboutJson, PrefixRecord, RepoDataRecord};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use super::Sbom;
+
+    fn record(name: &str, depends: &[&str]) -> RepoDataRecord {
+        serde_json::from_value(json!({
+            "name": name,
+            "version": "1.0",
+            "build": "h123_0",
+            "build_number": 0,
+            "subdir": "linux-64",
+            "depends": depends,
+            "license": "MIT",
+            "md5": "a98ea1e3abfdbbd201d60ff6b43ea7e4",
+            "sha256": "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+            "url": format!("https://conda.anaconda.org/conda-forge/linux-64/{name}-1.0-h123_0.conda"),
+            "fn": format!("{name}-1.0-h123_0.conda"),
+            "channel": "https://conda.anaconda.org/conda-forge/",
+        }))
+        .unwrap()
+    }
+
+    fn sbom() -> Sbom {
+        Sbom::from_records(
+            "test",
+            [
+                record("foo", &["bar >=1", "__glibc >=2.17"]),
+                record("bar", &[]),
+            ],
+        )
+        .with_creation_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        .with_serial_number(Uuid::nil())
+    }
+
+    #[test]
+    fn test_spdx() {
+        let document: Value = serde_json::from_str(&sbom().to_spdx_json().unwrap()).unwrap();
+
+        assert_eq!(document["spdxVersion"], "SPDX-2.3");
+        assert_eq!(document["creationInfo"]["created"], "2023-11-14T22:13:20Z");
+
+        let packages = document["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0]["SPDXID"], "SPDXRef-Package-0-bar");
+        assert_eq!(packages[1]["name"], "foo");
+        assert_eq!(packages[1]["licenseDeclared"], "MIT");
+        assert_eq!(packages[1]["checksums"][1]["algorithm"], "SHA256");
+        assert_eq!(
+            packages[1]["externalRefs"][0]["referenceLocator"],
+            "pkg:conda/foo@1.0?build=h123_0&channel=https%3A%2F%2Fconda.anaconda.org%2Fconda-forge%2F&subdir=linux-64&type=conda"
+        );
+
+        let depends_on = document["relationships"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["relationshipType"] == "DEPENDS_ON")
+            .collect::<Vec<_>>();
+        assert_eq!(depends_on.len(), 1);
+        assert_eq!(depends_on[0]["spdxElementId"], "SPDXRef-Package-1-foo");
+        assert_eq!(depends_on[0]["relatedSpdxElement"], "SPDXRef-Package-0-bar");
+    }
+
+    #[test]
+    fn test_spdx_namespace_encodes_name() {
+        let sbom = Sbom::from_records("my env/with spaces", [record("foo", &[])])
+            .with_serial_number(Uuid::nil());
+        let document: Value = serde_json::from_str(&sbom.to_spdx_json().unwrap()).unwrap();
+        assert_eq!(
+            document["documentNamespace"],
+            "https://spdx.org/spdxdocs/my%20env%2Fwith%20spaces-00000000-0000-0000-0000-000000000000"
+        );
+    }
+
+    #[test]
+    fn test_cyclonedx() {
+        let document: Value = serde_json::from_str(&sbom().to_cyclonedx_json().unwrap()).unwrap();
+
+        assert_eq!(document["bomFormat"], "CycloneDX");
+        assert_eq!(
+            document["serialNumber"],
+            "urn:uuid:00000000-0000-0000-0000-000000000000"
+        );
+
+        let components = document["components"].as_array().unwrap();
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[1]["name"], "foo");
+        assert_eq!(components[1]["licenses"][0]["expression"], "MIT");
+        assert_eq!(components[1]["hashes"][0]["alg"], "MD5");
+
+        let foo = document["dependencies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|d| d["ref"] == components[1]["bom-ref"])
+            .unwrap();
+        assert_eq!(
+            foo["dependsOn"],
+            Value::from(vec![components[0]["bom-ref"].clone()])
+        );
+    }
+
+    #[test]
+    fn test_non_spdx_license() {
+        let mut foo = record("foo", &["bar>=1.0,<2", "baz"]);
+        foo.package_record.license = Some("BSD".into());
+        let mut bar = record("bar", &[]);
+        bar.package_record.license = Some("MIT OR Apache-2.0".into());
+        let sbom = Sbom::from_records("test", [foo, bar]);
+
+        let document: Value = serde_json::from_str(&sbom.to_spdx_json().unwrap()).unwrap();
+        let packages = document["packages"].as_array().unwrap();
+        assert_eq!(packages[0]["licenseDeclared"], "MIT OR Apache-2.0");
+        assert_eq!(packages[1]["licenseDeclared"], "NOASSERTION");
+        let depends_on = document["relationships"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["relationshipType"] == "DEPENDS_ON")
+            .collect::<Vec<_>>();
+        assert_eq!(depends_on.len(), 1);
+        assert_eq!(depends_on[0]["relatedSpdxElement"], "SPDXRef-Package-0-bar");
+
+        let document: Value = serde_json::from_str(&sbom.to_cyclonedx_json().unwrap()).unwrap();
+        let components = document["components"].as_array().unwrap();
+        assert_eq!(
+            components[0]["licenses"][0]["expression"],
+            "MIT OR Apache-2.0"
+        );
+        assert_eq!(components[1]["licenses"][0]["license"]["name"], "BSD");
+        assert!(components[1]["licenses"][0]["expression"].is_null());
+    }
+
+    #[test]
+    fn test_from_prefix_records_reads_about_json() {
+        let package_dir = tempfile::tempdir().unwrap();
+        fs_err::create_dir_all(package_dir.path().join("info")).unwrap();
+        fs_err::write(
+            package_dir.path().join("info/about.json"),
+            r#"{"home": "https://example.com", "license": "BSD-3-Clause", "summary": "An example"}"#,
+        )
+        .unwrap();
+
+        let mut record = record("foo", &[]);
+        record.package_record.license = None;
+        let prefix_record = PrefixRecord {
+            extracted_package_dir: Some(package_dir.path().to_path_buf()),
+            ..PrefixRecord::from_repodata_record(record, Vec::new())
+        };
+
+        let sbom = Sbom::from_prefix_records("test", [&prefix_record]);
+        let about: &AboutJson = sbom.packages()[0].about.as_ref().unwrap();
+        assert_eq!(about.summary.as_deref(), Some("An example"));
+
+        let document: Value = serde_json::from_str(&sbom.to_spdx_json().unwrap()).unwrap();
+        assert_eq!(document["packages"][0]["licenseDeclared"], "BSD-3-Clause");
+        assert_eq!(document["packages"][0]["homepage"], "https://example.com/");
+    }
+}
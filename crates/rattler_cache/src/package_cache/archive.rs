@@ -0,0 +1,637 @@
+//! Retention of the original package archives next to the extracted package
+//! directories in the [`super::PackageCache`], and resumption of interrupted
+//! archive downloads.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use fs_err::tokio as tokio_fs;
+use futures::StreamExt;
+use rattler_conda_types::package::ArchiveType;
+use rattler_package_streaming::{DownloadReporter, ExtractError, ExtractResult};
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    StatusCode,
+};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+use super::segmented::{self, SegmentedDownload, SegmentedDownloads};
+
+/// Determines whether the downloaded package archives (`.conda` or
+/// `.tar.bz2` files) are kept in the cache after they have been extracted.
+///
+/// Retained archives can be used to re-export the exact artifacts, to serve a
+/// local channel from the cache or to verify signatures later on without
+/// downloading the packages again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArchiveRetention {
+    /// Archives are streamed directly into the extracted directory and never
+    /// stored on disk. This is the default.
+    #[default]
+    Discard,
+
+    /// Archives are kept for as long as the extracted package is in the cache.
+    Keep,
+
+    /// Archives are kept until they are older than the given duration. Expired
+    /// archives are removed by [`super::PackageCache::prune`].
+    KeepFor(Duration),
+}
+
+impl ArchiveRetention {
+    /// Returns true if archives should be stored on disk.
+    pub fn retains_archives(self) -> bool {
+        !matches!(self, ArchiveRetention::Discard)
+    }
+
+    /// Returns true if an archive that was last modified at `modified` should
+    /// be removed.
+    pub(super) fn is_expired(self, modified: SystemTime) -> bool {
+        match self {
+            ArchiveRetention::Discard => true,
+            ArchiveRetention::Keep => false,
+            ArchiveRetention::KeepFor(duration) => SystemTime::now()
+                .duration_since(modified)
+                .is_ok_and(|age| age > duration),
+        }
+    }
+}
+
+/// Returns the paths at which the archive of the entry at `entry_path` could
+/// be stored.
+pub(super) fn archive_paths(entry_path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    [ArchiveType::Conda, ArchiveType::TarBz2]
+        .into_iter()
+        .map(|archive_type| archive_path(entry_path, archive_type))
+}
+
+/// Returns the path at which the archive of the given type for the entry at
+/// `entry_path` is stored.
+pub(super) fn archive_path(entry_path: &Path, archive_type: ArchiveType) -> PathBuf {
+    let mut path = entry_path.as_os_str().to_owned();
+    path.push(archive_type.extension());
+    PathBuf::from(path)
+}
+
+/// Returns the path of the partially downloaded archive that will be moved to
+/// `archive_path` once the download completes.
+pub(super) fn partial_path(archive_path: &Path) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(".partial");
+    PathBuf::from(path)
+}
+
+/// Returns the path of the file that stores the validation state of a partial
+/// download.
+fn partial_state_path(partial_path: &Path) -> PathBuf {
+    let mut path = partial_path.as_os_str().to_owned();
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+/// The information required to verify that a partially downloaded archive
+/// still matches the resource on the server. It is stored next to the partial
+/// file so the download can be resumed by a later attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PartialDownloadState {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl PartialDownloadState {
+    fn from_headers(url: &Url, headers: &HeaderMap) -> Option<Self> {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+        let etag = header(ETAG).filter(|etag| !etag.starts_with("W/"));
+        let last_modified = header(LAST_MODIFIED);
+        if etag.is_none() && last_modified.is_none() {
+            // Without a validator we cannot make sure the remaining bytes belong to the same
+            // resource.
+            return None;
+        }
+        Some(Self {
+            url: url.to_string(),
+            etag,
+            last_modified,
+        })
+    }
+
+    /// The value of the `If-Range` header, strong validators are preferred.
+    fn if_range(&self) -> Option<&str> {
+        self.etag.as_deref().or(self.last_modified.as_deref())
+    }
+
+    async fn read(path: &Path) -> Option<Self> {
+        let contents = tokio_fs::read(path).await.ok()?;
+        let value: serde_json::Value = serde_json::from_slice(&contents).ok()?;
+        let field = |name| value.get(name).and_then(|v| v.as_str()).map(str::to_owned);
+        Some(Self {
+            url: field("url")?,
+            etag: field("etag"),
+            last_modified: field("last_modified"),
+        })
+    }
+
+    async fn write(&self, path: &Path) -> std::io::Result<()> {
+        let value = serde_json::json!({
+            "url": self.url,
+            "etag": self.etag,
+            "last_modified": self.last_modified,
+        });
+        tokio_fs::write(path, value.to_string()).await
+    }
+}
+
+/// Options that control how archives are downloaded to disk.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct DownloadOptions {
+    /// Whether the archive is kept after it has been extracted.
+    pub keep_archive: bool,
+
+    /// Whether large archives are downloaded in parallel segments.
+    pub segmented: Option<SegmentedDownloads>,
+}
+
+/// Downloads the archive at `url` to `archive_path` and extracts it into
+/// `destination`. The archive is only moved into place if extraction succeeds
+/// and [`DownloadOptions::keep_archive`] is true, otherwise it is removed after
+/// extraction.
+///
+/// If the download is interrupted, the partially downloaded file is kept
+/// together with the validators (`ETag`/`Last-Modified`) of the response. A
+/// subsequent call resumes the download with a `Range` request if the server
+/// supports it, and falls back to downloading the whole archive otherwise.
+pub(super) async fn download_and_extract(
+    client: reqwest_middleware::ClientWithMiddleware,
+    url: Url,
+    destination: &Path,
+    archive_path: &Path,
+    options: DownloadOptions,
+    reporter: Option<Arc<dyn DownloadReporter>>,
+) -> Result<ExtractResult, ExtractError> {
+    let partial_path = partial_path(archive_path);
+    let state_path = partial_state_path(&partial_path);
+
+    if let Err(e) = download(
+        client,
+        url,
+        &partial_path,
+        &state_path,
+        options.segmented,
+        reporter,
+    )
+    .await
+    {
+        // Keep the partial file around if the download can be resumed later on.
+        if tokio_fs::metadata(&state_path).await.is_err() {
+            let _ = tokio_fs::remove_file(&partial_path).await;
+        }
+        return Err(e);
+    }
+
+    // The partial file does not have the extension of the archive, so
+    // determine the archive type from the final path.
+    let result = match ArchiveType::try_from(archive_path) {
+        Some(ArchiveType::TarBz2) => {
+            rattler_package_streaming::tokio::fs::extract_tar_bz2(&partial_path, destination).await
+        }
+        Some(ArchiveType::Conda) => {
+            rattler_package_streaming::tokio::fs::extract_conda(&partial_path, destination).await
+        }
+        None => Err(ExtractError::UnsupportedArchiveType),
+    };
+    let _ = tokio_fs::remove_file(&state_path).await;
+    match result {
+        Ok(result) if options.keep_archive => {
+            tokio_fs::rename(&partial_path, archive_path).await?;
+            Ok(result)
+        }
+        Ok(result) => {
+            let _ = tokio_fs::remove_file(&partial_path).await;
+            Ok(result)
+        }
+        Err(e) => {
+            let _ = tokio_fs::remove_file(&partial_path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Downloads `url` to `path`, resuming a previous partial download if
+/// possible.
+async fn download(
+    client: reqwest_middleware::ClientWithMiddleware,
+    url: Url,
+    path: &Path,
+    state_path: &Path,
+    segmented: Option<SegmentedDownloads>,
+    reporter: Option<Arc<dyn DownloadReporter>>,
+) -> Result<(), ExtractError> {
+    if let Some(reporter) = &reporter {
+        reporter.on_download_start();
+    }
+
+    if url.scheme() == "file" {
+        let source = url
+            .to_file_path()
+            .map_err(|_err| ExtractError::IoError(std::io::ErrorKind::InvalidInput.into()))?;
+        tokio_fs::copy(source, path).await?;
+    } else {
+        // Determine whether there is a partial download that we can resume.
+        let mut resume = match PartialDownloadState::read(state_path).await {
+            Some(state) if state.url == url.as_str() => match tokio_fs::metadata(path).await {
+                Ok(metadata) if metadata.len() > 0 => Some((state, metadata.len())),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        // Without a partial download to resume, try to download the archive in
+        // segments if enabled.
+        let mut full_response = None;
+        if let (None, Some(segmented)) = (&resume, segmented) {
+            let _ = tokio_fs::remove_file(state_path).await;
+            match segmented::download(&client, &url, path, segmented, reporter.as_ref()).await? {
+                SegmentedDownload::Completed => {
+                    if let Some(reporter) = &reporter {
+                        reporter.on_download_complete();
+                    }
+                    return Ok(());
+                }
+                SegmentedDownload::Unsupported(response) => full_response = response,
+            }
+        }
+
+        let response = loop {
+            if let Some(response) = full_response.take() {
+                break response;
+            }
+
+            let mut request = client.get(url.clone());
+            if let Some((state, offset)) = &resume {
+                request = request.header(RANGE, format!("bytes={offset}-"));
+                if let Some(if_range) = state.if_range() {
+                    request = request.header(IF_RANGE, if_range);
+                }
+            }
+
+            let response = request.send().await.map_err(ExtractError::ReqwestError)?;
+
+            // The server no longer accepts our range, start over.
+            if resume.is_some() && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                tracing::debug!("server rejected resuming the download of {url}, restarting");
+                resume = None;
+                continue;
+            }
+
+            break response
+                .error_for_status()
+                .map_err(|e| ExtractError::ReqwestError(reqwest_middleware::Error::Reqwest(e)))?;
+        };
+
+        // Only append to the partial file if the server actually returned the
+        // range we asked for.
+        let offset = match resume {
+            Some((_, offset))
+                if response.status() == StatusCode::PARTIAL_CONTENT
+                    && segmented::content_range(response.headers())
+                        .is_some_and(|(start, _, _)| start == offset) =>
+            {
+                tracing::debug!("resuming the download of {url} at byte {offset}");
+                offset
+            }
+            _ => 0,
+        };
+
+        // Store the validators of the response so an interrupted download can be
+        // resumed.
+        if offset == 0 {
+            match PartialDownloadState::from_headers(&url, response.headers()) {
+                Some(state) => state.write(state_path).await?,
+                None => {
+                    let _ = tokio_fs::remove_file(state_path).await;
+                }
+            }
+        }
+
+        let total_bytes = response.content_length().map(|len| len + offset);
+        let mut bytes_received = offset;
+        let mut file = if offset > 0 {
+            tokio_fs::OpenOptions::new().append(true).open(path).await?
+        } else {
+            tokio_fs::File::create(path).await?
+        };
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| {
+                ExtractError::IoError(std::io::Error::new(std::io::ErrorKind::Interrupted, e))
+            })?;
+            file.write_all(&chunk).await?;
+            bytes_received += chunk.len() as u64;
+            if let Some(reporter) = &reporter {
+                reporter.on_download_progress(bytes_received, total_bytes);
+            }
+        }
+        file.flush().await?;
+
+        if total_bytes.is_some_and(|total| bytes_received < total) {
+            return Err(ExtractError::IoError(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "the connection was closed before the download completed",
+            )));
+        }
+    }
+
+    if let Some(reporter) = &reporter {
+        reporter.on_download_complete();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::IntoFuture,
+        net::SocketAddr,
+        path::Path,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use axum::{
+        body::Body,
+        extract::State,
+        http::{header, HeaderMap, StatusCode},
+        response::Response,
+        routing::get,
+        Router,
+    };
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use rattler_conda_types::package::ArchiveIdentifier;
+    use rattler_networking::retry_policies::ExponentialBackoffBuilder;
+    use reqwest::Client;
+    use reqwest_middleware::ClientBuilder;
+    use tempfile::tempdir;
+    use url::Url;
+
+    use super::ArchiveRetention;
+    use crate::package_cache::{PackageCache, PrunePolicy, SegmentedDownloads};
+
+    struct InterruptingServer {
+        data: Vec<u8>,
+        requested_ranges: Mutex<Vec<Option<String>>>,
+    }
+
+    /// Serves the archive with range support, but aborts the first full
+    /// download halfway through.
+    async fn serve_interrupted(
+        State(server): State<Arc<InterruptingServer>>,
+        headers: HeaderMap,
+    ) -> Response {
+        let range = headers
+            .get(header::RANGE)
+            .map(|value| value.to_str().unwrap().to_string());
+        server.requested_ranges.lock().unwrap().push(range.clone());
+
+        let len = server.data.len();
+        if let Some(range) = range {
+            let start: usize = range
+                .strip_prefix("bytes=")
+                .and_then(|range| range.strip_suffix('-'))
+                .unwrap()
+                .parse()
+                .unwrap();
+            return Response::builder()
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::ETAG, "\"archive\"")
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {start}-{}/{len}", len - 1),
+                )
+                .body(Body::from(server.data[start..].to_vec()))
+                .unwrap();
+        }
+
+        // Delay the interruption so that the headers and the first half of the
+        // body reach the client before the connection is aborted.
+        let half = Bytes::copy_from_slice(&server.data[..len / 2]);
+        let stream = futures::stream::once(async move { Ok::<_, std::io::Error>(half) }).chain(
+            futures::stream::once(async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "interrupted",
+                ))
+            }),
+        );
+        Response::builder()
+            .header(header::ETAG, "\"archive\"")
+            .header(header::CONTENT_LENGTH, len)
+            .body(Body::from_stream(stream))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resume_interrupted_download() {
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        let server = Arc::new(InterruptingServer {
+            data: fs_err::read(&package_path).unwrap(),
+            requested_ranges: Mutex::default(),
+        });
+
+        let router = Router::new()
+            .route("/{file}", get(serve_interrupted))
+            .with_state(server.clone());
+        let addr = SocketAddr::new([127, 0, 0, 1].into(), 0);
+        let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router.into_make_service()).into_future());
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path()).with_resumable_downloads(true);
+        let key = ArchiveIdentifier::try_from_path(&package_path).unwrap();
+        let url = Url::parse(&format!(
+            "http://127.0.0.1:{}/clobber-python-0.1.0-cpython.conda",
+            addr.port()
+        ))
+        .unwrap();
+
+        let retry_policy = ExponentialBackoffBuilder::default()
+            .retry_bounds(Duration::from_millis(1), Duration::from_millis(10))
+            .build_with_max_retries(3);
+        let cache_lock = cache
+            .get_or_fetch_from_url_with_retry(
+                key.clone(),
+                url,
+                ClientBuilder::new(Client::default()).build().into(),
+                retry_policy,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(cache_lock.path().join("info/index.json").is_file());
+
+        // The second request should have continued where the first one stopped.
+        let half = server.data.len() / 2;
+        assert_eq!(
+            *server.requested_ranges.lock().unwrap(),
+            vec![None, Some(format!("bytes={half}-"))]
+        );
+
+        // Neither the archive nor the partial download are kept around.
+        assert!(cache.retained_archive(key).is_none());
+        let leftovers = fs_err::read_dir(packages_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains(".partial"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    /// Serves the requested byte range of the archive.
+    async fn serve_ranges(
+        State(server): State<Arc<InterruptingServer>>,
+        headers: HeaderMap,
+    ) -> Response {
+        let range = headers
+            .get(header::RANGE)
+            .map(|value| value.to_str().unwrap().to_string())
+            .unwrap();
+        server
+            .requested_ranges
+            .lock()
+            .unwrap()
+            .push(Some(range.clone()));
+
+        let len = server.data.len();
+        let (start, end) = range
+            .strip_prefix("bytes=")
+            .and_then(|range| range.split_once('-'))
+            .unwrap();
+        let start: usize = start.parse().unwrap();
+        let end = end.parse::<usize>().unwrap().min(len - 1);
+        Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{len}"))
+            .body(Body::from(server.data[start..=end].to_vec()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_segmented_download() {
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        let server = Arc::new(InterruptingServer {
+            data: fs_err::read(&package_path).unwrap(),
+            requested_ranges: Mutex::default(),
+        });
+
+        let router = Router::new()
+            .route("/{file}", get(serve_ranges))
+            .with_state(server.clone());
+        let addr = SocketAddr::new([127, 0, 0, 1].into(), 0);
+        let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router.into_make_service()).into_future());
+
+        let segment_size = 512;
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path())
+            .with_archive_retention(ArchiveRetention::KeepFor(Duration::from_secs(3600)))
+            .with_segmented_downloads(
+                SegmentedDownloads::default()
+                    .with_segment_size(segment_size)
+                    .with_max_connections(3),
+            );
+        let key = ArchiveIdentifier::try_from_path(&package_path).unwrap();
+        let url = Url::parse(&format!(
+            "http://127.0.0.1:{}/clobber-python-0.1.0-cpython.conda",
+            addr.port()
+        ))
+        .unwrap();
+
+        let cache_lock = cache
+            .get_or_fetch_from_url(
+                key.clone(),
+                url,
+                ClientBuilder::new(Client::default()).build().into(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(cache_lock.path().join("info/index.json").is_file());
+
+        // Every segment is requested exactly once.
+        let len = server.data.len() as u64;
+        let mut requested_ranges = server.requested_ranges.lock().unwrap().clone();
+        requested_ranges.sort_by_key(|range| {
+            range
+                .as_deref()
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.split_once('-'))
+                .map(|(start, _)| start.parse::<u64>().unwrap())
+        });
+        let expected_ranges = (0..len)
+            .step_by(segment_size as usize)
+            .map(|start| {
+                let end = (start + segment_size - 1).min(len - 1);
+                Some(format!("bytes={start}-{end}"))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(requested_ranges, expected_ranges);
+
+        // The reassembled archive is identical to the original.
+        let archive = cache.retained_archive(key).unwrap();
+        assert_eq!(fs_err::read(&archive).unwrap(), server.data);
+    }
+
+    #[tokio::test]
+    async fn test_retain_archive() {
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        let key = ArchiveIdentifier::try_from_path(&package_path).unwrap();
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path())
+            .with_archive_retention(ArchiveRetention::KeepFor(Duration::ZERO));
+        let client = ClientBuilder::new(Client::default()).build();
+
+        let cache_lock = cache
+            .get_or_fetch_from_url(
+                key.clone(),
+                Url::from_file_path(&package_path).unwrap(),
+                client.into(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(cache_lock.path().join("info/index.json").is_file());
+        drop(cache_lock);
+
+        let archive = cache.retained_archive(key.clone()).unwrap();
+        assert_eq!(
+            fs_err::read(&archive).unwrap(),
+            fs_err::read(&package_path).unwrap()
+        );
+
+        // The archive has expired, so pruning removes it but keeps the entry.
+        let reclaimed = cache
+            .prune(PrunePolicy::KeepLatestVersions(1))
+            .await
+            .unwrap();
+        assert!(reclaimed > 0);
+        assert!(cache.retained_archive(key).is_none());
+    }
+}
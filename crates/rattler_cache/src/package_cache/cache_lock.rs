@@ -1,5 +1,5 @@
 use std::{
-    fmt::{Debug, Formatter},
+    fmt::{Debug, Display, Formatter},
     io::{Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     sync::Arc,
@@ -11,7 +11,7 @@ use fs4::fs_std::FileExt;
 use parking_lot::Mutex;
 use rattler_digest::Sha256Hash;
 
-use crate::package_cache::PackageCacheError;
+use crate::package_cache::{CacheReporter, PackageCacheError};
 
 /// A lock on the cache entry. As long as this lock is held, no other process is
 /// allowed to modify the cache entry. This however, does not guarantee that the
@@ -19,7 +19,7 @@ use crate::package_cache::PackageCacheError;
 /// guarantee that when concurrent processes access the package cache they do
 /// not interfere with each other.
 pub struct CacheLock {
-    pub(super) _lock: CacheRwLock,
+    pub(super) _lock: Option<CacheRwLock>,
     pub(super) revision: u64,
     pub(super) sha256: Option<Sha256Hash>,
     pub(super) path: PathBuf,
@@ -48,12 +48,90 @@ impl CacheLock {
     }
 }
 
+/// Describes how long to wait for a cache lock that is held by another process
+/// and who to notify while waiting.
+#[derive(Clone, Default)]
+pub(crate) struct LockWait {
+    pub timeout: Option<Duration>,
+    pub reporter: Option<Arc<dyn CacheReporter>>,
+}
+
+/// Information about the process that holds (or last held) the write lock on a
+/// cache entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    /// The process id of the holder.
+    pub pid: u32,
+
+    /// The hostname of the machine the holder runs on.
+    pub hostname: String,
+}
+
+impl Display for LockHolder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "process {} on '{}'", self.pid, self.hostname)
+    }
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            hostname: hostname(),
+        }
+    }
+
+    /// Reads the holder recorded next to the lock file at `path`.
+    pub(crate) fn read(path: &Path) -> Option<Self> {
+        let contents = fs_err::read_to_string(holder_file_path(path)).ok()?;
+        let (pid, hostname) = contents.trim().split_once(' ')?;
+        Some(Self {
+            pid: pid.parse().ok()?,
+            hostname: hostname.to_string(),
+        })
+    }
+
+    fn write(&self, path: &Path) {
+        let contents = format!("{} {}", self.pid, self.hostname);
+        if let Err(e) = fs_err::write(holder_file_path(path), contents) {
+            tracing::debug!("failed to record the holder of a cache lock: {e}");
+        }
+    }
+}
+
+/// Returns the path of the file that records which process holds the write
+/// lock. This is kept in a separate file because the lock file itself is
+/// shared by readers which must not modify it.
+pub(super) fn holder_file_path(lock_file_path: &Path) -> PathBuf {
+    let mut path = lock_file_path.as_os_str().to_owned();
+    path.push(".holder");
+    PathBuf::from(path)
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| {
+            fs_err::read_to_string("/etc/hostname")
+                .ok()
+                .map(|hostname| hostname.trim().to_string())
+        })
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| String::from("<unknown>"))
+}
+
 pub struct CacheRwLock {
     file: Arc<Mutex<std::fs::File>>,
+    holder_path: Option<PathBuf>,
 }
 
 impl Drop for CacheRwLock {
     fn drop(&mut self) {
+        if let Some(holder_path) = self.holder_path.take() {
+            let _ = fs_err::remove_file(holder_path);
+        }
+
         // Ensure that the lock is released when the lock is dropped.
         let _ = fs4::fs_std::FileExt::unlock(&*self.file.lock());
     }
@@ -61,96 +139,236 @@ impl Drop for CacheRwLock {
 
 impl CacheRwLock {
     pub async fn acquire_read(path: &Path) -> Result<Self, PackageCacheError> {
+        Self::acquire(path, false, &LockWait::default()).await
+    }
+
+    pub async fn acquire_write(path: &Path) -> Result<Self, PackageCacheError> {
+        Self::acquire(path, true, &LockWait::default()).await
+    }
+
+    /// Acquires a shared (`exclusive == false`) or exclusive lock on the lock
+    /// file at `path`. If the lock is held by another process, the reporter
+    /// is notified and the lock is awaited for at most the configured timeout.
+    pub async fn acquire(
+        path: &Path,
+        exclusive: bool,
+        wait: &LockWait,
+    ) -> Result<Self, PackageCacheError> {
         let lock_file_path = path.to_path_buf();
+        let file = simple_spawn_blocking::tokio::run_blocking_task(move || {
+            open_lock_file(&lock_file_path, exclusive)
+        })
+        .await?;
+        let file = Arc::new(Mutex::new(file));
 
-        let acquire_lock_fut = simple_spawn_blocking::tokio::run_blocking_task(move || {
-            let file = std::fs::OpenOptions::new()
-                .create(true)
-                .read(true)
-                .truncate(false)
-                .write(true)
-                .open(&lock_file_path)
-                .map_err(|e| {
-                    PackageCacheError::LockError(
-                        format!(
-                            "failed to open cache lock for reading: '{}'",
-                            lock_file_path.display()
-                        ),
-                        e,
-                    )
-                })?;
+        // First try to acquire the lock without blocking.
+        let acquired =
+            try_lock(&file.lock(), exclusive).map_err(|e| lock_error(path, exclusive, e))?;
 
-            fs4::fs_std::FileExt::lock_shared(&file).map_err(move |e| {
-                PackageCacheError::LockError(
-                    format!(
-                        "failed to acquire read lock on cache lock file: '{}'",
-                        lock_file_path.display()
-                    ),
-                    e,
-                )
-            })?;
+        if !acquired {
+            let holder = LockHolder::read(path);
+            if let Some(holder) = &holder {
+                tracing::debug!(
+                    "waiting for cache lock '{}' held by {holder}",
+                    path.display()
+                );
+            } else {
+                tracing::debug!("waiting for cache lock '{}'", path.display());
+            }
+            if let Some(reporter) = &wait.reporter {
+                reporter.on_lock_wait(path, holder.as_ref());
+            }
 
-            Ok(CacheRwLock {
-                file: Arc::new(Mutex::new(file)),
-            })
+            tokio::select!(
+                lock = wait_for_lock(&file, path, exclusive, wait.timeout) => lock?,
+                _ = warn_timeout_future(format!(
+                    "Blocking waiting for file lock on package cache for {}",
+                    path.file_name()
+                        .expect("lock file must have a name")
+                        .to_string_lossy()
+                )) => unreachable!("warn_timeout_future should never finish")
+            );
+        }
+
+        let holder_path = exclusive.then(|| {
+            LockHolder::current().write(path);
+            holder_file_path(path)
         });
 
-        tokio::select!(
-            lock = acquire_lock_fut => lock,
-            _ = warn_timeout_future(format!(
-                "Blocking waiting for file lock on package cache for {}",
-                path.file_name()
-                    .expect("lock file must have a name")
-                    .to_string_lossy()
-            )) => unreachable!("warn_timeout_future should never finish")
-        )
+        Ok(CacheRwLock { file, holder_path })
     }
 }
 
+/// The maximum interval between two attempts to acquire a lock that is held
+/// by another process when waiting with a timeout.
+const MAX_LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Waits until the lock on `file` is acquired.
+///
+/// Without a timeout a blocking task waits for the lock. With a timeout the
+/// lock is polled with an increasing interval instead. A blocking task cannot
+/// be cancelled, so it would still take the lock after the timeout expired
+/// while nobody holds on to it.
+async fn wait_for_lock(
+    file: &Arc<Mutex<std::fs::File>>,
+    path: &Path,
+    exclusive: bool,
+    timeout: Option<Duration>,
+) -> Result<(), PackageCacheError> {
+    let Some(timeout) = timeout else {
+        let lock_file_path = path.to_path_buf();
+        let blocking_file = file.clone();
+        return simple_spawn_blocking::tokio::run_blocking_task(move || {
+            let file = blocking_file.lock();
+            if exclusive {
+                file.lock_exclusive()
+            } else {
+                fs4::fs_std::FileExt::lock_shared(&*file)
+            }
+            .map_err(|e| lock_error(&lock_file_path, exclusive, e))
+        })
+        .await;
+    };
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut interval = Duration::from_millis(10);
+    loop {
+        if try_lock(&file.lock(), exclusive).map_err(|e| lock_error(path, exclusive, e))? {
+            return Ok(());
+        }
+
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return Err(PackageCacheError::LockTimeout {
+                path: path.to_path_buf(),
+                timeout,
+                holder: LockHolder::read(path),
+            });
+        }
+        tokio::time::sleep(interval.min(deadline - now)).await;
+        interval = (interval * 2).min(MAX_LOCK_POLL_INTERVAL);
+    }
+}
+
+/// Tries to acquire a shared (`exclusive == false`) or exclusive lock on the
+/// file without blocking. Returns `false` if another process holds the lock.
+fn try_lock(file: &std::fs::File, exclusive: bool) -> std::io::Result<bool> {
+    if exclusive {
+        fs4::fs_std::FileExt::try_lock_exclusive(file)
+    } else {
+        fs4::fs_std::FileExt::try_lock_shared(file)
+    }
+}
+
+fn open_lock_file(path: &Path, exclusive: bool) -> Result<std::fs::File, PackageCacheError> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .or_else(|e| {
+            // The lock file might be owned by another user of a shared cache. A
+            // shared lock does not require write access so fall back to opening
+            // the file for reading only.
+            if !exclusive && e.kind() == std::io::ErrorKind::PermissionDenied {
+                std::fs::File::open(path)
+            } else {
+                Err(e)
+            }
+        })
+        .map_err(|e| {
+            PackageCacheError::LockError(
+                format!(
+                    "failed to open cache lock for {}: '{}'",
+                    if exclusive { "writing" } else { "reading" },
+                    path.display()
+                ),
+                e,
+            )
+        })
+}
+
+fn lock_error(path: &Path, exclusive: bool, e: std::io::Error) -> PackageCacheError {
+    PackageCacheError::LockError(
+        format!(
+            "failed to acquire {} lock on cache lock file: '{}'",
+            if exclusive { "write" } else { "read" },
+            path.display()
+        ),
+        e,
+    )
+}
+
 impl CacheRwLock {
-    pub async fn acquire_write(path: &Path) -> Result<Self, PackageCacheError> {
+    /// Acquires a read lock on a lock file in a read-only cache layer. Returns
+    /// `None` if the lock file does not exist or the filesystem does not
+    /// support locking, since no writer can modify the layer through us.
+    pub async fn acquire_read_only(path: &Path) -> Result<Option<Self>, PackageCacheError> {
         let lock_file_path = path.to_path_buf();
-        let acquire_lock_fut = simple_spawn_blocking::tokio::run_blocking_task(move || {
-            let file = std::fs::OpenOptions::new()
-                .create(true)
-                .truncate(false)
-                .write(true)
-                .read(true)
-                .open(&lock_file_path)
-                .map_err(|e| {
-                    PackageCacheError::LockError(
+        simple_spawn_blocking::tokio::run_blocking_task(move || {
+            let file = match std::fs::File::open(&lock_file_path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => {
+                    return Err(PackageCacheError::LockError(
                         format!(
-                            "failed to open cache lock for writing: '{}",
+                            "failed to open cache lock for reading: '{}'",
                             lock_file_path.display()
                         ),
                         e,
-                    )
-                })?;
+                    ))
+                }
+            };
+
+            if let Err(e) = fs4::fs_std::FileExt::lock_shared(&file) {
+                tracing::debug!(
+                    "failed to acquire read lock on read-only cache lock file '{}': {e}",
+                    lock_file_path.display()
+                );
+                return Ok(None);
+            }
+
+            Ok(Some(CacheRwLock {
+                file: Arc::new(Mutex::new(file)),
+                holder_path: None,
+            }))
+        })
+        .await
+    }
+}
 
-            file.lock_exclusive().map_err(move |e| {
+impl CacheRwLock {
+    /// Tries to acquire a write lock without blocking. Returns `None` if
+    /// another process currently holds a lock on the file.
+    pub fn try_acquire_write(path: &Path) -> Result<Option<Self>, PackageCacheError> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .read(true)
+            .open(path)
+            .map_err(|e| {
                 PackageCacheError::LockError(
-                    format!(
-                        "failed to acquire write lock on cache lock file: '{}'",
-                        lock_file_path.display()
-                    ),
+                    format!("failed to open cache lock for writing: '{}", path.display()),
                     e,
                 )
             })?;
 
-            Ok(CacheRwLock {
-                file: Arc::new(Mutex::new(file)),
-            })
-        });
+        let acquired = fs4::fs_std::FileExt::try_lock_exclusive(&file).map_err(|e| {
+            PackageCacheError::LockError(
+                format!(
+                    "failed to acquire write lock on cache lock file: '{}'",
+                    path.display()
+                ),
+                e,
+            )
+        })?;
 
-        tokio::select!(
-            lock = acquire_lock_fut => lock,
-            _ = warn_timeout_future(format!(
-                "Blocking waiting for file lock on package cache for {}",
-                path.file_name()
-                    .expect("lock file must have a name")
-                    .to_string_lossy()
-            )) => unreachable!("warn_timeout_future should never finish")
-        )
+        Ok(acquired.then(|| CacheRwLock {
+            file: Arc::new(Mutex::new(file)),
+            holder_path: None,
+        }))
     }
 }
 
@@ -220,6 +438,17 @@ impl CacheRwLock {
     }
 }
 
+impl CacheRwLock {
+    /// Updates the modification time of the lock file to record that the
+    /// cache entry was accessed. Failures are ignored because the timestamp
+    /// is only used to determine which entries to evict.
+    pub fn touch(&self) {
+        if let Err(e) = self.file.lock().set_modified(std::time::SystemTime::now()) {
+            tracing::debug!("failed to update the access time of a cache entry: {e}");
+        }
+    }
+}
+
 impl CacheRwLock {
     /// Reads the revision from the cache lock file.
     pub fn read_revision(&mut self) -> Result<u64, PackageCacheError> {
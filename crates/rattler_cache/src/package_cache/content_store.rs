@@ -0,0 +1,200 @@
+//! An optional content-addressable store that deduplicates identical files
+//! across entries of the [`super::PackageCache`].
+//!
+//! Files are stored in a hidden directory inside the cache by their sha256
+//! hash. The files in the extracted package directories are replaced by hard
+//! links into the store, which means that identical files in different
+//! packages (or different versions of the same package) only occupy disk space
+//! once.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use rattler_digest::{compute_file_digest, Sha256};
+
+/// The name of the directory inside the cache that contains the store.
+pub(super) const CONTENT_STORE_DIR: &str = ".content-store";
+
+/// Replaces all regular files in `entry` with hard links into the store
+/// located at `store`. Returns the number of bytes that were deduplicated.
+pub(super) fn deduplicate_entry(store: &Path, entry: &Path) -> std::io::Result<u64> {
+    let mut saved = 0;
+    for dir_entry in fs_err::read_dir(entry)? {
+        let dir_entry = dir_entry?;
+        let file_type = dir_entry.file_type()?;
+        let path = dir_entry.path();
+        if file_type.is_dir() {
+            saved += deduplicate_entry(store, &path)?;
+        } else if file_type.is_file() {
+            saved += deduplicate_file(store, &path)?;
+        }
+    }
+    Ok(saved)
+}
+
+fn deduplicate_file(store: &Path, path: &Path) -> std::io::Result<u64> {
+    let metadata = fs_err::metadata(path)?;
+    let stored_path = stored_path(store, path, &metadata)?;
+
+    match fs_err::metadata(&stored_path) {
+        Ok(stored_metadata) => {
+            if is_same_file(&metadata, &stored_metadata) {
+                return Ok(0);
+            }
+
+            // Link the stored file next to the original and atomically replace the
+            // original with it.
+            let mut temp_path = path.as_os_str().to_owned();
+            temp_path.push(".dedup");
+            let temp_path = PathBuf::from(temp_path);
+            fs_err::hard_link(&stored_path, &temp_path)?;
+            if let Err(e) = fs_err::rename(&temp_path, path) {
+                let _ = fs_err::remove_file(&temp_path);
+                return Err(e);
+            }
+            Ok(metadata.len())
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if let Some(parent) = stored_path.parent() {
+                fs_err::create_dir_all(parent)?;
+            }
+            match fs_err::hard_link(path, &stored_path) {
+                Ok(()) => Ok(0),
+                // Another process stored the same file in the meantime.
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => deduplicate_file(store, path),
+                Err(e) => Err(e),
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the location of a file in the store. Executable files are stored
+/// separately from non-executable files with the same content because hard
+/// links share their permissions.
+fn stored_path(
+    store: &Path,
+    path: &Path,
+    metadata: &std::fs::Metadata,
+) -> std::io::Result<PathBuf> {
+    let hash = format!("{:x}", compute_file_digest::<Sha256>(path)?);
+    let name = if is_executable(metadata) {
+        format!("{hash}-x")
+    } else {
+        hash
+    };
+    Ok(store.join(&name[..2]).join(name))
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn is_same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Removes all files from the store that are no longer linked from any cache
+/// entry and returns the number of bytes that were reclaimed.
+///
+/// This is only supported on unix where the number of hard links of a file can
+/// be determined.
+#[cfg(unix)]
+pub(super) fn collect_garbage(store: &Path) -> std::io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    let read_dir = match fs_err::read_dir(store) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut reclaimed = 0;
+    for bucket in read_dir {
+        for file in fs_err::read_dir(bucket?.path())? {
+            let path = file?.path();
+            let metadata = fs_err::metadata(&path)?;
+            if metadata.nlink() <= 1 {
+                fs_err::remove_file(&path)?;
+                reclaimed += metadata.len();
+            }
+        }
+    }
+    Ok(reclaimed)
+}
+
+/// Removes all files from the store that are no longer linked from any cache
+/// entry. This is a no-op on platforms other than unix.
+#[cfg(not(unix))]
+pub(super) fn collect_garbage(_store: &Path) -> std::io::Result<u64> {
+    Ok(0)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{os::unix::fs::MetadataExt, path::Path};
+
+    use tempfile::tempdir;
+
+    use super::{collect_garbage, deduplicate_entry};
+    use crate::package_cache::PackageCache;
+
+    #[test]
+    fn test_identical_files_are_shared() {
+        let root = tempdir().unwrap();
+        let store = root.path().join("store");
+        let a = root.path().join("a");
+        let b = root.path().join("b");
+        for dir in [&a, &b] {
+            fs_err::create_dir_all(dir.join("lib")).unwrap();
+            fs_err::write(dir.join("lib/shared.txt"), "identical").unwrap();
+        }
+        fs_err::write(b.join("unique.txt"), "unique").unwrap();
+
+        assert_eq!(deduplicate_entry(&store, &a).unwrap(), 0);
+        assert_eq!(deduplicate_entry(&store, &b).unwrap(), 9);
+
+        let a_meta = fs_err::metadata(a.join("lib/shared.txt")).unwrap();
+        let b_meta = fs_err::metadata(b.join("lib/shared.txt")).unwrap();
+        assert_eq!(a_meta.ino(), b_meta.ino());
+        assert_eq!(a_meta.nlink(), 3);
+
+        // Once no entry references a file anymore, it is removed from the store.
+        fs_err::remove_dir_all(&b).unwrap();
+        assert_eq!(collect_garbage(&store).unwrap(), 6);
+        fs_err::remove_dir_all(&a).unwrap();
+        assert_eq!(collect_garbage(&store).unwrap(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_package_cache_with_content_store() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path()).with_content_addressable_store();
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+
+        let cache_lock = cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap();
+
+        let metadata = fs_err::metadata(cache_lock.path().join("info/index.json")).unwrap();
+        assert_eq!(metadata.nlink(), 2);
+    }
+}
@@ -0,0 +1,93 @@
+//! Fetching packages that are only known by their URL, e.g. from direct-URL
+//! match specs like `https://example.com/foo-1.0-h0.conda`.
+
+use std::sync::Arc;
+
+use rattler_conda_types::{
+    package::{ArchiveIdentifier, IndexJson, PackageFile},
+    PackageRecord, RepoDataRecord,
+};
+use rattler_digest::{Md5Hash, Sha256Hash};
+use rattler_networking::LazyClient;
+use rattler_redaction::Redact;
+use url::Url;
+
+use super::{CacheKey, CacheLock, CacheReporter, PackageCache, PackageCacheError};
+
+impl PackageCache {
+    /// Fetches and extracts the package at the given URL without requiring a
+    /// record that describes it.
+    ///
+    /// The cache key is derived from the filename in the URL and the metadata
+    /// of the package is synthesized from its `info/index.json`. Because the
+    /// size of the archive is not known it is not part of the returned record.
+    /// If `sha256` or `md5` are specified the downloaded archive is verified
+    /// against them.
+    pub async fn get_or_fetch_from_direct_url(
+        &self,
+        url: Url,
+        sha256: Option<Sha256Hash>,
+        md5: Option<Md5Hash>,
+        client: LazyClient,
+        reporter: Option<Arc<dyn CacheReporter>>,
+    ) -> Result<(CacheLock, RepoDataRecord), PackageCacheError> {
+        let archive_identifier = ArchiveIdentifier::try_from_url(&url).ok_or_else(|| {
+            PackageCacheError::InvalidArchiveUrl(url.clone().redact().to_string())
+        })?;
+        let file_name = archive_identifier.to_file_name();
+
+        let cache_key = CacheKey::from(archive_identifier)
+            .with_opt_sha256(sha256)
+            .with_opt_md5(md5);
+
+        let cache_lock = self
+            .get_or_fetch_from_url(cache_key, url.clone(), client, reporter)
+            .await?;
+
+        let index_json = IndexJson::from_package_directory(cache_lock.path()).map_err(|e| {
+            PackageCacheError::InvalidPackageMetadata(cache_lock.path().to_path_buf(), e)
+        })?;
+        let package_record =
+            PackageRecord::from_index_json(index_json, None, sha256.or(cache_lock.sha256), md5)?;
+
+        let record = RepoDataRecord {
+            package_record,
+            file_name,
+            url,
+            channel: None,
+        };
+
+        Ok((cache_lock, record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rattler_networking::LazyClient;
+    use tempfile::tempdir;
+    use url::Url;
+
+    use crate::package_cache::PackageCache;
+
+    #[tokio::test]
+    async fn test_fetch_from_direct_url() {
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        let url = Url::from_file_path(&package_path).unwrap();
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let (cache_lock, record) = cache
+            .get_or_fetch_from_direct_url(url.clone(), None, None, LazyClient::default(), None)
+            .await
+            .unwrap();
+
+        assert!(cache_lock.path().join("info/index.json").is_file());
+        assert_eq!(record.package_record.name.as_normalized(), "clobber-python");
+        assert_eq!(record.package_record.version.as_str(), "0.1.0");
+        assert_eq!(record.url, url);
+        assert_eq!(record.file_name, "clobber-python-0.1.0-cpython.conda");
+    }
+}
@@ -0,0 +1,177 @@
+//! Versioning of the on-disk layout of a [`PackageCache`].
+//!
+//! Every cache directory records the version of the layout it was created
+//! with in a small file. This ensures that future changes to the layout never
+//! silently mix with entries created by an older (or newer) version of this
+//! crate.
+
+use std::{io::ErrorKind, path::Path};
+
+use super::{cache_lock::CacheRwLock, PackageCache, PackageCacheError};
+
+/// The version of the cache layout that is written by this version of the
+/// crate.
+///
+/// * Version 1: Extracted packages with a lock file per entry. Caches created
+///   before layout versioning was introduced have this version.
+/// * Version 2: Adds the content-addressable store, retained archives and
+///   lock holder files.
+pub const CACHE_LAYOUT_VERSION: u32 = 2;
+
+/// The name of the file that stores the layout version inside the cache.
+const LAYOUT_VERSION_FILE: &str = ".layout-version";
+
+impl PackageCache {
+    /// Returns the layout version of the cache on disk, or `None` if the
+    /// cache does not exist yet.
+    pub fn layout_version(&self) -> Result<Option<u32>, PackageCacheError> {
+        read_layout_version(&self.inner.path)
+    }
+
+    /// Migrates the cache on disk to the current layout version.
+    ///
+    /// This is also performed automatically the first time a package is
+    /// requested from the cache. An error is returned if the cache was
+    /// created by a newer version that uses a layout this version does not
+    /// understand.
+    pub async fn migrate(&self) -> Result<(), PackageCacheError> {
+        let cache_path = self.inner.path.clone();
+        let io_error =
+            |e| PackageCacheError::IoError("failed to migrate the package cache".to_string(), e);
+
+        fs_err::tokio::create_dir_all(&cache_path)
+            .await
+            .map_err(io_error)?;
+
+        // Make sure only one process migrates the cache at a time.
+        let _lock = CacheRwLock::acquire_write(&cache_path.join(".layout-version.lock")).await?;
+
+        let version = match read_layout_version(&cache_path)? {
+            Some(version) => version,
+            None if is_empty(&cache_path).map_err(io_error)? => CACHE_LAYOUT_VERSION,
+            None => 1,
+        };
+
+        if version > CACHE_LAYOUT_VERSION {
+            return Err(PackageCacheError::IncompatibleLayout {
+                path: cache_path,
+                found: version,
+                supported: CACHE_LAYOUT_VERSION,
+            });
+        }
+
+        if version < CACHE_LAYOUT_VERSION {
+            tracing::info!(
+                "migrating package cache at '{}' from layout version {version} to {CACHE_LAYOUT_VERSION}",
+                cache_path.display()
+            );
+        }
+
+        // Version 1 to 2 does not require any changes to existing entries, all
+        // additions are opt-in.
+        write_layout_version(&cache_path, CACHE_LAYOUT_VERSION).map_err(io_error)
+    }
+
+    /// Ensures the cache on disk uses a compatible layout. The check is only
+    /// performed once per [`PackageCache`] instance.
+    pub(super) async fn ensure_compatible_layout(&self) -> Result<(), PackageCacheError> {
+        self.inner
+            .layout_checked
+            .get_or_try_init(|| async {
+                if read_layout_version(&self.inner.path)? != Some(CACHE_LAYOUT_VERSION) {
+                    self.migrate().await?;
+                }
+                Ok(())
+            })
+            .await
+            .map(|_| ())
+    }
+}
+
+fn read_layout_version(cache_path: &Path) -> Result<Option<u32>, PackageCacheError> {
+    let version_path = cache_path.join(LAYOUT_VERSION_FILE);
+    let contents = match fs_err::read_to_string(&version_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(PackageCacheError::IoError(
+                "failed to read the package cache layout version".to_string(),
+                e,
+            ))
+        }
+    };
+    contents.trim().parse().map(Some).map_err(|e| {
+        PackageCacheError::IoError(
+            format!(
+                "invalid package cache layout version in '{}'",
+                version_path.display()
+            ),
+            std::io::Error::new(ErrorKind::InvalidData, e),
+        )
+    })
+}
+
+fn write_layout_version(cache_path: &Path, version: u32) -> std::io::Result<()> {
+    let mut temp_file = tempfile::NamedTempFile::new_in(cache_path)?;
+    std::io::Write::write_all(&mut temp_file, version.to_string().as_bytes())?;
+    temp_file
+        .persist(cache_path.join(LAYOUT_VERSION_FILE))
+        .map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Returns true if the cache contains nothing but the files used to manage
+/// the layout itself.
+fn is_empty(cache_path: &Path) -> std::io::Result<bool> {
+    for entry in fs_err::read_dir(cache_path)? {
+        let name = entry?.file_name();
+        if !name.to_string_lossy().starts_with(".layout-version") {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use assert_matches::assert_matches;
+    use tempfile::tempdir;
+
+    use super::CACHE_LAYOUT_VERSION;
+    use crate::package_cache::{PackageCache, PackageCacheError};
+
+    #[tokio::test]
+    async fn test_layout_version() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        assert_eq!(cache.layout_version().unwrap(), None);
+
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap();
+        assert_eq!(cache.layout_version().unwrap(), Some(CACHE_LAYOUT_VERSION));
+
+        // A cache created by a newer version is rejected.
+        fs_err::write(packages_dir.path().join(".layout-version"), "999").unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        assert_matches!(
+            cache.get_or_fetch_from_path(&package_path, None).await,
+            Err(PackageCacheError::IncompatibleLayout { found: 999, .. })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_cache() {
+        let packages_dir = tempdir().unwrap();
+        fs_err::create_dir_all(packages_dir.path().join("foo-1.0-h0")).unwrap();
+
+        let cache = PackageCache::new(packages_dir.path());
+        cache.migrate().await.unwrap();
+        assert_eq!(cache.layout_version().unwrap(), Some(CACHE_LAYOUT_VERSION));
+    }
+}
@@ -6,36 +6,64 @@ use std::{
     fmt::Debug,
     future::Future,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
     time::{Duration, SystemTime},
 };
 
+pub use archive::ArchiveRetention;
 pub use cache_key::CacheKey;
-pub use cache_lock::CacheLock;
-use cache_lock::CacheRwLock;
+pub use cache_lock::{CacheLock, LockHolder};
+use cache_lock::{CacheRwLock, LockWait};
 use dashmap::DashMap;
 use fs_err::tokio as tokio_fs;
 use futures::TryFutureExt;
 use itertools::Itertools;
+pub use layout::CACHE_LAYOUT_VERSION;
+pub use negative_cache::FetchFailureKind;
+use negative_cache::NegativeCache;
 use parking_lot::Mutex;
-use rattler_conda_types::package::ArchiveIdentifier;
+pub use permissions::SharedCachePermissions;
+pub use prune::PrunePolicy;
+use rattler_conda_types::{
+    package::{ArchiveIdentifier, ArchiveType},
+    ConvertSubdirError,
+};
 use rattler_digest::Sha256Hash;
 use rattler_networking::{
-    retry_policies::{DoNotRetryPolicy, RetryDecision, RetryPolicy},
+    retry_policies::{DoNotRetryPolicy, RetryAttempt, RetryDecision, RetryPolicy},
     LazyClient,
 };
 use rattler_package_streaming::{DownloadReporter, ExtractError};
 use rattler_redaction::Redact;
 pub use reporter::CacheReporter;
+pub use revalidate::{CorruptedCacheEntry, RevalidationReport};
+pub use segmented::SegmentedDownloads;
 use simple_spawn_blocking::Cancelled;
+pub use stats::{AgeDistribution, PackageCacheStats, PackageStats};
 use tracing::instrument;
 use url::Url;
 
-use crate::validation::{validate_package_directory, ValidationMode};
+use crate::validation::{
+    validate_package_directory, validate_package_directory_with_progress, ValidationMode,
+};
 
+mod archive;
 mod cache_key;
 mod cache_lock;
+mod content_store;
+mod direct_url;
+mod layout;
+mod negative_cache;
+mod permissions;
+mod prune;
 mod reporter;
+mod revalidate;
+mod segmented;
+mod stats;
+mod transfer;
 
 /// A [`PackageCache`] manages a cache of extracted Conda packages on disk.
 ///
@@ -48,12 +76,26 @@ mod reporter;
 pub struct PackageCache {
     inner: Arc<PackageCacheInner>,
     cache_origin: bool,
+    max_size: Option<u64>,
+    shared_permissions: Option<SharedCachePermissions>,
+    content_addressable: bool,
+    lock_timeout: Option<Duration>,
+    archive_retention: ArchiveRetention,
+    resumable_downloads: bool,
+    segmented_downloads: Option<SegmentedDownloads>,
+    negative_cache_ttl: Option<Duration>,
 }
 
 #[derive(Default)]
 struct PackageCacheInner {
     path: PathBuf,
+    read_only_layers: Vec<PathBuf>,
     packages: DashMap<BucketKey, Arc<tokio::sync::Mutex<Entry>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    failed_fetches: NegativeCache,
+    layout_checked: tokio::sync::OnceCell<()>,
+    size_index: Mutex<prune::CacheSizeIndex>,
 }
 
 /// A key that defines the actual location of the package in the cache.
@@ -80,6 +122,7 @@ impl From<CacheKey> for BucketKey {
 struct Entry {
     last_revision: Option<u64>,
     last_sha256: Option<Sha256Hash>,
+    read_only_path: Option<PathBuf>,
 }
 
 /// An error that might be returned from one of the caching function of the
@@ -94,6 +137,63 @@ pub enum PackageCacheError {
     #[error("{0}")]
     LockError(String, #[source] std::io::Error),
 
+    /// Timed out while waiting for another process to release a lock on a
+    /// cache entry.
+    #[error(
+        "timed out after {timeout:?} waiting for the lock on '{}'{}",
+        path.display(),
+        holder.as_ref().map(|holder| format!(" held by {holder}")).unwrap_or_default()
+    )]
+    LockTimeout {
+        /// The path of the lock file.
+        path: PathBuf,
+        /// How long was waited for the lock.
+        timeout: Duration,
+        /// The process that holds the lock, if known.
+        holder: Option<LockHolder>,
+    },
+
+    /// Fetching the package recently failed and is not attempted again until
+    /// the negative cache entry expires.
+    #[error("fetching '{url}' recently failed ({kind}), not trying again")]
+    RecentFetchFailure {
+        /// The (redacted) URL of the package.
+        url: String,
+        /// Why fetching the package failed.
+        kind: FetchFailureKind,
+    },
+
+    /// The cache on disk uses a layout that is not supported by this version.
+    #[error(
+        "the package cache at '{}' uses layout version {found} but only versions up to {supported} are supported",
+        path.display()
+    )]
+    IncompatibleLayout {
+        /// The path of the cache.
+        path: PathBuf,
+        /// The layout version found on disk.
+        found: u32,
+        /// The newest layout version supported by this version.
+        supported: u32,
+    },
+
+    /// The package archive could not be determined from the filename in the
+    /// URL.
+    #[error("could not determine the package archive from the url '{0}'")]
+    InvalidArchiveUrl(String),
+
+    /// The metadata of an extracted package could not be read.
+    #[error("failed to read the metadata of the package at '{}'", .0.display())]
+    InvalidPackageMetadata(PathBuf, #[source] std::io::Error),
+
+    /// The metadata of the package does not describe a valid record.
+    #[error(transparent)]
+    ConvertSubdir(#[from] ConvertSubdirError),
+
+    /// An IO error occurred while accessing the cache
+    #[error("{0}")]
+    IoError(String, #[source] std::io::Error),
+
     /// The operation was cancelled
     #[error("operation was cancelled")]
     Cancelled,
@@ -108,12 +208,43 @@ impl From<Cancelled> for PackageCacheError {
 impl PackageCache {
     /// Constructs a new [`PackageCache`] located at the specified path.
     pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::new_layered(path, [])
+    }
+
+    /// Constructs a new [`PackageCache`] that writes to the `writable`
+    /// directory and additionally reads from the `read_only` directories.
+    ///
+    /// When a package is not present in the writable directory, the read-only
+    /// layers are searched in order before the package is fetched into the
+    /// writable directory. This allows sharing a large, read-only cache (e.g.
+    /// on a network drive) with a small local cache.
+    ///
+    /// Maintenance operations like [`Self::prune`] and [`Self::stats`] only
+    /// consider the writable directory.
+    pub fn new_layered(
+        writable: impl Into<PathBuf>,
+        read_only: impl IntoIterator<Item = PathBuf>,
+    ) -> Self {
         Self {
             inner: Arc::new(PackageCacheInner {
-                path: path.into(),
+                path: writable.into(),
+                read_only_layers: read_only.into_iter().collect(),
                 packages: DashMap::default(),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                failed_fetches: NegativeCache::default(),
+                layout_checked: tokio::sync::OnceCell::new(),
+                size_index: Mutex::default(),
             }),
             cache_origin: false,
+            max_size: None,
+            shared_permissions: None,
+            content_addressable: false,
+            lock_timeout: None,
+            archive_retention: ArchiveRetention::Discard,
+            resumable_downloads: false,
+            segmented_downloads: None,
+            negative_cache_ttl: None,
         }
     }
 
@@ -126,6 +257,115 @@ impl PackageCache {
         }
     }
 
+    /// Limits the total size of the extracted packages in the cache to the
+    /// given number of bytes.
+    ///
+    /// Whenever a new package is added to the cache, the least recently used
+    /// packages are evicted until the cache fits within the limit again.
+    /// Packages that are currently locked are never evicted.
+    pub fn with_max_size(self, bytes: u64) -> Self {
+        Self {
+            max_size: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Sets the maximum time to wait for a cache entry that is locked by
+    /// another process. If the lock is not released within this time a
+    /// [`PackageCacheError::LockTimeout`] error is returned. By default the
+    /// cache waits indefinitely.
+    pub fn with_lock_timeout(self, timeout: Duration) -> Self {
+        Self {
+            lock_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets whether the original package archives are kept next to the
+    /// extracted packages when packages are fetched from a URL. See
+    /// [`ArchiveRetention`].
+    pub fn with_archive_retention(self, retention: ArchiveRetention) -> Self {
+        Self {
+            archive_retention: retention,
+            ..self
+        }
+    }
+
+    /// Sets whether interrupted package downloads can be resumed.
+    ///
+    /// When enabled, packages are downloaded to a partial file in the cache
+    /// before they are extracted. If the download is interrupted the partial
+    /// file is kept together with the validators of the response, and the
+    /// next attempt continues where the previous one left off using a `Range`
+    /// request. If the server does not support range requests, or the
+    /// resource changed in the meantime, the whole package is downloaded again.
+    ///
+    /// This trades some additional disk IO for not having to restart large
+    /// downloads on unreliable connections. Downloads are also resumable when
+    /// archives are retained, see [`Self::with_archive_retention`].
+    pub fn with_resumable_downloads(self, resumable: bool) -> Self {
+        Self {
+            resumable_downloads: resumable,
+            ..self
+        }
+    }
+
+    /// Enables downloading large packages in multiple byte ranges in parallel.
+    /// See [`SegmentedDownloads`].
+    ///
+    /// Like resumable downloads, segmented downloads write the archive to the
+    /// cache before it is extracted.
+    pub fn with_segmented_downloads(self, segmented: SegmentedDownloads) -> Self {
+        Self {
+            segmented_downloads: Some(segmented),
+            ..self
+        }
+    }
+
+    /// Remembers URLs that could not be fetched because the package does not
+    /// exist or access was denied for the given duration. Requesting such a URL
+    /// again within this time fails immediately with
+    /// [`PackageCacheError::RecentFetchFailure`] instead of contacting the
+    /// server. Transient errors are never remembered.
+    pub fn with_negative_cache_ttl(self, ttl: Duration) -> Self {
+        Self {
+            negative_cache_ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    /// Returns the path of the retained archive of the given package, if any.
+    pub fn retained_archive(&self, pkg: impl Into<CacheKey>) -> Option<PathBuf> {
+        archive::archive_paths(&self.inner.path.join(pkg.into().to_string()))
+            .find(|path| path.is_file())
+    }
+
+    /// Enables the content-addressable file store.
+    ///
+    /// When enabled, every file of a newly extracted package is stored by its
+    /// hash in a hidden directory inside the cache and the file in the package
+    /// directory is replaced by a hard link to it. Identical files in
+    /// different packages therefore only occupy disk space once.
+    pub fn with_content_addressable_store(self) -> Self {
+        Self {
+            content_addressable: true,
+            ..self
+        }
+    }
+
+    /// Configures the cache to be shared between multiple users.
+    ///
+    /// New entries and their lock files are created with the given
+    /// permissions so that other users can use, lock and replace them.
+    /// Entries that are owned by another user and cannot be locked for
+    /// writing are still used as long as they are valid.
+    pub fn with_shared_permissions(self, permissions: SharedCachePermissions) -> Self {
+        Self {
+            shared_permissions: Some(permissions),
+            ..self
+        }
+    }
+
     /// Returns the directory that contains the specified package.
     ///
     /// If the package was previously successfully fetched and stored in the
@@ -147,6 +387,8 @@ impl PackageCache {
         Fut: Future<Output = Result<(), E>> + Send + 'static,
         E: std::error::Error + Send + Sync + 'static,
     {
+        self.ensure_compatible_layout().await?;
+
         let cache_key: CacheKey = pkg.into();
         let cache_path = self.inner.path.join(cache_key.to_string());
         let cache_entry = self
@@ -160,12 +402,51 @@ impl PackageCache {
         // accessing the cache entry.
         let mut cache_entry = cache_entry.lock().await;
 
+        // If the package is not available in the writable cache, look for it in the
+        // read-only layers before fetching it.
+        if !cache_path.is_dir() {
+            if let Some(cache_lock) = self
+                .find_in_read_only_layers(&cache_key, &mut cache_entry, reporter.as_deref())
+                .await?
+            {
+                self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(cache_lock);
+            }
+        }
+
+        // Keep track of whether the cache grew so we know when to evict entries.
+        let fetched = Arc::new(AtomicBool::new(false));
+        let fetch = {
+            let fetched = fetched.clone();
+            let permissions = self.shared_permissions.clone();
+            let content_store = self
+                .content_addressable
+                .then(|| self.inner.path.join(content_store::CONTENT_STORE_DIR));
+            move |destination: PathBuf| {
+                fetched.store(true, Ordering::Relaxed);
+                let fetch_fut = fetch(destination.clone());
+                let permissions = permissions.clone();
+                let content_store = content_store.clone();
+                async move {
+                    fetch_fut.await?;
+                    if let Some(content_store) = content_store {
+                        deduplicate_entry(content_store, destination.clone()).await;
+                    }
+                    if let Some(permissions) = permissions {
+                        apply_shared_permissions(permissions, destination).await;
+                    }
+                    Ok::<_, E>(())
+                }
+            }
+        };
+
         // Validate the cache entry or fetch the package if it is not valid.
         let cache_lock = validate_or_fetch_to_cache(
             cache_path,
             fetch,
             cache_entry.last_revision,
             cache_key.sha256.as_ref(),
+            self.lock_timeout,
             reporter,
         )
         .await?;
@@ -175,10 +456,98 @@ impl PackageCache {
         // is still valid.
         cache_entry.last_revision = Some(cache_lock.revision);
         cache_entry.last_sha256 = cache_lock.sha256;
+        drop(cache_entry);
+
+        let fetched = fetched.load(Ordering::Relaxed);
+        if fetched {
+            self.inner.misses.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.inner.hits.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(max_size) = self.max_size {
+            if fetched {
+                let inner = self.inner.clone();
+                let entry_path = cache_lock.path().to_path_buf();
+                let result = simple_spawn_blocking::tokio::run_blocking_task(move || {
+                    inner
+                        .size_index
+                        .lock()
+                        .add_and_evict(&inner.path, &entry_path, max_size)
+                })
+                .await;
+                if let Err(e) = result {
+                    tracing::warn!("failed to evict packages from the cache: {e}");
+                }
+            }
+        }
 
         Ok(cache_lock)
     }
 
+    /// Searches the read-only layers of the cache for a valid copy of the
+    /// package.
+    async fn find_in_read_only_layers(
+        &self,
+        cache_key: &CacheKey,
+        cache_entry: &mut Entry,
+        reporter: Option<&dyn CacheReporter>,
+    ) -> Result<Option<CacheLock>, PackageCacheError> {
+        for layer in &self.inner.read_only_layers {
+            let path = layer.join(cache_key.to_string());
+            if !path.is_dir() {
+                continue;
+            }
+
+            let mut lock = CacheRwLock::acquire_read_only(&lock_file_path(&path)).await?;
+            let (revision, sha256) = match lock.as_mut() {
+                Some(lock) => (lock.read_revision()?, lock.read_sha256()?),
+                None => (0, None),
+            };
+            if let (Some(expected), Some(actual)) = (cache_key.sha256.as_ref(), sha256.as_ref()) {
+                if expected != actual {
+                    continue;
+                }
+            }
+
+            // Only validate the directory once per process.
+            if cache_entry.read_only_path.as_ref() != Some(&path) {
+                let index = reporter.map(CacheReporter::on_validate_start);
+                let path_inner = path.clone();
+                let validation_result = tokio::task::spawn_blocking(move || {
+                    validate_package_directory(&path_inner, ValidationMode::Fast)
+                })
+                .await;
+                if let (Some(reporter), Some(index)) = (reporter, index) {
+                    reporter.on_validate_complete(index);
+                }
+
+                match validation_result {
+                    Ok(Ok(_)) => cache_entry.read_only_path = Some(path.clone()),
+                    Ok(Err(e)) => {
+                        tracing::debug!("validation for read-only {path:?} failed: {e}");
+                        continue;
+                    }
+                    Err(e) => {
+                        if let Ok(panic) = e.try_into_panic() {
+                            std::panic::resume_unwind(panic)
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            return Ok(Some(CacheLock {
+                _lock: lock,
+                revision,
+                sha256,
+                path,
+            }));
+        }
+
+        Ok(None)
+    }
+
     /// Returns the directory that contains the specified package.
     ///
     /// This is a convenience wrapper around `get_or_fetch` which fetches the
@@ -252,12 +621,36 @@ impl PackageCache {
         if self.cache_origin {
             cache_key = cache_key.with_url(url.clone());
         }
+
+        // Fail fast if fetching this URL recently failed and the package is not
+        // already present in the cache.
+        if let Some(ttl) = self.negative_cache_ttl {
+            if let Some(kind) = self.inner.failed_fetches.get(&url, ttl) {
+                if !self.inner.path.join(cache_key.to_string()).is_dir() {
+                    return Err(PackageCacheError::RecentFetchFailure {
+                        url: url.redact().to_string(),
+                        kind,
+                    });
+                }
+            }
+        }
+        let failed_url = self.negative_cache_ttl.map(|_| url.clone());
+
         // Sha256 of the expected package
         let sha256 = cache_key.sha256();
         let md5 = cache_key.md5();
         let download_reporter = reporter.clone();
+        let download_options = archive::DownloadOptions {
+            keep_archive: self.archive_retention.retains_archives(),
+            segmented: self.segmented_downloads,
+        };
+        let archive_type = ArchiveType::try_from(Path::new(url.path())).filter(|_| {
+            download_options.keep_archive
+                || download_options.segmented.is_some()
+                || self.resumable_downloads
+        });
         // Get or fetch the package, using the specified fetch function
-        self.get_or_fetch(cache_key, move |destination| {
+        let result = self.get_or_fetch(cache_key, move |destination| {
             let url = url.clone();
             let client = client.clone();
             let retry_policy = retry_policy.clone();
@@ -268,18 +661,32 @@ impl PackageCache {
                 loop {
                     current_try += 1;
                     tracing::debug!("downloading {} to {}", &url, destination.display());
-                    // Extract the package
-                    let result = rattler_package_streaming::reqwest::tokio::extract(
-                        client.client().clone(),
-                        url.clone(),
-                        &destination,
-                        sha256,
-                        download_reporter.clone().map(|reporter| Arc::new(PassthroughReporter {
-                            reporter,
-                            index: Mutex::new(None),
-                        }) as Arc::<dyn DownloadReporter>),
-                    )
-                        .await;
+                    let passthrough_reporter = download_reporter.clone().map(|reporter| Arc::new(PassthroughReporter {
+                        reporter,
+                        index: Mutex::new(None),
+                    }) as Arc::<dyn DownloadReporter>);
+
+                    // Extract the package, keeping the archive around if requested
+                    let archive_path = archive_type.map(|archive_type| archive::archive_path(&destination, archive_type));
+                    let result = match &archive_path {
+                        Some(archive_path) => archive::download_and_extract(
+                            client.client().clone(),
+                            url.clone(),
+                            &destination,
+                            archive_path,
+                            download_options,
+                            passthrough_reporter,
+                        )
+                            .await,
+                        None => rattler_package_streaming::reqwest::tokio::extract(
+                            client.client().clone(),
+                            url.clone(),
+                            &destination,
+                            sha256,
+                            passthrough_reporter,
+                        )
+                            .await,
+                    };
 
                     let err = match result {
                         Ok(result) => {
@@ -293,6 +700,9 @@ impl PackageCache {
                                 if sha256 != result.sha256 {
                                     // Delete the package if the hash does not match
                                     tokio_fs::remove_dir_all(&destination).await.unwrap();
+                                    if let Some(archive_path) = &archive_path {
+                                        let _ = tokio_fs::remove_file(archive_path).await;
+                                    }
                                     return Err(ExtractError::HashMismatch {
                                         url: url.clone().redact().to_string(),
                                         destination: destination.display().to_string(),
@@ -305,6 +715,9 @@ impl PackageCache {
                                 if md5 != result.md5 {
                                     // Delete the package if the hash does not match
                                     tokio_fs::remove_dir_all(&destination).await.unwrap();
+                                    if let Some(archive_path) = &archive_path {
+                                        let _ = tokio_fs::remove_file(archive_path).await;
+                                    }
                                     return Err(ExtractError::HashMismatch {
                                         url: url.clone().redact().to_string(),
                                         destination: destination.display().to_string(),
@@ -334,6 +747,15 @@ impl PackageCache {
                         RetryDecision::DoNotRetry => return Err(err),
                     };
                     let duration = execute_after.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO);
+                    if let Some(reporter) = &download_reporter {
+                        reporter.on_download_retry(&RetryAttempt {
+                            url: url.clone().redact(),
+                            attempt: current_try,
+                            max_retries: None,
+                            wait: duration,
+                            reason: err.to_string(),
+                        });
+                    }
 
                     // Wait for a second to let the remote service restore itself. This increases the
                     // chance of success.
@@ -349,10 +771,66 @@ impl PackageCache {
                 }
             }
         }, reporter)
-            .await
+            .await;
+
+        // Remember permanent failures so they are not retried immediately.
+        if let (Some(url), Err(PackageCacheError::FetchError(err))) = (failed_url, &result) {
+            if let Some(kind) = FetchFailureKind::classify(err.as_ref()) {
+                self.inner.failed_fetches.insert(url, kind);
+            }
+        }
+
+        result
     }
 }
 
+/// Moves the files of a newly populated cache entry into the content-addressable
+/// store. Failures are logged but otherwise ignored because the entry is still
+/// valid without deduplication.
+async fn deduplicate_entry(content_store: PathBuf, path: PathBuf) {
+    let result = tokio::task::spawn_blocking(move || {
+        content_store::deduplicate_entry(&content_store, &path).map_err(|e| (path, e))
+    })
+    .await;
+    match result {
+        Ok(Ok(saved)) => tracing::debug!("deduplicated {saved} bytes"),
+        Ok(Err((path, e))) => tracing::warn!(
+            "failed to deduplicate the files of cache entry '{}': {e}",
+            path.display()
+        ),
+        Err(_) => {}
+    }
+}
+
+/// Makes a newly populated cache entry and its lock file accessible to other
+/// users. Failures are logged but otherwise ignored because the entry itself is
+/// still usable by the current user.
+async fn apply_shared_permissions(permissions: SharedCachePermissions, path: PathBuf) {
+    let result = tokio::task::spawn_blocking(move || {
+        permissions
+            .apply(&lock_file_path(&path))
+            .and_then(|_| permissions.apply(&path))
+            .map_err(|e| (path, e))
+    })
+    .await;
+    if let Ok(Err((path, e))) = result {
+        tracing::warn!(
+            "failed to update the permissions of shared cache entry '{}': {e}",
+            path.display()
+        );
+    }
+}
+
+/// Returns the path of the lock file that guards the cache entry at `path`.
+fn lock_file_path(path: &Path) -> PathBuf {
+    // Append the `.lock` extension to the cache path to create the lock file path.
+    // `Path::with_extension` strips too much from the filename if it contains one
+    // or more dots.
+    let mut path_str = path.as_os_str().to_owned();
+    path_str.push(".lock");
+    PathBuf::from(path_str)
+}
+
 /// Validates that the package that is currently stored is a valid package and
 /// otherwise calls the `fetch` method to populate the cache.
 async fn validate_or_fetch_to_cache<F, Fut, E>(
@@ -360,6 +838,7 @@ async fn validate_or_fetch_to_cache<F, Fut, E>(
     fetch: F,
     known_valid_revision: Option<u64>,
     given_sha: Option<&Sha256Hash>,
+    lock_timeout: Option<Duration>,
     reporter: Option<Arc<dyn CacheReporter>>,
 ) -> Result<CacheLock, PackageCacheError>
 where
@@ -369,14 +848,7 @@ where
 {
     // Acquire a read lock on the cache entry. This ensures that no other process is
     // currently writing to the cache.
-    let lock_file_path = {
-        // Append the `.lock` extension to the cache path to create the lock file path.
-        // `Path::with_extension` strips too much from the filename if it contains one
-        // or more dots.
-        let mut path_str = path.as_os_str().to_owned();
-        path_str.push(".lock");
-        PathBuf::from(path_str)
-    };
+    let lock_file_path = lock_file_path(&path);
 
     // Ensure the directory containing the lock-file exists.
     if let Some(root_dir) = lock_file_path.parent() {
@@ -393,8 +865,13 @@ where
     // The revision of the cache entry that we already know is valid.
     let mut validated_revision = known_valid_revision;
 
+    let lock_wait = LockWait {
+        timeout: lock_timeout,
+        reporter: reporter.clone(),
+    };
+
     loop {
-        let mut read_lock = CacheRwLock::acquire_read(&lock_file_path).await?;
+        let mut read_lock = CacheRwLock::acquire(&lock_file_path, false, &lock_wait).await?;
         let cache_revision = read_lock.read_revision()?;
         let locked_sha256 = read_lock.read_sha256()?;
 
@@ -407,6 +884,7 @@ where
         if cache_dir_exists && !hash_mismatch {
             let path_inner = path.clone();
 
+            let progress_reporter = reporter.clone();
             let reporter = reporter.as_deref().map(|r| (r, r.on_validate_start()));
 
             // If we know the revision is already valid we can return immediately.
@@ -414,8 +892,9 @@ where
                 if let Some((reporter, index)) = reporter {
                     reporter.on_validate_complete(index);
                 }
+                read_lock.touch();
                 return Ok(CacheLock {
-                    _lock: read_lock,
+                    _lock: Some(read_lock),
                     revision: cache_revision,
                     sha256: locked_sha256,
                     path: path_inner,
@@ -423,8 +902,17 @@ where
             }
 
             // Validate the package directory.
+            let progress_reporter = progress_reporter.zip(reporter.map(|(_, index)| index));
             let validation_result = tokio::task::spawn_blocking(move || {
-                validate_package_directory(&path_inner, ValidationMode::Fast)
+                validate_package_directory_with_progress(
+                    &path_inner,
+                    ValidationMode::Fast,
+                    &|done, total| {
+                        if let Some((reporter, index)) = &progress_reporter {
+                            reporter.on_validate_progress(*index, done, total);
+                        }
+                    },
+                )
             })
             .await;
 
@@ -435,8 +923,9 @@ where
             match validation_result {
                 Ok(Ok(_)) => {
                     tracing::debug!("validation succeeded");
+                    read_lock.touch();
                     return Ok(CacheLock {
-                        _lock: read_lock,
+                        _lock: Some(read_lock),
                         revision: cache_revision,
                         sha256: locked_sha256,
                         path,
@@ -480,7 +969,7 @@ where
         // validation process.
         drop(read_lock);
 
-        let mut write_lock = CacheRwLock::acquire_write(&lock_file_path).await?;
+        let mut write_lock = CacheRwLock::acquire(&lock_file_path, true, &lock_wait).await?;
 
         let read_revision = write_lock.read_revision()?;
         if read_revision != cache_revision {
@@ -546,6 +1035,7 @@ mod test {
         net::SocketAddr,
         path::{Path, PathBuf},
         sync::{atomic::AtomicBool, Arc},
+        time::Duration,
     };
 
     use assert_matches::assert_matches;
@@ -572,7 +1062,7 @@ mod test {
     use tokio_stream::StreamExt;
     use url::Url;
 
-    use super::PackageCache;
+    use super::{cache_lock::CacheRwLock, PackageCache, PackageCacheError};
     use crate::{
         package_cache::CacheKey,
         validation::{validate_package_directory, ValidationMode},
@@ -839,6 +1329,90 @@ mod test {
         assert_eq!(cache_c_lock.revision(), 2);
     }
 
+    #[tokio::test]
+    async fn test_read_only_layer() {
+        let shared_dir = tempdir().unwrap();
+        let local_dir = tempdir().unwrap();
+        let package_path = get_test_data_dir().join("clobber/clobber-python-0.1.0-cpython.conda");
+
+        // Populate the shared cache.
+        let shared_path = PackageCache::new(shared_dir.path())
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap()
+            .path()
+            .to_path_buf();
+
+        // The layered cache should find the package in the read-only layer
+        // instead of extracting it into the writable layer.
+        let cache = PackageCache::new_layered(local_dir.path(), [shared_dir.path().to_path_buf()]);
+        let cache_lock = cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap();
+        assert_eq!(cache_lock.path(), shared_path);
+        assert!(!local_dir
+            .path()
+            .join(get_file_name_from_path(&shared_path))
+            .exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shared_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::package_cache::SharedCachePermissions;
+
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path())
+            .with_shared_permissions(SharedCachePermissions::default());
+        let package_path = get_test_data_dir().join("clobber/clobber-python-0.1.0-cpython.conda");
+
+        let cache_lock = cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap();
+
+        let index_json = cache_lock.path().join("info/index.json");
+        let mode = std::fs::metadata(index_json).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o664);
+        let mode = std::fs::metadata(cache_lock.path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o775);
+    }
+
+    #[tokio::test]
+    async fn test_lock_timeout() {
+        let packages_dir = tempdir().unwrap();
+        let package_path = get_test_data_dir().join("clobber/clobber-python-0.1.0-cpython.conda");
+        let key: CacheKey = ArchiveIdentifier::try_from_path(&package_path)
+            .unwrap()
+            .into();
+
+        // Hold a write lock on the entry as if another process was fetching it.
+        let lock_file = packages_dir.path().join(format!("{key}.lock"));
+        let write_lock = CacheRwLock::acquire_write(&lock_file).await.unwrap();
+
+        let cache =
+            PackageCache::new(packages_dir.path()).with_lock_timeout(Duration::from_millis(100));
+        let result = cache.get_or_fetch_from_path(&package_path, None).await;
+        assert_matches!(
+            result,
+            Err(PackageCacheError::LockTimeout { holder: Some(holder), .. }) if holder.pid == std::process::id()
+        );
+
+        // Once the lock is released nobody may still be waiting for it in the
+        // background.
+        drop(write_lock);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(CacheRwLock::try_acquire_write(&lock_file)
+            .unwrap()
+            .is_some());
+    }
+
     fn get_file_name_from_path(path: &Path) -> &str {
         path.file_name().unwrap().to_str().unwrap()
     }
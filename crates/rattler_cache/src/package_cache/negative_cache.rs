@@ -0,0 +1,150 @@
+//! Short-lived memory of package URLs that could not be fetched, so that bulk
+//! operations against partially broken channels do not request the same
+//! missing or forbidden package over and over again.
+
+use std::{
+    fmt::{Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use rattler_package_streaming::ExtractError;
+use url::Url;
+
+/// The class of error that caused fetching a package to fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FetchFailureKind {
+    /// The server responded that the package does not exist.
+    NotFound,
+
+    /// The server rejected the credentials (or lack thereof).
+    Unauthorized,
+}
+
+impl Display for FetchFailureKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchFailureKind::NotFound => write!(f, "not found"),
+            FetchFailureKind::Unauthorized => write!(f, "unauthorized"),
+        }
+    }
+}
+
+impl FetchFailureKind {
+    /// Determines whether the error is one that should be remembered.
+    /// Transient errors like timeouts or server errors are never cached.
+    pub(super) fn classify(error: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        let ExtractError::ReqwestError(error) = error.downcast_ref::<ExtractError>()? else {
+            return None;
+        };
+        match error.status()?.as_u16() {
+            404 | 410 => Some(FetchFailureKind::NotFound),
+            401 | 403 => Some(FetchFailureKind::Unauthorized),
+            _ => None,
+        }
+    }
+}
+
+/// Remembers recent fetch failures per URL.
+#[derive(Default)]
+pub(super) struct NegativeCache {
+    entries: DashMap<Url, (FetchFailureKind, Instant)>,
+}
+
+impl NegativeCache {
+    /// Returns the failure recorded for `url` if it happened less than `ttl`
+    /// ago.
+    pub(super) fn get(&self, url: &Url, ttl: Duration) -> Option<FetchFailureKind> {
+        let (kind, failed_at) = *self.entries.get(url)?;
+        if failed_at.elapsed() < ttl {
+            Some(kind)
+        } else {
+            self.entries.remove(url);
+            None
+        }
+    }
+
+    /// Records that fetching `url` failed.
+    pub(super) fn insert(&self, url: Url, kind: FetchFailureKind) {
+        self.entries.insert(url, (kind, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::IntoFuture,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use assert_matches::assert_matches;
+    use axum::{extract::State, http::StatusCode, routing::get, Router};
+    use rattler_conda_types::package::ArchiveIdentifier;
+    use reqwest::Client;
+    use reqwest_middleware::ClientBuilder;
+    use tempfile::tempdir;
+    use url::Url;
+
+    use super::FetchFailureKind;
+    use crate::package_cache::{PackageCache, PackageCacheError};
+
+    async fn not_found(State(count): State<Arc<AtomicUsize>>) -> StatusCode {
+        count.fetch_add(1, Ordering::SeqCst);
+        StatusCode::NOT_FOUND
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_is_remembered() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route("/{file}", get(not_found))
+            .with_state(count.clone());
+        let listener = tokio::net::TcpListener::bind(SocketAddr::new([127, 0, 0, 1].into(), 0))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router.into_make_service()).into_future());
+
+        let packages_dir = tempdir().unwrap();
+        let cache =
+            PackageCache::new(packages_dir.path()).with_negative_cache_ttl(Duration::from_secs(60));
+        let client = ClientBuilder::new(Client::default()).build();
+
+        let file_name = "missing-1.0-h0.conda";
+        let url = Url::parse(&format!("http://localhost:{}/{file_name}", addr.port())).unwrap();
+        for _ in 0..2 {
+            let result = cache
+                .get_or_fetch_from_url(
+                    ArchiveIdentifier::try_from_filename(file_name).unwrap(),
+                    url.clone(),
+                    client.clone().into(),
+                    None,
+                )
+                .await;
+            assert_matches!(result, Err(_));
+        }
+
+        // The second request is answered from the negative cache.
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        let result = cache
+            .get_or_fetch_from_url(
+                ArchiveIdentifier::try_from_filename(file_name).unwrap(),
+                url,
+                client.into(),
+                None,
+            )
+            .await;
+        assert_matches!(
+            result,
+            Err(PackageCacheError::RecentFetchFailure {
+                kind: FetchFailureKind::NotFound,
+                ..
+            })
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Permissions of entries in a package cache that is shared between users.
+
+use std::path::Path;
+
+/// Describes how the permissions of newly created cache entries are set when
+/// the cache is shared between multiple users of the same system.
+///
+/// By default files are created with the permissions of the current process
+/// which usually means that other users cannot modify (or even lock) entries
+/// created by someone else. With [`SharedCachePermissions`] all entries are
+/// made group-writable and can optionally be assigned to a specific group.
+///
+/// This only has an effect on unix platforms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedCachePermissions {
+    umask: u32,
+    group: Option<u32>,
+}
+
+impl Default for SharedCachePermissions {
+    fn default() -> Self {
+        Self {
+            umask: 0o002,
+            group: None,
+        }
+    }
+}
+
+impl SharedCachePermissions {
+    /// Sets the umask that is applied to the permissions of new entries. The
+    /// default is `0o002` which makes entries writable by the group.
+    pub fn with_umask(self, umask: u32) -> Self {
+        Self {
+            umask: umask & 0o777,
+            ..self
+        }
+    }
+
+    /// Sets the group that owns new entries. Directories are also marked with
+    /// the setgid bit so that files created later inherit the group.
+    pub fn with_group(self, gid: u32) -> Self {
+        Self {
+            group: Some(gid),
+            ..self
+        }
+    }
+
+    /// Applies the permissions to the given path and, if it is a directory,
+    /// to all of its contents. Symbolic links are not followed.
+    #[cfg(unix)]
+    pub(super) fn apply(&self, path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::{chown, PermissionsExt};
+
+        let metadata = fs_err::symlink_metadata(path)?;
+        if metadata.file_type().is_symlink() {
+            return Ok(());
+        }
+
+        if let Some(group) = self.group {
+            chown(path, None, Some(group))?;
+        }
+
+        let mode = if metadata.is_dir() {
+            let setgid = if self.group.is_some() { 0o2000 } else { 0 };
+            (0o777 & !self.umask) | setgid
+        } else if metadata.permissions().mode() & 0o111 != 0 {
+            0o777 & !self.umask
+        } else {
+            0o666 & !self.umask
+        };
+        fs_err::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+
+        if metadata.is_dir() {
+            for entry in fs_err::read_dir(path)? {
+                self.apply(&entry?.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the permissions to the given path. This is a no-op on
+    /// platforms other than unix.
+    #[cfg(not(unix))]
+    pub(super) fn apply(&self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+}
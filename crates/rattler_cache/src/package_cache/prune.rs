@@ -0,0 +1,548 @@
+//! Garbage collection of entries in the [`PackageCache`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use rattler_conda_types::{
+    package::{ArchiveType, IndexJson, PackageFile},
+    PrefixRecord, Version,
+};
+
+use super::{
+    archive, cache_lock::CacheRwLock, content_store, lock_file_path, ArchiveRetention,
+    PackageCache, PackageCacheError,
+};
+
+/// Determines which entries are removed by [`PackageCache::prune`].
+#[derive(Debug, Clone)]
+pub enum PrunePolicy {
+    /// Remove all entries that have not been used for longer than the
+    /// specified duration.
+    OlderThan(Duration),
+
+    /// Remove all entries that are not referenced by any of the packages
+    /// installed in the given prefixes.
+    NotReferencedBy(Vec<PathBuf>),
+
+    /// For every package name, keep only the specified number of highest
+    /// versions and remove all others. All builds of a kept version are
+    /// kept.
+    KeepLatestVersions(usize),
+}
+
+/// A single extracted package directory in the cache.
+pub(super) struct CachedEntry {
+    pub(super) path: PathBuf,
+    pub(super) last_used: SystemTime,
+    pub(super) index_json: Option<IndexJson>,
+}
+
+impl PackageCache {
+    /// Removes entries from the cache according to the given policy and
+    /// returns the number of bytes that were reclaimed.
+    ///
+    /// Entries that are currently locked by another task or process (e.g.
+    /// because they are being fetched or linked into a prefix) are skipped.
+    pub async fn prune(&self, policy: PrunePolicy) -> Result<u64, PackageCacheError> {
+        let cache_path = self.inner.path.clone();
+        let archive_retention = self.archive_retention;
+        simple_spawn_blocking::tokio::run_blocking_task(move || {
+            let reclaimed = prune_blocking(&cache_path, &policy)?;
+            Ok(reclaimed + remove_expired_archives(&cache_path, archive_retention)?)
+        })
+        .await
+    }
+}
+
+fn prune_blocking(cache_path: &Path, policy: &PrunePolicy) -> Result<u64, PackageCacheError> {
+    let entries = collect_entries(cache_path)?;
+    let candidates = match policy {
+        PrunePolicy::OlderThan(max_age) => {
+            let now = SystemTime::now();
+            entries
+                .into_iter()
+                .filter(|entry| {
+                    now.duration_since(entry.last_used)
+                        .is_ok_and(|age| age > *max_age)
+                })
+                .collect::<Vec<_>>()
+        }
+        PrunePolicy::NotReferencedBy(prefixes) => {
+            let referenced = referenced_entries(prefixes)?;
+            entries
+                .into_iter()
+                .filter(|entry| !referenced.contains(&normalize_path(&entry.path)))
+                .collect()
+        }
+        PrunePolicy::KeepLatestVersions(keep) => outdated_entries(entries, *keep),
+    };
+
+    let mut reclaimed = 0;
+    for entry in candidates {
+        reclaimed += remove_entry(&entry.path)?;
+    }
+    reclaimed += collect_content_store_garbage(cache_path)?;
+    Ok(reclaimed)
+}
+
+/// Removes files from the content-addressable store that are no longer used
+/// by any entry.
+fn collect_content_store_garbage(cache_path: &Path) -> Result<u64, PackageCacheError> {
+    content_store::collect_garbage(&cache_path.join(content_store::CONTENT_STORE_DIR))
+        .map_err(io_error)
+}
+
+/// Returns all extracted package directories in the cache.
+pub(super) fn collect_entries(cache_path: &Path) -> Result<Vec<CachedEntry>, PackageCacheError> {
+    let read_dir = match fs_err::read_dir(cache_path) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(e)),
+    };
+
+    let mut entries = Vec::new();
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry.map_err(io_error)?;
+        let path = dir_entry.path();
+        if !dir_entry.file_type().map_err(io_error)?.is_dir() {
+            continue;
+        }
+
+        // Skip internal directories like the content-addressable store.
+        if dir_entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        // Prefer the modification time of the lock file because it is
+        // updated every time the entry is accessed.
+        let last_used = fs_err::metadata(lock_file_path(&path))
+            .or_else(|_| dir_entry.metadata())
+            .and_then(|metadata| metadata.modified())
+            .map_err(io_error)?;
+
+        entries.push(CachedEntry {
+            index_json: IndexJson::from_package_directory(&path).ok(),
+            path,
+            last_used,
+        });
+    }
+    Ok(entries)
+}
+
+/// Keeps track of the size of the extracted packages in the cache so that the
+/// whole cache only has to be walked when entries actually have to be evicted.
+///
+/// The index is populated by walking the cache once and is then updated with
+/// the size of every package that is added. Changes made by other processes
+/// are picked up the next time the cache is walked.
+#[derive(Default)]
+pub(super) struct CacheSizeIndex {
+    sizes: Option<HashMap<PathBuf, u64>>,
+    total_size: u64,
+}
+
+impl CacheSizeIndex {
+    /// Records the size of an entry that was added to the cache and evicts
+    /// the least recently used entries if the cache exceeds `max_size` bytes.
+    /// Returns the number of bytes that were reclaimed.
+    pub(super) fn add_and_evict(
+        &mut self,
+        cache_path: &Path,
+        entry_path: &Path,
+        max_size: u64,
+    ) -> Result<u64, PackageCacheError> {
+        match &mut self.sizes {
+            Some(sizes) => {
+                let size = directory_size(entry_path);
+                if let Some(previous) = sizes.insert(entry_path.to_path_buf(), size) {
+                    self.total_size = self.total_size.saturating_sub(previous);
+                }
+                self.total_size += size;
+            }
+            None => self.reset(sized_entries(cache_path)?.into_iter()),
+        }
+        if self.total_size <= max_size {
+            return Ok(0);
+        }
+
+        let (reclaimed, remaining) = evict_to_size(cache_path, max_size)?;
+        self.reset(remaining.into_iter());
+        Ok(reclaimed)
+    }
+
+    fn reset(&mut self, entries: impl Iterator<Item = (u64, CachedEntry)>) {
+        let sizes: HashMap<_, _> = entries.map(|(size, entry)| (entry.path, size)).collect();
+        self.total_size = sizes.values().sum();
+        self.sizes = Some(sizes);
+    }
+}
+
+/// Returns all extracted package directories in the cache together with
+/// their size in bytes.
+fn sized_entries(cache_path: &Path) -> Result<Vec<(u64, CachedEntry)>, PackageCacheError> {
+    Ok(collect_entries(cache_path)?
+        .into_iter()
+        .map(|entry| (directory_size(&entry.path), entry))
+        .collect())
+}
+
+/// Evicts the least recently used entries until the total size of the cache
+/// no longer exceeds `max_size` bytes. Returns the number of bytes that were
+/// reclaimed and the entries that remain in the cache.
+fn evict_to_size(
+    cache_path: &Path,
+    max_size: u64,
+) -> Result<(u64, Vec<(u64, CachedEntry)>), PackageCacheError> {
+    let mut entries = sized_entries(cache_path)?;
+    let mut total_size: u64 = entries.iter().map(|(size, _)| size).sum();
+    if total_size <= max_size {
+        return Ok((0, entries));
+    }
+
+    entries.sort_by_key(|(_, entry)| entry.last_used);
+
+    let mut reclaimed = 0;
+    let mut remaining = Vec::new();
+    for (size, entry) in entries {
+        if total_size <= max_size {
+            remaining.push((size, entry));
+            continue;
+        }
+        let removed = remove_entry(&entry.path)?;
+        if entry.path.exists() {
+            // The entry is in use.
+            remaining.push((size, entry));
+        }
+        total_size = total_size.saturating_sub(removed);
+        reclaimed += removed;
+    }
+    reclaimed += collect_content_store_garbage(cache_path)?;
+
+    if total_size > max_size {
+        tracing::warn!(
+            "package cache at '{}' exceeds its maximum size because all remaining entries are in use",
+            cache_path.display()
+        );
+    }
+
+    Ok((reclaimed, remaining))
+}
+
+/// Returns the entries that are not among the `keep` highest versions of
+/// their package. All builds of a version count as a single version.
+fn outdated_entries(entries: Vec<CachedEntry>, keep: usize) -> Vec<CachedEntry> {
+    let mut by_name: HashMap<String, HashMap<Version, Vec<CachedEntry>>> = HashMap::new();
+    for entry in entries {
+        // Entries without a readable `index.json` cannot be ordered and are left
+        // alone.
+        let Some(index_json) = &entry.index_json else {
+            continue;
+        };
+        let name = index_json.name.as_normalized().to_string();
+        let version = index_json.version.version().clone();
+        by_name
+            .entry(name)
+            .or_default()
+            .entry(version)
+            .or_default()
+            .push(entry);
+    }
+
+    by_name
+        .into_values()
+        .flat_map(|versions| {
+            let mut versions = versions.into_iter().collect::<Vec<_>>();
+            versions.sort_by(|(a, _), (b, _)| b.cmp(a));
+            versions
+                .into_iter()
+                .skip(keep)
+                .flat_map(|(_, entries)| entries)
+        })
+        .collect()
+}
+
+/// Returns the cache directories that are used by packages installed in
+/// any of the given prefixes.
+fn referenced_entries(prefixes: &[PathBuf]) -> Result<HashSet<PathBuf>, PackageCacheError> {
+    let mut referenced = HashSet::new();
+    for prefix in prefixes {
+        let records = PrefixRecord::collect_from_prefix::<PrefixRecord>(prefix).map_err(|e| {
+            PackageCacheError::IoError(
+                format!(
+                    "failed to read installed packages of '{}'",
+                    prefix.display()
+                ),
+                e,
+            )
+        })?;
+        referenced.extend(
+            records
+                .into_iter()
+                .filter_map(|record| record.extracted_package_dir)
+                .map(|path| normalize_path(&path)),
+        );
+    }
+    Ok(referenced)
+}
+
+/// Removes a single entry from the cache while holding its write lock and
+/// returns the number of bytes that were freed. Entries that are in use are
+/// skipped.
+pub(super) fn remove_entry(path: &Path) -> Result<u64, PackageCacheError> {
+    let Some(_lock) = CacheRwLock::try_acquire_write(&lock_file_path(path))? else {
+        tracing::debug!("skipping '{}' because it is in use", path.display());
+        return Ok(0);
+    };
+
+    let mut size = directory_size(path);
+    match fs_err::remove_dir_all(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => size = 0,
+        Err(e) => return Err(io_error(e)),
+    }
+
+    // Also remove any archive that was retained for this entry.
+    for archive_path in archive::archive_paths(path) {
+        size += remove_file(&archive_path)?;
+    }
+
+    // The lock file is left in place. Removing it while it is held would allow
+    // another process that already opened it to lock the unlinked file while a
+    // third process locks a newly created one.
+    Ok(size)
+}
+
+/// Removes the retained archives that have expired according to the
+/// retention policy and returns the number of bytes that were reclaimed.
+fn remove_expired_archives(
+    cache_path: &Path,
+    retention: ArchiveRetention,
+) -> Result<u64, PackageCacheError> {
+    let read_dir = match fs_err::read_dir(cache_path) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(io_error(e)),
+    };
+
+    let mut reclaimed = 0;
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry.map_err(io_error)?;
+        let file_name = dir_entry.file_name().to_string_lossy().into_owned();
+        let Some((entry_name, _)) = ArchiveType::split_str(&file_name) else {
+            continue;
+        };
+        let modified = dir_entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(io_error)?;
+        if !retention.is_expired(modified) {
+            continue;
+        }
+
+        // Make sure the archive is not being written or read right now.
+        let entry_path = cache_path.join(entry_name);
+        let Some(_lock) = CacheRwLock::try_acquire_write(&lock_file_path(&entry_path))? else {
+            continue;
+        };
+        reclaimed += remove_file(&dir_entry.path())?;
+    }
+    Ok(reclaimed)
+}
+
+/// Removes a file and returns its size, or zero if it does not exist.
+fn remove_file(path: &Path) -> Result<u64, PackageCacheError> {
+    let size = match fs_err::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(io_error(e)),
+    };
+    fs_err::remove_file(path).map_err(io_error)?;
+    Ok(size)
+}
+
+/// Returns the total size of all files in the directory.
+pub(super) fn directory_size(path: &Path) -> u64 {
+    let Ok(read_dir) = fs_err::read_dir(path) else {
+        return 0;
+    };
+    read_dir
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+            Ok(_) => entry.metadata().map_or(0, |metadata| metadata.len()),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn io_error(e: std::io::Error) -> PackageCacheError {
+    PackageCacheError::IoError("failed to read the package cache".to_string(), e)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use tempfile::tempdir;
+
+    use super::{CacheSizeIndex, PrunePolicy};
+    use crate::package_cache::cache_lock::holder_file_path;
+    use crate::package_cache::{lock_file_path, PackageCache};
+
+    #[tokio::test]
+    async fn test_prune() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        let lock = cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap();
+        let entry_path = lock.path().to_path_buf();
+
+        // Entries that are in use are never removed.
+        let reclaimed = cache
+            .prune(PrunePolicy::OlderThan(Duration::ZERO))
+            .await
+            .unwrap();
+        assert_eq!(reclaimed, 0);
+        assert!(entry_path.is_dir());
+        drop(lock);
+
+        // Keeping the latest version does not remove the only version.
+        let reclaimed = cache
+            .prune(PrunePolicy::KeepLatestVersions(1))
+            .await
+            .unwrap();
+        assert_eq!(reclaimed, 0);
+
+        // The entry is not referenced by an empty prefix.
+        let prefix = tempdir().unwrap();
+        let reclaimed = cache
+            .prune(PrunePolicy::NotReferencedBy(vec![prefix
+                .path()
+                .to_path_buf()]))
+            .await
+            .unwrap();
+        assert!(reclaimed > 0);
+        assert!(!entry_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_max_size_evicts_least_recently_used() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path()).with_max_size(1);
+        let test_data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data/clobber");
+
+        let first = cache
+            .get_or_fetch_from_path(&test_data.join("clobber-1-0.1.0-h4616a5c_0.tar.bz2"), None)
+            .await
+            .unwrap();
+        let first_path = first.path().to_path_buf();
+        drop(first);
+
+        // Fetching another package exceeds the limit and evicts the first one.
+        let second = cache
+            .get_or_fetch_from_path(&test_data.join("clobber-2-0.1.0-h4616a5c_0.tar.bz2"), None)
+            .await
+            .unwrap();
+        assert!(!first_path.exists());
+
+        // The entry that is currently locked is kept even though the cache is
+        // still too large.
+        assert!(second.path().is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_keep_latest_versions_counts_builds_once() {
+        let packages_dir = tempdir().unwrap();
+        let add_entry = |version: &str, build: &str| {
+            let path = packages_dir.path().join(format!("foo-{version}-{build}"));
+            fs_err::create_dir_all(path.join("info")).unwrap();
+            fs_err::write(
+                path.join("info/index.json"),
+                format!(
+                    r#"{{"name": "foo", "version": "{version}", "build": "{build}", "build_number": 0}}"#
+                ),
+            )
+            .unwrap();
+            let lock_path = lock_file_path(&path);
+            fs_err::write(&lock_path, "").unwrap();
+            fs_err::write(holder_file_path(&lock_path), "").unwrap();
+            path
+        };
+
+        let latest = [add_entry("2.0", "h0"), add_entry("2.0", "h1")];
+        let previous = [add_entry("1.0", "h0"), add_entry("1.0", "h1")];
+        let oldest = add_entry("0.9", "h0");
+
+        let cache = PackageCache::new(packages_dir.path());
+        let reclaimed = cache
+            .prune(PrunePolicy::KeepLatestVersions(2))
+            .await
+            .unwrap();
+        assert!(reclaimed > 0);
+
+        // Both builds of the two latest versions are kept.
+        for path in latest.iter().chain(previous.iter()) {
+            assert!(path.is_dir(), "{} was removed", path.display());
+        }
+
+        // The entry is removed but its lock file is left in place.
+        assert!(!oldest.exists());
+        assert!(lock_file_path(&oldest).exists());
+    }
+
+    #[test]
+    fn test_size_index_only_walks_cache_when_needed() {
+        let packages_dir = tempdir().unwrap();
+        let add_entry = |name: &str, size: usize| {
+            let path = packages_dir.path().join(name);
+            fs_err::create_dir_all(&path).unwrap();
+            fs_err::write(path.join("file"), vec![0u8; size]).unwrap();
+            path
+        };
+
+        let mut index = CacheSizeIndex::default();
+        let first = add_entry("first-1-0", 10);
+        assert_eq!(
+            index
+                .add_and_evict(packages_dir.path(), &first, 100)
+                .unwrap(),
+            0
+        );
+        assert_eq!(index.total_size, 10);
+
+        // Entries that are added behind the back of the index are not
+        // counted until the cache is walked again.
+        add_entry("other-1-0", 50);
+        let second = add_entry("second-1-0", 20);
+        index
+            .add_and_evict(packages_dir.path(), &second, 100)
+            .unwrap();
+        assert_eq!(index.total_size, 30);
+
+        // Exceeding the limit walks the cache and evicts the least recently
+        // used entries.
+        let third = add_entry("third-1-0", 80);
+        let reclaimed = index
+            .add_and_evict(packages_dir.path(), &third, 100)
+            .unwrap();
+        assert!(reclaimed > 0);
+        assert!(index.total_size <= 100);
+        assert_eq!(
+            index.total_size,
+            index.sizes.as_ref().unwrap().values().sum::<u64>()
+        );
+    }
+}
@@ -1,3 +1,9 @@
+use std::path::Path;
+
+use rattler_networking::retry_policies::RetryAttempt;
+
+use super::LockHolder;
+
 /// A trait that can be implemented to report progress of the download and
 /// validation process.
 pub trait CacheReporter: Send + Sync {
@@ -5,10 +11,17 @@ pub trait CacheReporter: Send + Sync {
     fn on_validate_start(&self) -> usize;
     /// Called when validation completex
     fn on_validate_complete(&self, index: usize);
+    /// Called every time a file of the package has been validated
+    fn on_validate_progress(&self, _index: usize, _files_done: usize, _files_total: usize) {}
     /// Called when a download starts
     fn on_download_start(&self) -> usize;
     /// Called with regular updates on the download progress
     fn on_download_progress(&self, index: usize, progress: u64, total: Option<u64>);
     /// Called when a download completes
     fn on_download_completed(&self, index: usize);
+    /// Called when a cache entry is locked by another process and the
+    /// current process has to wait for it to be released
+    fn on_lock_wait(&self, _lock_file: &Path, _holder: Option<&LockHolder>) {}
+    /// Called when a failed download is about to be retried
+    fn on_download_retry(&self, _attempt: &RetryAttempt) {}
 }
@@ -0,0 +1,128 @@
+//! Full revalidation of all entries in a [`PackageCache`].
+
+use std::path::PathBuf;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    prune::{collect_entries, remove_entry},
+    PackageCache, PackageCacheError,
+};
+use crate::validation::{validate_package_directory, PackageValidationError, ValidationMode};
+
+/// The result of [`PackageCache::revalidate`].
+#[derive(Debug, Default)]
+pub struct RevalidationReport {
+    /// The number of entries whose contents matched their recorded metadata.
+    pub valid: usize,
+
+    /// The entries whose contents did not match their recorded metadata.
+    pub corrupted: Vec<CorruptedCacheEntry>,
+
+    /// The number of bytes reclaimed by removing corrupted entries.
+    pub reclaimed_bytes: u64,
+}
+
+/// A cache entry whose contents did not match the metadata of the package.
+#[derive(Debug)]
+pub struct CorruptedCacheEntry {
+    /// The path of the entry in the cache.
+    pub path: PathBuf,
+
+    /// Why the entry is considered corrupted.
+    pub error: PackageValidationError,
+
+    /// Whether the entry was removed from the cache.
+    pub removed: bool,
+}
+
+impl PackageCache {
+    /// Re-hashes every file of every package in the cache and compares it
+    /// against the sha256 and size recorded in the package metadata.
+    ///
+    /// If `remove_corrupted` is `true`, corrupted entries are deleted so they
+    /// are fetched again the next time they are requested. Entries that are
+    /// currently in use are never deleted.
+    pub async fn revalidate(
+        &self,
+        remove_corrupted: bool,
+    ) -> Result<RevalidationReport, PackageCacheError> {
+        let cache_path = self.inner.path.clone();
+        simple_spawn_blocking::tokio::run_blocking_task(move || {
+            let results = collect_entries(&cache_path)?
+                .into_par_iter()
+                .map(|entry| {
+                    let result = validate_package_directory(&entry.path, ValidationMode::Full);
+                    (entry.path, result.err())
+                })
+                .collect::<Vec<_>>();
+
+            let mut report = RevalidationReport::default();
+            for (path, error) in results {
+                let Some(error) = error else {
+                    report.valid += 1;
+                    continue;
+                };
+
+                tracing::warn!("cache entry '{}' is corrupted: {error}", path.display());
+                let mut removed = false;
+                if remove_corrupted {
+                    let reclaimed = remove_entry(&path)?;
+                    removed = !path.exists();
+                    report.reclaimed_bytes += reclaimed;
+                }
+                report.corrupted.push(CorruptedCacheEntry {
+                    path,
+                    error,
+                    removed,
+                });
+            }
+            Ok(report)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rattler_conda_types::package::{PackageFile, PathsJson};
+    use tempfile::tempdir;
+
+    use crate::package_cache::PackageCache;
+
+    #[tokio::test]
+    async fn test_revalidate() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+
+        let entry_path = cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap()
+            .path()
+            .to_path_buf();
+
+        let report = cache.revalidate(true).await.unwrap();
+        assert_eq!(report.valid, 1);
+        assert!(report.corrupted.is_empty());
+
+        // Corrupt one of the files of the package.
+        let paths = PathsJson::from_package_directory(&entry_path).unwrap();
+        let file = paths
+            .paths
+            .iter()
+            .find(|entry| entry.size_in_bytes.unwrap_or_default() > 0)
+            .unwrap();
+        fs_err::write(entry_path.join(&file.relative_path), b"corrupted").unwrap();
+
+        let report = cache.revalidate(true).await.unwrap();
+        assert_eq!(report.valid, 0);
+        assert_eq!(report.corrupted.len(), 1);
+        assert!(report.corrupted[0].removed);
+        assert!(!entry_path.exists());
+    }
+}
@@ -0,0 +1,244 @@
+//! Downloading large package archives in multiple byte ranges in parallel.
+
+use std::{
+    io::SeekFrom,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use fs_err::tokio as tokio_fs;
+use futures::{stream, StreamExt, TryStreamExt};
+use rattler_package_streaming::{DownloadReporter, ExtractError};
+use reqwest::{
+    header::{HeaderMap, CONTENT_RANGE, RANGE},
+    Response, StatusCode,
+};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use url::Url;
+
+/// Configures downloading large package archives in multiple segments in
+/// parallel.
+///
+/// On high-latency links a single connection often cannot saturate the
+/// available bandwidth. When segmented downloads are enabled, archives that
+/// are larger than [`Self::segment_size`] are split into byte ranges that are
+/// requested concurrently and written to their position in the archive. If the
+/// server does not support range requests the archive is downloaded with a
+/// single request instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentedDownloads {
+    /// The size of a single segment in bytes. Archives that are not larger
+    /// than this are downloaded with a single request.
+    pub segment_size: u64,
+
+    /// The maximum number of connections that are used to download a single
+    /// archive.
+    pub max_connections: usize,
+}
+
+impl Default for SegmentedDownloads {
+    fn default() -> Self {
+        Self {
+            segment_size: 16 * 1024 * 1024,
+            max_connections: 4,
+        }
+    }
+}
+
+impl SegmentedDownloads {
+    /// Sets the size of a single segment in bytes.
+    pub fn with_segment_size(self, segment_size: u64) -> Self {
+        Self {
+            segment_size: segment_size.max(1),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of connections used to download a single
+    /// archive.
+    pub fn with_max_connections(self, max_connections: usize) -> Self {
+        Self {
+            max_connections: max_connections.max(1),
+            ..self
+        }
+    }
+}
+
+/// The result of attempting a segmented download.
+pub(super) enum SegmentedDownload {
+    /// The archive was downloaded completely.
+    Completed,
+
+    /// The server does not support range requests. If the server returned the
+    /// whole archive, the response is returned so it doesn't have to be
+    /// requested again.
+    Unsupported(Option<Response>),
+}
+
+/// Downloads `url` to `path` in segments.
+///
+/// The first segment is requested with a `Range` request, the response tells
+/// whether the server supports range requests and what the total size of the
+/// archive is. The remaining segments are then downloaded in parallel.
+pub(super) async fn download(
+    client: &reqwest_middleware::ClientWithMiddleware,
+    url: &Url,
+    path: &Path,
+    config: SegmentedDownloads,
+    reporter: Option<&Arc<dyn DownloadReporter>>,
+) -> Result<SegmentedDownload, ExtractError> {
+    let segment_size = config.segment_size.max(1);
+    let first_response = request_range(client, url, 0, segment_size - 1).await?;
+    if first_response.status() != StatusCode::PARTIAL_CONTENT {
+        return Ok(SegmentedDownload::Unsupported(Some(first_response)));
+    }
+    let Some(total_size) = content_range(first_response.headers())
+        .filter(|(start, _, _)| *start == 0)
+        .and_then(|(_, _, total)| total)
+    else {
+        return Ok(SegmentedDownload::Unsupported(None));
+    };
+
+    tracing::debug!("downloading {url} ({total_size} bytes) in segments of {segment_size} bytes");
+
+    // Preallocate the file so every segment can be written at its own offset.
+    let file = tokio_fs::File::create(path).await?;
+    file.set_len(total_size).await?;
+    drop(file);
+
+    let progress = AtomicU64::new(0);
+    let first_end = segment_size.min(total_size) - 1;
+    let first_segment = write_segment(
+        first_response,
+        path,
+        0,
+        first_end,
+        total_size,
+        &progress,
+        reporter,
+    );
+
+    let remaining_segments = (segment_size..total_size)
+        .step_by(usize::try_from(segment_size).unwrap_or(usize::MAX))
+        .map(|start| {
+            let end = (start + segment_size).min(total_size) - 1;
+            let progress = &progress;
+            async move {
+                let response = request_range(client, url, start, end).await?;
+                if response.status() != StatusCode::PARTIAL_CONTENT
+                    || content_range(response.headers()).map(|(start, _, _)| start) != Some(start)
+                {
+                    return Err(ExtractError::IoError(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("the server did not return the requested range {start}-{end}"),
+                    )));
+                }
+                write_segment(response, path, start, end, total_size, progress, reporter).await
+            }
+        });
+
+    stream::once(first_segment)
+        .chain(stream::iter(remaining_segments).buffer_unordered(config.max_connections.max(1)))
+        .try_collect::<()>()
+        .await?;
+
+    Ok(SegmentedDownload::Completed)
+}
+
+async fn request_range(
+    client: &reqwest_middleware::ClientWithMiddleware,
+    url: &Url,
+    start: u64,
+    end: u64,
+) -> Result<Response, ExtractError> {
+    client
+        .get(url.clone())
+        .header(RANGE, format!("bytes={start}-{end}"))
+        .send()
+        .await
+        .and_then(|response| {
+            response
+                .error_for_status()
+                .map_err(reqwest_middleware::Error::Reqwest)
+        })
+        .map_err(ExtractError::ReqwestError)
+}
+
+/// Writes the body of `response` to the byte range `start..=end` of the file
+/// at `path`.
+async fn write_segment(
+    response: Response,
+    path: &Path,
+    start: u64,
+    end: u64,
+    total_size: u64,
+    progress: &AtomicU64,
+    reporter: Option<&Arc<dyn DownloadReporter>>,
+) -> Result<(), ExtractError> {
+    let mut file = tokio_fs::OpenOptions::new().write(true).open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let expected = end - start + 1;
+    let mut written = 0;
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| {
+            ExtractError::IoError(std::io::Error::new(std::io::ErrorKind::Interrupted, e))
+        })?;
+        let chunk = &chunk[..chunk.len().min((expected - written) as usize)];
+        file.write_all(chunk).await?;
+        written += chunk.len() as u64;
+
+        let downloaded =
+            progress.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+        if let Some(reporter) = reporter {
+            reporter.on_download_progress(downloaded, Some(total_size));
+        }
+    }
+    file.flush().await?;
+
+    if written < expected {
+        return Err(ExtractError::IoError(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("the connection was closed before segment {start}-{end} completed"),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Parses a `Content-Range: bytes <start>-<end>/<total>` header. The total is
+/// `None` if the server does not know the size.
+pub(super) fn content_range(headers: &HeaderMap) -> Option<(u64, u64, Option<u64>)> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    Some((
+        start.trim().parse().ok()?,
+        end.trim().parse().ok()?,
+        total.trim().parse().ok(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_RANGE};
+
+    use super::content_range;
+
+    #[test]
+    fn test_parse_content_range() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes 0-99/1234"));
+        assert_eq!(content_range(&headers), Some((0, 99, Some(1234))));
+
+        headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes 100-199/*"));
+        assert_eq!(content_range(&headers), Some((100, 199, None)));
+
+        headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes */1234"));
+        assert_eq!(content_range(&headers), None);
+    }
+}
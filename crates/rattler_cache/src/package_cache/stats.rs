@@ -0,0 +1,175 @@
+//! Statistics about the contents and usage of a [`PackageCache`].
+
+use std::{
+    collections::HashMap,
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    prune::{collect_entries, directory_size},
+    PackageCache, PackageCacheError,
+};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Statistics about a [`PackageCache`], see [`PackageCache::stats`].
+#[derive(Debug, Clone, Default)]
+pub struct PackageCacheStats {
+    /// The total size in bytes of all extracted packages in the cache.
+    pub total_size: u64,
+
+    /// The number of extracted packages in the cache.
+    pub entry_count: usize,
+
+    /// The size and number of entries per package name, sorted by size from
+    /// largest to smallest.
+    pub packages: Vec<PackageStats>,
+
+    /// The number of requests served from the cache by this process.
+    pub hits: u64,
+
+    /// The number of requests by this process that required fetching the
+    /// package.
+    pub misses: u64,
+
+    /// The number of entries grouped by when they were last used.
+    pub age_distribution: AgeDistribution,
+}
+
+/// Statistics about all cached entries of a single package.
+#[derive(Debug, Clone, Default)]
+pub struct PackageStats {
+    /// The name of the package. If the metadata of an entry could not be read
+    /// this is the name of the directory in the cache instead.
+    pub name: String,
+
+    /// The number of entries (e.g. different versions or builds) in the cache.
+    pub entry_count: usize,
+
+    /// The total size in bytes of the entries.
+    pub total_size: u64,
+}
+
+/// The number of cache entries grouped by when they were last used.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AgeDistribution {
+    /// Entries used within the last day.
+    pub last_day: usize,
+
+    /// Entries used within the last week but not within the last day.
+    pub last_week: usize,
+
+    /// Entries used within the last 30 days but not within the last week.
+    pub last_month: usize,
+
+    /// Entries that have not been used for more than 30 days.
+    pub older: usize,
+}
+
+impl AgeDistribution {
+    fn add(&mut self, age: Duration) {
+        if age <= DAY {
+            self.last_day += 1;
+        } else if age <= 7 * DAY {
+            self.last_week += 1;
+        } else if age <= 30 * DAY {
+            self.last_month += 1;
+        } else {
+            self.older += 1;
+        }
+    }
+}
+
+impl PackageCache {
+    /// Returns statistics about the contents of the cache on disk and the
+    /// cache hits and misses of this instance.
+    pub async fn stats(&self) -> Result<PackageCacheStats, PackageCacheError> {
+        let cache_path = self.inner.path.clone();
+        let entries = simple_spawn_blocking::tokio::run_blocking_task(move || {
+            collect_entries(&cache_path).map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| (directory_size(&entry.path), entry))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .await?;
+
+        let now = SystemTime::now();
+        let mut stats = PackageCacheStats {
+            entry_count: entries.len(),
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            ..PackageCacheStats::default()
+        };
+
+        let mut packages: HashMap<String, PackageStats> = HashMap::new();
+        for (size, entry) in entries {
+            stats.total_size += size;
+            stats
+                .age_distribution
+                .add(now.duration_since(entry.last_used).unwrap_or_default());
+
+            let name = match &entry.index_json {
+                Some(index_json) => index_json.name.as_normalized().to_string(),
+                None => entry
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            };
+            let package = packages
+                .entry(name.clone())
+                .or_insert_with(|| PackageStats {
+                    name,
+                    ..PackageStats::default()
+                });
+            package.entry_count += 1;
+            package.total_size += size;
+        }
+
+        stats.packages = packages.into_values().collect();
+        stats
+            .packages
+            .sort_by(|a, b| b.total_size.cmp(&a.total_size).then(a.name.cmp(&b.name)));
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tempfile::tempdir;
+
+    use crate::package_cache::PackageCache;
+
+    #[tokio::test]
+    async fn test_stats() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+
+        cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap();
+        cache
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap();
+
+        let stats = cache.stats().await.unwrap();
+        assert_eq!(stats.entry_count, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.age_distribution.last_day, 1);
+        assert_eq!(stats.packages.len(), 1);
+        assert_eq!(stats.packages[0].name, "clobber-python");
+        assert_eq!(stats.packages[0].total_size, stats.total_size);
+        assert!(stats.total_size > 0);
+    }
+}
@@ -0,0 +1,290 @@
+//! Exporting packages from a [`PackageCache`] into a single archive and
+//! importing them again, e.g. to transfer packages to an air-gapped machine.
+//!
+//! The archive is a tar file that starts with a `manifest.json` describing the
+//! exported entries, followed by the extracted package directories under
+//! `packages/`. The sha256 hash recorded for every entry is preserved so that
+//! the imported entries are treated exactly like entries that were fetched on
+//! the machine itself.
+
+use std::{
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use rattler_conda_types::PackageRecord;
+use rattler_digest::{parse_digest_from_hex, Sha256, Sha256Hash};
+
+use super::{cache_lock::CacheRwLock, lock_file_path, CacheKey, PackageCache, PackageCacheError};
+
+const MANIFEST_PATH: &str = "manifest.json";
+const PACKAGES_DIR: &str = "packages";
+
+/// An entry in the manifest of an exported archive.
+struct ManifestEntry {
+    key: String,
+    sha256: Option<Sha256Hash>,
+}
+
+impl PackageCache {
+    /// Writes all the given packages from the cache into a single tar archive.
+    /// Returns the number of packages that were exported.
+    ///
+    /// All packages must already be present in the cache, e.g. because the
+    /// transaction they belong to was installed before. The archive can be
+    /// loaded into another cache with [`Self::import`].
+    pub async fn export<'a>(
+        &self,
+        records: impl IntoIterator<Item = &'a PackageRecord>,
+        writer: impl Write + Send + 'static,
+    ) -> Result<usize, PackageCacheError> {
+        // Acquire read locks on all entries to ensure they are not modified while
+        // they are being archived.
+        let mut entries = Vec::new();
+        for record in records {
+            let key = CacheKey::from(record).to_string();
+            let path = self.inner.path.join(&key);
+            if !path.is_dir() {
+                return Err(PackageCacheError::IoError(
+                    format!("package '{key}' is not present in the cache"),
+                    std::io::ErrorKind::NotFound.into(),
+                ));
+            }
+            let mut lock = CacheRwLock::acquire_read(&lock_file_path(&path)).await?;
+            let sha256 = lock.read_sha256()?.or(record.sha256);
+            entries.push((ManifestEntry { key, sha256 }, path, lock));
+        }
+
+        simple_spawn_blocking::tokio::run_blocking_task(move || {
+            write_archive(writer, &entries)
+                .map_err(|e| PackageCacheError::IoError("failed to write archive".to_string(), e))
+        })
+        .await
+    }
+
+    /// Loads all packages from an archive created by [`Self::export`] into
+    /// the cache. Existing entries for the same packages are replaced. Returns
+    /// the paths of the imported entries.
+    pub async fn import(
+        &self,
+        reader: impl Read + Send + 'static,
+    ) -> Result<Vec<PathBuf>, PackageCacheError> {
+        self.ensure_compatible_layout().await?;
+
+        let cache_path = self.inner.path.clone();
+        let io_error =
+            |e| PackageCacheError::IoError("failed to import packages into cache".to_string(), e);
+
+        fs_err::tokio::create_dir_all(&cache_path)
+            .await
+            .map_err(io_error)?;
+        let staging_dir = tempfile::tempdir_in(&cache_path).map_err(io_error)?;
+
+        // Unpack the archive into a staging directory first so that a partial or
+        // corrupt archive never leaves broken entries behind.
+        let staging_path = staging_dir.path().to_path_buf();
+        let manifest = simple_spawn_blocking::tokio::run_blocking_task(move || {
+            read_archive(reader, &staging_path)
+                .map_err(|e| PackageCacheError::IoError("failed to read archive".to_string(), e))
+        })
+        .await?;
+
+        let mut imported = Vec::with_capacity(manifest.len());
+        for entry in manifest {
+            let source = staging_dir.path().join(&entry.key);
+            let destination = cache_path.join(&entry.key);
+
+            let mut lock = CacheRwLock::acquire_write(&lock_file_path(&destination)).await?;
+            let revision = lock.read_revision()?;
+            match fs_err::tokio::remove_dir_all(&destination).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(io_error(e)),
+            }
+            fs_err::tokio::rename(&source, &destination)
+                .await
+                .map_err(io_error)?;
+            lock.write_revision_and_sha(revision + 1, entry.sha256.as_ref())
+                .await?;
+
+            imported.push(destination);
+        }
+
+        Ok(imported)
+    }
+}
+
+fn write_archive(
+    writer: impl Write,
+    entries: &[(ManifestEntry, PathBuf, CacheRwLock)],
+) -> std::io::Result<usize> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+
+    let manifest = serde_json::Value::Array(
+        entries
+            .iter()
+            .map(|(entry, _, _)| {
+                serde_json::json!({
+                    "key": entry.key,
+                    "sha256": entry.sha256.map(|sha| format!("{sha:x}")),
+                })
+            })
+            .collect(),
+    );
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_PATH, manifest.as_slice())?;
+
+    for (entry, path, _) in entries {
+        builder.append_dir_all(Path::new(PACKAGES_DIR).join(&entry.key), path)?;
+    }
+
+    builder.into_inner()?.flush()?;
+    Ok(entries.len())
+}
+
+fn read_archive(reader: impl Read, staging_path: &Path) -> std::io::Result<Vec<ManifestEntry>> {
+    let invalid_data = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = archive.entries()?;
+
+    // The manifest is always the first entry of the archive.
+    let mut manifest_entry = entries
+        .next()
+        .ok_or_else(|| invalid_data("the archive is empty".to_string()))??;
+    if manifest_entry.path()?.as_ref() != Path::new(MANIFEST_PATH) {
+        return Err(invalid_data(format!(
+            "expected '{MANIFEST_PATH}' at the start of the archive"
+        )));
+    }
+    let mut manifest_bytes = Vec::new();
+    manifest_entry.read_to_end(&mut manifest_bytes)?;
+    let manifest = parse_manifest(&manifest_bytes).map_err(invalid_data)?;
+
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let relative_path = path
+            .strip_prefix(PACKAGES_DIR)
+            .map_err(|_err| invalid_data(format!("unexpected entry '{}'", path.display())))?;
+        if relative_path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(invalid_data(format!("invalid path '{}'", path.display())));
+        }
+        entry.unpack_in(staging_path)?;
+    }
+
+    // The entries were unpacked including the `packages/` prefix, move them up.
+    let packages_path = staging_path.join(PACKAGES_DIR);
+    for entry in &manifest {
+        fs_err::rename(
+            packages_path.join(&entry.key),
+            staging_path.join(&entry.key),
+        )?;
+    }
+
+    Ok(manifest)
+}
+
+fn parse_manifest(bytes: &[u8]) -> Result<Vec<ManifestEntry>, String> {
+    let value: serde_json::Value =
+        serde_json::from_slice(bytes).map_err(|e| format!("invalid manifest: {e}"))?;
+    let entries = value
+        .as_array()
+        .ok_or_else(|| "invalid manifest: expected an array".to_string())?;
+
+    entries
+        .iter()
+        .map(|entry| {
+            let key = entry
+                .get("key")
+                .and_then(serde_json::Value::as_str)
+                .filter(|key| {
+                    Path::new(key)
+                        .components()
+                        .all(|c| matches!(c, Component::Normal(_)))
+                })
+                .ok_or_else(|| "invalid manifest: invalid package key".to_string())?;
+            let sha256 = match entry.get("sha256").and_then(serde_json::Value::as_str) {
+                Some(sha256) => Some(
+                    parse_digest_from_hex::<Sha256>(sha256)
+                        .ok_or_else(|| format!("invalid manifest: invalid sha256 '{sha256}'"))?,
+                ),
+                None => None,
+            };
+            Ok(ManifestEntry {
+                key: key.to_string(),
+                sha256,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rattler_conda_types::{
+        package::{ArchiveIdentifier, IndexJson, PackageFile},
+        PackageRecord,
+    };
+    use tempfile::tempdir;
+
+    use crate::{
+        package_cache::PackageCache,
+        validation::{validate_package_directory, ValidationMode},
+    };
+
+    #[tokio::test]
+    async fn test_export_import() {
+        let package_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+
+        let source_dir = tempdir().unwrap();
+        let source = PackageCache::new(source_dir.path());
+        let entry_path = source
+            .get_or_fetch_from_path(&package_path, None)
+            .await
+            .unwrap()
+            .path()
+            .to_path_buf();
+
+        let index_json = IndexJson::from_package_directory(&entry_path).unwrap();
+        let record = PackageRecord::from_index_json(index_json, None, None, None).unwrap();
+
+        let archive_dir = tempdir().unwrap();
+        let archive_path = archive_dir.path().join("export.tar");
+        let exported = source
+            .export([&record], std::fs::File::create(&archive_path).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(exported, 1);
+
+        let target_dir = tempdir().unwrap();
+        let target = PackageCache::new(target_dir.path());
+        let imported = target
+            .import(std::fs::File::open(&archive_path).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(imported.len(), 1);
+        validate_package_directory(&imported[0], ValidationMode::Full).unwrap();
+
+        // The imported package is found without fetching it again.
+        let cache_lock = target
+            .get_or_fetch(
+                ArchiveIdentifier::try_from_path(&package_path).unwrap(),
+                |_| async { Err(std::io::Error::other("should not fetch")) },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache_lock.path(), imported[0]);
+    }
+}
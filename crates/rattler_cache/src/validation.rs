@@ -14,6 +14,7 @@
 use std::{
     io::{BufReader, ErrorKind},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use digest::Digest;
@@ -102,6 +103,20 @@ pub enum PackageEntryValidationError {
 pub fn validate_package_directory(
     package_dir: &Path,
     mode: ValidationMode,
+) -> Result<(IndexJson, PathsJson), PackageValidationError> {
+    validate_package_directory_with_progress(package_dir, mode, &|_, _| {})
+}
+
+/// Same as [`validate_package_directory`] but calls `on_progress` with the
+/// number of validated files and the total number of files every time a file
+/// has been validated.
+///
+/// Files are validated in parallel, so `on_progress` may be called from
+/// multiple threads.
+pub fn validate_package_directory_with_progress(
+    package_dir: &Path,
+    mode: ValidationMode,
+    on_progress: &(dyn Fn(usize, usize) + Sync),
 ) -> Result<(IndexJson, PathsJson), PackageValidationError> {
     // Validate that there is a valid IndexJson
     let index_json = IndexJson::from_package_directory(package_dir)
@@ -125,7 +140,7 @@ pub fn validate_package_directory(
     };
 
     // Validate all the entries
-    validate_package_directory_from_paths(package_dir, &paths, mode)
+    validate_package_directory_from_paths_with_progress(package_dir, &paths, mode, on_progress)
         .map_err(|(path, err)| PackageValidationError::CorruptedEntry(path, err))?;
 
     Ok((index_json, paths))
@@ -138,14 +153,39 @@ pub fn validate_package_directory_from_paths(
     paths: &PathsJson,
     mode: ValidationMode,
 ) -> Result<(), (PathBuf, PackageEntryValidationError)> {
+    validate_package_directory_from_paths_with_progress(package_dir, paths, mode, &|_, _| {})
+}
+
+/// Same as [`validate_package_directory_from_paths`] but calls `on_progress`
+/// with the number of validated files and the total number of files every time
+/// a file has been validated.
+pub fn validate_package_directory_from_paths_with_progress(
+    package_dir: &Path,
+    paths: &PathsJson,
+    mode: ValidationMode,
+    on_progress: &(dyn Fn(usize, usize) + Sync),
+) -> Result<(), (PathBuf, PackageEntryValidationError)> {
+    // Checking whether a file exists is cheap so only split up the work for
+    // large packages. Hashing files is expensive and always benefits from
+    // running in parallel.
+    let min_len = match mode {
+        ValidationMode::Fast => 1000,
+        ValidationMode::Full => 1,
+    };
+
+    let total = paths.paths.len();
+    let done = AtomicUsize::new(0);
+
     // Check every entry in the PathsJson object
     paths
         .paths
         .par_iter()
-        .with_min_len(1000)
+        .with_min_len(min_len)
         .try_for_each(|entry| {
             validate_package_entry(package_dir, entry, mode)
-                .map_err(|e| (entry.relative_path.clone(), e))
+                .map_err(|e| (entry.relative_path.clone(), e))?;
+            on_progress(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+            Ok(())
         })
 }
 
@@ -282,7 +322,8 @@ mod test {
 
     use super::{
         validate_package_directory, validate_package_directory_from_paths,
-        PackageEntryValidationError, PackageValidationError, ValidationMode,
+        validate_package_directory_with_progress, PackageEntryValidationError,
+        PackageValidationError, ValidationMode,
     };
 
     #[rstest]
@@ -345,6 +386,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_validate_progress() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let package_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        rattler_package_streaming::fs::extract(&package_path, temp_dir.path()).unwrap();
+
+        let calls = std::sync::Mutex::new(Vec::new());
+        let (_, paths) = validate_package_directory_with_progress(
+            temp_dir.path(),
+            ValidationMode::Full,
+            &|done, total| calls.lock().unwrap().push((done, total)),
+        )
+        .unwrap();
+
+        let mut calls = calls.into_inner().unwrap();
+        calls.sort_unstable();
+        let total = paths.paths.len();
+        assert_eq!(
+            calls,
+            (1..=total).map(|done| (done, total)).collect::<Vec<_>>()
+        );
+    }
+
     #[rstest]
     #[cfg(unix)]
     #[case::mamba(
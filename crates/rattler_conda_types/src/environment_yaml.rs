@@ -7,7 +7,9 @@ use serde::{
     Deserializer, Serializer,
 };
 
-use crate::{MatchSpec, NamedChannelOrUrl, ParseStrictness};
+use crate::{
+    Channel, ChannelConfig, MatchSpec, NamedChannelOrUrl, ParseChannelError, ParseStrictness,
+};
 
 /// A representation of an `environment.yaml` file.
 #[derive(Default, Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
@@ -88,6 +90,41 @@ impl EnvironmentYaml {
         self.find_sub_section("pip")
     }
 
+    /// Returns the channels that are required to solve the environment,
+    /// resolved with the given channel configuration. Together with
+    /// [`Self::match_specs`] these can be passed to the gateway and the solver.
+    ///
+    /// The special `nodefaults` channel, which conda uses to exclude its
+    /// default channels, is skipped. Channels that are only referenced by a
+    /// matchspec (e.g. `conda-forge::numpy`) are appended to the end.
+    pub fn resolve_channels(
+        &self,
+        config: &ChannelConfig,
+    ) -> Result<Vec<Channel>, ParseChannelError> {
+        let mut channels: Vec<Channel> = Vec::new();
+        let mut push_unique = |channel: Channel| {
+            if !channels.iter().any(|c| c.base_url == channel.base_url) {
+                channels.push(channel);
+            }
+        };
+
+        for channel in &self.channels {
+            if matches!(channel, NamedChannelOrUrl::Name(name) if name == "nodefaults") {
+                continue;
+            }
+            push_unique(channel.clone().into_channel(config)?);
+        }
+
+        for channel in self
+            .match_specs()
+            .filter_map(|spec| spec.channel.as_deref())
+        {
+            push_unique(channel.clone());
+        }
+
+        Ok(channels)
+    }
+
     /// Reads the contents of a file at the given path and parses it as an
     /// `environment.yaml` file.
     pub fn from_path(path: &Path) -> std::io::Result<Self> {
@@ -199,4 +236,35 @@ mod tests {
         .unwrap();
         insta::assert_debug_snapshot!(environment_yaml.pip_specs());
     }
+
+    #[test]
+    fn test_resolve_channels() {
+        let environment_yaml = EnvironmentYaml::from_yaml_str(
+            r#"
+            channels:
+              - conda-forge
+              - nodefaults
+              - https://prefix.dev/conda-forge
+            dependencies:
+              - python 3.12.*
+              - bioconda::samtools
+              - conda-forge::numpy
+            "#,
+        )
+        .unwrap();
+
+        let config = ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap());
+        let channels = environment_yaml.resolve_channels(&config).unwrap();
+        assert_eq!(
+            channels
+                .iter()
+                .map(|c| c.base_url.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "https://conda.anaconda.org/conda-forge/",
+                "https://prefix.dev/conda-forge/",
+                "https://conda.anaconda.org/bioconda/",
+            ]
+        );
+    }
 }
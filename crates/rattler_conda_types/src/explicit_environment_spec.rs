@@ -9,7 +9,7 @@
 //!
 //! To create an explicit environment file, you can use the `conda env export` command.
 
-use crate::{ParsePlatformError, Platform};
+use crate::{PackageRecord, ParsePlatformError, Platform, PrefixRecord, RepoDataRecord};
 use fs_err::{self as fs, File};
 use serde::{Deserialize, Serialize};
 use std::{io::Read, path::Path, str::FromStr};
@@ -107,6 +107,25 @@ impl ExplicitEnvironmentEntry {
     }
 }
 
+impl From<&RepoDataRecord> for ExplicitEnvironmentEntry {
+    /// Constructs an entry from the url of the record. The hash of the package archive is added
+    /// as the fragment of the url. The md5 hash is preferred because it is understood by all
+    /// versions of conda, the sha256 hash is only used if no md5 hash is available.
+    fn from(record: &RepoDataRecord) -> Self {
+        let mut url = record.url.clone();
+        let hash = match (
+            record.package_record.md5.as_ref(),
+            record.package_record.sha256.as_ref(),
+        ) {
+            (Some(md5), _) => Some(format!("{md5:x}")),
+            (None, Some(sha256)) => Some(format!("sha256:{sha256:x}")),
+            (None, None) => None,
+        };
+        url.set_fragment(hash.as_deref());
+        ExplicitEnvironmentEntry { url }
+    }
+}
+
 impl From<Url> for ExplicitEnvironmentEntry {
     fn from(url: Url) -> Self {
         ExplicitEnvironmentEntry { url }
@@ -152,6 +171,34 @@ impl ExplicitEnvironmentSpec {
         Self::from_reader(File::open(path)?)
     }
 
+    /// Constructs an explicit environment from a set of records, e.g. the records of a solved or
+    /// locked environment.
+    ///
+    /// The records are sorted topologically so that the packages are installed in the correct
+    /// order. Each package url contains the hash of the package archive, if known.
+    pub fn from_records<'a>(
+        platform: Option<Platform>,
+        records: impl IntoIterator<Item = &'a RepoDataRecord>,
+    ) -> Self {
+        let records = PackageRecord::sort_topologically(records.into_iter().collect::<Vec<_>>());
+        ExplicitEnvironmentSpec {
+            platform,
+            packages: records.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Constructs an explicit environment from the records of the packages installed in a prefix.
+    /// See [`ExplicitEnvironmentSpec::from_records`].
+    pub fn from_prefix_records<'a>(
+        platform: Option<Platform>,
+        records: impl IntoIterator<Item = &'a PrefixRecord>,
+    ) -> Self {
+        Self::from_records(
+            platform,
+            records.into_iter().map(|record| &record.repodata_record),
+        )
+    }
+
     /// Converts an [`ExplicitEnvironmentSpec`] to a string representing a valid explicit
     /// environment file
     pub fn to_spec_string(&self) -> String {
@@ -224,10 +271,12 @@ mod test {
     use super::{ExplicitEnvironmentSpec, ParseExplicitEnvironmentSpecError};
     use crate::{
         explicit_environment_spec::{PackageArchiveHash, ParsePackageArchiveHashError},
-        get_test_data_dir, ExplicitEnvironmentEntry,
+        get_test_data_dir, ExplicitEnvironmentEntry, PackageName, PackageRecord, Platform,
+        RepoDataRecord, Version,
     };
     use assert_matches::assert_matches;
     use hex_literal::hex;
+    use rattler_digest::{parse_digest_from_hex, Md5, Sha256};
     use rstest::rstest;
     use std::str::FromStr;
     use url::Url;
@@ -324,6 +373,68 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_from_records() {
+        let record = |name: &str, depends: &[&str], md5, sha256| {
+            let file_name = format!("{name}-1.0-h123_0.conda");
+            RepoDataRecord {
+                package_record: PackageRecord {
+                    subdir: "linux-64".into(),
+                    depends: depends.iter().map(ToString::to_string).collect(),
+                    md5,
+                    sha256,
+                    ..PackageRecord::new(
+                        PackageName::new_unchecked(name),
+                        Version::from_str("1.0").unwrap(),
+                        "h123_0".into(),
+                    )
+                },
+                url: Url::parse(&format!(
+                    "https://conda.anaconda.org/conda-forge/linux-64/{file_name}"
+                ))
+                .unwrap(),
+                file_name,
+                channel: Some("https://conda.anaconda.org/conda-forge/".into()),
+            }
+        };
+
+        let records = [
+            record(
+                "foo",
+                &["bar"],
+                None,
+                parse_digest_from_hex::<Sha256>(
+                    "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+                ),
+            ),
+            record(
+                "bar",
+                &[],
+                parse_digest_from_hex::<Md5>("a98ea1e3abfdbbd201d60ff6b43ea7e4"),
+                parse_digest_from_hex::<Sha256>(
+                    "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+                ),
+            ),
+            record("baz", &["foo"], None, None),
+        ];
+
+        let env = ExplicitEnvironmentSpec::from_records(Some(Platform::Linux64), &records);
+        assert_eq!(
+            env.to_spec_string(),
+            "# platform: linux-64\n\
+             @EXPLICIT\n\
+             https://conda.anaconda.org/conda-forge/linux-64/bar-1.0-h123_0.conda#a98ea1e3abfdbbd201d60ff6b43ea7e4\n\
+             https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-h123_0.conda#sha256:315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3\n\
+             https://conda.anaconda.org/conda-forge/linux-64/baz-1.0-h123_0.conda\n"
+        );
+
+        let parsed = ExplicitEnvironmentSpec::from_str(&env.to_spec_string()).unwrap();
+        assert_matches!(
+            parsed.packages[1].package_archive_hash(),
+            Ok(Some(PackageArchiveHash::Sha256(_)))
+        );
+    }
+
     #[test]
     fn test_entry_package_hash() {
         let entry: ExplicitEnvironmentEntry = Url::parse("https://repo.anaconda.com/pkgs/main/win-64/vs2015_runtime-14.16.27012-hf0eaf9b_3.conda#a98ea1e3abfdbbd201d60ff6b43ea7e4").unwrap().into();
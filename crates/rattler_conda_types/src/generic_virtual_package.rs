@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::{PackageName, Version};
-use std::fmt::{Display, Formatter};
+use crate::{InvalidPackageNameError, PackageName, ParseVersionError, Version};
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
 
 /// A `GenericVirtualPackage` is a Conda package description that contains a `name` and a
 /// `version` and a `build_string`.
@@ -27,28 +31,28 @@ impl Display for GenericVirtualPackage {
     }
 }
 
-impl Serialize for GenericVirtualPackage {
-    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let s = format!("{self}");
-        serializer.serialize_str(&s)
-    }
+/// An error that can occur when parsing a [`GenericVirtualPackage`] from a
+/// string.
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ParseGenericVirtualPackageError {
+    /// The name of the virtual package is invalid.
+    #[error(transparent)]
+    InvalidName(#[from] InvalidPackageNameError),
+
+    /// The version of the virtual package is invalid.
+    #[error(transparent)]
+    InvalidVersion(#[from] ParseVersionError),
 }
 
-impl<'de> Deserialize<'de> for GenericVirtualPackage {
-    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let s = String::deserialize(deserializer)?;
-        let mut parts = s.split('=');
+impl FromStr for GenericVirtualPackage {
+    type Err = ParseGenericVirtualPackageError;
 
-        let name = parts
-            .next()
-            .ok_or_else(|| serde::de::Error::custom("No package name given"))?
-            .parse()
-            .map_err(serde::de::Error::custom)?;
-        let version = parts
-            .next()
-            .unwrap_or("0")
-            .parse()
-            .map_err(serde::de::Error::custom)?;
+    /// Parses a virtual package in the form `name[=version[=build_string]]`,
+    /// e.g. `__cuda=12.2`. The version defaults to `0` if it is omitted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('=');
+        let name = parts.next().unwrap_or_default().parse()?;
+        let version = parts.next().unwrap_or("0").parse()?;
         let build_string = parts.next().unwrap_or("").to_string();
 
         Ok(GenericVirtualPackage {
@@ -59,6 +63,20 @@ impl<'de> Deserialize<'de> for GenericVirtualPackage {
     }
 }
 
+impl Serialize for GenericVirtualPackage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let s = format!("{self}");
+        serializer.serialize_str(&s)
+    }
+}
+
+impl<'de> Deserialize<'de> for GenericVirtualPackage {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,4 +107,20 @@ mod tests {
         let s = serde_json::to_string(&p2).unwrap();
         assert_eq!(s, "\"__cuda=0\"");
     }
+
+    #[test]
+    fn test_from_str() {
+        let cuda: GenericVirtualPackage = "__cuda=12.2".parse().unwrap();
+        assert_eq!(cuda.name.as_normalized(), "__cuda");
+        assert_eq!(cuda.version, "12.2".parse().unwrap());
+        assert_eq!(cuda.build_string, "");
+
+        let glibc: GenericVirtualPackage = "__glibc=2.28=0".parse().unwrap();
+        assert_eq!(glibc.to_string(), "__glibc=2.28=0");
+
+        assert!(matches!(
+            "__cuda=not a version".parse::<GenericVirtualPackage>(),
+            Err(ParseGenericVirtualPackageError::InvalidVersion(_))
+        ));
+    }
 }
@@ -27,6 +27,7 @@ pub mod package;
 mod package_name;
 pub mod prefix;
 pub mod prefix_record;
+mod pypi_mapping;
 mod record_traits;
 
 #[cfg(test)]
@@ -40,7 +41,7 @@ pub use explicit_environment_spec::{
     ExplicitEnvironmentEntry, ExplicitEnvironmentSpec, PackageArchiveHash,
     ParseExplicitEnvironmentSpecError, ParsePackageArchiveHashError,
 };
-pub use generic_virtual_package::GenericVirtualPackage;
+pub use generic_virtual_package::{GenericVirtualPackage, ParseGenericVirtualPackageError};
 pub use match_spec::{
     matcher::{StringMatcher, StringMatcherParseError},
     parse::ParseMatchSpecError,
@@ -54,6 +55,7 @@ pub use package_name::{InvalidPackageNameError, PackageName};
 pub use parse_mode::ParseStrictness;
 pub use platform::{Arch, ParseArchError, ParsePlatformError, Platform};
 pub use prefix_record::PrefixRecord;
+pub use pypi_mapping::{PypiNameMapping, PypiPackage, PypiPackageName};
 pub use record_traits::HasArtifactIdentificationRefs;
 pub use repo_data::{
     compute_package_url,
@@ -317,6 +317,10 @@ impl Display for NamelessMatchSpec {
 
         let mut keys = Vec::new();
 
+        if let Some(extras) = &self.extras {
+            keys.push(format!("extras=[{}]", extras.iter().format(", ")));
+        }
+
         if let Some(md5) = &self.md5 {
             keys.push(format!("md5={md5:x}"));
         }
@@ -671,6 +675,16 @@ mod tests {
         assert_eq!(spec, rebuild_spec);
     }
 
+    #[cfg(feature = "experimental_extras")]
+    #[test]
+    fn test_nameless_matchspec_extras_format_eq() {
+        let spec = NamelessMatchSpec::from_str(">=1.0[extras=[bar, baz]]", Strict).unwrap();
+        assert_eq!(spec.to_string(), ">=1.0[extras=[bar, baz]]");
+
+        let rebuild_spec = NamelessMatchSpec::from_str(&spec.to_string(), Strict).unwrap();
+        assert_eq!(spec, rebuild_spec);
+    }
+
     #[test]
     fn test_hash_match() {
         let spec1 = MatchSpec::from_str("tensorflow 2.6.*", Strict).unwrap();
@@ -379,6 +379,19 @@ impl Platform {
             Platform::ZosZ => Some(Arch::Z),
         }
     }
+
+    /// Returns the platforms whose packages can also be run on this platform
+    /// through emulation, in order of preference. For instance, `osx-64`
+    /// packages can run on `osx-arm64` through Rosetta 2 and `win-64`
+    /// packages can run on `win-arm64` through the x64 emulation of Windows.
+    pub const fn emulated_platforms(self) -> &'static [Platform] {
+        match self {
+            Platform::OsxArm64 => &[Platform::Osx64],
+            Platform::WinArm64 => &[Platform::Win64, Platform::Win32],
+            Platform::Win64 => &[Platform::Win32],
+            _ => &[],
+        }
+    }
 }
 
 impl fmt::Display for Platform {
@@ -577,4 +590,15 @@ mod tests {
         assert_eq!(Platform::NoArch.arch(), None);
         assert_eq!(Platform::ZosZ.arch(), Some(Arch::Z));
     }
+
+    #[test]
+    fn test_emulated_platforms() {
+        assert_eq!(Platform::OsxArm64.emulated_platforms(), &[Platform::Osx64]);
+        assert_eq!(
+            Platform::WinArm64.emulated_platforms(),
+            &[Platform::Win64, Platform::Win32]
+        );
+        assert!(Platform::Linux64.emulated_platforms().is_empty());
+        assert!(Platform::NoArch.emulated_platforms().is_empty());
+    }
 }
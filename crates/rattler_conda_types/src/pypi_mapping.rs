@@ -0,0 +1,264 @@
+//! Maps conda packages to the pypi packages they provide.
+//!
+//! Many conda packages repackage a Python distribution from pypi. Knowing
+//! which pypi packages are provided by a conda package is required to
+//! determine whether a pip requirement is already satisfied by a conda
+//! environment.
+//!
+//! Records can specify the pypi packages they provide through their
+//! [`PackageRecord::purls`]. For records without purls a [`PypiNameMapping`],
+//! e.g. the mapping published by grayskull, can be used instead.
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{PackageName, PackageRecord};
+
+/// The name of a package on pypi, normalized as described in
+/// [PEP 503](https://peps.python.org/pep-0503/#normalized-names).
+///
+/// Two names that only differ in case or in the separators that are used
+/// (`-`, `_` and `.`) are considered equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct PypiPackageName(String);
+
+impl PypiPackageName {
+    /// Constructs a new name by normalizing the given name.
+    pub fn new(name: &str) -> Self {
+        let mut normalized = String::with_capacity(name.len());
+        let mut last_was_separator = false;
+        for c in name.trim().chars() {
+            if matches!(c, '-' | '_' | '.') {
+                if !last_was_separator {
+                    normalized.push('-');
+                }
+                last_was_separator = true;
+            } else {
+                normalized.push(c.to_ascii_lowercase());
+                last_was_separator = false;
+            }
+        }
+        Self(normalized)
+    }
+
+    /// Returns the normalized name.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for PypiPackageName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for PypiPackageName {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s))
+    }
+}
+
+impl<'de> Deserialize<'de> for PypiPackageName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Self::new(&name))
+    }
+}
+
+/// A pypi package that is provided by a conda package.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PypiPackage {
+    /// The normalized name of the pypi package.
+    pub name: PypiPackageName,
+
+    /// The version of the pypi package, if known.
+    pub version: Option<String>,
+}
+
+impl PackageRecord {
+    /// Returns the pypi packages that are provided by this package according
+    /// to its [`Self::purls`].
+    ///
+    /// Returns `None` if the record does not specify purls, in which case it
+    /// is unknown whether the package provides any pypi packages. Returns an
+    /// empty list if the package is known to not provide any pypi packages.
+    pub fn pypi_packages(&self) -> Option<Vec<PypiPackage>> {
+        let purls = self.purls.as_ref()?;
+        Some(
+            purls
+                .iter()
+                .filter(|purl| purl.package_type().eq_ignore_ascii_case("pypi"))
+                .map(|purl| PypiPackage {
+                    name: PypiPackageName::new(purl.name()),
+                    version: purl.version().map(ToString::to_string),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A mapping from conda package names to the names of the pypi packages they
+/// provide, used for records that do not specify any purls.
+///
+/// The mapping can be deserialized from a JSON object that maps conda names to
+/// pypi names, which is the format of the mapping published by grayskull:
+///
+/// ```json
+/// { "pyyaml": "pyyaml", "pytorch": "torch", "python": null }
+/// ```
+///
+/// A `null` value indicates that the conda package does not provide a pypi
+/// package.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct PypiNameMapping {
+    conda_to_pypi: HashMap<PackageName, Option<PypiPackageName>>,
+}
+
+impl PypiNameMapping {
+    /// Constructs a new, empty mapping.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a mapping from a JSON string.
+    pub fn from_json_str(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Records that the conda package with the given name provides the given
+    /// pypi package, or no pypi package at all if `pypi_name` is `None`.
+    pub fn insert(&mut self, conda_name: PackageName, pypi_name: Option<PypiPackageName>) {
+        self.conda_to_pypi.insert(conda_name, pypi_name);
+    }
+
+    /// Returns the pypi packages that are provided by the given record.
+    ///
+    /// The purls of the record take precedence over the mapping. If the record
+    /// does not specify purls the mapping is consulted, in which case the
+    /// version of the pypi package is assumed to be equal to the version of
+    /// the conda package.
+    pub fn pypi_packages(&self, record: &PackageRecord) -> Vec<PypiPackage> {
+        if let Some(packages) = record.pypi_packages() {
+            return packages;
+        }
+        match self.conda_to_pypi.get(&record.name) {
+            Some(Some(name)) => vec![PypiPackage {
+                name: name.clone(),
+                version: Some(record.version.as_str().into_owned()),
+            }],
+            Some(None) | None => Vec::new(),
+        }
+    }
+
+    /// Returns true if the given record provides the pypi package with the
+    /// given name.
+    pub fn provides(&self, record: &PackageRecord, pypi_name: &PypiPackageName) -> bool {
+        self.pypi_packages(record)
+            .iter()
+            .any(|package| &package.name == pypi_name)
+    }
+
+    /// Returns the records that provide the pypi package with the given name.
+    pub fn find_providers<'r, R: AsRef<PackageRecord> + 'r>(
+        &self,
+        records: impl IntoIterator<Item = &'r R>,
+        pypi_name: &PypiPackageName,
+    ) -> Vec<&'r R> {
+        records
+            .into_iter()
+            .filter(|record| self.provides(record.as_ref(), pypi_name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::{PypiNameMapping, PypiPackage, PypiPackageName};
+    use crate::{PackageName, PackageRecord, PackageUrl, Version};
+
+    fn record(name: &str, purls: Option<&[&str]>) -> PackageRecord {
+        let mut record = PackageRecord::new(
+            PackageName::from_str(name).unwrap(),
+            Version::from_str("1.2.3").unwrap(),
+            "py_0".into(),
+        );
+        record.purls = purls.map(|purls| {
+            purls
+                .iter()
+                .map(|purl| PackageUrl::from_str(purl).unwrap())
+                .collect()
+        });
+        record
+    }
+
+    #[test]
+    fn test_normalize_pypi_name() {
+        assert_eq!(
+            PypiPackageName::new("Friendly-Bard").as_str(),
+            "friendly-bard"
+        );
+        assert_eq!(
+            PypiPackageName::new("friendly.bard").as_str(),
+            "friendly-bard"
+        );
+        assert_eq!(
+            PypiPackageName::new("FRIENDLY__bard").as_str(),
+            "friendly-bard"
+        );
+        assert_eq!(PypiPackageName::new("ruamel.yaml").as_str(), "ruamel-yaml");
+    }
+
+    #[test]
+    fn test_pypi_packages_from_purls() {
+        let ruamel = record(
+            "ruamel.yaml",
+            Some(&["pkg:pypi/ruamel.yaml@1.2.3", "pkg:generic/foo"]),
+        );
+        assert_eq!(
+            ruamel.pypi_packages(),
+            Some(vec![PypiPackage {
+                name: PypiPackageName::new("ruamel-yaml"),
+                version: Some("1.2.3".to_string()),
+            }])
+        );
+
+        assert_eq!(record("python", Some(&[])).pypi_packages(), Some(vec![]));
+        assert_eq!(record("python", None).pypi_packages(), None);
+    }
+
+    #[test]
+    fn test_pypi_name_mapping() {
+        let mapping =
+            PypiNameMapping::from_json_str(r#"{"pytorch": "torch", "python": null}"#).unwrap();
+
+        let pytorch = record("pytorch", None);
+        let python = record("python", None);
+        let torchvision = record("torchvision", Some(&["pkg:pypi/torchvision@1.2.3"]));
+        let records = [pytorch.clone(), python.clone(), torchvision.clone()];
+
+        assert_eq!(
+            mapping.pypi_packages(&pytorch),
+            vec![PypiPackage {
+                name: PypiPackageName::new("torch"),
+                version: Some("1.2.3".to_string()),
+            }]
+        );
+        assert!(mapping.pypi_packages(&python).is_empty());
+        assert!(mapping.provides(&torchvision, &PypiPackageName::new("TorchVision")));
+
+        let providers = mapping.find_providers(&records, &PypiPackageName::new("torch"));
+        assert_eq!(providers, vec![&pytorch]);
+    }
+}
@@ -170,6 +170,42 @@ pub struct PatchInstructions {
     pub conda_packages: FxHashMap<String, PackageRecordPatch>,
 }
 
+impl PatchInstructions {
+    /// Applies the instructions to a single record with the given file name.
+    /// Returns `false` if the instructions remove the record.
+    ///
+    /// This gives the same result as patching the record as part of a complete
+    /// [`RepoData`] with [`RepoData::apply_patches`].
+    pub fn apply_to_record(&self, file_name: &str, record: &mut PackageRecord) -> bool {
+        let Some((pkg_name, archive_type)) = ArchiveType::split_str(file_name) else {
+            if let Some(patch) = self.packages.get(file_name) {
+                record.apply_patch(patch);
+            }
+            return true;
+        };
+
+        // Patches and removals of `.tar.bz2` archives also apply to the
+        // equivalent `.conda` archive.
+        let tar_bz2_name = format!("{pkg_name}.tar.bz2");
+        if self.remove.contains(&tar_bz2_name)
+            || (archive_type == ArchiveType::Conda && self.remove.contains(file_name))
+        {
+            return false;
+        }
+
+        if let Some(patch) = self.packages.get(&tar_bz2_name) {
+            record.apply_patch(patch);
+        }
+        if archive_type == ArchiveType::Conda {
+            if let Some(patch) = self.conda_packages.get(file_name) {
+                record.apply_patch(patch);
+            }
+        }
+
+        true
+    }
+}
+
 impl PackageRecord {
     /// Apply a patch to a single package record
     pub fn apply_patch(&mut self, patch: &PackageRecordPatch) {
@@ -358,6 +394,34 @@ mod test {
         insta::assert_yaml_snapshot!(repodata);
     }
 
+    #[test]
+    fn test_apply_to_record() {
+        let repodata = load_test_repodata("repodata_from_packages.json");
+        for name in [
+            "patch_instructions.json",
+            "patch_instructions_2.json",
+            "patch_instructions_3.json",
+            "patch_instructions_4.json",
+        ] {
+            let patch_instructions = load_patch_instructions(name);
+            let mut patched = repodata.clone();
+            patched.apply_patches(&patch_instructions);
+
+            for (file_name, record) in repodata.packages.iter().chain(&repodata.conda_packages) {
+                let mut record = record.clone();
+                let expected = patched
+                    .packages
+                    .get(file_name)
+                    .or_else(|| patched.conda_packages.get(file_name));
+                if patch_instructions.apply_to_record(file_name, &mut record) {
+                    assert_eq!(Some(&record), expected, "{name}: {file_name}");
+                } else {
+                    assert_eq!(None, expected, "{name}: {file_name}");
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_patch_tracked_features() {
         // test data
@@ -1,7 +1,7 @@
 use std::path::{Path, PathBuf};
 
 use indexmap::IndexMap;
-use rattler_conda_types::{ChannelConfig, NamedChannelOrUrl};
+use rattler_conda_types::{ChannelConfig, GenericVirtualPackage, NamedChannelOrUrl};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 use url::Url;
@@ -119,6 +119,12 @@ pub struct ConfigBase<T> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub run_post_link_scripts: Option<RunPostLinkScripts>,
 
+    /// Additional virtual packages (e.g. `__slurm=23.02`) that are added to
+    /// the virtual packages detected on the system.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra_virtual_packages: Vec<GenericVirtualPackage>,
+
     #[serde(flatten)]
     pub extensions: T,
 
@@ -154,6 +160,7 @@ where
             proxy_config: ProxyConfig::default(),
             s3_options: S3OptionsMap::default(),
             run_post_link_scripts: None,
+            extra_virtual_packages: Vec::new(),
             extensions: T::default(),
             loaded_from: Vec::new(),
         }
@@ -275,6 +282,19 @@ where
                 .run_post_link_scripts
                 .clone()
                 .or(self.run_post_link_scripts),
+            // Packages from the other configuration replace packages with the same name.
+            extra_virtual_packages: self
+                .extra_virtual_packages
+                .iter()
+                .filter(|package| {
+                    !other
+                        .extra_virtual_packages
+                        .iter()
+                        .any(|other| other.name == package.name)
+                })
+                .chain(&other.extra_virtual_packages)
+                .cloned()
+                .collect(),
             loaded_from: self
                 .loaded_from
                 .iter()
@@ -311,6 +331,7 @@ where
         keys.push("authentication_override_file".to_string());
         keys.push("tls_no_verify".to_string());
         keys.push("mirrors".to_string());
+        keys.push("extra_virtual_packages".to_string());
         keys.push("loaded_from".to_string());
         keys.push("extensions".to_string());
         keys.push("default".to_string());
@@ -110,6 +110,18 @@ where
                     .unwrap_or_default();
                 Ok(())
             }
+            "extra-virtual-packages" => {
+                self.extra_virtual_packages = value
+                    .map(|v| {
+                        serde_json::de::from_str(&v).map_err(|e| ConfigEditError::JsonParseError {
+                            key: key.to_string(),
+                            source: e,
+                        })
+                    })
+                    .transpose()?
+                    .unwrap_or_default();
+                Ok(())
+            }
             "run-post-link-scripts" => {
                 let value = value.ok_or_else(|| ConfigEditError::MissingValue {
                     key: key.to_string(),
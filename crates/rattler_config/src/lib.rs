@@ -272,6 +272,43 @@ mod tests {
         assert!(config.run_post_link_scripts.is_some());
     }
 
+    #[test]
+    fn test_edit_extra_virtual_packages() {
+        let mut config = TestConfig::default();
+
+        config
+            .set(
+                "extra-virtual-packages",
+                Some(r#"["__slurm=23.11", "__internal-network"]"#.to_string()),
+            )
+            .unwrap();
+        assert_eq!(
+            config
+                .extra_virtual_packages
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["__slurm=23.11", "__internal-network=0"]
+        );
+
+        let other = TestConfig {
+            extra_virtual_packages: vec!["__slurm=24.11".parse().unwrap()],
+            ..Default::default()
+        };
+        let merged = config.clone().merge_config(&other).unwrap();
+        assert_eq!(
+            merged
+                .extra_virtual_packages
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["__internal-network=0", "__slurm=24.11"]
+        );
+
+        config.set("extra-virtual-packages", None).unwrap();
+        assert!(config.extra_virtual_packages.is_empty());
+    }
+
     #[test]
     fn test_config_merge() {
         let config1 = TestConfig {
@@ -0,0 +1,307 @@
+//! Computes the differences between two lock-files or two environments.
+
+use std::collections::{BTreeMap, HashMap};
+
+use rattler_conda_types::Platform;
+
+use crate::{Environment, LockFile, LockedPackageRef, PackageHashes};
+
+/// The differences between two lock-files, per environment.
+///
+/// Environments that only exist in one of the lock-files are compared against
+/// an empty environment. Environments without changes are omitted.
+#[derive(Debug, Clone, Default)]
+pub struct LockFileDiff<'lock> {
+    /// The differences per environment name.
+    pub environments: BTreeMap<String, EnvironmentDiff<'lock>>,
+}
+
+/// The differences between two environments, per platform.
+///
+/// Platforms without changes are omitted.
+#[derive(Debug, Clone, Default)]
+pub struct EnvironmentDiff<'lock> {
+    /// The differences per platform.
+    pub platforms: BTreeMap<Platform, PackagesDiff<'lock>>,
+}
+
+/// The differences between the packages locked for a single platform.
+#[derive(Debug, Clone, Default)]
+pub struct PackagesDiff<'lock> {
+    /// Packages that only exist in the new environment.
+    pub added: Vec<LockedPackageRef<'lock>>,
+
+    /// Packages that only exist in the old environment.
+    pub removed: Vec<LockedPackageRef<'lock>>,
+
+    /// Packages that exist in both environments but differ.
+    pub changed: Vec<PackageChange<'lock>>,
+}
+
+/// A package that exists in both environments but differs between them.
+#[derive(Debug, Clone, Copy)]
+pub struct PackageChange<'lock> {
+    /// The package in the old environment.
+    pub before: LockedPackageRef<'lock>,
+
+    /// The package in the new environment.
+    pub after: LockedPackageRef<'lock>,
+}
+
+impl LockFile {
+    /// Computes the differences between this lock-file and `other`. This
+    /// lock-file is considered to be the old one.
+    pub fn diff<'lock>(&'lock self, other: &'lock LockFile) -> LockFileDiff<'lock> {
+        let mut names = self
+            .environments()
+            .map(|(name, _)| name)
+            .chain(other.environments().map(|(name, _)| name))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+
+        let environments = names
+            .into_iter()
+            .map(|name| {
+                let diff =
+                    EnvironmentDiff::between(self.environment(name), other.environment(name));
+                (name.to_string(), diff)
+            })
+            .filter(|(_, diff)| !diff.is_empty())
+            .collect();
+
+        LockFileDiff { environments }
+    }
+}
+
+impl<'lock> Environment<'lock> {
+    /// Computes the differences between this environment and `other`. This
+    /// environment is considered to be the old one.
+    pub fn diff(&self, other: &Environment<'lock>) -> EnvironmentDiff<'lock> {
+        EnvironmentDiff::between(Some(*self), Some(*other))
+    }
+}
+
+impl LockFileDiff<'_> {
+    /// Returns true if the lock-files are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.environments.is_empty()
+    }
+}
+
+impl<'lock> EnvironmentDiff<'lock> {
+    fn between(old: Option<Environment<'lock>>, new: Option<Environment<'lock>>) -> Self {
+        let mut platforms = old
+            .iter()
+            .chain(new.iter())
+            .flat_map(|env| env.platforms().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        platforms.sort_unstable();
+        platforms.dedup();
+
+        let platforms = platforms
+            .into_iter()
+            .map(|platform| {
+                let packages = |env: Option<Environment<'lock>>| {
+                    env.and_then(|env| env.packages(platform).map(Iterator::collect::<Vec<_>>))
+                        .unwrap_or_default()
+                };
+                (
+                    platform,
+                    PackagesDiff::between(packages(old), packages(new)),
+                )
+            })
+            .filter(|(_, diff)| !diff.is_empty())
+            .collect();
+
+        Self { platforms }
+    }
+
+    /// Returns true if the environments are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.platforms.is_empty()
+    }
+}
+
+impl<'lock> PackagesDiff<'lock> {
+    fn between(old: Vec<LockedPackageRef<'lock>>, new: Vec<LockedPackageRef<'lock>>) -> Self {
+        let mut old_by_key = old
+            .into_iter()
+            .map(|package| (PackageKey::from(package), package))
+            .collect::<HashMap<_, _>>();
+
+        let mut diff = Self::default();
+        for package in new {
+            match old_by_key.remove(&PackageKey::from(package)) {
+                None => diff.added.push(package),
+                Some(before) if !same_package(before, package) => {
+                    diff.changed.push(PackageChange {
+                        before,
+                        after: package,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed.extend(old_by_key.into_values());
+
+        diff.added.sort_by_key(|p| PackageKey::from(*p));
+        diff.removed.sort_by_key(|p| PackageKey::from(*p));
+        diff.changed.sort_by_key(|c| PackageKey::from(c.after));
+        diff
+    }
+
+    /// Returns true if there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl<'lock> PackageChange<'lock> {
+    /// Returns the name of the package.
+    pub fn name(&self) -> &'lock str {
+        self.after.name()
+    }
+
+    /// Returns the old and the new version if the version changed.
+    pub fn version_change(&self) -> Option<(String, String)> {
+        let before = package_version(self.before);
+        let after = package_version(self.after);
+        (before != after).then_some((before, after))
+    }
+
+    /// Returns the old and the new hashes if the hashes changed.
+    pub fn hash_change(&self) -> Option<(Option<PackageHashes>, Option<PackageHashes>)> {
+        let before = package_hashes(self.before);
+        let after = package_hashes(self.after);
+        (before != after).then_some((before, after))
+    }
+}
+
+/// Identifies the same package across environments. Conda and pypi packages
+/// with the same name are considered different packages.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum PackageKey {
+    Conda(String),
+    Pypi(String),
+}
+
+impl From<LockedPackageRef<'_>> for PackageKey {
+    fn from(package: LockedPackageRef<'_>) -> Self {
+        match package {
+            LockedPackageRef::Conda(data) => {
+                PackageKey::Conda(data.record().name.as_normalized().to_string())
+            }
+            LockedPackageRef::Pypi(data, _) => PackageKey::Pypi(data.name.to_string()),
+        }
+    }
+}
+
+pub(crate) fn same_package(a: LockedPackageRef<'_>, b: LockedPackageRef<'_>) -> bool {
+    match (a, b) {
+        (LockedPackageRef::Conda(a), LockedPackageRef::Conda(b)) => a == b,
+        (LockedPackageRef::Pypi(a, a_env), LockedPackageRef::Pypi(b, b_env)) => {
+            a == b && a_env.extras == b_env.extras
+        }
+        _ => false,
+    }
+}
+
+fn package_version(package: LockedPackageRef<'_>) -> String {
+    match package {
+        LockedPackageRef::Conda(data) => data.record().version.to_string(),
+        LockedPackageRef::Pypi(data, _) => data.version.to_string(),
+    }
+}
+
+fn package_hashes(package: LockedPackageRef<'_>) -> Option<PackageHashes> {
+    match package {
+        LockedPackageRef::Conda(data) => {
+            PackageHashes::from_hashes(data.record().md5, data.record().sha256)
+        }
+        LockedPackageRef::Pypi(data, _) => data.hash.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rattler_conda_types::Platform;
+
+    use crate::{test_utils::conda_package, LockFile};
+
+    #[test]
+    fn test_diff() {
+        let old = LockFile::builder()
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package("foo", "1.0", Platform::Linux64, &[]).into(),
+            )
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package("bar", "1.0", Platform::Linux64, &[]).into(),
+            )
+            .with_conda_package(
+                "default",
+                Platform::Osx64,
+                conda_package("foo", "1.0", Platform::Osx64, &[]).into(),
+            )
+            .with_conda_package(
+                "test",
+                Platform::Linux64,
+                conda_package("foo", "1.0", Platform::Linux64, &[]).into(),
+            )
+            .finish();
+        let new = LockFile::builder()
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package("foo", "2.0", Platform::Linux64, &[]).into(),
+            )
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package("baz", "1.0", Platform::Linux64, &[]).into(),
+            )
+            .with_conda_package(
+                "default",
+                Platform::Osx64,
+                conda_package("foo", "1.0", Platform::Osx64, &[]).into(),
+            )
+            .with_conda_package(
+                "test",
+                Platform::Linux64,
+                conda_package("foo", "1.0", Platform::Linux64, &[]).into(),
+            )
+            .finish();
+
+        assert!(old.diff(&old).is_empty());
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.environments.keys().collect::<Vec<_>>(), ["default"]);
+
+        let default = &diff.environments["default"];
+        assert_eq!(
+            default.platforms.keys().copied().collect::<Vec<_>>(),
+            [Platform::Linux64]
+        );
+
+        let linux = &default.platforms[&Platform::Linux64];
+        assert_eq!(
+            linux.added.iter().map(|p| p.name()).collect::<Vec<_>>(),
+            ["baz"]
+        );
+        assert_eq!(
+            linux.removed.iter().map(|p| p.name()).collect::<Vec<_>>(),
+            ["bar"]
+        );
+        assert_eq!(linux.changed.len(), 1);
+        assert_eq!(linux.changed[0].name(), "foo");
+        assert_eq!(
+            linux.changed[0].version_change(),
+            Some((String::from("1.0"), String::from("2.0")))
+        );
+        assert_eq!(linux.changed[0].hash_change(), None);
+    }
+}
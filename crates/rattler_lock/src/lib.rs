@@ -80,18 +80,23 @@ use std::{collections::HashMap, io::Read, path::Path, str::FromStr, sync::Arc};
 
 use fxhash::FxHashMap;
 use indexmap::IndexSet;
-use rattler_conda_types::{Platform, RepoDataRecord};
+use rattler_conda_types::{ExplicitEnvironmentSpec, Platform, RepoDataRecord};
 
 mod builder;
 mod channel;
 mod conda;
+mod diff;
 mod file_format_version;
 mod hash;
+mod merge;
 pub mod options;
 mod parse;
 mod pypi;
 mod pypi_indexes;
+mod satisfiability;
 pub mod source;
+#[cfg(test)]
+mod test_utils;
 mod url_or_path;
 mod utils;
 
@@ -101,13 +106,16 @@ pub use conda::{
     CondaBinaryData, CondaPackageData, CondaSourceData, ConversionError, GitShallowSpec, InputHash,
     PackageBuildSource, PackageBuildSourceKind,
 };
+pub use diff::{EnvironmentDiff, LockFileDiff, PackageChange, PackagesDiff};
 pub use file_format_version::FileFormatVersion;
 pub use hash::PackageHashes;
+pub use merge::{LockFileMerge, MergeConflict};
 pub use options::SolveOptions;
-pub use parse::ParseCondaLockError;
+pub use parse::{ParseCondaLockError, RenderCondaLockV1Error};
 pub use pypi::{PypiPackageData, PypiPackageEnvironmentData, PypiSourceTreeHashable};
 pub use pypi_indexes::{FindLinksUrlOrPath, PypiIndexes};
 pub use rattler_conda_types::Matches;
+pub use satisfiability::SatisfiabilityError;
 pub use url_or_path::UrlOrPath;
 
 /// The name of the default environment in a [`LockFile`]. This is the
@@ -422,6 +430,21 @@ impl<'lock> Environment<'lock> {
             .transpose()
     }
 
+    /// Returns the conda packages for a specific platform as an explicit
+    /// environment (`@EXPLICIT`) file that can be installed with plain conda.
+    /// Returns `None` if the platform is not defined for this environment.
+    ///
+    /// Only binary conda packages can be represented in an explicit
+    /// environment, source and pypi packages are omitted.
+    pub fn explicit_environment_spec(
+        &self,
+        platform: Platform,
+    ) -> Result<Option<ExplicitEnvironmentSpec>, ConversionError> {
+        Ok(self
+            .conda_repodata_records(platform)?
+            .map(|records| ExplicitEnvironmentSpec::from_records(Some(platform), &records)))
+    }
+
     /// Returns all the pypi packages and their associated environment data for
     /// the specified platform. Returns `None` if the platform is not
     /// defined for this environment.
@@ -476,7 +499,7 @@ impl OwnedEnvironment {
 }
 
 /// Data related to a single locked package in an [`Environment`].
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub enum LockedPackageRef<'lock> {
     /// A conda package
     Conda(&'lock CondaPackageData),
@@ -680,6 +703,37 @@ mod test {
         assert!(!conda_lock.is_empty());
     }
 
+    #[test]
+    fn test_explicit_environment_spec() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock")
+            .join("v6/python-from-conda-only-lock.yml");
+        let conda_lock = LockFile::from_path(&path).unwrap();
+        let environment = conda_lock.default_environment().unwrap();
+
+        let spec = environment
+            .explicit_environment_spec(Platform::OsxArm64)
+            .unwrap()
+            .unwrap();
+        assert_eq!(spec.platform, Some(Platform::OsxArm64));
+        assert_eq!(
+            spec.packages.len(),
+            environment
+                .conda_packages(Platform::OsxArm64)
+                .unwrap()
+                .count()
+        );
+        assert!(spec
+            .packages
+            .iter()
+            .all(|entry| entry.package_archive_hash().unwrap().is_some()));
+
+        assert!(environment
+            .explicit_environment_spec(Platform::Win64)
+            .unwrap()
+            .is_none());
+    }
+
     #[test]
     fn solve_roundtrip() {
         // load repodata from JSON
@@ -0,0 +1,293 @@
+//! A structural three-way merge of lock-files.
+//!
+//! When two branches modify the same lock-file, a textual merge often results
+//! in conflicts even though the changes are independent. This module merges
+//! the lock-files package by package instead, so only genuine conflicts have
+//! to be resolved, e.g. by re-locking the affected packages.
+
+use std::collections::{BTreeSet, HashMap};
+
+use rattler_conda_types::Platform;
+
+use crate::{
+    builder::LockedPackage,
+    diff::{same_package, PackageKey},
+    Environment, LockFile, LockFileBuilder,
+};
+
+/// The result of a three-way merge of lock-files.
+#[derive(Debug, Clone)]
+pub struct LockFileMerge {
+    /// The merged lock-file. For conflicting changes the state of "ours" is
+    /// used.
+    pub lock_file: LockFile,
+
+    /// The changes that could not be merged automatically.
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl LockFileMerge {
+    /// Returns true if the lock-files could be merged without conflicts.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// A change that could not be merged automatically because both sides changed
+/// the same thing in different ways.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum MergeConflict {
+    /// Both sides changed the channels, pypi indexes or solve options of an
+    /// environment.
+    Metadata {
+        /// The name of the environment.
+        environment: String,
+    },
+
+    /// Both sides changed the same package.
+    Package {
+        /// The name of the environment.
+        environment: String,
+
+        /// The platform for which the package is locked.
+        platform: Platform,
+
+        /// The name of the package.
+        name: String,
+
+        /// The package in the common ancestor, if any.
+        base: Option<LockedPackage>,
+
+        /// The package in our lock-file, if any.
+        ours: Option<LockedPackage>,
+
+        /// The package in their lock-file, if any.
+        theirs: Option<LockedPackage>,
+    },
+}
+
+impl LockFile {
+    /// Performs a three-way merge of two lock-files, `ours` and `theirs`,
+    /// that both derive from the common ancestor `base`.
+    ///
+    /// Packages are matched by name per environment and platform. A package
+    /// that was only changed on one side takes the state of that side, a
+    /// package that was changed on both sides in different ways is reported
+    /// as a conflict.
+    pub fn three_way_merge(base: &LockFile, ours: &LockFile, theirs: &LockFile) -> LockFileMerge {
+        let names = [base, ours, theirs]
+            .iter()
+            .flat_map(|lock_file| lock_file.environments().map(|(name, _)| name))
+            .collect::<BTreeSet<_>>();
+
+        let mut builder = LockFile::builder();
+        let mut conflicts = Vec::new();
+        for name in names {
+            merge_environment(
+                name,
+                [
+                    base.environment(name),
+                    ours.environment(name),
+                    theirs.environment(name),
+                ],
+                &mut builder,
+                &mut conflicts,
+            );
+        }
+
+        LockFileMerge {
+            lock_file: builder.finish(),
+            conflicts,
+        }
+    }
+}
+
+fn merge_environment(
+    name: &str,
+    environments: [Option<Environment<'_>>; 3],
+    builder: &mut LockFileBuilder,
+    conflicts: &mut Vec<MergeConflict>,
+) {
+    let [base, ours, theirs] = environments.each_ref().map(|env| {
+        env.as_ref()
+            .map(|env| (env.channels(), env.pypi_indexes(), env.solve_options()))
+    });
+    let metadata = merge3(base, ours, theirs, |a, b| a == b).unwrap_or_else(|| {
+        conflicts.push(MergeConflict::Metadata {
+            environment: name.to_string(),
+        });
+        ours
+    });
+    if let Some((channels, indexes, options)) = metadata {
+        builder.set_channels(name, channels.iter().cloned());
+        if let Some(indexes) = indexes {
+            builder.set_pypi_indexes(name, indexes.clone());
+        }
+        builder.set_options(name, options.clone());
+    }
+
+    let platforms = environments
+        .iter()
+        .flatten()
+        .flat_map(Environment::platforms)
+        .collect::<BTreeSet<_>>();
+    for platform in platforms {
+        let [base, ours, theirs] = environments.map(|env| {
+            env.and_then(|env| env.packages(platform).map(Iterator::collect::<Vec<_>>))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|package| (PackageKey::from(package), package))
+                .collect::<HashMap<_, _>>()
+        });
+
+        let keys = base
+            .keys()
+            .chain(ours.keys())
+            .chain(theirs.keys())
+            .collect::<BTreeSet<_>>();
+        for key in keys {
+            let (base, ours, theirs) = (
+                base.get(key).copied(),
+                ours.get(key).copied(),
+                theirs.get(key).copied(),
+            );
+            let package = merge3(base, ours, theirs, same_package).unwrap_or_else(|| {
+                let package = ours
+                    .or(theirs)
+                    .or(base)
+                    .expect("at least one side has the package");
+                conflicts.push(MergeConflict::Package {
+                    environment: name.to_string(),
+                    platform,
+                    name: package.name().to_string(),
+                    base: base.map(LockedPackage::from),
+                    ours: ours.map(LockedPackage::from),
+                    theirs: theirs.map(LockedPackage::from),
+                });
+                ours
+            });
+            if let Some(package) = package {
+                builder.add_package(name, platform, package.into());
+            }
+        }
+    }
+}
+
+/// Merges a single value. Returns `None` if both sides changed the value in
+/// different ways.
+#[allow(clippy::option_option)]
+fn merge3<T: Copy>(
+    base: Option<T>,
+    ours: Option<T>,
+    theirs: Option<T>,
+    eq: impl Fn(T, T) -> bool,
+) -> Option<Option<T>> {
+    let same = |a: Option<T>, b: Option<T>| match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => eq(a, b),
+        _ => false,
+    };
+
+    if same(ours, theirs) || same(base, theirs) {
+        Some(ours)
+    } else if same(base, ours) {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rattler_conda_types::Platform;
+
+    use super::MergeConflict;
+    use crate::{test_utils::conda_package, Channel, LockFile};
+
+    fn lock_file(channels: &[&str], packages: &[(&str, &str)]) -> LockFile {
+        packages
+            .iter()
+            .fold(
+                LockFile::builder()
+                    .with_channels("default", channels.iter().copied().map(Channel::from)),
+                |builder, (name, version)| {
+                    builder.with_conda_package(
+                        "default",
+                        Platform::Linux64,
+                        conda_package(name, version, Platform::Linux64, &[]).into(),
+                    )
+                },
+            )
+            .finish()
+    }
+
+    fn versions(lock_file: &LockFile) -> Vec<(String, String)> {
+        let mut versions = lock_file
+            .default_environment()
+            .unwrap()
+            .conda_packages(Platform::Linux64)
+            .unwrap()
+            .map(|p| {
+                (
+                    p.record().name.as_normalized().to_string(),
+                    p.record().version.to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        versions.sort();
+        versions
+    }
+
+    #[test]
+    fn test_merge_without_conflicts() {
+        let base = lock_file(
+            &["conda-forge"],
+            &[("foo", "1"), ("bar", "1"), ("qux", "1")],
+        );
+        let ours = lock_file(
+            &["conda-forge"],
+            &[("foo", "2"), ("bar", "1"), ("qux", "1")],
+        );
+        let theirs = lock_file(
+            &["conda-forge", "bioconda"],
+            &[("foo", "1"), ("bar", "1"), ("baz", "1")],
+        );
+
+        let merge = LockFile::three_way_merge(&base, &ours, &theirs);
+        assert!(merge.is_clean());
+        assert_eq!(
+            versions(&merge.lock_file),
+            [
+                (String::from("bar"), String::from("1")),
+                (String::from("baz"), String::from("1")),
+                (String::from("foo"), String::from("2")),
+            ]
+        );
+        assert_eq!(
+            merge.lock_file.default_environment().unwrap().channels(),
+            [Channel::from("conda-forge"), Channel::from("bioconda")]
+        );
+    }
+
+    #[test]
+    fn test_merge_with_conflicts() {
+        let base = lock_file(&["conda-forge"], &[("foo", "1"), ("bar", "1")]);
+        let ours = lock_file(&["conda-forge"], &[("foo", "2"), ("bar", "2")]);
+        let theirs = lock_file(&["conda-forge"], &[("foo", "3"), ("bar", "2")]);
+
+        let merge = LockFile::three_way_merge(&base, &ours, &theirs);
+        assert_eq!(merge.conflicts.len(), 1);
+        assert!(matches!(
+            &merge.conflicts[0],
+            MergeConflict::Package { name, platform: Platform::Linux64, .. } if name == "foo"
+        ));
+        assert_eq!(
+            versions(&merge.lock_file),
+            [
+                (String::from("bar"), String::from("2")),
+                (String::from("foo"), String::from("2")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! A module that enables writing environments in the conda-lock v1 format.
+//!
+//! Reading conda-lock v1 files is handled by the version 3 parser, this module only
+//! implements the other direction so that lock-files created by rattler can be
+//! consumed by `conda-lock` and tools that understand its format.
+
+use std::collections::BTreeMap;
+
+use pep508_rs::VersionOrUrl;
+use rattler_conda_types::{MatchSpec, ParseStrictness, Platform};
+use serde::Serialize;
+use url::Url;
+
+use crate::{Channel, Environment, LockedPackageRef, PackageHashes, UrlOrPath};
+
+/// An error that can occur when rendering an environment as a conda-lock v1
+/// file.
+#[derive(Debug, thiserror::Error)]
+pub enum RenderCondaLockV1Error {
+    /// Source packages cannot be represented in a conda-lock v1 file.
+    #[error("source package '{0}' cannot be represented in a conda-lock v1 file")]
+    SourcePackage(UrlOrPath),
+
+    /// conda-lock requires a hash for every conda package.
+    #[error("conda package '{0}' does not have a md5 or sha256 hash")]
+    MissingHash(UrlOrPath),
+
+    /// The location of a package cannot be converted to a URL
+    #[error(transparent)]
+    LocationToUrlConversionError(#[from] file_url::FileURLParseError),
+
+    /// Failed to serialize the lock-file.
+    #[error(transparent)]
+    SerializeError(#[from] serde_yaml::Error),
+}
+
+#[derive(Serialize)]
+struct CondaLockV1<'a> {
+    version: u64,
+    metadata: MetadataV1<'a>,
+    package: Vec<PackageV1<'a>>,
+}
+
+#[derive(Serialize)]
+struct MetadataV1<'a> {
+    content_hash: BTreeMap<&'static str, &'static str>,
+    channels: &'a [Channel],
+    platforms: Vec<Platform>,
+    sources: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ManagerV1 {
+    Conda,
+    Pip,
+}
+
+#[derive(Serialize)]
+struct PackageV1<'a> {
+    name: &'a str,
+    version: String,
+    manager: ManagerV1,
+    platform: Platform,
+    dependencies: BTreeMap<String, String>,
+    url: Url,
+    hash: Option<PackageHashes>,
+    category: &'static str,
+    optional: bool,
+}
+
+impl Environment<'_> {
+    /// Renders this environment as a conda-lock v1 (`conda-lock.yml`) file.
+    ///
+    /// conda-lock has no notion of source packages or local paths, so
+    /// environments that contain source packages cannot be exported. Conda
+    /// packages are also required to have a hash.
+    ///
+    /// Metadata that conda-lock uses to check if the lock-file is up to date
+    /// (e.g. the `content_hash` and `sources`) is not known to rattler and is
+    /// left empty.
+    pub fn render_conda_lock_v1(&self) -> Result<String, RenderCondaLockV1Error> {
+        let mut platforms = self.platforms().collect::<Vec<_>>();
+        platforms.sort();
+
+        let mut packages = Vec::new();
+        for (platform, platform_packages) in self.packages_by_platform() {
+            for package in platform_packages {
+                packages.push(render_package(platform, package)?);
+            }
+        }
+        packages.sort_by(|a, b| {
+            a.platform
+                .as_str()
+                .cmp(b.platform.as_str())
+                .then_with(|| a.name.cmp(b.name))
+        });
+
+        let lock_file = CondaLockV1 {
+            version: 1,
+            metadata: MetadataV1 {
+                content_hash: platforms.iter().map(|p| (p.as_str(), "")).collect(),
+                channels: self.channels(),
+                platforms,
+                sources: Vec::new(),
+            },
+            package: packages,
+        };
+
+        Ok(serde_yaml::to_string(&lock_file)?)
+    }
+}
+
+fn render_package(
+    platform: Platform,
+    package: LockedPackageRef<'_>,
+) -> Result<PackageV1<'_>, RenderCondaLockV1Error> {
+    if let Some((data, _)) = package.as_pypi() {
+        let dependencies = data
+            .requires_dist
+            .iter()
+            .map(|req| {
+                let spec = match &req.version_or_url {
+                    Some(VersionOrUrl::VersionSpecifier(spec)) if !spec.is_empty() => {
+                        spec.to_string()
+                    }
+                    _ => String::from("*"),
+                };
+                (req.name.to_string(), spec)
+            })
+            .collect();
+
+        return Ok(PackageV1 {
+            name: data.name.as_ref(),
+            version: data.version.to_string(),
+            manager: ManagerV1::Pip,
+            platform,
+            dependencies,
+            url: data.location.try_into_url()?,
+            hash: data.hash.clone(),
+            category: "main",
+            optional: false,
+        });
+    }
+
+    let Some(data) = package.as_binary_conda() else {
+        return Err(RenderCondaLockV1Error::SourcePackage(
+            package.location().clone(),
+        ));
+    };
+
+    let record = &data.package_record;
+    let hash = PackageHashes::from_hashes(record.md5, record.sha256)
+        .ok_or_else(|| RenderCondaLockV1Error::MissingHash(data.location.clone()))?;
+
+    // conda-lock stores dependencies as a mapping from the package name to the
+    // rest of the spec.
+    let dependencies = record
+        .depends
+        .iter()
+        .map(|dep| {
+            match MatchSpec::from_str(dep, ParseStrictness::Lenient).map(MatchSpec::into_nameless) {
+                Ok((Some(name), spec)) => (name.as_normalized().to_string(), spec.to_string()),
+                _ => match dep.trim().split_once(' ') {
+                    Some((name, spec)) => (name.to_string(), spec.trim().to_string()),
+                    None => (dep.trim().to_string(), String::from("*")),
+                },
+            }
+        })
+        .collect();
+
+    Ok(PackageV1 {
+        name: record.name.as_normalized(),
+        version: record.version.to_string(),
+        manager: ManagerV1::Conda,
+        platform,
+        dependencies,
+        url: data.location.try_into_url()?,
+        hash: Some(hash),
+        category: "main",
+        optional: false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, str::FromStr};
+
+    use crate::LockFile;
+
+    #[test]
+    fn test_conda_lock_v1_roundtrip() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock/v1/pip-conda-lock.yml");
+        let lock_file = LockFile::from_path(&path).unwrap();
+        let environment = lock_file.default_environment().unwrap();
+
+        let rendered = environment.render_conda_lock_v1().unwrap();
+        let reparsed = LockFile::from_str(&rendered).unwrap();
+        let reparsed_environment = reparsed.default_environment().unwrap();
+
+        let package_urls = |env: &crate::Environment<'_>| {
+            let mut urls = env
+                .packages_by_platform()
+                .flat_map(|(platform, packages)| {
+                    packages.map(move |p| (platform, p.location().to_string()))
+                })
+                .collect::<Vec<_>>();
+            urls.sort();
+            urls
+        };
+
+        assert_eq!(
+            package_urls(&environment),
+            package_urls(&reparsed_environment)
+        );
+        assert_eq!(environment.channels(), reparsed_environment.channels());
+
+        let pypi_requirements = |env: &crate::Environment<'_>| {
+            let mut requirements = env
+                .pypi_packages(rattler_conda_types::Platform::Linux64)
+                .unwrap()
+                .map(|(data, _)| (data.name.to_string(), data.requires_dist.clone()))
+                .collect::<Vec<_>>();
+            requirements.sort_by(|a, b| a.0.cmp(&b.0));
+            requirements
+        };
+        assert_eq!(
+            pypi_requirements(&environment),
+            pypi_requirements(&reparsed_environment)
+        );
+    }
+}
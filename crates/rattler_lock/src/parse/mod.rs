@@ -1,3 +1,4 @@
+mod conda_lock_v1;
 mod deserialize;
 mod models;
 mod serialize;
@@ -10,6 +11,8 @@ use serde::de::Error;
 use serde_yaml::Value;
 use v3::parse_v3_or_lower;
 
+pub use conda_lock_v1::RenderCondaLockV1Error;
+
 use super::{LockFile, UrlOrPath};
 use crate::{file_format_version::FileFormatVersion, parse::deserialize::parse_from_document_v5};
 
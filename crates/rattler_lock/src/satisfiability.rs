@@ -0,0 +1,284 @@
+//! Verifies whether the packages locked for an environment still satisfy a set
+//! of requested specs without solving.
+
+use std::collections::{HashSet, VecDeque};
+
+use rattler_conda_types::{MatchSpec, ParseStrictness, Platform};
+
+use crate::{Channel, CondaPackageData, Environment, Matches};
+
+/// The reason why a locked environment does not satisfy the requested input.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SatisfiabilityError {
+    /// The environment does not contain packages for the platform.
+    #[error("the environment is not locked for {0}")]
+    MissingPlatform(Platform),
+
+    /// The channels of the environment differ from the requested channels.
+    #[error("the channels of the environment have changed")]
+    ChannelsMismatch {
+        /// The channels that are stored in the lock-file.
+        locked: Vec<Channel>,
+
+        /// The channels that were requested.
+        requested: Vec<Channel>,
+    },
+
+    /// None of the locked packages satisfies a requested spec.
+    #[error("no locked package satisfies '{0}'")]
+    UnsatisfiedSpec(Box<MatchSpec>),
+
+    /// A dependency of a locked package is not satisfied by any other locked
+    /// package.
+    #[error("the dependency '{dependency}' of '{package}' is not satisfied by any locked package")]
+    UnsatisfiedDependency {
+        /// The name of the package that has the dependency.
+        package: String,
+
+        /// The dependency that is not satisfied.
+        dependency: String,
+    },
+
+    /// A locked package violates a constraint of another locked package.
+    #[error("'{package}' does not satisfy the constraint '{constraint}' of '{constrained_by}'")]
+    ConstraintViolated {
+        /// The name of the package that violates the constraint.
+        package: String,
+
+        /// The constraint that is violated.
+        constraint: String,
+
+        /// The name of the package that imposes the constraint.
+        constrained_by: String,
+    },
+
+    /// A dependency or constraint of a locked package could not be parsed.
+    #[error("failed to parse '{dependency}' of '{package}'")]
+    InvalidDependency {
+        /// The name of the package.
+        package: String,
+
+        /// The dependency that could not be parsed.
+        dependency: String,
+    },
+
+    /// A locked package is not required by any of the requested specs, e.g.
+    /// because a spec was removed.
+    #[error("'{0}' is locked but not required by any of the requested specs")]
+    SuperfluousPackage(String),
+}
+
+impl Environment<'_> {
+    /// Verifies that the conda packages locked for `platform` are still
+    /// consistent with the requested `specs` and `channels` without solving.
+    ///
+    /// This checks that:
+    ///
+    /// * the channels, including their order, did not change,
+    /// * every spec is satisfied by a locked package,
+    /// * the dependencies and constraints of all reachable packages are
+    ///   satisfied by the locked packages, and
+    /// * every locked package is reachable from the specs.
+    ///
+    /// If this returns an error the environment has to be locked again.
+    /// Dependencies on virtual packages are not verified and pypi packages are
+    /// ignored.
+    pub fn satisfies(
+        &self,
+        specs: &[MatchSpec],
+        channels: &[Channel],
+        platform: Platform,
+    ) -> Result<(), SatisfiabilityError> {
+        if self.channels() != channels {
+            return Err(SatisfiabilityError::ChannelsMismatch {
+                locked: self.channels().to_vec(),
+                requested: channels.to_vec(),
+            });
+        }
+
+        let packages = self
+            .conda_packages(platform)
+            .ok_or(SatisfiabilityError::MissingPlatform(platform))?
+            .collect::<Vec<_>>();
+
+        let find_package = |spec: &MatchSpec| packages.iter().position(|p| p.matches(spec));
+
+        // Walk the dependency graph starting from the requested specs.
+        let mut reachable = HashSet::new();
+        let mut queue = VecDeque::new();
+        for spec in specs {
+            let idx = find_package(spec)
+                .ok_or_else(|| SatisfiabilityError::UnsatisfiedSpec(Box::new(spec.clone())))?;
+            if reachable.insert(idx) {
+                queue.push_back(idx);
+            }
+        }
+
+        while let Some(idx) = queue.pop_front() {
+            let record = packages[idx].record();
+            for dependency in &record.depends {
+                let spec = parse_dependency(packages[idx], dependency)?;
+                if is_virtual(&spec) {
+                    continue;
+                }
+                let dep_idx = find_package(&spec).ok_or_else(|| {
+                    SatisfiabilityError::UnsatisfiedDependency {
+                        package: record.name.as_source().to_string(),
+                        dependency: dependency.clone(),
+                    }
+                })?;
+                if reachable.insert(dep_idx) {
+                    queue.push_back(dep_idx);
+                }
+            }
+        }
+
+        // Verify that the constraints of the packages are not violated.
+        for package in &packages {
+            let record = package.record();
+            for constraint in &record.constrains {
+                let spec = parse_dependency(package, constraint)?;
+                let Some(name) = &spec.name else {
+                    continue;
+                };
+                if let Some(violating) = packages
+                    .iter()
+                    .find(|p| &p.record().name == name && !p.matches(&spec))
+                {
+                    return Err(SatisfiabilityError::ConstraintViolated {
+                        package: violating.record().name.as_source().to_string(),
+                        constraint: constraint.clone(),
+                        constrained_by: record.name.as_source().to_string(),
+                    });
+                }
+            }
+        }
+
+        if let Some((_, package)) = packages
+            .iter()
+            .enumerate()
+            .find(|(idx, _)| !reachable.contains(idx))
+        {
+            return Err(SatisfiabilityError::SuperfluousPackage(
+                package.record().name.as_source().to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_dependency(
+    package: &CondaPackageData,
+    dependency: &str,
+) -> Result<MatchSpec, SatisfiabilityError> {
+    MatchSpec::from_str(dependency, ParseStrictness::Lenient).map_err(|_err| {
+        SatisfiabilityError::InvalidDependency {
+            package: package.record().name.as_source().to_string(),
+            dependency: dependency.to_string(),
+        }
+    })
+}
+
+/// Virtual packages are provided by the system and are therefore never locked.
+fn is_virtual(spec: &MatchSpec) -> bool {
+    spec.name
+        .as_ref()
+        .is_some_and(|name| name.as_normalized().starts_with("__"))
+}
+
+#[cfg(test)]
+mod test {
+    use rattler_conda_types::{MatchSpec, ParseStrictness, Platform};
+
+    use super::SatisfiabilityError;
+    use crate::{test_utils::conda_package, Channel, LockFile};
+
+    fn specs(specs: &[&str]) -> Vec<MatchSpec> {
+        specs
+            .iter()
+            .map(|s| MatchSpec::from_str(s, ParseStrictness::Strict).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_satisfies() {
+        let channels = vec![Channel::from("https://prefix.dev/example")];
+        let lock_file = LockFile::builder()
+            .with_channels("default", channels.clone())
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package(
+                    "foo",
+                    "1.0",
+                    Platform::Linux64,
+                    &["bar >=2", "__glibc >=2.17"],
+                )
+                .into(),
+            )
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package("bar", "2.1", Platform::Linux64, &[]).into(),
+            )
+            .finish();
+        let env = lock_file.default_environment().unwrap();
+
+        assert_eq!(
+            env.satisfies(&specs(&["foo >=1"]), &channels, Platform::Linux64),
+            Ok(())
+        );
+        assert_eq!(
+            env.satisfies(&specs(&["foo", "bar 2.*"]), &channels, Platform::Linux64),
+            Ok(())
+        );
+        assert_eq!(
+            env.satisfies(&specs(&["foo >=2"]), &channels, Platform::Linux64),
+            Err(SatisfiabilityError::UnsatisfiedSpec(Box::new(
+                specs(&["foo >=2"]).remove(0)
+            )))
+        );
+        assert_eq!(
+            env.satisfies(&specs(&["bar"]), &channels, Platform::Linux64),
+            Err(SatisfiabilityError::SuperfluousPackage("foo".to_string()))
+        );
+        assert_eq!(
+            env.satisfies(&specs(&["foo"]), &channels, Platform::Osx64),
+            Err(SatisfiabilityError::MissingPlatform(Platform::Osx64))
+        );
+        assert!(matches!(
+            env.satisfies(
+                &specs(&["foo"]),
+                &[Channel::from("conda-forge")],
+                Platform::Linux64
+            ),
+            Err(SatisfiabilityError::ChannelsMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_satisfies_unsatisfied_dependency() {
+        let lock_file = LockFile::builder()
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package("foo", "1.0", Platform::Linux64, &["bar >=3"]).into(),
+            )
+            .with_conda_package(
+                "default",
+                Platform::Linux64,
+                conda_package("bar", "2.1", Platform::Linux64, &[]).into(),
+            )
+            .finish();
+        let env = lock_file.default_environment().unwrap();
+
+        assert_eq!(
+            env.satisfies(&specs(&["foo"]), &[], Platform::Linux64),
+            Err(SatisfiabilityError::UnsatisfiedDependency {
+                package: "foo".to_string(),
+                dependency: "bar >=3".to_string(),
+            })
+        );
+    }
+}
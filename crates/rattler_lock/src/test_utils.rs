@@ -0,0 +1,35 @@
+use std::str::FromStr;
+
+use rattler_conda_types::{PackageName, PackageRecord, Platform, Version};
+use url::Url;
+
+use crate::CondaBinaryData;
+
+/// Creates a conda package with the given name, version and dependencies that
+/// is located in a fake channel.
+pub(crate) fn conda_package(
+    name: &str,
+    version: &str,
+    platform: Platform,
+    depends: &[&str],
+) -> CondaBinaryData {
+    let file_name = format!("{name}-{version}-build.tar.bz2");
+    CondaBinaryData {
+        package_record: PackageRecord {
+            subdir: platform.to_string(),
+            depends: depends.iter().map(ToString::to_string).collect(),
+            ..PackageRecord::new(
+                PackageName::new_unchecked(name),
+                Version::from_str(version).unwrap(),
+                "build".into(),
+            )
+        },
+        location: Url::parse(&format!(
+            "https://prefix.dev/example/{platform}/{file_name}"
+        ))
+        .unwrap()
+        .into(),
+        file_name,
+        channel: None,
+    }
+}
@@ -2,7 +2,7 @@ use fxhash::FxBuildHasher;
 use indexmap::IndexMap;
 use pep508_rs::{Requirement, VersionOrUrl};
 use serde::{Deserialize, Deserializer};
-use serde_with::{serde_as, DeserializeAs, DisplayFromStr};
+use serde_with::DeserializeAs;
 
 pub(crate) struct Pep440MapOrVec;
 
@@ -11,15 +11,11 @@ impl<'de> DeserializeAs<'de, Vec<Requirement>> for Pep440MapOrVec {
     where
         D: Deserializer<'de>,
     {
-        #[serde_as]
         #[derive(Deserialize)]
         #[serde(untagged)]
         enum MapOrVec {
             Vec(Vec<Requirement>),
-            Map(
-                #[serde_as(as = "IndexMap<_, DisplayFromStr, FxBuildHasher>")]
-                IndexMap<String, pep440_rs::VersionSpecifiers, FxBuildHasher>,
-            ),
+            Map(IndexMap<String, String, FxBuildHasher>),
         }
 
         Ok(match MapOrVec::deserialize(deserializer)? {
@@ -27,8 +23,15 @@ impl<'de> DeserializeAs<'de, Vec<Requirement>> for Pep440MapOrVec {
             MapOrVec::Map(m) => m
                 .into_iter()
                 .map(|(name, spec)| {
-                    Ok::<_, pep508_rs::InvalidNameError>(pep508_rs::Requirement {
-                        name: pep508_rs::PackageName::new(name)?,
+                    // conda-lock uses `*` for dependencies without a version constraint.
+                    let spec = match spec.trim() {
+                        "*" => "",
+                        spec => spec,
+                    }
+                    .parse::<pep440_rs::VersionSpecifiers>()
+                    .map_err(|e| e.to_string())?;
+                    Ok::<_, String>(pep508_rs::Requirement {
+                        name: pep508_rs::PackageName::new(name).map_err(|e| e.to_string())?,
                         extras: Vec::new(),
                         version_or_url: if spec.is_empty() {
                             None
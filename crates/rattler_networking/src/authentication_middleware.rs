@@ -3,16 +3,30 @@ use crate::authentication_storage::AuthenticationStorageError;
 use crate::{Authentication, AuthenticationStorage};
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
-use reqwest::{Request, Response};
+use reqwest::{Request, Response, StatusCode};
 use reqwest_middleware::{Middleware, Next};
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 use url::Url;
 
 /// `reqwest` middleware to authenticate requests
 #[derive(Clone)]
 pub struct AuthenticationMiddleware {
     auth_storage: AuthenticationStorage,
+    token_refresher: Option<Arc<dyn TokenRefresher>>,
+}
+
+/// Obtains a new bearer token when a server rejects the stored one, e.g.
+/// because a short-lived OIDC token expired.
+///
+/// See [`AuthenticationMiddleware::with_token_refresher`].
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+pub trait TokenRefresher: Send + Sync {
+    /// Returns a new token to replace `expired_token`, which was rejected by
+    /// the server when requesting `url`. Returns `None` if no new token can be
+    /// obtained, in which case the original response is returned.
+    async fn refresh(&self, url: &Url, expired_token: &str) -> anyhow::Result<Option<String>>;
 }
 
 #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
@@ -30,18 +44,47 @@ impl Middleware for AuthenticationMiddleware {
         }
 
         let url = req.url().clone();
-        match self.auth_storage.get_by_url(url) {
+        match self.auth_storage.get_by_url_with_key(url) {
             Err(_) => {
                 // Forward error to caller (invalid URL)
                 next.run(req, extensions).await
             }
-            Ok((url, auth)) => {
+            Ok((url, credentials)) => {
+                let (key, auth) = credentials.unzip();
                 let url = Self::authenticate_url(url, &auth);
 
                 let mut req = req;
                 *req.url_mut() = url;
 
+                // Keep a copy of the request in case the token has to be refreshed.
+                let retry_req = match (&self.token_refresher, &auth) {
+                    (Some(_), Some(Authentication::BearerToken(_))) => req.try_clone(),
+                    _ => None,
+                };
+
                 let req = Self::authenticate_request(req, &auth).await?;
+                let response = next.clone().run(req, extensions).await?;
+                if response.status() != StatusCode::UNAUTHORIZED {
+                    return Ok(response);
+                }
+
+                let (
+                    Some(retry_req),
+                    Some(refresher),
+                    Some(key),
+                    Some(Authentication::BearerToken(expired_token)),
+                ) = (retry_req, &self.token_refresher, key, &auth)
+                else {
+                    return Ok(response);
+                };
+                let Some(refreshed) = self
+                    .refresh_token(refresher.as_ref(), &key, retry_req.url(), expired_token)
+                    .await
+                else {
+                    return Ok(response);
+                };
+
+                let req = Self::authenticate_request(retry_req, &Some(refreshed)).await?;
                 next.run(req, extensions).await
             }
         }
@@ -51,14 +94,60 @@ impl Middleware for AuthenticationMiddleware {
 impl AuthenticationMiddleware {
     /// Create a new authentication middleware with the given authentication storage
     pub fn from_auth_storage(auth_storage: AuthenticationStorage) -> Self {
-        Self { auth_storage }
+        Self {
+            auth_storage,
+            token_refresher: None,
+        }
     }
 
     /// Create a new authentication middleware with the default authentication storage
     pub fn from_env_and_defaults() -> Result<Self, AuthenticationStorageError> {
-        Ok(Self {
-            auth_storage: AuthenticationStorage::from_env_and_defaults()?,
-        })
+        Ok(Self::from_auth_storage(
+            AuthenticationStorage::from_env_and_defaults()?,
+        ))
+    }
+
+    /// Sets a hook that is invoked when a request that was authenticated with
+    /// a bearer token is rejected with `401 Unauthorized`. The new token is
+    /// stored in the authentication storage and the request is retried once.
+    #[must_use]
+    pub fn with_token_refresher(self, token_refresher: Arc<dyn TokenRefresher>) -> Self {
+        Self {
+            token_refresher: Some(token_refresher),
+            ..self
+        }
+    }
+
+    /// Returns a new token for the credentials stored under `key`.
+    async fn refresh_token(
+        &self,
+        refresher: &dyn TokenRefresher,
+        key: &str,
+        url: &Url,
+        expired_token: &str,
+    ) -> Option<Authentication> {
+        // Another request might have refreshed the token in the meantime.
+        if let Ok(Some(Authentication::BearerToken(token))) = self.auth_storage.get(key) {
+            if token != expired_token {
+                return Some(Authentication::BearerToken(token));
+            }
+        }
+
+        match refresher.refresh(url, expired_token).await {
+            Ok(Some(token)) => {
+                tracing::debug!("refreshed the bearer token for {key}");
+                let auth = Authentication::BearerToken(token);
+                if let Err(e) = self.auth_storage.store(key, &auth) {
+                    tracing::warn!("failed to store the refreshed token for {key}: {e}");
+                }
+                Some(auth)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("failed to refresh the bearer token for {key}: {e:#}");
+                None
+            }
+        }
     }
 
     /// Authenticate the given URL with the given authentication information
@@ -113,7 +202,9 @@ impl AuthenticationMiddleware {
                         .insert(reqwest::header::AUTHORIZATION, header_value);
                     Ok(req)
                 }
-                Authentication::CondaToken(_) | Authentication::S3Credentials { .. } => Ok(req),
+                Authentication::CondaToken(_)
+                | Authentication::S3Credentials { .. }
+                | Authentication::AzureSasToken(_) => Ok(req),
             }
         } else {
             Ok(req)
@@ -428,6 +519,117 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_path_scoped_credentials() -> anyhow::Result<()> {
+        let tdir = tempdir()?;
+        let mut storage = AuthenticationStorage::empty();
+        storage.add_backend(Arc::from(FileStorage::from_path(
+            tdir.path().to_path_buf().join("auth.json"),
+        )?));
+
+        let host_auth = Authentication::BearerToken("host".to_string());
+        let team_auth = Authentication::BearerToken("team".to_string());
+        let team_a_auth = Authentication::BearerToken("team-a".to_string());
+        storage.store("*.internal.corp", &host_auth)?;
+        storage.store("*.internal.corp/channels/team-*", &team_auth)?;
+        storage.store("conda.internal.corp/channels/team-a", &team_a_auth)?;
+
+        for (url, expected) in [
+            (
+                "https://conda.internal.corp/channels/team-a/noarch/repodata.json",
+                &team_a_auth,
+            ),
+            (
+                "https://conda.internal.corp/channels/team-b/noarch/repodata.json",
+                &team_auth,
+            ),
+            (
+                "https://mirror.internal.corp/channels/team-a/noarch/repodata.json",
+                &team_auth,
+            ),
+            (
+                "https://conda.internal.corp/channels/public/noarch/repodata.json",
+                &host_auth,
+            ),
+        ] {
+            assert_eq!(storage.get_by_url(url)?.1.as_ref(), Some(expected), "{url}");
+        }
+
+        // Deleting the most specific credentials falls back to the next match.
+        storage.delete("conda.internal.corp/channels/team-a")?;
+        assert_eq!(
+            storage
+                .get_by_url("https://conda.internal.corp/channels/team-a/noarch/repodata.json")?
+                .1,
+            Some(team_auth)
+        );
+
+        Ok(())
+    }
+
+    struct CountingRefresher {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl TokenRefresher for CountingRefresher {
+        async fn refresh(&self, _url: &Url, expired_token: &str) -> anyhow::Result<Option<String>> {
+            assert_eq!(expired_token, "stale");
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Some("fresh".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_expired_bearer_token() -> anyhow::Result<()> {
+        use axum::{http::HeaderMap, http::StatusCode, routing::get, Router};
+
+        use crate::test_utils::spawn_test_server;
+
+        async fn handler(headers: HeaderMap) -> StatusCode {
+            match headers.get(reqwest::header::AUTHORIZATION) {
+                Some(value) if value == "Bearer fresh" => StatusCode::OK,
+                _ => StatusCode::UNAUTHORIZED,
+            }
+        }
+
+        let server = spawn_test_server(Router::new().route("/repodata.json", get(handler))).await;
+
+        let mut storage = AuthenticationStorage::empty();
+        storage.add_backend(Arc::new(
+            crate::authentication_storage::backends::memory::MemoryStorage::default(),
+        ));
+        storage.store(
+            "127.0.0.1",
+            &Authentication::BearerToken("stale".to_string()),
+        )?;
+
+        let refresher = Arc::new(CountingRefresher {
+            calls: std::sync::atomic::AtomicUsize::new(0),
+        });
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::default())
+            .with(
+                AuthenticationMiddleware::from_auth_storage(storage.clone())
+                    .with_token_refresher(refresher.clone()),
+            )
+            .build();
+
+        let url = server.join("repodata.json")?;
+        for _ in 0..2 {
+            let response = client.get(url.clone()).send().await?;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+        }
+
+        // The token is only refreshed once and stored for later requests.
+        assert_eq!(refresher.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(
+            storage.get("127.0.0.1")?,
+            Some(Authentication::BearerToken("fresh".to_string()))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_rattler_auth_file_env_var_handling() -> anyhow::Result<()> {
         let tdir = tempdir()?;
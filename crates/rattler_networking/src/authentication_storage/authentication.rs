@@ -28,6 +28,9 @@ pub enum Authentication {
         /// The session token to use for S3 authentication
         session_token: Option<String>,
     },
+    /// An Azure shared access signature (SAS) token that is appended to the
+    /// query of requests to Azure Blob Storage
+    AzureSasToken(String),
 }
 
 /// An error that can occur when parsing an authentication string
@@ -56,6 +59,7 @@ impl Authentication {
             Authentication::BasicHTTP { .. } => "BasicHTTP",
             Authentication::CondaToken(_) => "CondaToken",
             Authentication::S3Credentials { .. } => "S3",
+            Authentication::AzureSasToken(_) => "AzureSasToken",
         }
     }
 }
@@ -8,7 +8,7 @@ use std::{
 };
 
 use crate::{
-    authentication_storage::{AuthenticationStorageError, StorageBackend},
+    authentication_storage::{path_scope, AuthenticationStorageError, StorageBackend},
     Authentication,
 };
 
@@ -151,6 +151,16 @@ impl StorageBackend for FileStorage {
             Ok(())
         }
     }
+
+    fn path_scoped_keys(&self) -> Result<Vec<String>, AuthenticationStorageError> {
+        let cache = self.cache.read().unwrap();
+        Ok(cache
+            .content
+            .keys()
+            .filter(|key| path_scope::is_path_scoped(key))
+            .cloned()
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -4,10 +4,14 @@ use keyring::Entry;
 use std::str::FromStr;
 
 use crate::{
-    authentication_storage::{AuthenticationStorageError, StorageBackend},
+    authentication_storage::{path_scope, AuthenticationStorageError, StorageBackend},
     Authentication,
 };
 
+/// The keyring cannot be enumerated, so the keys that are scoped to a path are
+/// recorded in a separate entry.
+const PATH_SCOPED_KEYS_ENTRY: &str = "__path_scoped_keys__";
+
 #[derive(Clone, Debug)]
 /// A storage backend that stores credentials in the operating system's keyring
 pub struct KeyringAuthenticationStorage {
@@ -43,6 +47,51 @@ pub enum KeyringAuthenticationStorageError {
         /// The host for which the credentials could not be parsed
         host: String,
     },
+
+    /// An error occurred when locking the list of path scoped keys
+    #[error("Could not lock the list of path scoped credentials")]
+    LockError(#[from] std::io::Error),
+}
+
+impl KeyringAuthenticationStorage {
+    /// Acquires a lock that serializes the updates of the
+    /// [`PATH_SCOPED_KEYS_ENTRY`] across threads and processes, because the
+    /// keyring cannot update an entry atomically. The lock is released when
+    /// the returned file is dropped.
+    fn lock_path_scoped_keys(&self) -> Result<fs_err::File, KeyringAuthenticationStorageError> {
+        let path = std::env::temp_dir().join(format!("{}-path-scoped-keys.lock", self.store_key));
+        let file = fs_err::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        fs4::fs_std::FileExt::lock_exclusive(file.file())?;
+        Ok(file)
+    }
+
+    fn read_path_scoped_keys(&self) -> Result<Vec<String>, KeyringAuthenticationStorageError> {
+        let entry = Entry::new(&self.store_key, PATH_SCOPED_KEYS_ENTRY)?;
+        match entry.get_password() {
+            Ok(keys) => Ok(serde_json::from_str(&keys)?),
+            Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_path_scoped_keys(
+        &self,
+        keys: &[String],
+    ) -> Result<(), KeyringAuthenticationStorageError> {
+        let entry = Entry::new(&self.store_key, PATH_SCOPED_KEYS_ENTRY)?;
+        if keys.is_empty() {
+            match entry.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(e.into()),
+            }
+        } else {
+            Ok(entry.set_password(&serde_json::to_string(keys)?)?)
+        }
+    }
 }
 
 impl Default for KeyringAuthenticationStorage {
@@ -66,6 +115,15 @@ impl StorageBackend for KeyringAuthenticationStorage {
             .set_password(&password)
             .map_err(KeyringAuthenticationStorageError::from)?;
 
+        if path_scope::is_path_scoped(host) {
+            let _lock = self.lock_path_scoped_keys()?;
+            let mut keys = self.read_path_scoped_keys()?;
+            if !keys.iter().any(|key| key == host) {
+                keys.push(host.to_string());
+                self.write_path_scoped_keys(&keys)?;
+            }
+        }
+
         Ok(())
     }
 
@@ -99,6 +157,17 @@ impl StorageBackend for KeyringAuthenticationStorage {
             .delete_credential()
             .map_err(KeyringAuthenticationStorageError::from)?;
 
+        if path_scope::is_path_scoped(host) {
+            let _lock = self.lock_path_scoped_keys()?;
+            let mut keys = self.read_path_scoped_keys()?;
+            keys.retain(|key| key != host);
+            self.write_path_scoped_keys(&keys)?;
+        }
+
         Ok(())
     }
+
+    fn path_scoped_keys(&self) -> Result<Vec<String>, AuthenticationStorageError> {
+        Ok(self.read_path_scoped_keys()?)
+    }
 }
@@ -2,7 +2,7 @@
 use std::{collections::HashMap, sync::Mutex};
 
 use crate::{
-    authentication_storage::{AuthenticationStorageError, StorageBackend},
+    authentication_storage::{path_scope, AuthenticationStorageError, StorageBackend},
     Authentication,
 };
 
@@ -66,6 +66,18 @@ impl StorageBackend for MemoryStorage {
         store.remove(host);
         Ok(())
     }
+
+    fn path_scoped_keys(&self) -> Result<Vec<String>, AuthenticationStorageError> {
+        let store = self
+            .store
+            .lock()
+            .map_err(|_err| MemoryStorageError::LockError)?;
+        Ok(store
+            .keys()
+            .filter(|key| path_scope::is_path_scoped(key))
+            .cloned()
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -8,7 +8,7 @@ use netrc_rs::{Machine, Netrc};
 use std::{collections::HashMap, env, io::ErrorKind, path::Path, path::PathBuf};
 
 /// A struct that implements storage and access of authentication
-/// information backed by a on-disk JSON file
+/// information backed by a `.netrc` file
 #[derive(Debug, Clone, Default)]
 pub struct NetRcStorage {
     /// The netrc file contents
@@ -34,7 +34,7 @@ pub enum NetRcStorageError {
 impl NetRcStorage {
     /// Create a new fallback storage by retrieving the netrc file from the user environment.
     /// This uses the same environment variable as curl and will read the file from $NETRC
-    /// falling back to `~/.netrc`.
+    /// falling back to `~/.netrc`. On Windows `~/_netrc` is used if `~/.netrc` does not exist.
     ///
     /// If reading the file fails or parsing the file fails, this will return an error. However,
     /// if the file does not exist an empty storage will be returned.
@@ -42,16 +42,10 @@ impl NetRcStorage {
     /// When an error is returned the path to the file that the was read from is returned as well.
     pub fn from_env() -> Result<Self, (PathBuf, NetRcStorageError)> {
         // Get the path to the netrc file
-        let path = match env::var("NETRC") {
-            Ok(val) => PathBuf::from(val),
-            Err(_) => match dirs::home_dir() {
-                Some(mut path) => {
-                    #[cfg(windows)]
-                    path.push("_netrc");
-                    #[cfg(not(windows))]
-                    path.push(".netrc");
-                    path
-                }
+        let path = match env::var_os("NETRC") {
+            Some(val) if !val.is_empty() => PathBuf::from(val),
+            _ => match dirs::home_dir() {
+                Some(home) => Self::default_path(&home),
                 None => PathBuf::from(".netrc"),
             },
         };
@@ -65,23 +59,41 @@ impl NetRcStorage {
         }
     }
 
+    /// Returns the location of the netrc file in the given home directory. Like curl, on Windows
+    /// `_netrc` is used when there is no `.netrc` file.
+    fn default_path(home: &Path) -> PathBuf {
+        let path = home.join(".netrc");
+        #[cfg(windows)]
+        if !path.is_file() {
+            return home.join("_netrc");
+        }
+        path
+    }
+
     /// Constructs a new [`NetRcStorage`] by reading the `.netrc` file at the given path. Returns
     /// an error if reading from the file failed or if parsing the file failed.
     pub fn from_path(path: &Path) -> Result<Self, NetRcStorageError> {
         let content = std::fs::read_to_string(path)?;
+        Self::from_content(content)
+    }
+
+    /// Constructs a new [`NetRcStorage`] from the contents of a `.netrc` file.
+    ///
+    /// Host names are matched case-insensitively. The `default` entry is ignored because its
+    /// credentials would otherwise be sent to every channel host.
+    pub fn from_content(content: String) -> Result<Self, NetRcStorageError> {
         let netrc = Netrc::parse(content, false).map_err(NetRcStorageError::ParseError)?;
         let machines = netrc
             .machines
             .into_iter()
-            .map(|m| (m.name.clone(), m))
-            .filter_map(|(name, value)| name.map(|n| (n, value)))
+            .filter_map(|m| Some((m.name.as_deref()?.to_ascii_lowercase(), m)))
             .collect();
         Ok(Self { machines })
     }
 
     /// Retrieve the authentication information for the given host
     pub fn get_password(&self, host: &str) -> Result<Option<Authentication>, NetRcStorageError> {
-        match self.machines.get(host) {
+        match self.machines.get(&host.to_ascii_lowercase()) {
             Some(machine) => Ok(Some(Authentication::BasicHTTP {
                 username: machine.login.clone().unwrap_or_default(),
                 password: machine.password.clone().unwrap_or_default(),
@@ -146,6 +158,25 @@ mod tests {
         assert_eq!(storage.get("test_unknown").unwrap(), None);
     }
 
+    #[test]
+    fn test_machine_names_are_case_insensitive() {
+        let storage = NetRcStorage::from_content(
+            "machine Repo.Prefix.dev login user password secret\ndefault login anonymous password x\n"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            storage.get("repo.prefix.dev").unwrap(),
+            Some(Authentication::BasicHTTP {
+                username: "user".to_string(),
+                password: "secret".to_string(),
+            })
+        );
+
+        // The default entry is never used for other hosts.
+        assert_eq!(storage.get("conda.anaconda.org").unwrap(), None);
+    }
+
     #[test]
     fn test_file_storage_from_env() {
         let file = tempdir().unwrap();
@@ -1,8 +1,11 @@
 //! This module contains the authentication storage backend trait and implementations
+use std::{fmt, str::FromStr};
+
 use self::authentication::Authentication;
 
 pub mod authentication;
 pub mod backends;
+pub(crate) mod path_scope;
 pub mod storage;
 
 /// An error occurred when accessing the authentication storage
@@ -24,6 +27,69 @@ pub enum AuthenticationStorageError {
     /// An error occurred when accessing the memory storage
     #[error("MemoryStorageError")]
     MemoryStorageError(#[from] crate::authentication_storage::backends::memory::MemoryStorageError),
+    /// An unknown storage backend was requested
+    #[error("unknown authentication storage backend '{0}', expected one of 'file', 'keyring' or 'netrc'")]
+    UnknownBackend(String),
+    /// A storage backend was requested that is not available in this build
+    #[error("the '{0}' authentication storage backend is not available in this build")]
+    BackendNotAvailable(StorageBackendKind),
+}
+
+/// The kinds of storage backends that can be selected when constructing an
+/// [`storage::AuthenticationStorage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageBackendKind {
+    /// A JSON file in the user's data directory
+    /// (see [`backends::file::FileStorage`]).
+    File,
+    /// The operating system's keyring: the macOS Keychain, the Windows
+    /// Credential Manager or the Secret Service on Linux
+    /// (see [`backends::keyring::KeyringAuthenticationStorage`]).
+    Keyring,
+    /// The user's `.netrc` file (see [`backends::netrc::NetRcStorage`]).
+    Netrc,
+}
+
+impl StorageBackendKind {
+    /// The backends that are used by default, in order of precedence.
+    pub const DEFAULT: [StorageBackendKind; 3] = [
+        StorageBackendKind::Keyring,
+        StorageBackendKind::File,
+        StorageBackendKind::Netrc,
+    ];
+
+    /// Parses a comma separated list of backends, e.g. `keyring,file`.
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, AuthenticationStorageError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .map(Self::from_str)
+            .collect()
+    }
+}
+
+impl FromStr for StorageBackendKind {
+    type Err = AuthenticationStorageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "file" => Ok(Self::File),
+            "keyring" => Ok(Self::Keyring),
+            "netrc" => Ok(Self::Netrc),
+            _ => Err(AuthenticationStorageError::UnknownBackend(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for StorageBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File => write!(f, "file"),
+            Self::Keyring => write!(f, "keyring"),
+            Self::Netrc => write!(f, "netrc"),
+        }
+    }
 }
 
 /// A trait that defines the interface for authentication storage backends
@@ -40,4 +106,33 @@ pub trait StorageBackend: std::fmt::Debug {
 
     /// Delete the authentication information for the given host
     fn delete(&self, host: &str) -> Result<(), AuthenticationStorageError>;
+
+    /// Returns the stored keys that are scoped to a URL path, e.g.
+    /// `*.internal.corp/channels/team-*`. These keys cannot be looked up by
+    /// host, so backends that can store them must be able to list them.
+    fn path_scoped_keys(&self) -> Result<Vec<String>, AuthenticationStorageError> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backend_list() {
+        assert_eq!(
+            StorageBackendKind::parse_list("keyring, File,netrc").unwrap(),
+            vec![
+                StorageBackendKind::Keyring,
+                StorageBackendKind::File,
+                StorageBackendKind::Netrc
+            ]
+        );
+        assert_eq!(StorageBackendKind::parse_list("").unwrap(), vec![]);
+        assert!(matches!(
+            StorageBackendKind::parse_list("keyring,vault"),
+            Err(AuthenticationStorageError::UnknownBackend(kind)) if kind == "vault"
+        ));
+    }
 }
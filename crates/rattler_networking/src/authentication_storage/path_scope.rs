@@ -0,0 +1,186 @@
+//! Matching of credentials that are scoped to a URL path.
+//!
+//! Besides plain hosts (`repo.prefix.dev`) and wildcard hosts
+//! (`*.prefix.dev`), credentials can be stored for a host followed by a path
+//! prefix, e.g. `*.internal.corp/channels/team-*`. Every path segment of such
+//! a key must match the corresponding segment of the URL, a `*` in a segment
+//! matches any sequence of characters within that segment.
+
+use url::Url;
+
+/// Returns true if the key scopes credentials to a path, as opposed to a plain
+/// host or a full URL (like the `s3://` keys).
+pub(crate) fn is_path_scoped(key: &str) -> bool {
+    !key.contains("://") && PathScopedKey::parse(key).is_some()
+}
+
+/// A parsed key of the form `<host>/<segment>/<segment>...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PathScopedKey<'a> {
+    host: &'a str,
+    segments: Vec<&'a str>,
+}
+
+/// How specific a matching [`PathScopedKey`] is. Keys that compare greater are
+/// more specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Specificity {
+    /// The number of path segments in the key.
+    segments: usize,
+    /// Whether the host is matched exactly instead of by a wildcard.
+    exact_host: bool,
+    /// The number of labels in the host, e.g. 3 for `*.internal.corp`.
+    host_labels: usize,
+    /// The number of characters in the path that are not wildcards.
+    literal_chars: usize,
+}
+
+impl<'a> PathScopedKey<'a> {
+    /// Parses a key, returns `None` if the key is not scoped to a path.
+    pub fn parse(key: &'a str) -> Option<Self> {
+        let (host, path) = key.split_once('/')?;
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        if host.is_empty() || segments.is_empty() {
+            return None;
+        }
+        Some(Self { host, segments })
+    }
+
+    /// Returns how specific this key is if it matches the given URL.
+    pub fn matches(&self, url: &Url) -> Option<Specificity> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let pattern_host = self.host.to_ascii_lowercase();
+        let exact_host = match pattern_host.strip_prefix("*.") {
+            // Consistent with host keys, `*.prefix.dev` also matches
+            // `prefix.dev` itself.
+            Some(domain) if host == domain || host.ends_with(&format!(".{domain}")) => false,
+            None if host == pattern_host => true,
+            _ => return None,
+        };
+
+        let mut url_segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+        for pattern in &self.segments {
+            if !glob_matches(pattern, url_segments.next()?) {
+                return None;
+            }
+        }
+
+        Some(Specificity {
+            segments: self.segments.len(),
+            exact_host,
+            host_labels: self.host.split('.').count(),
+            literal_chars: self
+                .segments
+                .iter()
+                .map(|segment| segment.chars().filter(|c| *c != '*').count())
+                .sum(),
+        })
+    }
+}
+
+/// Returns the most specific of the given keys that matches the URL. Ties are
+/// broken by the lexicographically smallest key so the result is
+/// deterministic.
+pub(crate) fn most_specific_match<'k>(
+    keys: impl IntoIterator<Item = &'k str>,
+    url: &Url,
+) -> Option<&'k str> {
+    keys.into_iter()
+        .filter_map(|key| Some((PathScopedKey::parse(key)?.matches(url)?, key)))
+        .max_by(|(a, a_key), (b, b_key)| a.cmp(b).then_with(|| b_key.cmp(a_key)))
+        .map(|(_, key)| key)
+}
+
+/// Matches a single path segment against a pattern in which `*` matches any
+/// sequence of characters.
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(first) = parts.next() else {
+        return true;
+    };
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // The pattern contains no wildcard.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("team-*", "team-a"));
+        assert!(glob_matches("team-*", "team-"));
+        assert!(!glob_matches("team-*", "teams"));
+        assert!(glob_matches("*", "anything"));
+        assert!(glob_matches("a*b*c", "aXXbYYc"));
+        assert!(!glob_matches("a*b*c", "aXXcYYb"));
+        assert!(glob_matches("exact", "exact"));
+        assert!(!glob_matches("exact", "exactly"));
+    }
+
+    #[test]
+    fn test_is_path_scoped() {
+        assert!(is_path_scoped("*.internal.corp/channels/team-*"));
+        assert!(is_path_scoped("repo.prefix.dev/my-channel"));
+        assert!(!is_path_scoped("repo.prefix.dev"));
+        assert!(!is_path_scoped("repo.prefix.dev/"));
+        assert!(!is_path_scoped("s3://bucket/channel"));
+    }
+
+    #[test]
+    fn test_most_specific_match() {
+        let keys = [
+            "*.internal.corp/channels",
+            "*.internal.corp/channels/team-*",
+            "conda.internal.corp/channels",
+            "conda.internal.corp/channels/team-*",
+            "*.corp/channels/team-*",
+            "conda.internal.corp/channels/team-a",
+            "other.corp/channels/team-a",
+        ];
+        let best = |url: &str| most_specific_match(keys, &Url::parse(url).unwrap());
+
+        assert_eq!(
+            best("https://conda.internal.corp/channels/team-a/noarch/repodata.json"),
+            Some("conda.internal.corp/channels/team-a")
+        );
+        assert_eq!(
+            best("https://conda.internal.corp/channels/team-b/noarch/repodata.json"),
+            Some("conda.internal.corp/channels/team-*")
+        );
+        assert_eq!(
+            best("https://mirror.internal.corp/channels/team-b/noarch/repodata.json"),
+            Some("*.internal.corp/channels/team-*")
+        );
+        assert_eq!(
+            best("https://mirror.internal.corp/channels/public/noarch/repodata.json"),
+            Some("*.internal.corp/channels")
+        );
+        assert_eq!(
+            best("https://internal.corp/channels/team-b/repodata.json"),
+            Some("*.internal.corp/channels/team-*")
+        );
+        assert_eq!(
+            best("https://conda.internal.corp/other/repodata.json"),
+            None
+        );
+        assert_eq!(best("https://notinternal.corp/channels/x"), None);
+    }
+}
@@ -10,7 +10,7 @@ use url::Url;
 
 use crate::authentication_storage::{backends::file::FileStorage, AuthenticationStorageError};
 
-use super::{authentication::Authentication, StorageBackend};
+use super::{authentication::Authentication, path_scope, StorageBackend, StorageBackendKind};
 
 #[cfg(feature = "netrc-rs")]
 use super::backends::netrc::NetRcStorage;
@@ -30,6 +30,7 @@ pub struct AuthenticationStorage {
     /// Authentication backends
     pub backends: Vec<Arc<dyn StorageBackend + Send + Sync>>,
     cache: Arc<Mutex<HashMap<String, Option<Authentication>>>>,
+    path_scoped_keys: Arc<Mutex<Option<Vec<String>>>>,
 }
 
 impl AuthenticationStorage {
@@ -38,15 +39,22 @@ impl AuthenticationStorage {
         Self {
             backends: vec![],
             cache: Arc::new(Mutex::new(HashMap::new())),
+            path_scoped_keys: Arc::default(),
         }
     }
 
     /// Create a new authentication storage with the default backends
     /// Following order:
     /// - file storage from `RATTLER_AUTH_FILE` (if set)
-    /// - keyring storage
+    /// - keyring storage (the macOS Keychain, the Windows Credential Manager
+    ///   or the Secret Service on Linux)
     /// - file storage from the default location
     /// - netrc storage
+    ///
+    /// The backends after the `RATTLER_AUTH_FILE` storage can be selected and
+    /// reordered by setting `RATTLER_AUTH_BACKENDS` to a comma separated list
+    /// of backends, e.g. `RATTLER_AUTH_BACKENDS=file,keyring`. Backends that
+    /// are not available in this build are skipped.
     pub fn from_env_and_defaults() -> Result<Self, AuthenticationStorageError> {
         let mut storage = Self::empty();
 
@@ -58,25 +66,65 @@ impl AuthenticationStorage {
             );
             storage.add_backend(Arc::from(FileStorage::from_path(path.into())?));
         }
-        #[cfg(feature = "keyring")]
-        storage.add_backend(Arc::from(KeyringAuthenticationStorage::default()));
-        #[cfg(feature = "dirs")]
-        storage.add_backend(Arc::from(FileStorage::new()?));
-        #[cfg(feature = "netrc-rs")]
-        storage.add_backend(Arc::from(NetRcStorage::from_env().unwrap_or_else(
-            |(path, err)| {
-                tracing::warn!("error reading netrc file from {}: {}", path.display(), err);
-                NetRcStorage::default()
-            },
-        )));
+
+        let kinds = match std::env::var("RATTLER_AUTH_BACKENDS") {
+            Ok(kinds) => StorageBackendKind::parse_list(&kinds)?,
+            Err(_) => StorageBackendKind::DEFAULT.to_vec(),
+        };
+        for kind in kinds {
+            match Self::backend(kind) {
+                Ok(backend) => storage.add_backend(backend),
+                Err(AuthenticationStorageError::BackendNotAvailable(kind)) => {
+                    tracing::debug!("skipping the unavailable '{kind}' authentication backend");
+                }
+                Err(e) => return Err(e),
+            }
+        }
 
         Ok(storage)
     }
 
+    /// Create a new authentication storage with the given backends. Backends
+    /// are tried in the order they are given.
+    ///
+    /// Returns an error if one of the backends is not available in this build.
+    pub fn from_backends(
+        kinds: impl IntoIterator<Item = StorageBackendKind>,
+    ) -> Result<Self, AuthenticationStorageError> {
+        let mut storage = Self::empty();
+        for kind in kinds {
+            storage.add_backend(Self::backend(kind)?);
+        }
+        Ok(storage)
+    }
+
+    /// Constructs the backend of the given kind with its default
+    /// configuration.
+    fn backend(
+        kind: StorageBackendKind,
+    ) -> Result<Arc<dyn StorageBackend + Send + Sync>, AuthenticationStorageError> {
+        match kind {
+            #[cfg(feature = "keyring")]
+            StorageBackendKind::Keyring => Ok(Arc::from(KeyringAuthenticationStorage::default())),
+            #[cfg(feature = "dirs")]
+            StorageBackendKind::File => Ok(Arc::from(FileStorage::new()?)),
+            #[cfg(feature = "netrc-rs")]
+            StorageBackendKind::Netrc => Ok(Arc::from(NetRcStorage::from_env().unwrap_or_else(
+                |(path, err)| {
+                    tracing::warn!("error reading netrc file from {}: {}", path.display(), err);
+                    NetRcStorage::default()
+                },
+            ))),
+            #[allow(unreachable_patterns)]
+            kind => Err(AuthenticationStorageError::BackendNotAvailable(kind)),
+        }
+    }
+
     /// Add a new storage backend to the authentication storage
     /// (backends are tried in the order they are added)
     pub fn add_backend(&mut self, backend: Arc<dyn StorageBackend + Send + Sync>) {
         self.backends.push(backend);
+        self.path_scoped_keys.lock().unwrap().take();
     }
 
     /// Store the given authentication information for the given host
@@ -85,6 +133,7 @@ impl AuthenticationStorage {
             let mut cache = self.cache.lock().unwrap();
             cache.insert(host.to_string(), Some(authentication.clone()));
         }
+        self.path_scoped_keys.lock().unwrap().take();
 
         for backend in &self.backends {
             #[allow(unused_variables)]
@@ -143,6 +192,26 @@ impl AuthenticationStorage {
         Ok(None)
     }
 
+    /// Returns the keys of all backends that are scoped to a URL path.
+    fn path_scoped_keys(&self) -> Vec<String> {
+        let mut cached = self.path_scoped_keys.lock().unwrap();
+        if let Some(keys) = cached.as_ref() {
+            return keys.clone();
+        }
+
+        let mut keys = Vec::new();
+        for backend in &self.backends {
+            match backend.path_scoped_keys() {
+                Ok(backend_keys) => keys.extend(backend_keys),
+                Err(e) => tracing::debug!("Error listing path scoped credentials: {}", e),
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        *cached = Some(keys.clone());
+        keys
+    }
+
     /// Retrieve the authentication information for the given URL
     /// (including the authentication information for the wildcard
     /// host if no credentials are found for the given host)
@@ -150,19 +219,49 @@ impl AuthenticationStorage {
     /// E.g. if credentials are stored for `*.prefix.dev` and the
     /// given URL is `https://repo.prefix.dev`, the credentials
     /// for `*.prefix.dev` will be returned.
+    ///
+    /// Credentials can also be scoped to a path by storing them for a host
+    /// followed by a path, e.g. `*.internal.corp/channels/team-*`, where a `*`
+    /// matches any characters within a path segment. If several keys match
+    /// the URL, the most specific one is used: the key with the most path
+    /// segments, then an exact host over a wildcard host, then the host with
+    /// the most labels and finally the key with the fewest wildcards. Path
+    /// scoped credentials take precedence over credentials for the host.
     pub fn get_by_url<U: IntoUrl>(
         &self,
         url: U,
     ) -> Result<(Url, Option<Authentication>), reqwest::Error> {
+        let (url, credentials) = self.get_by_url_with_key(url)?;
+        Ok((url, credentials.map(|(_, credentials)| credentials)))
+    }
+
+    /// Like [`Self::get_by_url`] but also returns the key under which the
+    /// authentication information is stored.
+    pub fn get_by_url_with_key<U: IntoUrl>(
+        &self,
+        url: U,
+    ) -> Result<(Url, Option<(String, Authentication)>), reqwest::Error> {
         let url = url.into_url()?;
         let Some(host) = url.host_str() else {
             return Ok((url, None));
         };
 
+        let path_scoped_keys = self.path_scoped_keys();
+        if let Some(key) =
+            path_scope::most_specific_match(path_scoped_keys.iter().map(String::as_str), &url)
+        {
+            if let Ok(Some(credentials)) = self.get(key) {
+                return Ok((url, Some((key.to_string(), credentials))));
+            }
+        }
+
         match self.get(host) {
             Ok(None) => {}
             Err(_) => return Ok((url, None)),
-            Ok(Some(credentials)) => return Ok((url, Some(credentials))),
+            Ok(Some(credentials)) => {
+                let host = host.to_string();
+                return Ok((url, Some((host, credentials))));
+            }
         };
 
         // S3 protocol URLs need to be treated separately since they follow a different schema
@@ -185,7 +284,9 @@ impl AuthenticationStorage {
                             _ => return Ok((url, None)), // No more subpaths to check
                         }
                     }
-                    Ok(Some(credentials)) => return Ok((url, Some(credentials))),
+                    Ok(Some(credentials)) => {
+                        return Ok((url, Some((current_url.to_string(), credentials))))
+                    }
                     Err(_) => return Ok((url, None)),
                 }
             }
@@ -204,7 +305,7 @@ impl AuthenticationStorage {
             };
 
             if let Some(credentials) = credentials {
-                return Ok((url, Some(credentials)));
+                return Ok((url, Some((wildcard_host, credentials))));
             }
 
             let possible_rest = domain.split_once('.').map(|(_, rest)| rest);
@@ -224,6 +325,7 @@ impl AuthenticationStorage {
             let mut cache = self.cache.lock().unwrap();
             cache.insert(host.to_string(), None);
         }
+        self.path_scoped_keys.lock().unwrap().take();
 
         let mut all_failed = true;
 
@@ -0,0 +1,381 @@
+//! Middleware to handle `az://` URLs to pull artifacts from Azure Blob Storage
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next, Result as MiddlewareResult};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{Authentication, AuthenticationStorage};
+
+/// The resource for which managed identity tokens are requested.
+const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+
+/// The version of the Blob Storage REST API to use. Bearer token
+/// authentication requires at least `2017-11-09`.
+const STORAGE_API_VERSION: &str = "2020-04-08";
+
+/// The Azure Instance Metadata Service endpoint for managed identity tokens.
+const IMDS_TOKEN_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Tokens are refreshed this long before they expire.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// How long to wait before requesting a managed identity token again after
+/// the first failed request. The delay doubles with every further failure up
+/// to [`MAX_MANAGED_IDENTITY_BACKOFF`].
+const MIN_MANAGED_IDENTITY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The maximum delay between two managed identity token requests.
+const MAX_MANAGED_IDENTITY_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Azure Blob Storage middleware to authenticate requests.
+///
+/// URLs of the form `az://<account>/<container>/<path>` are redirected to
+/// `https://<account>.blob.core.windows.net/<container>/<path>`. Requests are
+/// authenticated with, in order of preference:
+///
+/// 1. a SAS token stored for the account in the [`AuthenticationStorage`],
+/// 2. a SAS token from the `AZURE_STORAGE_SAS_TOKEN` environment variable,
+/// 3. a managed identity token (App Service or the Instance Metadata Service).
+///
+/// If none of these are available the request is sent anonymously, which
+/// works for containers that allow public access.
+#[derive(Clone, Debug)]
+pub struct AzureMiddleware {
+    auth_storage: AuthenticationStorage,
+    client: reqwest::Client,
+    managed_identity: Arc<Mutex<ManagedIdentityState>>,
+}
+
+/// The state of the managed identity lookup.
+#[derive(Clone, Debug, Default)]
+enum ManagedIdentityState {
+    /// No token has been requested yet, or the last token expired.
+    #[default]
+    Unknown,
+
+    /// A token was obtained.
+    Token(AccessToken),
+
+    /// Requesting a token failed, e.g. because we are not running on Azure or
+    /// the endpoint had a transient failure. The endpoint is not probed again
+    /// before `retry_at`.
+    Unavailable {
+        retry_at: Instant,
+        backoff: Duration,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct AccessToken {
+    token: String,
+    expires_on: SystemTime,
+}
+
+/// The response of a managed identity token endpoint.
+#[derive(Debug, Deserialize)]
+struct ManagedIdentityTokenResponse {
+    access_token: String,
+    /// Seconds since the unix epoch, encoded as a string.
+    expires_on: String,
+}
+
+impl AzureMiddleware {
+    /// Create a new Azure middleware.
+    pub fn new(auth_storage: AuthenticationStorage) -> Self {
+        Self {
+            auth_storage,
+            client: reqwest::Client::new(),
+            managed_identity: Arc::default(),
+        }
+    }
+
+    /// Returns the SAS token for the account of the given URL, if any.
+    fn sas_token(&self, url: &Url) -> Option<String> {
+        if let Ok((_, Some(Authentication::AzureSasToken(token)))) =
+            self.auth_storage.get_by_url(url.clone())
+        {
+            return Some(token);
+        }
+        std::env::var("AZURE_STORAGE_SAS_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Returns a managed identity access token for Azure Storage.
+    ///
+    /// If requesting a token fails, no further attempts are made until an
+    /// exponentially increasing backoff has passed so that requests outside of
+    /// Azure do not repeatedly wait for the metadata service to time out.
+    async fn managed_identity_token(&self) -> anyhow::Result<String> {
+        let previous_backoff = match &*self.managed_identity.lock().unwrap() {
+            ManagedIdentityState::Token(token)
+                if token.expires_on > SystemTime::now() + TOKEN_EXPIRY_MARGIN =>
+            {
+                return Ok(token.token.clone());
+            }
+            ManagedIdentityState::Unavailable { retry_at, .. } if *retry_at > Instant::now() => {
+                anyhow::bail!("a previous managed identity token request failed")
+            }
+            ManagedIdentityState::Unavailable { backoff, .. } => Some(*backoff),
+            ManagedIdentityState::Token(_) | ManagedIdentityState::Unknown => None,
+        };
+
+        let result = self.request_managed_identity_token().await;
+        *self.managed_identity.lock().unwrap() = if let Ok(token) = &result {
+            ManagedIdentityState::Token(token.clone())
+        } else {
+            let backoff = previous_backoff.map_or(MIN_MANAGED_IDENTITY_BACKOFF, |backoff| {
+                (backoff * 2).min(MAX_MANAGED_IDENTITY_BACKOFF)
+            });
+            ManagedIdentityState::Unavailable {
+                retry_at: Instant::now() + backoff,
+                backoff,
+            }
+        };
+        result.map(|token| token.token)
+    }
+
+    /// Requests a new managed identity access token.
+    async fn request_managed_identity_token(&self) -> anyhow::Result<AccessToken> {
+        let mut request = if let (Ok(endpoint), Ok(header)) = (
+            std::env::var("IDENTITY_ENDPOINT"),
+            std::env::var("IDENTITY_HEADER"),
+        ) {
+            // App Service and Azure Functions expose their own endpoint.
+            self.client
+                .get(endpoint)
+                .header("X-IDENTITY-HEADER", header)
+                .query(&[
+                    ("api-version", "2019-08-01"),
+                    ("resource", STORAGE_RESOURCE),
+                ])
+        } else {
+            self.client
+                .get(IMDS_TOKEN_ENDPOINT)
+                .header("Metadata", "true")
+                .query(&[
+                    ("api-version", "2018-02-01"),
+                    ("resource", STORAGE_RESOURCE),
+                ])
+                // The metadata service is link-local, if it doesn't respond quickly we are
+                // not running on Azure.
+                .timeout(Duration::from_secs(2))
+        };
+        if let Ok(client_id) = std::env::var("AZURE_CLIENT_ID") {
+            request = request.query(&[("client_id", client_id)]);
+        }
+
+        let response: ManagedIdentityTokenResponse = request
+            .send()
+            .await
+            .context("failed to reach the managed identity endpoint")?
+            .error_for_status()
+            .context("the managed identity endpoint returned an error")?
+            .json()
+            .await
+            .context("failed to parse the managed identity token")?;
+
+        let expires_on = response.expires_on.parse::<u64>().map_or_else(
+            |_err| SystemTime::now(),
+            |secs| UNIX_EPOCH + Duration::from_secs(secs),
+        );
+        Ok(AccessToken {
+            token: response.access_token,
+            expires_on,
+        })
+    }
+
+    /// Rewrites an `az://` request into an authenticated HTTPS request.
+    ///
+    /// Returns the HTTPS URL without the SAS token, which is safe to show in
+    /// error messages.
+    async fn authenticate(&self, req: &mut Request) -> MiddlewareResult<Url> {
+        let https_url = to_https_url(req.url())?;
+        let mut url = https_url.clone();
+
+        if let Some(sas_token) = self.sas_token(req.url()) {
+            append_sas_token(&mut url, &sas_token);
+        } else {
+            match self.managed_identity_token().await {
+                Ok(token) => {
+                    let mut header_value =
+                        reqwest::header::HeaderValue::from_str(&format!("Bearer {token}"))
+                            .map_err(reqwest_middleware::Error::middleware)?;
+                    header_value.set_sensitive(true);
+                    req.headers_mut()
+                        .insert(reqwest::header::AUTHORIZATION, header_value);
+                    req.headers_mut().insert(
+                        "x-ms-version",
+                        reqwest::header::HeaderValue::from_static(STORAGE_API_VERSION),
+                    );
+                }
+                Err(e) => {
+                    tracing::debug!(
+                        "no Azure credentials available, sending anonymous request: {e:#}"
+                    );
+                }
+            }
+        }
+
+        *req.url_mut() = url;
+        Ok(https_url)
+    }
+}
+
+/// Converts an `az://<account>/<container>/<path>` URL into the HTTPS URL of
+/// the blob.
+fn to_https_url(url: &Url) -> MiddlewareResult<Url> {
+    let account = url.host_str().ok_or_else(|| {
+        reqwest_middleware::Error::Middleware(anyhow::anyhow!(
+            "host should be present in Azure URL"
+        ))
+    })?;
+    let mut new_url = format!("https://{account}.blob.core.windows.net{}", url.path());
+    if let Some(query) = url.query() {
+        new_url.push('?');
+        new_url.push_str(query);
+    }
+    Url::parse(&new_url).map_err(reqwest_middleware::Error::middleware)
+}
+
+/// Appends the query parameters of a SAS token to the URL.
+fn append_sas_token(url: &mut Url, sas_token: &str) {
+    let sas_token = sas_token.trim_start_matches('?');
+    let query = match url.query() {
+        Some(query) if !query.is_empty() => format!("{query}&{sas_token}"),
+        _ => sas_token.to_string(),
+    };
+    url.set_query(Some(&query));
+}
+
+#[async_trait]
+impl Middleware for AzureMiddleware {
+    /// Create a new authentication middleware for Azure Blob Storage.
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> MiddlewareResult<Response> {
+        // Only intercept `az://` requests.
+        if req.url().scheme() != "az" {
+            return next.run(req, extensions).await;
+        }
+
+        let https_url = self.authenticate(&mut req).await?;
+        next.run(req, extensions).await.map_err(|err| match err {
+            // reqwest includes the request URL in its errors, replace it so the SAS token does
+            // not leak into logs.
+            reqwest_middleware::Error::Reqwest(err) if err.url().is_some() => {
+                reqwest_middleware::Error::Reqwest(err.with_url(https_url))
+            }
+            err => err,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use temp_env::async_with_vars;
+
+    use super::*;
+    use crate::{
+        authentication_storage::backends::memory::MemoryStorage, test_utils::unused_local_url,
+    };
+
+    #[test]
+    fn test_to_https_url() {
+        let url = Url::parse("az://myaccount/channel/noarch/repodata.json").unwrap();
+        assert_eq!(
+            to_https_url(&url).unwrap().as_str(),
+            "https://myaccount.blob.core.windows.net/channel/noarch/repodata.json"
+        );
+    }
+
+    #[test]
+    fn test_append_sas_token() {
+        let mut url = Url::parse("https://myaccount.blob.core.windows.net/c/file").unwrap();
+        append_sas_token(&mut url, "?sv=2022-11-02&sig=abc");
+        assert_eq!(url.query(), Some("sv=2022-11-02&sig=abc"));
+
+        let mut url = Url::parse("https://myaccount.blob.core.windows.net/c/file?a=b").unwrap();
+        append_sas_token(&mut url, "sv=2022-11-02&sig=abc");
+        assert_eq!(url.query(), Some("a=b&sv=2022-11-02&sig=abc"));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_with_stored_sas_token() {
+        let storage = MemoryStorage::default();
+        let mut auth_storage = AuthenticationStorage::empty();
+        auth_storage.add_backend(Arc::new(storage));
+        auth_storage
+            .store(
+                "myaccount",
+                &Authentication::AzureSasToken("sv=2022-11-02&sig=abc".to_string()),
+            )
+            .unwrap();
+
+        let middleware = AzureMiddleware::new(auth_storage);
+        let mut req = Request::new(
+            http::Method::GET,
+            Url::parse("az://myaccount/channel/noarch/repodata.json").unwrap(),
+        );
+        let https_url = async_with_vars([("AZURE_STORAGE_SAS_TOKEN", None::<&str>)], async {
+            middleware.authenticate(&mut req).await.unwrap()
+        })
+        .await;
+        assert!(https_url.query().is_none());
+
+        assert_eq!(
+            req.url().as_str(),
+            "https://myaccount.blob.core.windows.net/channel/noarch/repodata.json?sv=2022-11-02&sig=abc"
+        );
+        assert!(req.headers().get(reqwest::header::AUTHORIZATION).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_managed_identity_failure_is_retried_after_backoff() {
+        let endpoint = unused_local_url().await.join("token").unwrap();
+
+        let middleware = AzureMiddleware::new(AuthenticationStorage::empty());
+        let vars = [
+            ("IDENTITY_ENDPOINT", Some(endpoint.as_str())),
+            ("IDENTITY_HEADER", Some("header")),
+        ];
+        async_with_vars(vars, async {
+            assert!(middleware.managed_identity_token().await.is_err());
+        })
+        .await;
+        assert!(matches!(
+            *middleware.managed_identity.lock().unwrap(),
+            ManagedIdentityState::Unavailable { backoff, .. } if backoff == MIN_MANAGED_IDENTITY_BACKOFF
+        ));
+
+        // Subsequent requests fail immediately without probing the endpoint.
+        assert!(middleware.managed_identity_token().await.is_err());
+
+        // Once the backoff has passed the endpoint is probed again, and the
+        // backoff grows if it still fails.
+        *middleware.managed_identity.lock().unwrap() = ManagedIdentityState::Unavailable {
+            retry_at: Instant::now(),
+            backoff: MIN_MANAGED_IDENTITY_BACKOFF,
+        };
+        async_with_vars(vars, async {
+            assert!(middleware.managed_identity_token().await.is_err());
+        })
+        .await;
+        assert!(matches!(
+            *middleware.managed_identity.lock().unwrap(),
+            ManagedIdentityState::Unavailable { backoff, .. } if backoff == MIN_MANAGED_IDENTITY_BACKOFF * 2
+        ));
+    }
+}
@@ -1,13 +1,42 @@
-//! Middleware to handle `gcs://` URLs to pull artifacts from an GCS
+//! Middleware to handle `gcs://` and `gs://` URLs to pull artifacts from an GCS
+use std::sync::OnceLock;
+
 use async_trait::async_trait;
-use google_cloud_auth::credentials::{Builder as AccessTokenCredentialBuilder, CacheableResource};
+use google_cloud_auth::credentials::{
+    Builder as AccessTokenCredentialBuilder, CacheableResource, Credentials,
+};
 use reqwest::{Request, Response};
 use reqwest_middleware::{Middleware, Next, Result as MiddlewareResult};
 use url::Url;
 
 /// GCS middleware to authenticate requests
+///
+/// Requests to `gcs://bucket/path` or `gs://bucket/path` URLs are redirected to
+/// the GCS JSON/XML endpoint and authenticated with Application Default
+/// Credentials. Other headers of the request (like `Range`) are passed through
+/// unchanged, so partial downloads can be resumed.
 pub struct GCSMiddleware;
 
+/// Returns true if the URL refers to an object in Google Cloud Storage.
+pub fn is_gcs_url(url: &Url) -> bool {
+    matches!(url.scheme(), "gcs" | "gs")
+}
+
+/// Converts a `gcs://` or `gs://` URL into the HTTPS URL of the object.
+fn to_https_url(url: &Url) -> Url {
+    let bucket_name = url.host_str().expect("Host should be present in GCS URL");
+    let mut new_url = format!(
+        "https://storage.googleapis.com/{}{}",
+        bucket_name,
+        url.path()
+    );
+    if let Some(query) = url.query() {
+        new_url.push('?');
+        new_url.push_str(query);
+    }
+    Url::parse(&new_url).expect("Failed to parse URL")
+}
+
 #[async_trait]
 impl Middleware for GCSMiddleware {
     /// Create a new authentication middleware for GCS
@@ -17,46 +46,46 @@ impl Middleware for GCSMiddleware {
         extensions: &mut http::Extensions,
         next: Next<'_>,
     ) -> MiddlewareResult<Response> {
-        if req.url().scheme() == "gcs" {
-            let mut url = req.url().clone();
-            let bucket_name = url.host_str().expect("Host should be present in GCS URL");
-            let new_url = format!(
-                "https://storage.googleapis.com/{}{}",
-                bucket_name,
-                url.path()
-            );
-            url = Url::parse(&new_url).expect("Failed to parse URL");
-            *req.url_mut() = url;
+        if is_gcs_url(req.url()) {
+            *req.url_mut() = to_https_url(req.url());
             req = authenticate_with_google_cloud(req).await?;
         }
         next.run(req, extensions).await
     }
 }
 
-/// Auth to GCS
-async fn authenticate_with_google_cloud(mut req: Request) -> MiddlewareResult<Request> {
-    let scopes = ["https://www.googleapis.com/auth/devstorage.read_only"];
+/// Returns the Application Default Credentials. The credentials are created
+/// once and shared between requests so that access tokens are cached and
+/// only refreshed when they expire.
+fn credentials() -> MiddlewareResult<Credentials> {
+    static CREDENTIALS: OnceLock<Credentials> = OnceLock::new();
+    if let Some(credentials) = CREDENTIALS.get() {
+        return Ok(credentials.clone());
+    }
 
-    match AccessTokenCredentialBuilder::default()
+    let scopes = ["https://www.googleapis.com/auth/devstorage.read_only"];
+    let credentials = AccessTokenCredentialBuilder::default()
         .with_scopes(scopes)
         .build()
-    {
-        Ok(token_source) => {
-            let extensions = http::Extensions::new();
-            let headers = match token_source.headers(extensions).await {
-                Ok(CacheableResource::New { data, .. }) => data,
-                Ok(CacheableResource::NotModified) => unreachable!(
-                    "we are not passing in any extensions so they should never be cached"
-                ),
-                Err(e) => {
-                    return Err(reqwest_middleware::Error::Middleware(anyhow::Error::new(e)));
-                }
-            };
-            req.headers_mut().extend(headers);
-            Ok(req)
+        .map_err(|e| reqwest_middleware::Error::Middleware(anyhow::Error::new(e)))?;
+    Ok(CREDENTIALS.get_or_init(|| credentials).clone())
+}
+
+/// Auth to GCS
+async fn authenticate_with_google_cloud(mut req: Request) -> MiddlewareResult<Request> {
+    let token_source = credentials()?;
+    let extensions = http::Extensions::new();
+    let headers = match token_source.headers(extensions).await {
+        Ok(CacheableResource::New { data, .. }) => data,
+        Ok(CacheableResource::NotModified) => {
+            unreachable!("we are not passing in any extensions so they should never be cached")
         }
-        Err(e) => Err(reqwest_middleware::Error::Middleware(anyhow::Error::new(e))),
-    }
+        Err(e) => {
+            return Err(reqwest_middleware::Error::Middleware(anyhow::Error::new(e)));
+        }
+    };
+    req.headers_mut().extend(headers);
+    Ok(req)
 }
 
 #[cfg(test)]
@@ -66,6 +95,26 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_to_https_url() {
+        for scheme in ["gcs", "gs"] {
+            let url = Url::parse(&format!("{scheme}://my-bucket/noarch/repodata.json")).unwrap();
+            assert!(is_gcs_url(&url));
+            assert_eq!(
+                to_https_url(&url).as_str(),
+                "https://storage.googleapis.com/my-bucket/noarch/repodata.json"
+            );
+        }
+
+        let url = Url::parse("gs://my-bucket/linux-64/foo-1.0-0.conda?generation=1").unwrap();
+        assert_eq!(
+            to_https_url(&url).as_str(),
+            "https://storage.googleapis.com/my-bucket/linux-64/foo-1.0-0.conda?generation=1"
+        );
+
+        assert!(!is_gcs_url(&Url::parse("s3://my-bucket/foo").unwrap()));
+    }
+
     #[tokio::test]
     async fn test_gcs_middleware() {
         let credentials = match std::env::var("GOOGLE_CLOUD_TEST_KEY_JSON") {
@@ -93,6 +142,10 @@ mod tests {
         let response = client.get(url).send().await.unwrap();
         assert!(response.status().is_success());
 
+        let url = "gs://test-channel/noarch/repodata.json";
+        let response = client.get(url).send().await.unwrap();
+        assert!(response.status().is_success());
+
         let url = "gcs://test-channel-nonexist/noarch/repodata.json";
         let response = client.get(url).send().await.unwrap();
         assert!(response.status().is_client_error());
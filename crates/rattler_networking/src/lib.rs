@@ -3,9 +3,23 @@
 //! Networking utilities for Rattler, specifically authenticating requests
 pub use authentication_middleware::AuthenticationMiddleware;
 pub use authentication_storage::{authentication::Authentication, storage::AuthenticationStorage};
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    any(feature = "rustls-tls", feature = "native-tls")
+))]
+pub use client_builder::{HttpClientBuilder, HttpClientBuilderError, TlsBackend};
 pub use lazy_client::LazyClient;
+#[cfg(not(target_arch = "wasm32"))]
+pub use logging_middleware::{HarRecorder, LoggingMiddleware};
 pub use mirror_middleware::MirrorMiddleware;
 pub use oci_middleware::OciMiddleware;
+#[cfg(not(target_arch = "wasm32"))]
+pub use retry_middleware::RetryMiddleware;
+
+#[cfg(feature = "azure")]
+pub mod azure_middleware;
+#[cfg(feature = "azure")]
+pub use azure_middleware::AzureMiddleware;
 
 #[cfg(feature = "gcs")]
 pub mod gcs_middleware;
@@ -19,8 +33,19 @@ pub use s3_middleware::S3Middleware;
 
 pub mod authentication_middleware;
 pub mod authentication_storage;
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    any(feature = "rustls-tls", feature = "native-tls")
+))]
+pub mod client_builder;
 
 mod lazy_client;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logging_middleware;
 pub mod mirror_middleware;
 pub mod oci_middleware;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod retry_middleware;
 pub mod retry_policies;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test_utils;
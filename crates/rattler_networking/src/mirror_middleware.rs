@@ -1,7 +1,8 @@
 //! Middleware to handle mirrors
 use std::{
     collections::HashMap,
-    sync::atomic::{self, AtomicUsize},
+    sync::atomic::{self, AtomicU64, AtomicUsize},
+    time::Duration,
 };
 
 use http::Extensions;
@@ -25,9 +26,24 @@ pub struct Mirror {
     pub max_failures: Option<usize>,
 }
 
-#[allow(dead_code)]
+/// Determines the order in which the mirrors of a channel are tried.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MirrorSelection {
+    /// Try the mirrors in the order in which they were configured.
+    #[default]
+    Ordered,
+
+    /// Try the mirror with the lowest measured response latency first.
+    /// Mirrors that have not been measured yet are tried before measured
+    /// ones so that every mirror gets a chance to be measured.
+    Latency,
+}
+
 struct MirrorState {
     failures: AtomicUsize,
+    /// Exponentially weighted moving average of the response latency in
+    /// microseconds, or `0` if no latency has been measured yet.
+    latency_micros: AtomicU64,
     mirror: Mirror,
 }
 
@@ -35,12 +51,65 @@ impl MirrorState {
     pub fn add_failure(&self) {
         self.failures.fetch_add(1, atomic::Ordering::Relaxed);
     }
+
+    fn failures(&self) -> usize {
+        self.failures.load(atomic::Ordering::Relaxed)
+    }
+
+    fn is_alive(&self) -> bool {
+        let failures = self.failures();
+        self.mirror.max_failures.is_none_or(|max| failures < max)
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        match self.latency_micros.load(atomic::Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn record_latency(&self, latency: Duration) {
+        let sample = u64::try_from(latency.as_micros())
+            .unwrap_or(u64::MAX)
+            .max(1);
+        // Weigh the new sample with 1/4 to smooth out outliers.
+        let _ = self.latency_micros.fetch_update(
+            atomic::Ordering::Relaxed,
+            atomic::Ordering::Relaxed,
+            |current| match current {
+                0 => Some(sample),
+                current => Some((current * 3 + sample) / 4),
+            },
+        );
+    }
+
+    /// Returns the reason why this mirror cannot serve the given path, if
+    /// any.
+    fn unsupported_reason(&self, url_rest: &str) -> Option<&'static str> {
+        if url_rest.ends_with(".json.zst") && self.mirror.no_zstd {
+            Some("Mirror does not support zstd")
+        } else if url_rest.ends_with(".json.bz2") && self.mirror.no_bz2 {
+            Some("Mirror does not support bz2")
+        } else if url_rest.ends_with(".jlap") && self.mirror.no_jlap {
+            Some("Mirror does not support jlap")
+        } else {
+            None
+        }
+    }
 }
 
-/// Middleware to handle mirrors
+/// Middleware to handle mirrors.
+///
+/// Requests to a URL that starts with one of the configured channel URLs are
+/// redirected to one of the mirrors of that channel. If a mirror responds
+/// with a server error or the request fails (e.g. because of a timeout), the
+/// failure is remembered for the lifetime of the middleware and the request
+/// is transparently retried on the next mirror.
 pub struct MirrorMiddleware {
     mirror_map: HashMap<Url, Vec<MirrorState>>,
     sorted_keys: Vec<(String, Url)>,
+    selection: MirrorSelection,
 }
 
 impl MirrorMiddleware {
@@ -53,6 +122,7 @@ impl MirrorMiddleware {
                     .into_iter()
                     .map(|mirror| MirrorState {
                         failures: AtomicUsize::new(0),
+                        latency_micros: AtomicU64::new(0),
                         mirror,
                     })
                     .collect();
@@ -70,9 +140,17 @@ impl MirrorMiddleware {
         Self {
             mirror_map,
             sorted_keys,
+            selection: MirrorSelection::default(),
         }
     }
 
+    /// Sets the strategy that determines the order in which mirrors are
+    /// tried.
+    #[must_use]
+    pub fn with_selection(self, selection: MirrorSelection) -> Self {
+        Self { selection, ..self }
+    }
+
     /// Get sorted keys. The keys are sorted by length of the path,
     /// so the longest path comes first.
     pub fn keys(&self) -> &[(String, Url)] {
@@ -80,21 +158,16 @@ impl MirrorMiddleware {
     }
 }
 
-fn select_mirror(mirrors: &[MirrorState]) -> Option<&MirrorState> {
-    let mut min_failures = usize::MAX;
-    let mut min_failures_index = usize::MAX;
-
-    for (i, mirror) in mirrors.iter().enumerate() {
-        let failures = mirror.failures.load(atomic::Ordering::Relaxed);
-        if failures < min_failures && mirror.mirror.max_failures.is_none_or(|max| failures < max) {
-            min_failures = failures;
-            min_failures_index = i;
-        }
-    }
-    if min_failures_index == usize::MAX {
-        return None;
+/// Returns the mirrors that are still alive in the order in which they should
+/// be tried. Mirrors with fewer failures are always preferred.
+fn order_mirrors(mirrors: &[MirrorState], selection: MirrorSelection) -> Vec<&MirrorState> {
+    let alive = mirrors.iter().filter(|mirror| mirror.is_alive());
+    match selection {
+        MirrorSelection::Ordered => alive.sorted_by_key(|mirror| mirror.failures()).collect(),
+        MirrorSelection::Latency => alive
+            .sorted_by_key(|mirror| (mirror.failures(), mirror.latency()))
+            .collect(),
     }
-    Some(&mirrors[min_failures_index])
 }
 
 #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
@@ -113,46 +186,68 @@ impl Middleware for MirrorMiddleware {
                 let url_rest = url_rest.trim_start_matches('/');
                 // replace the key with the mirror
                 let mirrors = self.mirror_map.get(url).unwrap();
-                let selected_mirror = select_mirror(mirrors);
+                let alive_mirrors = order_mirrors(mirrors, self.selection);
 
-                let Some(selected_mirror) = selected_mirror else {
+                let Some(first_mirror) = alive_mirrors.first() else {
                     return Ok(create_404_response(req.url(), "All mirrors are dead"));
                 };
 
-                let mirror = &selected_mirror.mirror;
-                let selected_url = mirror.url.join(url_rest).unwrap();
-
-                // Short-circuit if the mirror does not support the file type
-                if url_rest.ends_with(".json.zst") && mirror.no_zstd {
-                    return Ok(create_404_response(
-                        &selected_url,
-                        "Mirror does not support zstd",
-                    ));
-                }
-                if url_rest.ends_with(".json.bz2") && mirror.no_bz2 {
+                // Skip mirrors that do not support the file type, if none of them do we
+                // short-circuit.
+                let candidates = alive_mirrors
+                    .iter()
+                    .filter(|mirror| mirror.unsupported_reason(url_rest).is_none())
+                    .collect_vec();
+                if candidates.is_empty() {
+                    let reason = first_mirror
+                        .unsupported_reason(url_rest)
+                        .unwrap_or_default();
                     return Ok(create_404_response(
-                        &selected_url,
-                        "Mirror does not support bz2",
+                        &first_mirror.mirror.url.join(url_rest).unwrap(),
+                        reason,
                     ));
                 }
-                if url_rest.ends_with(".jlap") && mirror.no_jlap {
-                    return Ok(create_404_response(
-                        &selected_url,
-                        "Mirror does not support jlap",
-                    ));
-                }
-
-                *req.url_mut() = selected_url;
-                let res = next.run(req, extensions).await;
 
-                // record a failure if the request failed so we can avoid the mirror in the future
-                match res.as_ref() {
-                    Ok(res) if res.status().is_server_error() => selected_mirror.add_failure(),
-                    Err(_) => selected_mirror.add_failure(),
-                    _ => {}
+                let mut candidates = candidates.into_iter().peekable();
+                loop {
+                    let mirror = candidates.next().expect("there is at least one candidate");
+
+                    // Keep a copy of the request around so we can retry it on the next mirror.
+                    // Requests with a streaming body cannot be cloned, those are only tried once.
+                    let retry_req = if candidates.peek().is_some() {
+                        req.try_clone()
+                    } else {
+                        None
+                    };
+
+                    *req.url_mut() = mirror.mirror.url.join(url_rest).unwrap();
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let start = std::time::Instant::now();
+                    let res = next.clone().run(req, extensions).await;
+
+                    // Record a failure if the request failed so we can avoid the mirror in the
+                    // future.
+                    let failed = match &res {
+                        Ok(res) => res.status().is_server_error(),
+                        Err(_) => true,
+                    };
+                    if !failed {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        mirror.record_latency(start.elapsed());
+                        return res;
+                    }
+                    mirror.add_failure();
+
+                    let Some(retry_req) = retry_req else {
+                        return res;
+                    };
+                    tracing::debug!(
+                        "mirror {} failed, retrying on the next mirror",
+                        mirror.mirror.url
+                    );
+                    req = retry_req;
                 }
-
-                return res;
             }
         }
 
@@ -180,7 +275,12 @@ pub(crate) fn create_404_response(_url: &Url, _body: &str) -> Response {
 
 #[cfg(test)]
 mod test {
-    use std::{future::IntoFuture, net::SocketAddr};
+    use std::{
+        future::IntoFuture,
+        net::SocketAddr,
+        sync::atomic::{AtomicU64, AtomicUsize},
+        time::Duration,
+    };
 
     use axum::{extract::State, http::StatusCode, routing::get, Router};
     use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
@@ -188,7 +288,7 @@ mod test {
 
     use crate::MirrorMiddleware;
 
-    use super::Mirror;
+    use super::{order_mirrors, Mirror, MirrorSelection, MirrorState};
 
     async fn count(State(name): State<String>) -> String {
         format!("Hi from counter: {name}")
@@ -271,8 +371,10 @@ mod test {
             .with(middleware)
             .build();
 
+        // the first server fails, so the request is transparently retried on the second
         let res = client.get("http://bla.com/count").send().await.unwrap();
-        assert!(res.status().is_server_error());
+        assert!(res.status().is_success());
+        assert!(res.text().await.unwrap() == "Hi from counter: server 2");
         // only the second server should be used
         let res = client.get("http://bla.com/count").send().await.unwrap();
         assert!(res.status().is_success());
@@ -293,6 +395,74 @@ mod test {
         assert!(res.text().await.unwrap() == "Hi from counter: server 2");
     }
 
+    #[tokio::test]
+    async fn test_mirror_middleware_all_broken() {
+        let addr_1 = test_server("server 1", true).await;
+        let addr_2 = test_server("server 2", true).await;
+
+        let mut mirror_map = std::collections::HashMap::new();
+        mirror_map.insert(
+            "http://bla.com".parse().unwrap(),
+            vec![mirror_setting(addr_1), mirror_setting(addr_2)],
+        );
+
+        let middleware = MirrorMiddleware::from_map(mirror_map);
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(middleware)
+            .build();
+
+        // the response of the last mirror is returned
+        let res = client.get("http://bla.com/count").send().await.unwrap();
+        assert!(res.status().is_server_error());
+
+        // every request marks both mirrors as failed, after three attempts all mirrors are dead
+        for _ in 0..2 {
+            client.get("http://bla.com/count").send().await.unwrap();
+        }
+        let res = client.get("http://bla.com/count").send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.text().await.unwrap(), "All mirrors are dead");
+    }
+
+    #[test]
+    fn test_order_mirrors_by_latency() {
+        let state = |url: &str| MirrorState {
+            failures: AtomicUsize::new(0),
+            latency_micros: AtomicU64::new(0),
+            mirror: mirror_setting(url.parse().unwrap()),
+        };
+        let mirrors = vec![
+            state("http://slow.com"),
+            state("http://fast.com"),
+            state("http://unmeasured.com"),
+        ];
+        mirrors[0].record_latency(Duration::from_millis(200));
+        mirrors[1].record_latency(Duration::from_millis(20));
+
+        let urls = |selection| {
+            order_mirrors(&mirrors, selection)
+                .into_iter()
+                .map(|m| m.mirror.url.host_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            urls(MirrorSelection::Ordered),
+            ["slow.com", "fast.com", "unmeasured.com"]
+        );
+        assert_eq!(
+            urls(MirrorSelection::Latency),
+            ["unmeasured.com", "fast.com", "slow.com"]
+        );
+
+        // Mirrors with failures are tried last, regardless of their latency.
+        mirrors[1].add_failure();
+        assert_eq!(
+            urls(MirrorSelection::Latency),
+            ["unmeasured.com", "slow.com", "fast.com"]
+        );
+    }
+
     #[test]
     fn test_mirror_sort() {
         let keys: Vec<Url> = vec![
@@ -2,18 +2,19 @@
 use std::{
     collections::HashMap,
     fmt::{Display, Formatter},
+    sync::{Arc, Mutex},
 };
 
 use http::{
-    header::{ACCEPT, AUTHORIZATION},
-    Extensions,
+    header::{ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE},
+    Extensions, StatusCode,
 };
 use reqwest::{Request, Response};
 use reqwest_middleware::{Middleware, Next};
 use serde::Deserialize;
 use url::{ParseError, Url};
 
-use crate::mirror_middleware::create_404_response;
+use crate::{mirror_middleware::create_404_response, Authentication, AuthenticationStorage};
 
 #[derive(thiserror::Error, Debug)]
 enum OciMiddlewareError {
@@ -25,11 +26,74 @@ enum OciMiddlewareError {
 
     #[error("Layer not found")]
     LayerNotFound,
+
+    #[error("the token response of the registry did not contain a token")]
+    MissingToken,
 }
 
 /// Middleware to handle `oci://` URLs
-#[derive(Default, Debug, Clone)]
-pub struct OciMiddleware;
+///
+/// Requests are authenticated with bearer tokens obtained from the token
+/// service of the registry. The token service is discovered through the
+/// `WWW-Authenticate` challenge of the registry. If credentials for the
+/// registry host are stored in the [`AuthenticationStorage`], they are used to
+/// request the token (basic authentication) or used as the token directly
+/// (bearer token). Tokens are cached for the lifetime of the middleware and
+/// refreshed when the registry rejects them.
+///
+/// Use [`OciMiddleware::new()`] for a middleware that only requests anonymous
+/// tokens and [`OciMiddleware::with_authentication_storage`] to authenticate
+/// with registries.
+#[derive(Debug, Clone)]
+pub struct OciMiddleware {
+    auth_storage: AuthenticationStorage,
+    client: reqwest::Client,
+    token_endpoints: Arc<Mutex<HashMap<String, TokenEndpoint>>>,
+    tokens: Arc<Mutex<HashMap<String, String>>>,
+}
+
+/// Creates a middleware without any stored credentials that requests
+/// anonymous tokens with a default [`reqwest::Client`].
+impl Default for OciMiddleware {
+    fn default() -> Self {
+        Self {
+            auth_storage: AuthenticationStorage::empty(),
+            client: reqwest::Client::new(),
+            token_endpoints: Arc::default(),
+            tokens: Arc::default(),
+        }
+    }
+}
+
+impl OciMiddleware {
+    /// Create a new `OciMiddleware` without any stored credentials that
+    /// requests anonymous tokens. This is the same as
+    /// [`OciMiddleware::default()`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the storage that is used to look up the credentials for a
+    /// registry.
+    pub fn with_authentication_storage(self, auth_storage: AuthenticationStorage) -> Self {
+        Self {
+            auth_storage,
+            ..self
+        }
+    }
+
+    /// Sets the client that is used to discover the token service of a
+    /// registry and to request tokens from it.
+    ///
+    /// By default a new [`reqwest::Client`] is used which does not share the
+    /// configuration (e.g. the root certificates or the proxy settings) of
+    /// the client this middleware is attached to. Pass the same client, e.g.
+    /// one created with [`crate::HttpClientBuilder`], to apply that
+    /// configuration to the token requests as well.
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        Self { client, ..self }
+    }
+}
 
 /// The action to perform on the OCI registry
 pub enum OciAction {
@@ -41,9 +105,76 @@ pub enum OciAction {
     PushPull,
 }
 
+/// The response of a registry token service. Registries may return the token
+/// in either of the two fields.
 #[derive(Clone, Debug, Deserialize)]
 struct OCIToken {
-    token: String,
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+impl OCIToken {
+    fn into_token(self) -> Option<String> {
+        self.token.or(self.access_token)
+    }
+}
+
+/// The token service of a registry, as advertised by the `WWW-Authenticate`
+/// header of the registry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TokenEndpoint {
+    realm: Url,
+    service: Option<String>,
+}
+
+impl TokenEndpoint {
+    /// The token service that is used if the registry does not advertise one.
+    fn fallback(host: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            realm: format!("https://{host}/token").parse()?,
+            service: None,
+        })
+    }
+
+    /// Parses a challenge of the form
+    /// `Bearer realm="https://ghcr.io/token",service="ghcr.io"`.
+    fn from_challenge(challenge: &str) -> Option<Self> {
+        let (scheme, params) = challenge.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+
+        let mut realm = None;
+        let mut service = None;
+        for param in params.split(',') {
+            let Some((key, value)) = param.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"');
+            match key.trim() {
+                "realm" => realm = Some(value),
+                "service" => service = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            realm: realm?.parse().ok()?,
+            service,
+        })
+    }
+
+    fn token_url(&self, path: &str, action: &OciAction) -> Url {
+        let mut url = self.realm.clone();
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(service) = &self.service {
+                query.append_pair("service", service);
+            }
+            query.append_pair("scope", &format!("repository:{path}:{action}"));
+        }
+        url
+    }
 }
 
 impl Display for OciAction {
@@ -56,21 +187,138 @@ impl Display for OciAction {
     }
 }
 
-// [oci://ghcr.io/channel-mirrors/conda-forge]/[osx-arm64/xtensor]
-async fn get_token(url: &OCIUrl, action: OciAction) -> Result<String, OciMiddlewareError> {
-    let token_url = url.token_url(action)?;
+impl OciMiddleware {
+    /// Returns the token service of the given registry host.
+    async fn token_endpoint(&self, host: &str) -> Result<TokenEndpoint, OciMiddlewareError> {
+        if let Some(endpoint) = self.token_endpoints.lock().unwrap().get(host) {
+            return Ok(endpoint.clone());
+        }
+
+        // An unauthenticated request to the base endpoint of the registry returns a challenge
+        // that describes where tokens can be obtained.
+        let base_url: Url = format!("https://{host}/v2/").parse()?;
+        let endpoint = match self.client.get(base_url).send().await {
+            Ok(response) => response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|challenge| challenge.to_str().ok())
+                .and_then(TokenEndpoint::from_challenge),
+            Err(e) => {
+                tracing::debug!("OCI Mirror: failed to discover token service of {host}: {e}");
+                None
+            }
+        };
+        let endpoint = match endpoint {
+            Some(endpoint) => endpoint,
+            None => TokenEndpoint::fallback(host)?,
+        };
 
-    let response = reqwest::get(token_url.clone()).await?;
+        self.token_endpoints
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), endpoint.clone());
+        Ok(endpoint)
+    }
 
-    match response.error_for_status() {
-        Ok(response) => {
-            let token = response.json::<OCIToken>().await?;
-            Ok(token.token)
+    /// Returns a token for the given action on the repository of the URL and
+    /// whether it was taken from the cache.
+    async fn get_token(
+        &self,
+        url: &OCIUrl,
+        action: OciAction,
+    ) -> Result<(String, bool), OciMiddlewareError> {
+        let cache_key = url.token_cache_key(&action);
+        if let Some(token) = self.tokens.lock().unwrap().get(&cache_key) {
+            return Ok((token.clone(), true));
         }
-        Err(e) => {
-            tracing::error!("OCI Mirror: failed to get token with URL: {}", token_url);
-            Err(OciMiddlewareError::Reqwest(e))
+
+        let credentials = self.auth_storage.get(&url.host).ok().flatten();
+        let token = if let Some(Authentication::BearerToken(token)) = credentials {
+            // A stored bearer token is used as the registry token directly.
+            token
+        } else {
+            let endpoint = self.token_endpoint(&url.host).await?;
+            let token_url = endpoint.token_url(&url.path, &action);
+
+            let mut request = self.client.get(token_url.clone());
+            if let Some(Authentication::BasicHTTP { username, password }) = credentials {
+                request = request.basic_auth(username, Some(password));
+            }
+
+            match request.send().await?.error_for_status() {
+                Ok(response) => response
+                    .json::<OCIToken>()
+                    .await?
+                    .into_token()
+                    .ok_or(OciMiddlewareError::MissingToken)?,
+                Err(e) => {
+                    tracing::error!("OCI Mirror: failed to get token with URL: {}", token_url);
+                    return Err(OciMiddlewareError::Reqwest(e));
+                }
+            }
+        };
+
+        self.tokens.lock().unwrap().insert(cache_key, token.clone());
+        Ok((token, false))
+    }
+
+    /// Forgets a cached token, e.g. because it expired.
+    fn invalidate_token(&self, url: &OCIUrl, action: OciAction) {
+        self.tokens
+            .lock()
+            .unwrap()
+            .remove(&url.token_cache_key(&action));
+    }
+
+    /// Rewrites the request to pull the blob from the registry. Returns
+    /// whether a cached token was used to authenticate the request.
+    async fn get_blob_url(&self, req: &mut Request) -> Result<bool, OciMiddlewareError> {
+        let oci_url = OCIUrl::new(req.url())?;
+        let (token, cached) = self.get_token(&oci_url, OciAction::Pull).await?;
+
+        let mut header_value: http::HeaderValue = format!("Bearer {token}")
+            .parse()
+            .expect("Could not parse token header");
+        header_value.set_sensitive(true);
+        req.headers_mut().insert(AUTHORIZATION, header_value);
+
+        // if we know the hash, we can pull the artifact directly
+        // if we don't, we need to pull the manifest and then pull the artifact
+        if let Some(expected_sha_hash) = req
+            .headers()
+            .get("X-Expected-Sha256")
+            .and_then(|s| s.to_str().ok())
+        {
+            *req.url_mut() = oci_url.blob_url(&format!("sha256:{expected_sha_hash}"))?;
+        } else {
+            // get the tag from the URL retrieve the manifest
+            let manifest_url = oci_url.manifest_url()?;
+
+            let manifest = self
+                .client
+                .get(manifest_url)
+                .header(AUTHORIZATION, format!("Bearer {token}"))
+                .header(ACCEPT, "application/vnd.oci.image.manifest.v1+json")
+                .send()
+                .await?
+                .error_for_status()?;
+
+            let manifest: Manifest = manifest.json().await?;
+
+            let layer = if let Some(layer) = manifest
+                .layers
+                .iter()
+                .find(|l| l.media_type == oci_url.media_type)
+            {
+                layer
+            } else {
+                return Err(OciMiddlewareError::LayerNotFound);
+            };
+
+            *req.url_mut() = oci_url.blob_url(&layer.digest)?;
         }
+
+        Ok(cached)
     }
 }
 
@@ -109,12 +357,8 @@ impl OCIUrl {
         .parse()
     }
 
-    pub fn token_url(&self, action: OciAction) -> Result<Url, ParseError> {
-        format!(
-            "https://{}/token?scope=repository:{}:{}",
-            self.host, self.path, action
-        )
-        .parse()
+    pub fn token_cache_key(&self, action: &OciAction) -> String {
+        format!("{}/{}:{}", self.host, self.path, action)
     }
 
     pub fn blob_url(&self, sha256: &str) -> Result<Url, ParseError> {
@@ -171,54 +415,6 @@ impl OCIUrl {
         res.path = res.url.path().trim_start_matches('/').to_string();
         Ok(res)
     }
-
-    pub async fn get_blob_url(req: &mut Request) -> Result<(), OciMiddlewareError> {
-        let oci_url = OCIUrl::new(req.url())?;
-        let token = get_token(&oci_url, OciAction::Pull).await?;
-
-        req.headers_mut().insert(
-            AUTHORIZATION,
-            format!("Bearer {token}")
-                .parse()
-                .expect("Could not parse token header"),
-        );
-
-        // if we know the hash, we can pull the artifact directly
-        // if we don't, we need to pull the manifest and then pull the artifact
-        if let Some(expected_sha_hash) = req
-            .headers()
-            .get("X-Expected-Sha256")
-            .and_then(|s| s.to_str().ok())
-        {
-            *req.url_mut() = oci_url.blob_url(&format!("sha256:{expected_sha_hash}"))?;
-        } else {
-            // get the tag from the URL retrieve the manifest
-            let manifest_url = oci_url.manifest_url()?; // TODO: handle error
-
-            let manifest = reqwest::Client::new()
-                .get(manifest_url)
-                .header(AUTHORIZATION, format!("Bearer {token}"))
-                .header(ACCEPT, "application/vnd.oci.image.manifest.v1+json")
-                .send()
-                .await?;
-
-            let manifest: Manifest = manifest.json().await?;
-
-            let layer = if let Some(layer) = manifest
-                .layers
-                .iter()
-                .find(|l| l.media_type == oci_url.media_type)
-            {
-                layer
-            } else {
-                return Err(OciMiddlewareError::LayerNotFound);
-            };
-
-            *req.url_mut() = oci_url.blob_url(&layer.digest)?;
-        }
-
-        Ok(())
-    }
 }
 
 #[allow(dead_code)]
@@ -263,21 +459,39 @@ impl Middleware for OciMiddleware {
             ));
         }
 
-        let res = OCIUrl::get_blob_url(&mut req).await;
+        // Keep a copy of the original request around in case the cached token was rejected.
+        let original_req = req.try_clone();
 
-        match res {
+        let used_cached_token = match self.get_blob_url(&mut req).await {
+            Ok(cached) => cached,
+            Err(OciMiddlewareError::LayerNotFound) => {
+                return Ok(create_404_response(
+                    req.url(),
+                    "No layer available for media type",
+                ));
+            }
+            Err(e) => return Err(reqwest_middleware::Error::Middleware(e.into())),
+        };
+
+        let response = next.clone().run(req, extensions).await?;
+        if response.status() != StatusCode::UNAUTHORIZED || !used_cached_token {
+            return Ok(response);
+        }
+        let Some(mut req) = original_req else {
+            return Ok(response);
+        };
+
+        // The cached token most likely expired, request a new one and try again.
+        if let Ok(oci_url) = OCIUrl::new(req.url()) {
+            self.invalidate_token(&oci_url, OciAction::Pull);
+        }
+        match self.get_blob_url(&mut req).await {
             Ok(_) => next.run(req, extensions).await,
-            Err(e) => match e {
-                OciMiddlewareError::LayerNotFound => {
-                    return Ok(create_404_response(
-                        req.url(),
-                        "No layer available for media type",
-                    ));
-                }
-                _ => {
-                    return Err(reqwest_middleware::Error::Middleware(e.into()));
-                }
-            },
+            Err(OciMiddlewareError::LayerNotFound) => Ok(create_404_response(
+                req.url(),
+                "No layer available for media type",
+            )),
+            Err(e) => Err(reqwest_middleware::Error::Middleware(e.into())),
         }
     }
 }
@@ -286,13 +500,50 @@ impl Middleware for OciMiddleware {
 mod tests {
     use sha2::{Digest, Sha256};
 
+    use super::{OCIToken, OCIUrl, OciAction, TokenEndpoint};
     use crate::OciMiddleware;
 
+    #[test]
+    fn test_parse_token_challenge() {
+        let endpoint =
+            TokenEndpoint::from_challenge(r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:user/image:pull""#)
+                .unwrap();
+        assert_eq!(endpoint.realm.as_str(), "https://ghcr.io/token");
+        assert_eq!(endpoint.service.as_deref(), Some("ghcr.io"));
+
+        let url = OCIUrl::new(
+            &"oci://ghcr.io/channel-mirrors/conda-forge/osx-arm64/xtensor-0.25.0-h2ffa867_0.conda"
+                .parse()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            endpoint.token_url(&url.path, &OciAction::Pull).as_str(),
+            "https://ghcr.io/token?service=ghcr.io&scope=repository%3Achannel-mirrors%2Fconda-forge%2Fosx-arm64%2Fxtensor%3Apull"
+        );
+
+        assert!(TokenEndpoint::from_challenge(r#"Basic realm="registry""#).is_none());
+        assert!(TokenEndpoint::from_challenge("Bearer service=\"ghcr.io\"").is_none());
+    }
+
+    #[test]
+    fn test_parse_token_response() {
+        let token: OCIToken = serde_json::from_str(r#"{"token": "abc"}"#).unwrap();
+        assert_eq!(token.into_token().as_deref(), Some("abc"));
+
+        let token: OCIToken =
+            serde_json::from_str(r#"{"access_token": "def", "expires_in": 300}"#).unwrap();
+        assert_eq!(token.into_token().as_deref(), Some("def"));
+
+        let token: OCIToken = serde_json::from_str("{}").unwrap();
+        assert_eq!(token.into_token(), None);
+    }
+
     // test pulling an image from OCI registry
     #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
     #[tokio::test]
     async fn test_oci_middleware() {
-        let middleware = OciMiddleware;
+        let middleware = OciMiddleware::default();
 
         let client = reqwest::Client::new();
         let client_with_middleware = reqwest_middleware::ClientBuilder::new(client)
@@ -325,7 +576,7 @@ mod tests {
     #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
     #[tokio::test]
     async fn test_oci_middleware_repodata() {
-        let middleware = OciMiddleware;
+        let middleware = OciMiddleware::default();
 
         let client = reqwest::Client::new();
         let client_with_middleware = reqwest_middleware::ClientBuilder::new(client)
@@ -0,0 +1,308 @@
+//! Middleware that retries transient failures, honoring `Retry-After` headers.
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use http::Extensions;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next, Result};
+
+use crate::{
+    logging_middleware::{redact_error, redact_url},
+    retry_policies::{
+        default_retry_policy, retry_after, ExponentialBackoff, RetryAttempt, RetryDecision,
+        RetryPolicy,
+    },
+};
+
+/// A callback that is invoked every time a request is about to be retried.
+pub type RetryCallback = Arc<dyn Fn(&RetryAttempt) + Send + Sync>;
+
+/// `reqwest` middleware that retries requests that failed with a transient
+/// error: connection errors, timeouts and `408`, `429` and `5xx` responses.
+///
+/// The time between attempts is determined by the [`RetryPolicy`]. If the
+/// server sends a `Retry-After` header with a `429` or `503` response the
+/// request is not retried before the requested time. If the server asks to
+/// wait longer than [`Self::with_max_retry_after`] the response is returned
+/// immediately instead.
+///
+/// A callback can be registered for the whole client with
+/// [`Self::with_retry_callback`], or for a single request by adding a
+/// [`RetryCallback`] to the request extensions.
+#[derive(Clone)]
+pub struct RetryMiddleware {
+    policy: Arc<dyn RetryPolicy + Send + Sync>,
+    max_retries: Option<u32>,
+    jitter: f64,
+    max_retry_after: Duration,
+    on_retry: Option<RetryCallback>,
+}
+
+impl Default for RetryMiddleware {
+    fn default() -> Self {
+        Self::new(default_retry_policy())
+    }
+}
+
+impl RetryMiddleware {
+    /// Creates a new middleware that uses the given exponential backoff
+    /// policy.
+    pub fn new(policy: ExponentialBackoff) -> Self {
+        let max_retries = policy.max_n_retries;
+        Self {
+            max_retries,
+            ..Self::new_with_policy(policy)
+        }
+    }
+
+    /// Creates a new middleware with an arbitrary retry policy. Because the
+    /// maximum number of retries cannot be determined from the policy, it is
+    /// not reported to the callbacks.
+    pub fn new_with_policy(policy: impl RetryPolicy + Send + Sync + 'static) -> Self {
+        Self {
+            policy: Arc::new(policy),
+            max_retries: None,
+            jitter: 0.1,
+            max_retry_after: Duration::from_secs(300),
+            on_retry: None,
+        }
+    }
+
+    /// Sets the fraction by which the time between attempts is randomly
+    /// extended, so that many clients that were rejected at the same time do
+    /// not all retry at the same moment. A value of `0.5` extends the wait by
+    /// up to 50%. Defaults to `0.1`.
+    #[must_use]
+    pub fn with_jitter(self, jitter: f64) -> Self {
+        Self {
+            jitter: jitter.max(0.0),
+            ..self
+        }
+    }
+
+    /// Sets the longest `Retry-After` delay that is honored. Defaults to five
+    /// minutes.
+    #[must_use]
+    pub fn with_max_retry_after(self, max_retry_after: Duration) -> Self {
+        Self {
+            max_retry_after,
+            ..self
+        }
+    }
+
+    /// Sets a callback that is invoked every time a request is retried.
+    #[must_use]
+    pub fn with_retry_callback(self, on_retry: RetryCallback) -> Self {
+        Self {
+            on_retry: Some(on_retry),
+            ..self
+        }
+    }
+
+    fn apply_jitter(&self, wait: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return wait;
+        }
+        wait.mul_f64(1.0 + self.jitter * rand::random::<f64>())
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_transient_error(err: &reqwest_middleware::Error) -> bool {
+    match err {
+        reqwest_middleware::Error::Reqwest(err) => {
+            err.is_connect() || err.is_timeout() || err.is_request()
+        }
+        reqwest_middleware::Error::Middleware(_) => false,
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RetryMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let request_start = SystemTime::now();
+        let mut past_retries = 0;
+        loop {
+            // Requests with a streaming body cannot be retried.
+            let Some(attempt_req) = req.try_clone() else {
+                return next.run(req, extensions).await;
+            };
+
+            let result = next.clone().run(attempt_req, extensions).await;
+            let (reason, retry_after) = match &result {
+                Ok(response) if is_transient_status(response.status()) => {
+                    (response.status().to_string(), retry_after(response))
+                }
+                Err(err) if is_transient_error(err) => (redact_error(err, req.url()), None),
+                _ => return result,
+            };
+
+            let policy_wait = match self.policy.should_retry(request_start, past_retries) {
+                RetryDecision::Retry { execute_after } => execute_after
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO),
+                RetryDecision::DoNotRetry => return result,
+            };
+            let wait = match retry_after {
+                Some(retry_after) if retry_after > self.max_retry_after => {
+                    tracing::debug!(
+                        "not retrying {} because the server asked to wait {retry_after:?}",
+                        redact_url(req.url())
+                    );
+                    return result;
+                }
+                Some(retry_after) => retry_after.max(policy_wait),
+                None => policy_wait,
+            };
+            let wait = self.apply_jitter(wait);
+            past_retries += 1;
+
+            let attempt = RetryAttempt {
+                url: redact_url(req.url()),
+                attempt: past_retries,
+                max_retries: self.max_retries,
+                wait,
+                reason,
+            };
+            tracing::warn!(
+                "request to {} failed: {}, retrying ({attempt})",
+                attempt.url,
+                attempt.reason
+            );
+            if let Some(on_retry) = &self.on_retry {
+                on_retry(&attempt);
+            }
+            if let Some(on_retry) = extensions.get::<RetryCallback>() {
+                on_retry(&attempt);
+            }
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
+
+    use super::*;
+    use crate::test_utils::{spawn_test_server, unused_local_url};
+
+    #[tokio::test]
+    async fn test_honor_retry_after() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route(
+            "/repodata.json",
+            get({
+                let requests = requests.clone();
+                move || async move {
+                    if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                        (StatusCode::SERVICE_UNAVAILABLE, [("Retry-After", "1")]).into_response()
+                    } else {
+                        "{}".into_response()
+                    }
+                }
+            }),
+        );
+        let url = spawn_test_server(router).await;
+
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let policy = ExponentialBackoff::builder()
+            .retry_bounds(Duration::from_millis(1), Duration::from_millis(10))
+            .build_with_max_retries(5);
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::default())
+            .with(
+                RetryMiddleware::new(policy)
+                    .with_jitter(0.0)
+                    .with_retry_callback({
+                        let attempts = attempts.clone();
+                        Arc::new(move |attempt| attempts.lock().unwrap().push(attempt.clone()))
+                    }),
+            )
+            .build();
+
+        let start = std::time::Instant::now();
+        let response = client
+            .get(url.join("repodata.json").unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        let attempts = attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].attempt, 1);
+        assert_eq!(attempts[0].max_retries, Some(5));
+        assert_eq!(attempts[0].wait, Duration::from_secs(1));
+        assert_eq!(attempts[0].to_string(), "attempt 1/5, waiting 1s");
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_too_long() {
+        let router = Router::new().route(
+            "/repodata.json",
+            get(|| async { (StatusCode::TOO_MANY_REQUESTS, [("Retry-After", "3600")]) }),
+        );
+        let url = spawn_test_server(router).await;
+
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::default())
+            .with(RetryMiddleware::default())
+            .build();
+        let response = client
+            .get(url.join("repodata.json").unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_retry_reason_is_redacted() {
+        let url = unused_local_url().await;
+
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let policy = ExponentialBackoff::builder()
+            .retry_bounds(Duration::from_millis(1), Duration::from_millis(10))
+            .build_with_max_retries(1);
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::default())
+            .with(
+                RetryMiddleware::new(policy)
+                    .with_jitter(0.0)
+                    .with_retry_callback({
+                        let attempts = attempts.clone();
+                        Arc::new(move |attempt| attempts.lock().unwrap().push(attempt.clone()))
+                    }),
+            )
+            .build();
+
+        client
+            .get(url.join("t/tk-123/repodata.json?sig=secret").unwrap())
+            .send()
+            .await
+            .unwrap_err();
+
+        let attempts = attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert!(!attempts[0].reason.contains("secret"));
+        assert!(!attempts[0].reason.contains("tk-123"));
+        assert!(!attempts[0].url.as_str().contains("secret"));
+    }
+}
@@ -5,7 +5,13 @@
 //! anything.
 
 pub use retry_policies::{policies::*, Jitter, RetryDecision, RetryPolicy};
-use std::time::SystemTime;
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use url::Url;
 
 /// A simple [`RetryPolicy`] that just never retries.
 #[derive(Clone, Copy)]
@@ -23,3 +29,97 @@ impl RetryPolicy for DoNotRetryPolicy {
 pub fn default_retry_policy() -> ExponentialBackoff {
     ExponentialBackoff::builder().build_with_max_retries(3)
 }
+
+/// Information about a request that is about to be retried. This is passed to
+/// retry callbacks so progress reporters can show messages like
+/// "retrying numpy (attempt 2/5, waiting 8s)".
+#[derive(Debug, Clone)]
+pub struct RetryAttempt {
+    /// The URL of the request that is retried (with secrets redacted).
+    pub url: Url,
+
+    /// The number of the upcoming retry, starting at 1.
+    pub attempt: u32,
+
+    /// The maximum number of retries, if known.
+    pub max_retries: Option<u32>,
+
+    /// How long to wait before the request is retried.
+    pub wait: Duration,
+
+    /// Why the previous attempt failed.
+    pub reason: String,
+}
+
+impl fmt::Display for RetryAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max_retries {
+            Some(max_retries) => write!(f, "attempt {}/{}", self.attempt, max_retries)?,
+            None => write!(f, "attempt {}", self.attempt)?,
+        }
+        write!(f, ", waiting {}s", self.wait.as_secs_f64().ceil())
+    }
+}
+
+/// Returns how long the server asked us to wait before retrying, based on the
+/// `Retry-After` header of a `429 Too Many Requests` or `503 Service
+/// Unavailable` response. The header either contains a number of seconds or an
+/// HTTP date.
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    if !matches!(
+        response.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+    parse_retry_after(
+        response.headers().get(RETRY_AFTER)?.to_str().ok()?,
+        SystemTime::now(),
+    )
+}
+
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let date = SystemTime::from(date);
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:47 GMT", now),
+            Some(Duration::from_secs(10))
+        );
+        // Dates in the past mean the request can be retried immediately.
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:27 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_display_retry_attempt() {
+        let attempt = RetryAttempt {
+            url: Url::parse("https://conda.anaconda.org/conda-forge/numpy.conda").unwrap(),
+            attempt: 2,
+            max_retries: Some(5),
+            wait: Duration::from_millis(7500),
+            reason: "503 Service Unavailable".to_string(),
+        };
+        assert_eq!(attempt.to_string(), "attempt 2/5, waiting 8s");
+    }
+}
@@ -0,0 +1,22 @@
+use axum::Router;
+use url::Url;
+
+/// Serves the router on a random local port and returns the URL of the
+/// server.
+pub(crate) async fn spawn_test_server(router: Router) -> Url {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Url::parse(&format!("http://{addr}/")).unwrap()
+}
+
+/// Returns the URL of a local port that nobody listens on.
+pub(crate) async fn unused_local_url() -> Url {
+    // Bind and immediately drop a listener to get a port nobody listens on.
+    let addr = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    Url::parse(&format!("http://{addr}/")).unwrap()
+}
@@ -154,4 +154,10 @@ pub enum CacheAction {
 
     /// Do not use the cache even if there is an up to date entry.
     NoCache,
+
+    /// Use the cache, but always check with the server whether it is still
+    /// up to date, even if it has not expired yet. If the server reports that
+    /// nothing changed (e.g. based on the `ETag` or `Last-Modified` headers)
+    /// the cached value is used without downloading it again.
+    ForceRefresh,
 }
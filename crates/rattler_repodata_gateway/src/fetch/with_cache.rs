@@ -52,6 +52,12 @@ pub struct FetchRepoDataOptions {
     /// When enabled, the bz2 variant will be used if available
     pub bz2_enabled: bool,
 
+    /// Allows using cached repodata that expired at most this long ago
+    /// without checking with the server. This trades freshness for speed.
+    /// Cached repodata without expiration information is considered expired
+    /// as soon as it was downloaded.
+    pub max_stale: Option<Duration>,
+
     /// Retry policy to use when streaming the response is interrupted. If this
     /// is `None` the default retry policy is used.
     pub retry_policy: Option<Arc<dyn RetryPolicy + Send + Sync>>,
@@ -65,6 +71,7 @@ impl Default for FetchRepoDataOptions {
             jlap_enabled: true,
             zstd_enabled: true,
             bz2_enabled: true,
+            max_stale: None,
             retry_policy: None,
         }
     }
@@ -226,11 +233,23 @@ pub async fn fetch_repo_data(
         let owned_subdir_url = subdir_url.clone();
         let owned_cache_path = cache_path.clone();
         let owned_cache_key = cache_key.clone();
+        let max_stale = options.max_stale;
         let cache_state = tokio::task::spawn_blocking(move || {
-            validate_cached_state(&owned_cache_path, &owned_subdir_url, &owned_cache_key)
+            validate_cached_state(
+                &owned_cache_path,
+                &owned_subdir_url,
+                &owned_cache_key,
+                max_stale,
+            )
         })
         .await?;
         match (cache_state, options.cache_action) {
+            (ValidatedCacheState::UpToDate(cache_state), CacheAction::ForceRefresh) => {
+                // The cache is up to date but we have been asked to check with the server
+                // anyway. The cache headers are sent along so we only download the
+                // repodata if it changed.
+                Some(cache_state)
+            }
             (ValidatedCacheState::UpToDate(cache_state), _)
             | (ValidatedCacheState::OutOfDate(cache_state), CacheAction::ForceCacheOnly) => {
                 // Cache is up to date or we dont care about whether or not its up to date,
@@ -272,7 +291,7 @@ pub async fn fetch_repo_data(
 
     // Determine the availability of variants based on the cache or by querying the
     // remote.
-    let variant_availability = check_variant_availability(
+    let mut variant_availability = check_variant_availability(
         &client,
         &subdir_url,
         cache_state.as_ref(),
@@ -338,17 +357,14 @@ pub async fn fetch_repo_data(
     };
 
     // Determine which variant to download
-    let repo_data_url = if has_zst {
-        subdir_url
-            .join(&format!("{}.zst", options.variant.file_name()))
-            .unwrap()
+    let mut encoding = if has_zst {
+        Encoding::Zst
     } else if has_bz2 {
-        subdir_url
-            .join(&format!("{}.bz2", options.variant.file_name()))
-            .unwrap()
+        Encoding::Bz2
     } else {
-        subdir_url.join(options.variant.file_name()).unwrap()
+        Encoding::Passthrough
     };
+    let mut repo_data_url = variant_url(&subdir_url, options.variant.file_name(), encoding);
 
     // Construct the HTTP request
     tracing::debug!("fetching '{}'", &repo_data_url);
@@ -371,17 +387,18 @@ pub async fn fetch_repo_data(
     );
 
     // Add previous cache headers if we have them
+    let mut conditional_headers = headers.clone();
     if let Some(cache_headers) = cache_state.as_ref().map(|state| &state.cache_headers) {
-        cache_headers.add_to_request(&mut headers);
+        cache_headers.add_to_request(&mut conditional_headers);
     }
     // Send the request and wait for a reply
-    let download_reporter = reporter
+    let mut download_reporter = reporter
         .as_deref()
         .and_then(|reporter| reporter.download_reporter())
         .map(|r| (r, r.on_download_start(&repo_data_url)));
 
-    let (client, request) = request_builder.headers(headers).build_split();
-    let request = request.expect("must have a valid request at this point");
+    let (client, request) = request_builder.headers(conditional_headers).build_split();
+    let mut request = request.expect("must have a valid request at this point");
     let default_retry_behavior = default_retry_policy();
     let retry_behavior = options
         .retry_policy
@@ -391,6 +408,45 @@ pub async fn fetch_repo_data(
     let (temp_file, blake2_hash, response_url, cache_headers) = loop {
         let request_start_time = SystemTime::now();
         let response = match client.execute(request.try_clone().unwrap()).await {
+            Ok(response)
+                if response.status() == StatusCode::NOT_FOUND
+                    && encoding != Encoding::Passthrough =>
+            {
+                // The compressed variant seemed to be available but the server doesn't have
+                // it (anymore). Mark it as unavailable and fall back to the next variant.
+                tracing::info!(
+                    "'{}' was not found, falling back to another variant",
+                    repo_data_url.clone().redact()
+                );
+                let unavailable = Some(Expiring {
+                    value: false,
+                    last_checked: chrono::Utc::now(),
+                });
+                if encoding == Encoding::Zst {
+                    variant_availability.has_zst = unavailable;
+                } else {
+                    variant_availability.has_bz2 = unavailable;
+                }
+                encoding = if has_bz2 && variant_availability.has_bz2() {
+                    Encoding::Bz2
+                } else {
+                    Encoding::Passthrough
+                };
+                repo_data_url = variant_url(&subdir_url, options.variant.file_name(), encoding);
+
+                // The cache headers belong to a different file so we don't send them.
+                request = client
+                    .get(repo_data_url.clone())
+                    .headers(headers.clone())
+                    .build()
+                    .expect("must have a valid request at this point");
+                if let Some((reporter, index)) = download_reporter.as_mut() {
+                    reporter.on_download_complete(response.url(), *index);
+                    *index = reporter.on_download_start(&repo_data_url);
+                }
+                retry_count = 0;
+                continue;
+            }
             Ok(response) if response.status() == StatusCode::NOT_FOUND => {
                 return Err(FetchRepoDataError::NotFound(RepoDataNotFoundError::from(
                     response.error_for_status().unwrap_err(),
@@ -454,13 +510,7 @@ pub async fn fetch_repo_data(
         let stream_result = stream_and_decode_to_file(
             repo_data_url.clone(),
             response,
-            if has_zst {
-                Encoding::Zst
-            } else if has_bz2 {
-                Encoding::Bz2
-            } else {
-                Encoding::Passthrough
-            },
+            encoding,
             &cache_path,
             download_reporter,
         )
@@ -626,6 +676,16 @@ async fn stream_and_decode_to_file(
     Ok((temp_file, hash))
 }
 
+/// Returns the url of the variant of `file_name` with the given encoding.
+fn variant_url(subdir_url: &Url, file_name: &str, encoding: Encoding) -> Url {
+    let file_name = match encoding {
+        Encoding::Zst => format!("{file_name}.zst"),
+        Encoding::Bz2 => format!("{file_name}.bz2"),
+        Encoding::Passthrough | Encoding::GZip => file_name.to_string(),
+    };
+    subdir_url.join(&file_name).expect("file name is valid")
+}
+
 /// Describes the availability of certain `repodata.json`.
 #[derive(Debug)]
 pub struct VariantAvailability {
@@ -837,6 +897,7 @@ fn validate_cached_state(
     cache_path: &Path,
     subdir_url: &Url,
     cache_key: &str,
+    max_stale: Option<Duration>,
 ) -> ValidatedCacheState {
     let repo_data_json_path = cache_path.join(format!("{cache_key}.json"));
     let cache_state_path = cache_path.join(format!("{cache_key}.info.json"));
@@ -942,6 +1003,7 @@ fn validate_cached_state(
 
     // Parse the cache control header, and determine if the cache is out of date or
     // not.
+    let max_stale = max_stale.unwrap_or_default();
     if let Some(cache_control) = cache_state.cache_headers.cache_control.as_deref() {
         match CacheControl::from_value(cache_control) {
             None => {
@@ -955,11 +1017,11 @@ fn validate_cached_state(
                 max_age: Some(duration),
                 ..
             }) => {
-                if cache_age > duration {
+                if cache_age > duration + max_stale {
                     tracing::debug!(
                         "Cache is {} old but can at most be {} old. Assuming out of date...",
                         humantime::format_duration(cache_age),
-                        humantime::format_duration(duration),
+                        humantime::format_duration(duration + max_stale),
                     );
                     return ValidatedCacheState::OutOfDate(cache_state);
                 }
@@ -972,7 +1034,7 @@ fn validate_cached_state(
                 return ValidatedCacheState::OutOfDate(cache_state);
             }
         }
-    } else {
+    } else if cache_age > max_stale {
         tracing::warn!(
             "previous cache state does not contain cache_control header. Assuming out of date..."
         );
@@ -999,11 +1061,11 @@ mod test {
     use axum::{
         body::Body,
         extract::State,
-        http::{Request, StatusCode},
+        http::{Method, Request, StatusCode},
         middleware,
         middleware::Next,
         response::{IntoResponse, Response},
-        routing::get,
+        routing::{any, get},
         Router,
     };
     use bytes::Bytes;
@@ -1020,7 +1082,7 @@ mod test {
     use crate::{
         fetch::{
             with_cache::{fetch_repo_data, CacheResult, CachedRepoData, FetchRepoDataOptions},
-            FetchRepoDataError, RepoDataNotFoundError,
+            CacheAction, FetchRepoDataError, RepoDataNotFoundError,
         },
         utils::{simple_channel_server::SimpleChannelServer, Encoding},
         DownloadReporter, JLAPReporter, Reporter,
@@ -1191,6 +1253,46 @@ mod test {
         assert_matches!(cache_result, CacheResult::CacheOutdated);
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_max_stale_and_force_refresh() {
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |options: FetchRepoDataOptions| {
+            fetch_repo_data(
+                server.url(),
+                LazyClient::default(),
+                cache_dir.path().to_owned(),
+                options,
+                None,
+            )
+        };
+
+        let CachedRepoData { cache_result, .. } =
+            fetch(FetchRepoDataOptions::default()).await.unwrap();
+        assert_matches!(cache_result, CacheResult::CacheNotPresent);
+
+        // The server does not send cache-control headers so the cache is immediately
+        // stale, but we allow using stale repodata.
+        let max_stale = FetchRepoDataOptions {
+            max_stale: Some(std::time::Duration::from_secs(3600)),
+            ..FetchRepoDataOptions::default()
+        };
+        let CachedRepoData { cache_result, .. } = fetch(max_stale.clone()).await.unwrap();
+        assert_matches!(cache_result, CacheResult::CacheHit);
+
+        // Forcing a refresh revalidates the cache with the server.
+        let CachedRepoData { cache_result, .. } = fetch(FetchRepoDataOptions {
+            cache_action: CacheAction::ForceRefresh,
+            ..max_stale
+        })
+        .await
+        .unwrap();
+        assert_matches!(cache_result, CacheResult::CacheHitAfterFetch);
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_zst_works() {
@@ -1325,6 +1427,51 @@ mod test {
         );
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_missing_zst_falls_back() {
+        // A server that claims to have a zst variant when asked with a HEAD request
+        // but that doesn't actually serve it.
+        let router = Router::new()
+            .route(
+                "/repodata.json.zst",
+                any(|method: Method| async move {
+                    if method == Method::HEAD {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::NOT_FOUND
+                    }
+                }),
+            )
+            .route("/repodata.json", get(|| async { FAKE_REPO_DATA }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router.into_make_service()).into_future());
+        let server_url = Url::parse(&format!("http://localhost:{}", addr.port())).unwrap();
+
+        let cache_dir = TempDir::new().unwrap();
+        let result = fetch_repo_data(
+            server_url,
+            LazyClient::default(),
+            cache_dir.path().into(),
+            FetchRepoDataOptions::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(result.repo_data_json_path).unwrap(),
+            FAKE_REPO_DATA
+        );
+        assert!(result.cache_state.url.path().ends_with("repodata.json"));
+        assert_matches!(
+            result.cache_state.has_zst, Some(super::Expiring {
+                value, ..
+            }) if !value
+        );
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_gzip_transfer_encoding() {
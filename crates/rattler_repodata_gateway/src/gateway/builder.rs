@@ -1,8 +1,9 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use coalesced_map::CoalescedMap;
 #[cfg(not(target_arch = "wasm32"))]
 use rattler_cache::package_cache::PackageCache;
+use rattler_conda_types::{ChannelUrl, MatchSpec, RepoDataPatch};
 use rattler_networking::LazyClient;
 use reqwest::Client;
 use reqwest_middleware::ClientWithMiddleware;
@@ -49,6 +50,8 @@ pub struct GatewayBuilder {
     #[cfg(not(target_arch = "wasm32"))]
     package_cache: Option<PackageCache>,
     max_concurrent_requests: MaxConcurrency,
+    repodata_patches: HashMap<ChannelUrl, Vec<RepoDataPatch>>,
+    excluded_packages: HashMap<ChannelUrl, Vec<MatchSpec>>,
 }
 
 impl GatewayBuilder {
@@ -133,6 +136,68 @@ impl GatewayBuilder {
         self
     }
 
+    /// Adds repodata patches that are applied to the records of the channel
+    /// with the given base url before they are returned from a query. This
+    /// can be used to apply the hotfixes from a channel's
+    /// `patch_instructions.json` files (see [`RepoDataPatch::from_package`])
+    /// or custom patches on top of the repodata a channel serves.
+    ///
+    /// Multiple patches can be added for the same channel, they are applied in
+    /// the order they were added.
+    #[must_use]
+    pub fn with_repodata_patch(
+        mut self,
+        channel: impl Into<ChannelUrl>,
+        patch: RepoDataPatch,
+    ) -> Self {
+        self.add_repodata_patch(channel, patch);
+        self
+    }
+
+    /// Adds repodata patches that are applied to the records of the channel
+    /// with the given base url. See [`Self::with_repodata_patch`].
+    pub fn add_repodata_patch(
+        &mut self,
+        channel: impl Into<ChannelUrl>,
+        patch: RepoDataPatch,
+    ) -> &mut Self {
+        self.repodata_patches
+            .entry(channel.into())
+            .or_default()
+            .push(patch);
+        self
+    }
+
+    /// Excludes all records of the channel with the given base url that match
+    /// any of the given specs. Excluded records are never returned from a
+    /// query, which for instance makes it possible to never take `openssl`
+    /// from a specific channel.
+    ///
+    /// Exclusions are applied after any repodata patches.
+    #[must_use]
+    pub fn with_excluded_packages(
+        mut self,
+        channel: impl Into<ChannelUrl>,
+        specs: impl IntoIterator<Item = MatchSpec>,
+    ) -> Self {
+        self.add_excluded_packages(channel, specs);
+        self
+    }
+
+    /// Excludes all records of the channel with the given base url that match
+    /// any of the given specs. See [`Self::with_excluded_packages`].
+    pub fn add_excluded_packages(
+        &mut self,
+        channel: impl Into<ChannelUrl>,
+        specs: impl IntoIterator<Item = MatchSpec>,
+    ) -> &mut Self {
+        self.excluded_packages
+            .entry(channel.into())
+            .or_default()
+            .extend(specs);
+        self
+    }
+
     /// Finish the construction of the gateway returning a constructed gateway.
     pub fn finish(self) -> Gateway {
         let client = self.client.unwrap_or_else(|| {
@@ -172,6 +237,10 @@ impl GatewayBuilder {
                 package_cache,
                 subdir_run_exports_cache: Arc::default(),
                 concurrent_requests_semaphore,
+                repodata_patches: self.repodata_patches,
+                excluded_packages: self.excluded_packages,
+                #[cfg(not(target_arch = "wasm32"))]
+                background_refreshes: parking_lot::Mutex::default(),
             }),
         }
     }
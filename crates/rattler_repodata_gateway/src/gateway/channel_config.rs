@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use rattler_conda_types::ChannelUrl;
 use url::Url;
@@ -21,12 +21,29 @@ pub struct SourceConfig {
     /// true)
     pub bz2_enabled: bool,
 
-    /// When enabled, sharded repodata will be used if available.
+    /// When enabled, sharded repodata (CEP-16) will be used if available.
+    /// Only the shards of the requested packages are downloaded instead of the
+    /// entire `repodata.json`. If the `repodata_shards.msgpack.zst` index
+    /// cannot be fetched, for instance because the channel does not provide
+    /// it or access to it is denied, `repodata.json` is used instead
+    /// (defaults to true)
     pub sharded_enabled: bool,
 
     /// Describes fetching repodata from a channel should interact with any
     /// caches.
     pub cache_action: CacheAction,
+
+    /// Allows using a cached `repodata.json` or shard index that expired at
+    /// most this long ago without checking with the server (defaults to
+    /// `None`)
+    pub max_stale: Option<Duration>,
+
+    /// When enabled, cached repodata is used immediately regardless of its
+    /// age while it is refreshed in the background. Use
+    /// [`crate::Gateway::wait_for_background_refreshes`] to wait for the
+    /// refresh to complete. Only applies when the `cache_action` is
+    /// [`CacheAction::CacheOrFetch`] (defaults to false)
+    pub stale_while_revalidate: bool,
 }
 
 impl Default for SourceConfig {
@@ -35,8 +52,10 @@ impl Default for SourceConfig {
             jlap_enabled: true,
             zstd_enabled: true,
             bz2_enabled: true,
-            sharded_enabled: false,
+            sharded_enabled: true,
             cache_action: CacheAction::default(),
+            max_stale: None,
+            stale_while_revalidate: false,
         }
     }
 }
@@ -50,6 +69,8 @@ impl From<rattler_config::config::repodata_config::RepodataChannelConfig> for So
             bz2_enabled: !value.disable_bzip2.unwrap_or(false),
             sharded_enabled: !value.disable_sharded.unwrap_or(false),
             cache_action: CacheAction::default(),
+            max_stale: None,
+            stale_while_revalidate: false,
         }
     }
 }
@@ -1,11 +1,8 @@
 use std::{future::IntoFuture, sync::Arc};
 
 use futures::FutureExt;
-use rattler_cache::package_cache::{CacheKey, PackageCache, PackageCacheError};
-use rattler_conda_types::{
-    package::{ArchiveIdentifier, IndexJson, PackageFile},
-    ConvertSubdirError, PackageRecord, RepoDataRecord,
-};
+use rattler_cache::package_cache::{PackageCache, PackageCacheError};
+use rattler_conda_types::{package::ArchiveIdentifier, ConvertSubdirError, RepoDataRecord};
 use rattler_digest::{Md5Hash, Sha256Hash};
 use rattler_networking::LazyClient;
 use url::Url;
@@ -55,50 +52,34 @@ impl DirectUrlQuery {
     /// Execute the Repodata query using the cache as a source for the
     /// index.json
     pub async fn execute(self) -> Result<Arc<[RepoDataRecord]>, DirectUrlQueryError> {
-        // Convert the url to an archive identifier.
-        let Some(archive_identifier) = ArchiveIdentifier::try_from_url(&self.url) else {
+        // Make sure the url points to a package archive.
+        if ArchiveIdentifier::try_from_url(&self.url).is_none() {
             let filename = self.url.path_segments().and_then(Iterator::last);
             return Err(DirectUrlQueryError::InvalidFilename(
                 filename.unwrap_or("").to_string(),
             ));
-        };
-
-        // Construct a cache key
-        let cache_key = CacheKey::from(archive_identifier)
-            .with_opt_sha256(self.sha256)
-            .with_opt_md5(self.md5);
+        }
 
         // TODO: Optimize this by only parsing the index json from stream.
         // Get package on system
-        let cache_lock = self
+        let (_cache_lock, repodata_record) = self
             .package_cache
-            .get_or_fetch_from_url(
-                cache_key,
+            .get_or_fetch_from_direct_url(
                 self.url.clone(),
+                self.sha256,
+                self.md5,
                 self.client.clone(),
                 // Should we add a reporter?
                 None,
             )
             .await?;
 
-        // Extract package record from index json
-        let index_json = IndexJson::from_package_directory(cache_lock.path())?;
-        let package_record = PackageRecord::from_index_json(
-            index_json,
-            None, // size is unknown for direct urls
-            self.sha256,
-            self.md5,
-        )?;
-
-        tracing::debug!("Package record build from direct url: {:?}", package_record);
-
-        Ok(Arc::new([RepoDataRecord {
-            package_record,
-            // File name is the same as the url.
-            file_name: self.url.clone().to_string(),
-            url: self.url.clone(),
-            channel: None,
-        }]))
+        tracing::debug!(
+            "Package record build from direct url: {:?}",
+            repodata_record.package_record
+        );
+
+        Ok(Arc::new([repodata_record]))
     }
 }
 
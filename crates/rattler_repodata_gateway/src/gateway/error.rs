@@ -58,6 +58,9 @@ pub enum GatewayError {
     #[error("{0}")]
     CacheError(String),
 
+    #[error(transparent)]
+    RunExportExtractorError(#[from] super::RunExportExtractorError),
+
     #[error("direct url queries are not supported ({0})")]
     DirectUrlQueryNotSupported(String),
 }
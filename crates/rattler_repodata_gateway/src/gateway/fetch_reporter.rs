@@ -0,0 +1,143 @@
+//! Translates the download events of a single subdirectory into
+//! [`GatewayReporter`] events.
+
+use std::{sync::Arc, time::Instant};
+
+use parking_lot::Mutex;
+use url::Url;
+
+use crate::{
+    reporter::{GatewayReporter, SubdirFetchInfo, SubdirFetchSource},
+    DownloadReporter, JLAPReporter, Reporter,
+};
+
+/// A [`Reporter`] that is used while loading a single subdirectory. Download
+/// events are forwarded to the wrapped reporter and aggregated into the
+/// progress of the subdirectory.
+pub(super) struct SubdirFetchReporter {
+    reporter: Arc<dyn Reporter>,
+    index: usize,
+    start: Instant,
+    state: Mutex<FetchState>,
+}
+
+#[derive(Default)]
+struct FetchState {
+    downloads: Vec<Download>,
+    last_download_complete: Option<Instant>,
+}
+
+struct Download {
+    /// The index returned by the wrapped download reporter.
+    index: usize,
+    bytes_downloaded: usize,
+    total_bytes: Option<usize>,
+}
+
+impl SubdirFetchReporter {
+    /// Notifies the reporter that loading the subdirectory at `url` started.
+    /// Returns `None` if the reporter is not interested in gateway events.
+    pub fn start(reporter: Arc<dyn Reporter>, url: &Url) -> Option<Arc<Self>> {
+        let index = reporter.gateway_reporter()?.on_subdir_fetch_start(url);
+        Some(Arc::new(Self {
+            reporter,
+            index,
+            start: Instant::now(),
+            state: Mutex::default(),
+        }))
+    }
+
+    fn gateway_reporter(&self) -> &dyn GatewayReporter {
+        self.reporter
+            .gateway_reporter()
+            .expect("the gateway reporter was available when the fetch started")
+    }
+
+    /// Notifies the reporter that loading the subdirectory finished.
+    pub fn finish(&self) {
+        let now = Instant::now();
+        let state = self.state.lock();
+        let bytes_downloaded = state.downloads.iter().map(|d| d.bytes_downloaded).sum();
+        let info = SubdirFetchInfo {
+            source: if bytes_downloaded > 0 {
+                SubdirFetchSource::Download
+            } else {
+                SubdirFetchSource::Cache
+            },
+            bytes_downloaded,
+            duration: now - self.start,
+            parse_duration: now - state.last_download_complete.unwrap_or(self.start),
+        };
+        self.gateway_reporter()
+            .on_subdir_fetch_complete(self.index, &info);
+    }
+}
+
+impl DownloadReporter for SubdirFetchReporter {
+    fn on_download_start(&self, url: &Url) -> usize {
+        let index = self
+            .reporter
+            .download_reporter()
+            .map_or(0, |reporter| reporter.on_download_start(url));
+        let mut state = self.state.lock();
+        state.downloads.push(Download {
+            index,
+            bytes_downloaded: 0,
+            total_bytes: None,
+        });
+        state.downloads.len() - 1
+    }
+
+    fn on_download_progress(
+        &self,
+        url: &Url,
+        index: usize,
+        bytes_downloaded: usize,
+        total_bytes: Option<usize>,
+    ) {
+        let (inner_index, subdir_bytes_downloaded, subdir_total_bytes) = {
+            let mut state = self.state.lock();
+            let download = &mut state.downloads[index];
+            download.bytes_downloaded = bytes_downloaded;
+            download.total_bytes = total_bytes;
+            (
+                download.index,
+                state.downloads.iter().map(|d| d.bytes_downloaded).sum(),
+                state
+                    .downloads
+                    .iter()
+                    .map(|d| d.total_bytes)
+                    .sum::<Option<usize>>(),
+            )
+        };
+        if let Some(reporter) = self.reporter.download_reporter() {
+            reporter.on_download_progress(url, inner_index, bytes_downloaded, total_bytes);
+        }
+        self.gateway_reporter().on_subdir_fetch_progress(
+            self.index,
+            subdir_bytes_downloaded,
+            subdir_total_bytes,
+        );
+    }
+
+    fn on_download_complete(&self, url: &Url, index: usize) {
+        let inner_index = {
+            let mut state = self.state.lock();
+            state.last_download_complete = Some(Instant::now());
+            state.downloads[index].index
+        };
+        if let Some(reporter) = self.reporter.download_reporter() {
+            reporter.on_download_complete(url, inner_index);
+        }
+    }
+}
+
+impl Reporter for SubdirFetchReporter {
+    fn download_reporter(&self) -> Option<&dyn DownloadReporter> {
+        Some(self)
+    }
+
+    fn jlap_reporter(&self) -> Option<&dyn JLAPReporter> {
+        self.reporter.jlap_reporter()
+    }
+}
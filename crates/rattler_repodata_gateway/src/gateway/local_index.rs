@@ -0,0 +1,221 @@
+//! Indexing of local channel directories that contain packages but no
+//! `repodata.json`.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use rattler_conda_types::{package::ArchiveType, ChannelInfo, Platform, RepoData};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use url::Url;
+
+use crate::{utils::url_to_cache_filename, GatewayError};
+
+/// Describes a package archive in an indexed directory. If any of the archives
+/// in a directory changes, the directory is indexed again.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexedArchive {
+    file_name: String,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Indexes the package archives in `subdir_path` and stores the resulting
+/// `repodata.json` in `cache_dir`. Returns the path of the cached
+/// `repodata.json`, or `None` if the directory does not exist or does not
+/// contain any packages.
+///
+/// The index is only recomputed if the archives in the directory have changed
+/// since the last time it was indexed.
+pub(crate) fn index_local_subdir(
+    subdir_path: &Path,
+    platform: Platform,
+    cache_dir: &Path,
+) -> Result<Option<PathBuf>, GatewayError> {
+    let entries = match fs_err::read_dir(subdir_path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(GatewayError::IoError(
+                format!("failed to read '{}'", subdir_path.display()),
+                err,
+            ))
+        }
+    };
+
+    let mut archives = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| {
+            GatewayError::IoError(format!("failed to read '{}'", subdir_path.display()), err)
+        })?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if ArchiveType::try_from(&file_name).is_none() {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(|err| {
+            GatewayError::IoError(format!("failed to read '{}'", entry.path().display()), err)
+        })?;
+        if !metadata.is_file() {
+            continue;
+        }
+        archives.push(IndexedArchive {
+            file_name,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+    if archives.is_empty() {
+        return Ok(None);
+    }
+    archives.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    fs_err::create_dir_all(cache_dir).map_err(|err| {
+        GatewayError::IoError(format!("failed to create '{}'", cache_dir.display()), err)
+    })?;
+    let cache_key = Url::from_directory_path(subdir_path)
+        .map(|url| url_to_cache_filename(&url))
+        .map_err(|_| {
+            GatewayError::UnsupportedUrl(format!(
+                "'{}' is not an absolute path",
+                subdir_path.display()
+            ))
+        })?;
+    let repodata_path = cache_dir.join(format!("{cache_key}.indexed.json"));
+    let archives_path = cache_dir.join(format!("{cache_key}.indexed.info.json"));
+
+    // Reuse the previous index if none of the archives changed.
+    let cached_archives = fs_err::read_to_string(&archives_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Vec<IndexedArchive>>(&content).ok());
+    if cached_archives.as_ref() == Some(&archives) && repodata_path.is_file() {
+        tracing::debug!("using cached index of '{}'", subdir_path.display());
+        return Ok(Some(repodata_path));
+    }
+
+    tracing::info!(
+        "indexing {} packages in '{}'",
+        archives.len(),
+        subdir_path.display()
+    );
+    let mut repodata = RepoData {
+        info: Some(ChannelInfo {
+            subdir: Some(platform.to_string()),
+            base_url: None,
+        }),
+        packages: Default::default(),
+        conda_packages: Default::default(),
+        removed: Default::default(),
+        version: Some(2),
+    };
+    for archive in &archives {
+        let archive_path = subdir_path.join(&archive.file_name);
+        let Some(archive_type) = ArchiveType::try_from(&archive.file_name) else {
+            continue;
+        };
+        let record = match archive_type {
+            ArchiveType::Conda => rattler_index::package_record_from_conda(&archive_path),
+            ArchiveType::TarBz2 => rattler_index::package_record_from_tar_bz2(&archive_path),
+        };
+        match record {
+            Ok(record) if record.subdir == platform.as_str() => {
+                let packages = match archive_type {
+                    ArchiveType::Conda => &mut repodata.conda_packages,
+                    ArchiveType::TarBz2 => &mut repodata.packages,
+                };
+                packages.insert(archive.file_name.clone(), record);
+            }
+            Ok(record) => tracing::warn!(
+                "skipping '{}' because it belongs to '{}'",
+                archive_path.display(),
+                record.subdir
+            ),
+            Err(err) => {
+                tracing::warn!("skipping '{}': {err}", archive_path.display());
+            }
+        }
+    }
+
+    // Write the files atomically so concurrent processes never observe a partial
+    // index.
+    let write_json = |path: &Path, content: Vec<u8>| {
+        let mut file = NamedTempFile::new_in(cache_dir)?;
+        std::io::Write::write_all(&mut file, &content)?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    };
+    let repodata_json =
+        serde_json::to_vec(&repodata).expect("serializing repodata should never fail");
+    write_json(&repodata_path, repodata_json).map_err(|err| {
+        GatewayError::IoError(
+            format!("failed to write '{}'", repodata_path.display()),
+            err,
+        )
+    })?;
+    let archives_json =
+        serde_json::to_vec(&archives).expect("serializing the archive list should never fail");
+    write_json(&archives_path, archives_json).map_err(|err| {
+        GatewayError::IoError(
+            format!("failed to write '{}'", archives_path.display()),
+            err,
+        )
+    })?;
+
+    Ok(Some(repodata_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rattler_conda_types::Platform;
+    use tempfile::TempDir;
+
+    use super::index_local_subdir;
+
+    fn test_package() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/packages/empty-0.1.0-h4616a5c_0.conda")
+    }
+
+    #[test]
+    fn test_index_local_subdir() {
+        let channel_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let subdir = channel_dir.path().join("noarch");
+        fs_err::create_dir_all(&subdir).unwrap();
+
+        // A directory without packages is not indexed.
+        assert!(
+            index_local_subdir(&subdir, Platform::NoArch, cache_dir.path())
+                .unwrap()
+                .is_none()
+        );
+
+        fs_err::copy(test_package(), subdir.join("empty-0.1.0-h4616a5c_0.conda")).unwrap();
+        let repodata_path = index_local_subdir(&subdir, Platform::NoArch, cache_dir.path())
+            .unwrap()
+            .unwrap();
+        let repodata: rattler_conda_types::RepoData =
+            serde_json::from_str(&fs_err::read_to_string(&repodata_path).unwrap()).unwrap();
+        assert!(repodata
+            .conda_packages
+            .contains_key("empty-0.1.0-h4616a5c_0.conda"));
+
+        // Indexing again without changes reuses the cached index.
+        let modified = fs_err::metadata(&repodata_path)
+            .unwrap()
+            .modified()
+            .unwrap();
+        index_local_subdir(&subdir, Platform::NoArch, cache_dir.path()).unwrap();
+        assert_eq!(
+            fs_err::metadata(&repodata_path)
+                .unwrap()
+                .modified()
+                .unwrap(),
+            modified
+        );
+    }
+}
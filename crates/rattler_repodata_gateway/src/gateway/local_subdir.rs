@@ -70,6 +70,14 @@ impl SubdirClient for LocalSubdirClient {
         name: &PackageName,
         _reporter: Option<&dyn Reporter>,
     ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
+        // Avoid spawning a task for packages that are not in the subdirectory.
+        if !self
+            .sparse
+            .contains_package(name, PackageFormatSelection::PreferConda)
+        {
+            return Ok(Arc::from([]));
+        }
+
         let sparse_repodata = self.sparse.clone();
         let name = name.clone();
 
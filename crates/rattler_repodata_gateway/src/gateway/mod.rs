@@ -4,6 +4,10 @@ mod channel_config;
 #[cfg(not(target_arch = "wasm32"))]
 mod direct_url_query;
 mod error;
+#[cfg(not(target_arch = "wasm32"))]
+mod fetch_reporter;
+#[cfg(all(feature = "indexing", not(target_arch = "wasm32")))]
+mod local_index;
 mod local_subdir;
 mod query;
 mod remote_subdir;
@@ -13,7 +17,10 @@ mod sharded_subdir;
 mod subdir;
 mod subdir_builder;
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{gateway::subdir_builder::SubdirBuilder, Reporter};
 pub use barrier_cell::BarrierCell;
@@ -21,10 +28,13 @@ pub use builder::{GatewayBuilder, MaxConcurrency};
 pub use channel_config::{ChannelConfig, SourceConfig};
 use coalesced_map::{CoalescedGetError, CoalescedMap};
 pub use error::GatewayError;
-pub use query::{NamesQuery, RepoDataQuery};
+pub use query::{NamesQuery, PlatformFallback, RepoDataQuery};
 #[cfg(not(target_arch = "wasm32"))]
 use rattler_cache::package_cache::PackageCache;
-use rattler_conda_types::{Channel, MatchSpec, Platform, RepoDataRecord};
+use rattler_conda_types::{
+    package::RunExportsJson, Channel, ChannelUrl, MatchSpec, Platform, RepoDataPatch,
+    RepoDataRecord,
+};
 use rattler_networking::LazyClient;
 pub use repo_data::RepoData;
 use run_exports_extractor::{RunExportExtractor, SubdirRunExportsCache};
@@ -115,7 +125,10 @@ impl Gateway {
         )
     }
 
-    /// Return all names from repodata
+    /// Constructs a new [`NamesQuery`] which returns the names of all packages
+    /// that are available in the given channels and platforms. This is
+    /// considerably cheaper than querying records because no records are
+    /// parsed.
     pub fn names<AsChannel, ChannelIter, PlatformIter>(
         &self,
         channels: ChannelIter,
@@ -177,6 +190,69 @@ impl Gateway {
         Ok(())
     }
 
+    /// Returns the run exports of all records that match the given specs.
+    ///
+    /// Run exports are read from the `run_exports.json` file of a channel if
+    /// it is available. Otherwise they are extracted from the package
+    /// archives. Records that do not have any run exports are returned with
+    /// an empty [`RunExportsJson`].
+    pub async fn run_exports<AsChannel, ChannelIter, PlatformIter, PackageNameIter, IntoMatchSpec>(
+        &self,
+        channels: ChannelIter,
+        platforms: PlatformIter,
+        specs: PackageNameIter,
+        progress_reporter: Option<Arc<dyn RunExportsReporter>>,
+    ) -> Result<Vec<(RepoDataRecord, RunExportsJson)>, GatewayError>
+    where
+        AsChannel: Into<Channel>,
+        ChannelIter: IntoIterator<Item = AsChannel>,
+        PlatformIter: IntoIterator<Item = Platform>,
+        <PlatformIter as IntoIterator>::IntoIter: Clone,
+        PackageNameIter: IntoIterator<Item = IntoMatchSpec>,
+        IntoMatchSpec: Into<MatchSpec>,
+    {
+        let mut records = self
+            .query(channels, platforms, specs)
+            .execute_records()
+            .await?;
+        self.ensure_run_exports(records.iter_mut(), progress_reporter)
+            .await?;
+        Ok(records
+            .into_iter()
+            .map(|record| {
+                let run_exports = record
+                    .package_record
+                    .run_exports
+                    .clone()
+                    .unwrap_or_default();
+                (record, run_exports)
+            })
+            .collect())
+    }
+
+    /// Waits until all repodata refreshes that were started in the background
+    /// have completed. See [`SourceConfig::stale_while_revalidate`].
+    ///
+    /// Queries that are executed after this function returns use the
+    /// refreshed repodata. Refreshes that fail are logged and otherwise
+    /// ignored, in which case the cached repodata keeps being used.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn wait_for_background_refreshes(&self) {
+        loop {
+            let refreshes = std::mem::take(&mut *self.inner.background_refreshes.lock());
+            if refreshes.is_empty() {
+                return;
+            }
+            for refresh in refreshes {
+                if let Err(err) = refresh.await {
+                    if let Ok(panic) = err.try_into_panic() {
+                        std::panic::resume_unwind(panic);
+                    }
+                }
+            }
+        }
+    }
+
     /// Clears any in-memory cache for the given channel.
     ///
     /// Any subsequent query will re-fetch any required data from the source.
@@ -212,6 +288,17 @@ struct GatewayInner {
 
     /// A semaphore to limit the number of concurrent requests.
     concurrent_requests_semaphore: Option<Arc<tokio::sync::Semaphore>>,
+
+    /// Repodata patches to apply to the records of specific channels.
+    repodata_patches: HashMap<ChannelUrl, Vec<RepoDataPatch>>,
+
+    /// Specs of records that are excluded from specific channels.
+    excluded_packages: HashMap<ChannelUrl, Vec<MatchSpec>>,
+
+    /// Repodata refreshes that are running in the background. Finished
+    /// refreshes are removed whenever a new refresh is started.
+    #[cfg(not(target_arch = "wasm32"))]
+    background_refreshes: parking_lot::Mutex<Vec<tokio::task::JoinHandle<()>>>,
 }
 
 impl GatewayInner {
@@ -225,7 +312,7 @@ impl GatewayInner {
     /// error.
     #[instrument(skip(self, reporter, channel), fields(channel = %channel.base_url), err(level = Level::INFO))]
     async fn get_or_create_subdir(
-        &self,
+        self: &Arc<Self>,
         channel: &Channel,
         platform: Platform,
         reporter: Option<Arc<dyn Reporter>>,
@@ -249,7 +336,7 @@ impl GatewayInner {
     }
 
     async fn create_subdir(
-        &self,
+        self: &Arc<Self>,
         channel: &Channel,
         platform: Platform,
         reporter: Option<Arc<dyn Reporter>>,
@@ -270,7 +357,7 @@ mod test {
     use std::{
         path::{Path, PathBuf},
         str::FromStr,
-        sync::Arc,
+        sync::{Arc, Mutex},
         time::Instant,
     };
 
@@ -278,19 +365,19 @@ mod test {
     use dashmap::DashSet;
     use rattler_cache::{default_cache_dir, package_cache::PackageCache};
     use rattler_conda_types::{
-        Channel, ChannelConfig, MatchSpec, PackageName,
+        Channel, ChannelConfig, MatchSpec, Matches, PackageName,
         ParseStrictness::{Lenient, Strict},
-        Platform, RepoDataRecord,
+        PatchInstructions, Platform, RepoDataPatch, RepoDataRecord,
     };
     use rstest::rstest;
     use url::Url;
 
     use crate::{
         fetch::CacheAction,
-        gateway::Gateway,
+        gateway::{Gateway, PlatformFallback},
         utils::{simple_channel_server::SimpleChannelServer, test::fetch_repo_data},
-        DownloadReporter, GatewayError, JLAPReporter, RepoData, Reporter, SourceConfig,
-        SubdirSelection,
+        DownloadReporter, GatewayError, GatewayReporter, JLAPReporter, RepoData, Reporter,
+        SourceConfig, SubdirFetchInfo, SubdirFetchSource, SubdirSelection,
     };
 
     async fn local_conda_forge() -> Channel {
@@ -326,6 +413,218 @@ mod test {
         assert_eq!(total_records, 45060);
     }
 
+    #[tokio::test]
+    async fn test_execute_records_deduplicates() {
+        let gateway = Gateway::new();
+        let index = local_conda_forge().await;
+
+        let records = gateway
+            .query(
+                vec![index.clone()],
+                vec![Platform::Linux64, Platform::NoArch],
+                vec![PackageName::from_str("rubin-env").unwrap()].into_iter(),
+            )
+            .recursive(true)
+            .execute_records()
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 45060);
+
+        // Specifying the same channel twice should not result in duplicate records.
+        let duplicated_records = gateway
+            .query(
+                vec![index.clone(), index],
+                vec![Platform::Linux64, Platform::NoArch],
+                vec![PackageName::from_str("rubin-env").unwrap()].into_iter(),
+            )
+            .recursive(true)
+            .execute_records()
+            .await
+            .unwrap();
+        assert_eq!(duplicated_records, records);
+    }
+
+    #[tokio::test]
+    async fn test_names() {
+        let gateway = Gateway::new();
+        let channel = local_conda_forge().await;
+
+        let names = gateway
+            .names(
+                vec![channel.clone()],
+                vec![Platform::Linux64, Platform::NoArch],
+            )
+            .await
+            .unwrap();
+        assert!(names.contains(&PackageName::from_str("rubin-env").unwrap()));
+        assert!(names.windows(2).all(|w| w[0] < w[1]));
+
+        let names = gateway
+            .names(vec![channel], vec![Platform::Linux64, Platform::NoArch])
+            .with_prefix("Rubin-")
+            .await
+            .unwrap();
+        assert!(!names.is_empty());
+        assert!(names
+            .iter()
+            .all(|name| name.as_normalized().starts_with("rubin-")));
+    }
+
+    #[tokio::test]
+    async fn test_platform_fallback() {
+        let gateway = Gateway::new();
+
+        // Querying only `linux-64` falls back to `noarch` by default, which
+        // should be the same as querying both subdirs.
+        let records = gateway
+            .query(
+                vec![local_conda_forge().await],
+                vec![Platform::Linux64],
+                vec![PackageName::from_str("rubin-env").unwrap()].into_iter(),
+            )
+            .recursive(true)
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+        let total_records: usize = records.iter().map(RepoData::len).sum();
+        assert_eq!(total_records, 45060);
+
+        // Without the fallback only `linux-64` is queried.
+        let records = gateway
+            .query(
+                vec![local_conda_forge().await],
+                vec![Platform::Linux64],
+                vec![PackageName::from_str("rubin-env").unwrap()].into_iter(),
+            )
+            .with_platform_fallback(PlatformFallback::Exact)
+            .recursive(true)
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repodata_patches() {
+        let channel = local_conda_forge().await;
+        let query = |gateway: Gateway| {
+            let channel = channel.clone();
+            async move {
+                gateway
+                    .query(
+                        vec![channel],
+                        vec![Platform::Linux64],
+                        vec![PackageName::from_str("openssl").unwrap()].into_iter(),
+                    )
+                    .execute_records()
+                    .await
+                    .unwrap()
+            }
+        };
+        let records = query(Gateway::new()).await;
+
+        // Removing a `.conda` archive does not affect the other archives.
+        let removed = records
+            .iter()
+            .find(|r| r.file_name.ends_with(".conda"))
+            .unwrap()
+            .file_name
+            .clone();
+        let patched = records
+            .iter()
+            .find(|r| r.file_name != removed)
+            .unwrap()
+            .file_name
+            .clone();
+
+        let mut instructions = serde_json::json!({ "remove": [removed] });
+        let packages_key = if patched.ends_with(".conda") {
+            "packages.conda"
+        } else {
+            "packages"
+        };
+        instructions[packages_key] = serde_json::json!({});
+        instructions[packages_key][&patched] =
+            serde_json::json!({ "depends": ["patched-dependency"] });
+        let instructions: PatchInstructions = serde_json::from_value(instructions).unwrap();
+        let patch = RepoDataPatch {
+            subdirs: [("linux-64".to_string(), instructions)]
+                .into_iter()
+                .collect(),
+        };
+        let patched_records = query(
+            Gateway::builder()
+                .with_repodata_patch(channel.base_url.clone(), patch)
+                .finish(),
+        )
+        .await;
+
+        assert_eq!(patched_records.len(), records.len() - 1);
+        assert!(patched_records.iter().all(|r| r.file_name != removed));
+        let patched_record = patched_records
+            .iter()
+            .find(|r| r.file_name == patched)
+            .unwrap();
+        assert_eq!(
+            patched_record.package_record.depends,
+            vec!["patched-dependency".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_excluded_packages() {
+        let channel = local_conda_forge().await;
+        let exclusion = MatchSpec::from_str("openssl >=3", Lenient).unwrap();
+        let gateway = Gateway::builder()
+            .with_excluded_packages(channel.base_url.clone(), [exclusion.clone()])
+            .finish();
+
+        let records = gateway
+            .query(
+                vec![channel],
+                vec![Platform::Linux64],
+                vec![MatchSpec::from_str("openssl", Lenient).unwrap()],
+            )
+            .execute_records()
+            .await
+            .unwrap();
+
+        assert!(!records.is_empty());
+        assert!(records.iter().all(|record| !exclusion.matches(record)));
+    }
+
+    #[cfg(feature = "indexing")]
+    #[tokio::test]
+    async fn test_unindexed_local_channel() {
+        let channel_dir = tempfile::TempDir::new().unwrap();
+        let noarch = channel_dir.path().join("noarch");
+        fs_err::create_dir_all(&noarch).unwrap();
+        fs_err::copy(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/packages/empty-0.1.0-h4616a5c_0.conda"),
+            noarch.join("empty-0.1.0-h4616a5c_0.conda"),
+        )
+        .unwrap();
+
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let gateway = Gateway::builder()
+            .with_cache_dir(cache_dir.path().to_path_buf())
+            .finish();
+        let records = gateway
+            .query(
+                vec![Channel::from_directory(channel_dir.path())],
+                vec![Platform::Linux64, Platform::NoArch],
+                vec![PackageName::from_str("empty").unwrap()].into_iter(),
+            )
+            .execute_records()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].file_name, "empty-0.1.0-h4616a5c_0.conda");
+    }
+
     #[tokio::test]
     async fn test_remote_gateway() {
         let gateway = Gateway::new();
@@ -346,6 +645,41 @@ mod test {
         assert_eq!(total_records, 45060);
     }
 
+    #[tokio::test]
+    async fn test_sharded_index_forbidden_falls_back() {
+        tokio::try_join!(fetch_repo_data("noarch"), fetch_repo_data("linux-64")).unwrap();
+
+        // Serve the channel, but deny access to the shard indices like S3
+        // buckets without sharded repodata do.
+        let forbidden = axum::routing::get(|| async { axum::http::StatusCode::FORBIDDEN });
+        let app = axum::Router::new()
+            .route("/linux-64/repodata_shards.msgpack.zst", forbidden.clone())
+            .route("/noarch/repodata_shards.msgpack.zst", forbidden)
+            .fallback_service(axum::routing::get_service(
+                tower_http::services::ServeDir::new(
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("../../test-data/channels/conda-forge"),
+                )
+                .precompressed_gzip(),
+            ));
+        let index = SimpleChannelServer::from_router(app).await;
+
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let gateway = Gateway::builder().with_cache_dir(cache_dir.path()).finish();
+        let records = gateway
+            .query(
+                vec![index.channel()],
+                vec![Platform::Linux64, Platform::NoArch],
+                vec![PackageName::from_str("rubin-env").unwrap()].into_iter(),
+            )
+            .recursive(true)
+            .await
+            .unwrap();
+
+        let total_records: usize = records.iter().map(RepoData::len).sum();
+        assert_eq!(total_records, 45060);
+    }
+
     #[tokio::test]
     #[cfg(not(target_arch = "wasm32"))]
     async fn test_direct_url_spec_from_gateway() {
@@ -439,6 +773,7 @@ mod test {
                 ]
                 .into_iter(),
             )
+            .with_platform_fallback(PlatformFallback::Exact)
             .recursive(true)
             .await
             .unwrap();
@@ -474,6 +809,7 @@ mod test {
                 vec![MatchSpec::from_str("mamba ==0.9.2 py39h951de11_0", Strict).unwrap()]
                     .into_iter(),
             )
+            .with_platform_fallback(PlatformFallback::Exact)
             .recursive(true)
             .await
             .unwrap();
@@ -699,6 +1035,126 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_gateway_reporter() {
+        #[derive(Default)]
+        struct Subdirs {
+            started: Mutex<Vec<Url>>,
+            completed: Mutex<Vec<SubdirFetchInfo>>,
+        }
+        impl GatewayReporter for Arc<Subdirs> {
+            fn on_subdir_fetch_start(&self, url: &Url) -> usize {
+                let mut started = self.started.lock().unwrap();
+                started.push(url.clone());
+                started.len() - 1
+            }
+            fn on_subdir_fetch_complete(&self, _index: usize, info: &SubdirFetchInfo) {
+                self.completed.lock().unwrap().push(info.clone());
+            }
+        }
+        impl Reporter for Arc<Subdirs> {
+            fn download_reporter(&self) -> Option<&dyn DownloadReporter> {
+                None
+            }
+            fn jlap_reporter(&self) -> Option<&dyn JLAPReporter> {
+                None
+            }
+            fn gateway_reporter(&self) -> Option<&dyn GatewayReporter> {
+                Some(self)
+            }
+        }
+
+        let local_channel = remote_conda_forge().await;
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let query = |gateway: &Gateway, reporter: Arc<Subdirs>| {
+            gateway
+                .query(
+                    vec![local_channel.channel()],
+                    vec![Platform::Linux64, Platform::NoArch],
+                    vec![PackageName::from_str("python").unwrap()].into_iter(),
+                )
+                .with_reporter(reporter)
+                .execute()
+        };
+
+        // The first time the repodata is downloaded.
+        let subdirs = Arc::new(Subdirs::default());
+        let gateway = Gateway::builder().with_cache_dir(cache_dir.path()).finish();
+        query(&gateway, subdirs.clone()).await.unwrap();
+        assert_eq!(subdirs.started.lock().unwrap().len(), 2);
+        let completed = subdirs.completed.lock().unwrap();
+        assert_eq!(completed.len(), 2);
+        assert!(completed
+            .iter()
+            .all(|info| info.source == SubdirFetchSource::Download && info.bytes_downloaded > 0));
+        drop(completed);
+
+        // A new gateway reads the repodata from the cache.
+        let subdirs = Arc::new(Subdirs::default());
+        let gateway = Gateway::builder().with_cache_dir(cache_dir.path()).finish();
+        query(&gateway, subdirs.clone()).await.unwrap();
+        let completed = subdirs.completed.lock().unwrap();
+        assert_eq!(completed.len(), 2);
+        assert!(completed
+            .iter()
+            .all(|info| info.source == SubdirFetchSource::Cache && info.bytes_downloaded == 0));
+    }
+
+    #[tokio::test]
+    async fn test_stale_while_revalidate() {
+        let local_channel = remote_conda_forge().await;
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let query = |gateway: &Gateway| {
+            gateway
+                .query(
+                    vec![local_channel.channel()],
+                    vec![Platform::Linux64, Platform::NoArch],
+                    vec![PackageName::from_str("python").unwrap()].into_iter(),
+                )
+                .execute_records()
+        };
+
+        // Populate the cache.
+        let gateway = Gateway::builder().with_cache_dir(cache_dir.path()).finish();
+        let records = query(&gateway).await.unwrap();
+
+        // The cached repodata is used immediately and refreshed in the background.
+        let gateway = Gateway::builder()
+            .with_cache_dir(cache_dir.path())
+            .with_channel_config(super::ChannelConfig {
+                default: SourceConfig {
+                    stale_while_revalidate: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .finish();
+        assert_eq!(query(&gateway).await.unwrap(), records);
+        assert!(!gateway.inner.background_refreshes.lock().is_empty());
+
+        // The server reports that the repodata did not change, so the subdirs
+        // are kept in memory.
+        gateway.wait_for_background_refreshes().await;
+        assert!(gateway.inner.background_refreshes.lock().is_empty());
+        assert_eq!(gateway.inner.subdirs.len(), 2);
+        assert_eq!(query(&gateway).await.unwrap(), records);
+
+        // Nothing is refreshed if the cache is still up to date.
+        let gateway = Gateway::builder()
+            .with_cache_dir(cache_dir.path())
+            .with_channel_config(super::ChannelConfig {
+                default: SourceConfig {
+                    stale_while_revalidate: true,
+                    max_stale: Some(std::time::Duration::from_secs(3600)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .finish();
+        assert_eq!(query(&gateway).await.unwrap(), records);
+        assert!(gateway.inner.background_refreshes.lock().is_empty());
+    }
+
     fn run_exports_missing(records: &[RepoDataRecord]) -> bool {
         records
             .iter()
@@ -751,6 +1207,27 @@ mod test {
         assert!(run_exports_in_place(&repodata_records));
     }
 
+    #[tokio::test]
+    async fn test_run_exports() {
+        let gateway = Gateway::new();
+
+        let run_exports = gateway
+            .run_exports(
+                vec![local_conda_forge().await],
+                vec![Platform::Linux64],
+                vec![MatchSpec::from_str("openssl=3.*=*_1", Lenient).unwrap()],
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(run_exports.len(), 3);
+        for (record, run_exports) in &run_exports {
+            assert_eq!(record.package_record.name.as_normalized(), "openssl");
+            assert!(!run_exports.is_empty());
+        }
+    }
+
     #[tokio::test]
     async fn test_ensure_run_exports_remote_conda_forge() {
         let gateway = Gateway::new();
@@ -6,11 +6,57 @@ use std::{
 
 use futures::{select_biased, stream::FuturesUnordered, FutureExt, StreamExt};
 use itertools::Itertools;
-use rattler_conda_types::{Channel, MatchSpec, Matches, PackageName, Platform};
+use rattler_conda_types::{Channel, MatchSpec, Matches, PackageName, Platform, RepoDataRecord};
 
 use super::{subdir::Subdir, BarrierCell, GatewayError, GatewayInner, RepoData};
 use crate::Reporter;
 
+/// Determines which additional platforms are queried besides the platforms
+/// that were explicitly requested.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PlatformFallback {
+    /// Only query the requested platforms.
+    Exact,
+
+    /// Also query the `noarch` subdirectory, which contains packages that can
+    /// be installed on any platform. This is the default because packages of
+    /// a platform regularly depend on `noarch` packages.
+    #[default]
+    NoArch,
+
+    /// Also query the `noarch` subdirectory and the platforms whose packages
+    /// can run on the requested platforms through emulation, e.g. `osx-64`
+    /// when targeting `osx-arm64`. See [`Platform::emulated_platforms`].
+    Compatible,
+}
+
+impl PlatformFallback {
+    /// Returns the platforms to query for the given requested platforms.
+    ///
+    /// The requested platforms come first, in their original order, followed
+    /// by the fallback platforms. Every platform is only returned once.
+    pub fn expand(self, platforms: impl IntoIterator<Item = Platform>) -> Vec<Platform> {
+        let platforms = platforms.into_iter().collect_vec();
+        let emulated = match self {
+            PlatformFallback::Compatible => platforms
+                .iter()
+                .flat_map(|platform| platform.emulated_platforms().iter().copied())
+                .collect_vec(),
+            PlatformFallback::Exact | PlatformFallback::NoArch => Vec::new(),
+        };
+        let noarch = match self {
+            PlatformFallback::Exact => None,
+            PlatformFallback::NoArch | PlatformFallback::Compatible => Some(Platform::NoArch),
+        };
+        platforms
+            .into_iter()
+            .chain(emulated)
+            .chain(noarch)
+            .unique()
+            .collect()
+    }
+}
+
 /// Represents a query to execute with a [`Gateway`].
 ///
 /// When executed the query will asynchronously load the repodata from all
@@ -33,6 +79,9 @@ pub struct RepoDataQuery {
     /// The platforms the fetch from
     platforms: Vec<Platform>,
 
+    /// Which platforms to query in addition to `platforms`.
+    platform_fallback: PlatformFallback,
+
     /// The specs to fetch records for
     specs: Vec<MatchSpec>,
 
@@ -67,6 +116,7 @@ impl RepoDataQuery {
             platforms,
             specs,
 
+            platform_fallback: PlatformFallback::default(),
             recursive: false,
             reporter: None,
         }
@@ -83,6 +133,19 @@ impl RepoDataQuery {
         Self { recursive, ..self }
     }
 
+    /// Sets which platforms are queried in addition to the requested
+    /// platforms. The results of the fallback platforms are returned after
+    /// the results of the requested platforms of the same channel.
+    ///
+    /// Defaults to [`PlatformFallback::NoArch`].
+    #[must_use]
+    pub fn with_platform_fallback(self, platform_fallback: PlatformFallback) -> Self {
+        Self {
+            platform_fallback,
+            ..self
+        }
+    }
+
     /// Sets the reporter to use for this query.
     ///
     /// The reporter is notified of important evens during the execution of the
@@ -105,7 +168,7 @@ impl RepoDataQuery {
         let channels_and_platforms = self
             .channels
             .iter()
-            .cartesian_product(self.platforms.into_iter())
+            .cartesian_product(self.platform_fallback.expand(self.platforms))
             .collect_vec();
 
         // Collect all the specs that have a direct url and the ones that have a name.
@@ -310,6 +373,24 @@ impl RepoDataQuery {
 
         Ok(result)
     }
+
+    /// Execute the query and return the records of all subdirectories as a
+    /// single list that can be passed to a solver.
+    ///
+    /// Records are ordered by the order of the channels and platforms of the
+    /// query. Records that are returned by more than one subdirectory, for
+    /// instance because a channel was specified twice or because a record
+    /// was also requested through a direct url, are only returned once.
+    pub async fn execute_records(self) -> Result<Vec<RepoDataRecord>, GatewayError> {
+        let repo_data = self.execute().await?;
+        let mut seen = HashSet::new();
+        Ok(repo_data
+            .iter()
+            .flat_map(RepoData::iter)
+            .filter(|record| seen.insert(&record.url))
+            .cloned()
+            .collect())
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -352,6 +433,12 @@ pub struct NamesQuery {
     /// The platforms the fetch from
     platforms: Vec<Platform>,
 
+    /// Which platforms to query in addition to `platforms`.
+    platform_fallback: PlatformFallback,
+
+    /// Only return names that start with this prefix.
+    prefix: Option<String>,
+
     /// The reporter to use by the query.
     reporter: Option<Arc<dyn Reporter>>,
 }
@@ -368,11 +455,32 @@ impl NamesQuery {
             gateway,
             channels,
             platforms,
-
+            platform_fallback: PlatformFallback::default(),
+            prefix: None,
             reporter: None,
         }
     }
 
+    /// Only return the names of packages that start with the given prefix.
+    /// This is useful to provide completions for partially typed names.
+    #[must_use]
+    pub fn with_prefix(self, prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: Some(prefix.into().to_lowercase()),
+            ..self
+        }
+    }
+
+    /// Sets which platforms are queried in addition to the requested
+    /// platforms. Defaults to [`PlatformFallback::NoArch`].
+    #[must_use]
+    pub fn with_platform_fallback(self, platform_fallback: PlatformFallback) -> Self {
+        Self {
+            platform_fallback,
+            ..self
+        }
+    }
+
     /// Sets the reporter to use for this query.
     ///
     /// The reporter is notified of important evens during the execution of the
@@ -384,13 +492,17 @@ impl NamesQuery {
         }
     }
 
-    /// Execute the query and return the package names.
+    /// Execute the query and return the unique package names, sorted
+    /// alphabetically.
+    ///
+    /// The names are read from the index of the repodata, no records are
+    /// parsed.
     pub async fn execute(self) -> Result<Vec<PackageName>, GatewayError> {
         // Collect all the channels and platforms together
         let channels_and_platforms = self
             .channels
             .iter()
-            .cartesian_product(self.platforms.into_iter())
+            .cartesian_product(self.platform_fallback.expand(self.platforms))
             .collect_vec();
 
         // Create barrier cells for each subdirectory.
@@ -419,10 +531,18 @@ impl NamesQuery {
             names.extend(subdir_names);
         }
 
-        Ok(names
+        let mut names = names
             .into_iter()
             .map(PackageName::try_from)
-            .collect::<Result<Vec<PackageName>, _>>()?)
+            .filter_ok(|name| {
+                self.prefix
+                    .as_deref()
+                    .is_none_or(|prefix| name.as_normalized().starts_with(prefix))
+            })
+            .collect::<Result<Vec<PackageName>, _>>()?;
+        names.sort();
+        names.dedup();
+        Ok(names)
     }
 }
 
@@ -444,8 +564,40 @@ fn package_name_from_match_spec_str(spec: &str) -> PackageName {
 
 #[cfg(test)]
 mod test {
+    use rattler_conda_types::Platform;
     use rstest::*;
 
+    use super::PlatformFallback;
+
+    #[test]
+    fn test_platform_fallback_expand() {
+        assert_eq!(
+            PlatformFallback::Exact.expand([Platform::OsxArm64]),
+            vec![Platform::OsxArm64]
+        );
+        assert_eq!(
+            PlatformFallback::NoArch.expand([Platform::OsxArm64, Platform::NoArch]),
+            vec![Platform::OsxArm64, Platform::NoArch]
+        );
+        assert_eq!(
+            PlatformFallback::default().expand([Platform::Linux64]),
+            vec![Platform::Linux64, Platform::NoArch]
+        );
+        assert_eq!(
+            PlatformFallback::Compatible.expand([Platform::OsxArm64]),
+            vec![Platform::OsxArm64, Platform::Osx64, Platform::NoArch]
+        );
+        assert_eq!(
+            PlatformFallback::Compatible.expand([Platform::WinArm64, Platform::Win64]),
+            vec![
+                Platform::WinArm64,
+                Platform::Win64,
+                Platform::Win32,
+                Platform::NoArch
+            ]
+        );
+    }
+
     #[rstest]
     #[case("pillow", "pillow")]
     #[case("pillow >=10", "pillow")]
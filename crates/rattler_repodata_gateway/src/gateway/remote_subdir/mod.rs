@@ -8,6 +8,7 @@ cfg_if::cfg_if! {
         mod wasm;
         pub use wasm::RemoteSubdirClient;
     } else {
+        mod record_cache;
         mod tokio;
         pub use tokio::RemoteSubdirClient;
     }
@@ -21,7 +22,7 @@ impl SubdirClient for RemoteSubdirClient {
         name: &PackageName,
         reporter: Option<&dyn Reporter>,
     ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
-        self.sparse.fetch_package_records(name, reporter).await
+        self.fetch_records(name, reporter).await
     }
 
     fn package_names(&self) -> Vec<String> {
@@ -0,0 +1,239 @@
+//! A binary cache of the records that were parsed from a cached
+//! `repodata.json`.
+//!
+//! Parsing the records of a package from JSON is relatively expensive,
+//! especially for packages with many records. The first time the records of a
+//! package are parsed they are also stored in a compact msgpack file, next to
+//! the cached `repodata.json`. Subsequent runs read the records from that file
+//! instead as long as the `repodata.json` did not change.
+//!
+//! The files are stored in a directory per hash of the `repodata.json`, the
+//! directories of previous versions are removed when the `repodata.json` is
+//! refreshed.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use rattler_conda_types::{PackageName, PackageRecord, RepoDataRecord};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use url::Url;
+
+/// Magic number that identifies the cache file format. The version must be
+/// bumped whenever the serialized format of the records changes.
+const MAGIC_NUMBER: &[u8] = b"RECORD-CACHE-V1";
+
+/// Stores the records of the packages in a single `repodata.json` file.
+#[derive(Debug, Clone)]
+pub(super) struct RecordCache {
+    /// The directory that contains a file per package name. This is a
+    /// subdirectory of `root` that is named after the `source_hash`.
+    dir: PathBuf,
+
+    /// The directory that contains the record caches of all versions of the
+    /// `repodata.json`.
+    root: PathBuf,
+
+    /// Identifies the contents of the `repodata.json` file the records were
+    /// parsed from. Cached records with a different hash are ignored.
+    source_hash: String,
+}
+
+/// The contents of a cache file.
+#[derive(Deserialize)]
+struct CachedRecords {
+    source_hash: String,
+    records: Vec<CachedRecord>,
+}
+
+/// A [`RepoDataRecord`] without the flattened package record, because
+/// flattening is not supported by binary formats.
+#[derive(Deserialize)]
+struct CachedRecord {
+    package_record: PackageRecord,
+    file_name: String,
+    url: Url,
+    channel: Option<String>,
+}
+
+/// Borrowed version of [`CachedRecords`] to avoid cloning when writing.
+#[derive(Serialize)]
+struct CachedRecordsRef<'a> {
+    source_hash: &'a str,
+    records: Vec<CachedRecordRef<'a>>,
+}
+
+/// Borrowed version of [`CachedRecord`] to avoid cloning when writing.
+#[derive(Serialize)]
+struct CachedRecordRef<'a> {
+    package_record: &'a PackageRecord,
+    file_name: &'a str,
+    url: &'a Url,
+    channel: Option<&'a str>,
+}
+
+impl RecordCache {
+    /// Constructs a cache for the records parsed from the `repodata.json` at
+    /// the given path, with contents identified by `source_hash`.
+    pub fn new(repo_data_json_path: &Path, source_hash: String) -> Self {
+        let root = repo_data_json_path.with_extension("records");
+        Self {
+            dir: root.join(&source_hash),
+            root,
+            source_hash,
+        }
+    }
+
+    /// Removes the cached records of all other versions of the
+    /// `repodata.json`.
+    pub fn remove_stale(&self) -> std::io::Result<()> {
+        let read_dir = match fs_err::read_dir(&self.root) {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for entry in read_dir {
+            let entry = entry?;
+            if entry.path() == self.dir {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                fs_err::remove_dir_all(entry.path())?;
+            } else {
+                fs_err::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, name: &PackageName) -> PathBuf {
+        self.dir.join(format!("{}.msgpack", name.as_normalized()))
+    }
+
+    /// Reads the records of the package with the given name. Returns `None`
+    /// if the records are not cached or were parsed from a different
+    /// `repodata.json`.
+    pub fn read(&self, name: &PackageName) -> Option<Vec<RepoDataRecord>> {
+        let path = self.path(name);
+        let bytes = fs_err::read(&path).ok()?;
+        let cached = match bytes
+            .strip_prefix(MAGIC_NUMBER)
+            .map(rmp_serde::from_slice::<CachedRecords>)
+        {
+            Some(Ok(cached)) => cached,
+            Some(Err(err)) => {
+                tracing::debug!("ignoring invalid record cache '{}': {err}", path.display());
+                return None;
+            }
+            None => {
+                tracing::debug!(
+                    "ignoring record cache '{}' with an unknown format",
+                    path.display()
+                );
+                return None;
+            }
+        };
+        if cached.source_hash != self.source_hash {
+            return None;
+        }
+
+        Some(
+            cached
+                .records
+                .into_iter()
+                .map(|record| RepoDataRecord {
+                    package_record: record.package_record,
+                    file_name: record.file_name,
+                    url: record.url,
+                    channel: record.channel,
+                })
+                .collect(),
+        )
+    }
+
+    /// Stores the records of the package with the given name.
+    pub fn write(&self, name: &PackageName, records: &[RepoDataRecord]) -> std::io::Result<()> {
+        let bytes = rmp_serde::to_vec_named(&CachedRecordsRef {
+            source_hash: &self.source_hash,
+            records: records
+                .iter()
+                .map(|record| CachedRecordRef {
+                    package_record: &record.package_record,
+                    file_name: &record.file_name,
+                    url: &record.url,
+                    channel: record.channel.as_deref(),
+                })
+                .collect(),
+        })
+        .map_err(std::io::Error::other)?;
+
+        // Write the file atomically so concurrent processes never observe a
+        // partial cache file.
+        fs_err::create_dir_all(&self.dir)?;
+        let mut file = NamedTempFile::new_in(&self.dir)?;
+        file.write_all(MAGIC_NUMBER)?;
+        file.write_all(&bytes)?;
+        file.persist(self.path(name)).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{PackageName, PackageRecord, RepoDataRecord, Version};
+    use tempfile::TempDir;
+
+    use super::RecordCache;
+
+    #[test]
+    fn test_record_cache_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let repodata_path = dir.path().join("repodata.json");
+        let name = PackageName::from_str("foo").unwrap();
+        let mut package_record =
+            PackageRecord::new(name.clone(), Version::from_str("1.0").unwrap(), "h0".into());
+        package_record.depends = vec!["bar >=1".to_string()];
+        package_record.sha256 = Some(
+            rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
+                "ee9172dbe9ebd158e8e68d6d0f7dc2060f0c8230b44d2e9a3595b7cd7336b915",
+            )
+            .unwrap(),
+        );
+        let records = vec![RepoDataRecord {
+            package_record,
+            file_name: "foo-1.0-h0.conda".to_string(),
+            url: "https://example.com/noarch/foo-1.0-h0.conda"
+                .parse()
+                .unwrap(),
+            channel: Some("https://example.com/".to_string()),
+        }];
+
+        let cache = RecordCache::new(&repodata_path, "abc".to_string());
+        assert_eq!(cache.read(&name), None);
+        cache.write(&name, &records).unwrap();
+        assert_eq!(cache.read(&name).as_ref(), Some(&records));
+
+        // Records parsed from a different repodata.json are ignored.
+        let cache = RecordCache::new(&repodata_path, "def".to_string());
+        assert_eq!(cache.read(&name), None);
+        cache.write(&name, &records).unwrap();
+
+        // Removing the stale records only keeps the current version.
+        cache.remove_stale().unwrap();
+        assert_eq!(cache.read(&name), Some(records));
+        assert_eq!(
+            RecordCache::new(&repodata_path, "abc".to_string()).read(&name),
+            None
+        );
+        assert_eq!(
+            fs_err::read_dir(dir.path().join("repodata.records"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+}
@@ -1,17 +1,28 @@
 use std::{path::PathBuf, sync::Arc};
 
+use super::record_cache::RecordCache;
 use crate::{
-    fetch::{fetch_repo_data, FetchRepoDataError, FetchRepoDataOptions},
+    fetch::{fetch_repo_data, CacheResult, FetchRepoDataError, FetchRepoDataOptions},
     gateway::{
-        error::SubdirNotFoundError, local_subdir::LocalSubdirClient, GatewayError, SourceConfig,
+        error::SubdirNotFoundError, local_subdir::LocalSubdirClient, subdir::SubdirClient,
+        GatewayError, SourceConfig,
     },
     Reporter,
 };
-use rattler_conda_types::{Channel, Platform};
+use rattler_conda_types::{Channel, PackageName, Platform, RepoDataRecord};
 use rattler_networking::LazyClient;
+use simple_spawn_blocking::tokio::run_blocking_task;
 
 pub struct RemoteSubdirClient {
     pub(super) sparse: LocalSubdirClient,
+
+    /// A binary cache of previously parsed records. Only available if the hash
+    /// of the cached `repodata.json` is known.
+    record_cache: Option<RecordCache>,
+
+    /// Whether the `repodata.json` was downloaded, as opposed to read from the
+    /// cache.
+    downloaded: bool,
 }
 
 impl RemoteSubdirClient {
@@ -35,6 +46,7 @@ impl RemoteSubdirClient {
                 jlap_enabled: source_config.jlap_enabled,
                 zstd_enabled: source_config.zstd_enabled,
                 bz2_enabled: source_config.bz2_enabled,
+                max_stale: source_config.max_stale,
                 ..FetchRepoDataOptions::default()
             },
             reporter,
@@ -51,9 +63,30 @@ impl RemoteSubdirClient {
             e => GatewayError::FetchRepoDataError(e),
         })?;
 
+        let downloaded = matches!(
+            repodata.cache_result,
+            CacheResult::CacheOutdated | CacheResult::CacheNotPresent
+        );
+        let record_cache = repodata
+            .cache_state
+            .blake2_hash
+            .map(|hash| RecordCache::new(&repodata.repo_data_json_path, format!("{hash:x}")));
+
+        // The records cached for the previous `repodata.json` are never read
+        // again once it was refreshed.
+        if let Some(record_cache) = record_cache.clone().filter(|_| downloaded) {
+            run_blocking_task(move || {
+                if let Err(err) = record_cache.remove_stale() {
+                    tracing::debug!("failed to remove stale record caches: {err}");
+                }
+                Ok::<_, GatewayError>(())
+            })
+            .await?;
+        }
+
         // Create a new sparse repodata client that can be used to read records from the
         // repodata.
-        let sparse = simple_spawn_blocking::tokio::run_blocking_task(move || {
+        let sparse = run_blocking_task(move || {
             LocalSubdirClient::from_file(
                 &repodata.repo_data_json_path,
                 channel.clone(),
@@ -62,6 +95,56 @@ impl RemoteSubdirClient {
         })
         .await?;
 
-        Ok(Self { sparse })
+        Ok(Self {
+            sparse,
+            record_cache,
+            downloaded,
+        })
+    }
+
+    /// Returns true if the `repodata.json` was downloaded when this instance
+    /// was created, as opposed to read from the cache.
+    pub fn downloaded(&self) -> bool {
+        self.downloaded
+    }
+
+    /// Returns the records of the package with the given name, either from the
+    /// binary record cache or by parsing them from the `repodata.json`.
+    pub(super) async fn fetch_records(
+        &self,
+        name: &PackageName,
+        reporter: Option<&dyn Reporter>,
+    ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
+        let Some(record_cache) = &self.record_cache else {
+            return self.sparse.fetch_package_records(name, reporter).await;
+        };
+
+        let cache = record_cache.clone();
+        let cache_name = name.clone();
+        if let Some(records) =
+            run_blocking_task(move || Ok::<_, GatewayError>(cache.read(&cache_name))).await?
+        {
+            return Ok(records.into());
+        }
+
+        let records = self.sparse.fetch_package_records(name, reporter).await?;
+
+        // Failing to write the cache is not fatal, the records are simply parsed
+        // again next time.
+        let cache = record_cache.clone();
+        let cache_name = name.clone();
+        let cache_records = records.clone();
+        run_blocking_task(move || {
+            if let Err(err) = cache.write(&cache_name, &cache_records) {
+                tracing::debug!(
+                    "failed to write record cache for '{}': {err}",
+                    cache_name.as_normalized()
+                );
+            }
+            Ok::<_, GatewayError>(())
+        })
+        .await?;
+
+        Ok(records)
     }
 }
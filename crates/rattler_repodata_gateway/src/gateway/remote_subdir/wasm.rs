@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use rattler_conda_types::{Channel, Platform};
+use rattler_conda_types::{Channel, PackageName, Platform, RepoDataRecord};
 use rattler_networking::LazyClient;
 
 use crate::{
@@ -9,7 +9,8 @@ use crate::{
         FetchRepoDataError,
     },
     gateway::{
-        error::SubdirNotFoundError, local_subdir::LocalSubdirClient, GatewayError, SourceConfig,
+        error::SubdirNotFoundError, local_subdir::LocalSubdirClient, subdir::SubdirClient,
+        GatewayError, SourceConfig,
     },
     Reporter,
 };
@@ -58,4 +59,13 @@ impl RemoteSubdirClient {
 
         Ok(Self { sparse })
     }
+
+    /// Returns the records of the package with the given name.
+    pub(super) async fn fetch_records(
+        &self,
+        name: &PackageName,
+        reporter: Option<&dyn Reporter>,
+    ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
+        self.sparse.fetch_package_records(name, reporter).await
+    }
 }
@@ -13,7 +13,7 @@ cfg_if! {
         pub use wasm::ShardedSubdir;
     } else {
         mod tokio;
-        pub use tokio::ShardedSubdir;
+        pub use tokio::{ShardedSubdir, ShardedSubdirCacheOptions};
     }
 }
 
@@ -1,4 +1,9 @@
-use std::{path::Path, str::FromStr, sync::Arc, time::SystemTime};
+use std::{
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use super::ShardedRepodata;
 use crate::{
@@ -12,7 +17,7 @@ use async_fd_lock::{LockWrite, RwLockWriteGuard};
 use bytes::Bytes;
 use fs_err::tokio as tokio_fs;
 use futures::{future::OptionFuture, TryFutureExt};
-use http::{HeaderMap, Method, Uri};
+use http::{header::CACHE_CONTROL, HeaderMap, HeaderValue, Method, Uri};
 use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy, RequestLike};
 use rattler_networking::LazyClient;
 use rattler_redaction::Redact;
@@ -27,15 +32,20 @@ use url::Url;
 
 const REPODATA_SHARDS_FILENAME: &str = "repodata_shards.msgpack.zst";
 
-// Fetches the shard index from the url or read it from the cache.
+// Fetches the shard index from the url or read it from the cache. Also returns
+// whether the index was downloaded, as opposed to read from the cache.
+//
+// A cached index that is out of date is still considered fresh if it expired
+// no longer than `max_stale` ago.
 pub async fn fetch_index(
     client: LazyClient,
     channel_base_url: &Url,
     cache_dir: &Path,
     cache_action: CacheAction,
+    max_stale: Option<Duration>,
     concurrent_requests_semaphore: Option<Arc<tokio::sync::Semaphore>>,
     reporter: Option<&dyn Reporter>,
-) -> Result<ShardedRepodata, GatewayError> {
+) -> Result<(ShardedRepodata, bool), GatewayError> {
     async fn from_response(
         mut cache_file: RwLockWriteGuard<File>,
         cache_path: &Path,
@@ -146,6 +156,17 @@ pub async fn fetch_index(
 
     let canonical_request = SimpleRequest::get(&canonical_shards_url);
 
+    // The request that is used to determine whether the cached index is still
+    // fresh. It allows the cached index to be stale for at most `max_stale`.
+    let mut freshness_request = SimpleRequest::get(&canonical_shards_url);
+    if let Some(max_stale) = max_stale {
+        freshness_request.headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_str(&format!("max-stale={}", max_stale.as_secs()))
+                .expect("invalid max-stale header"),
+        );
+    }
+
     // Try reading the cached file
     if cache_action != CacheAction::NoCache {
         if let Ok(cache_header) = read_cached_index(&mut cache_reader).await {
@@ -153,19 +174,24 @@ pub async fn fetch_index(
             if cache_action == CacheAction::ForceCacheOnly {
                 if let Ok(shard_index) = read_shard_index_from_reader(&mut cache_reader).await {
                     tracing::debug!("using locally cached shard index for {channel_base_url}");
-                    return Ok(shard_index);
+                    return Ok((shard_index, false));
                 }
             } else {
                 match cache_header
                     .policy
-                    .before_request(&canonical_request, SystemTime::now())
+                    .before_request(&freshness_request, SystemTime::now())
                 {
+                    BeforeRequest::Fresh(_) if cache_action == CacheAction::ForceRefresh => {
+                        tracing::debug!(
+                            "ignoring fresh shard index cache because a refresh was forced"
+                        );
+                    }
                     BeforeRequest::Fresh(_) => {
                         if let Ok(shard_index) =
                             read_shard_index_from_reader(&mut cache_reader).await
                         {
                             tracing::debug!("shard index cache hit");
-                            return Ok(shard_index);
+                            return Ok((shard_index, false));
                         }
                     }
                     BeforeRequest::Stale {
@@ -183,11 +209,14 @@ pub async fn fetch_index(
                             .join(REPODATA_SHARDS_FILENAME)
                             .expect("invalid shard base url");
 
-                        // Construct the actual request that we will send
+                        // Construct the actual request that we will send. The
+                        // `max-stale` directive is only meant for the local cache.
+                        let mut headers = state_request.headers().clone();
+                        headers.remove(CACHE_CONTROL);
                         let request = client
                             .client()
                             .get(shards_url.clone())
-                            .headers(state_request.headers().clone())
+                            .headers(headers)
                             .build()
                             .expect("failed to build request for shard index");
 
@@ -222,7 +251,7 @@ pub async fn fetch_index(
                                         }
                                         // If reading the file failed for some reason we'll just
                                         // fetch it again.
-                                        return Ok(shard_index);
+                                        return Ok((shard_index, false));
                                     }
                                     Err(e) => {
                                         tracing::warn!(
@@ -245,7 +274,8 @@ pub async fn fetch_index(
                                     download_reporter,
                                     request_permit,
                                 )
-                                .await;
+                                .await
+                                .map(|shard_index| (shard_index, true));
                             }
                         }
                     }
@@ -254,7 +284,10 @@ pub async fn fetch_index(
         }
     }
 
-    if cache_action == CacheAction::ForceCacheOnly {
+    if matches!(
+        cache_action,
+        CacheAction::UseCacheOnly | CacheAction::ForceCacheOnly
+    ) {
         return Err(GatewayError::CacheError(format!(
             "the sharded index cache for {channel_base_url} is not available"
         )));
@@ -307,6 +340,7 @@ pub async fn fetch_index(
         request_permit,
     )
     .await
+    .map(|shard_index| (shard_index, true))
 }
 
 /// Magic number that identifies the cache file format.
@@ -431,3 +465,80 @@ impl RequestLike for SimpleRequest {
         &self.uri() == other
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use axum::{http::header::CACHE_CONTROL, routing::get};
+    use rattler_conda_types::{ShardedRepodata, ShardedSubdirInfo};
+    use rattler_networking::LazyClient;
+
+    use super::fetch_index;
+    use crate::{fetch::CacheAction, utils::simple_channel_server::SimpleChannelServer};
+
+    #[tokio::test]
+    async fn test_max_stale() {
+        let index = ShardedRepodata {
+            info: ShardedSubdirInfo {
+                subdir: "linux-64".to_string(),
+                base_url: "./".to_string(),
+                shards_base_url: "./shards/".to_string(),
+                created_at: None,
+            },
+            shards: Default::default(),
+        };
+        let body =
+            zstd::encode_all(rmp_serde::to_vec_named(&index).unwrap().as_slice(), 0).unwrap();
+
+        // Serve a shard index that immediately becomes stale.
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/linux-64/repodata_shards.msgpack.zst",
+            get({
+                let requests = requests.clone();
+                move || async move {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    ([(CACHE_CONTROL, "public, max-age=0")], body)
+                }
+            }),
+        );
+        let server = SimpleChannelServer::from_router(app).await;
+        let base_url = server.url().join("linux-64/").unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let fetch = |cache_action, max_stale| {
+            fetch_index(
+                LazyClient::default(),
+                &base_url,
+                cache_dir.path(),
+                cache_action,
+                max_stale,
+                None,
+                None,
+            )
+        };
+
+        let (_, downloaded) = fetch(CacheAction::CacheOrFetch, None).await.unwrap();
+        assert!(downloaded);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // Without `max_stale` the cached index is out of date.
+        assert!(fetch(CacheAction::UseCacheOnly, None).await.is_err());
+
+        // With `max_stale` the cached index is used without contacting the
+        // server.
+        let max_stale = Some(Duration::from_secs(3600));
+        let (_, downloaded) = fetch(CacheAction::UseCacheOnly, max_stale).await.unwrap();
+        assert!(!downloaded);
+        let (_, downloaded) = fetch(CacheAction::CacheOrFetch, max_stale).await.unwrap();
+        assert!(!downloaded);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,6 +1,6 @@
 mod index;
 
-use std::{io::Write, path::PathBuf, sync::Arc};
+use std::{io::Write, path::PathBuf, sync::Arc, time::Duration};
 
 use super::{add_trailing_slash, decode_zst_bytes_async, parse_records};
 use crate::{
@@ -26,6 +26,21 @@ pub struct ShardedSubdir {
     concurrent_requests_semaphore: Option<Arc<tokio::sync::Semaphore>>,
     cache_dir: PathBuf,
     cache_action: CacheAction,
+    index_downloaded: bool,
+}
+
+/// Describes how a [`ShardedSubdir`] interacts with its caches.
+#[derive(Debug, Clone, Copy)]
+pub struct ShardedSubdirCacheOptions {
+    /// How to interact with the cached shards.
+    pub cache_action: CacheAction,
+
+    /// How to interact with the cached shard index.
+    pub index_cache_action: CacheAction,
+
+    /// Allows using a cached shard index that expired at most this long ago
+    /// without checking with the server.
+    pub max_stale: Option<Duration>,
 }
 
 impl ShardedSubdir {
@@ -34,7 +49,7 @@ impl ShardedSubdir {
         subdir: String,
         client: LazyClient,
         cache_dir: PathBuf,
-        cache_action: CacheAction,
+        cache_options: ShardedSubdirCacheOptions,
         concurrent_requests_semaphore: Option<Arc<tokio::sync::Semaphore>>,
         reporter: Option<&dyn Reporter>,
     ) -> Result<Self, GatewayError> {
@@ -46,11 +61,12 @@ impl ShardedSubdir {
             .expect("invalid subdir url");
 
         // Fetch the shard index
-        let sharded_repodata = index::fetch_index(
+        let (sharded_repodata, index_downloaded) = index::fetch_index(
             client.clone(),
             &index_base_url,
             &cache_dir,
-            cache_action,
+            cache_options.index_cache_action,
+            cache_options.max_stale,
             concurrent_requests_semaphore.clone(),
             reporter,
         )
@@ -99,10 +115,17 @@ impl ShardedSubdir {
             package_base_url: add_trailing_slash(&package_base_url).into_owned(),
             sharded_repodata,
             cache_dir,
-            cache_action,
+            cache_action: cache_options.cache_action,
             concurrent_requests_semaphore,
+            index_downloaded,
         })
     }
+
+    /// Returns true if the shard index was downloaded when this instance was
+    /// created, as opposed to read from the cache.
+    pub fn index_downloaded(&self) -> bool {
+        self.index_downloaded
+    }
 }
 
 #[async_trait::async_trait]
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use rattler_conda_types::{PackageName, RepoDataRecord};
+use rattler_conda_types::{MatchSpec, Matches, PackageName, PatchInstructions, RepoDataRecord};
 
 use super::GatewayError;
 use crate::Reporter;
@@ -32,6 +32,12 @@ pub struct SubdirData {
 
     /// Previously fetched or currently pending records.
     records: CoalescedMap<PackageName, Arc<[RepoDataRecord]>>,
+
+    /// Patches that are applied to the records after they have been fetched.
+    patches: Arc<[PatchInstructions]>,
+
+    /// Records that match any of these specs are excluded.
+    exclusions: Arc<[MatchSpec]>,
 }
 
 impl SubdirData {
@@ -39,6 +45,25 @@ impl SubdirData {
         Self {
             client: Arc::new(client),
             records: CoalescedMap::new(),
+            patches: Arc::new([]),
+            exclusions: Arc::new([]),
+        }
+    }
+
+    /// Applies the given patch instructions, in order, to all records of the
+    /// subdirectory.
+    pub fn with_patches(self, patches: impl Into<Arc<[PatchInstructions]>>) -> Self {
+        Self {
+            patches: patches.into(),
+            ..self
+        }
+    }
+
+    /// Excludes all records that match any of the given specs.
+    pub fn with_exclusions(self, exclusions: impl Into<Arc<[MatchSpec]>>) -> Self {
+        Self {
+            exclusions: exclusions.into(),
+            ..self
         }
     }
 
@@ -49,12 +74,30 @@ impl SubdirData {
     ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
         let client = self.client.clone();
         let name_clone = name.clone();
+        let patches = self.patches.clone();
+        let exclusions = self.exclusions.clone();
 
         self.records
             .get_or_try_init(name.clone(), || async move {
-                client
+                let records = client
                     .fetch_package_records(&name_clone, reporter.as_deref())
-                    .await
+                    .await?;
+                if patches.is_empty() && exclusions.is_empty() {
+                    return Ok(records);
+                }
+                Ok(records
+                    .iter()
+                    .filter_map(|record| {
+                        let mut record = record.clone();
+                        patches
+                            .iter()
+                            .all(|patch| {
+                                patch.apply_to_record(&record.file_name, &mut record.package_record)
+                            })
+                            .then_some(record)
+                    })
+                    .filter(|record| !exclusions.iter().any(|spec| spec.matches(record)))
+                    .collect())
             })
             .await
             .map_err(|e| match e {
@@ -1,10 +1,13 @@
 use std::{path::Path, sync::Arc};
 
 use file_url::url_to_path;
-use rattler_conda_types::{Channel, Platform};
+use rattler_conda_types::{Channel, MatchSpec, PatchInstructions, Platform};
+use url::Url;
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gateway::fetch_reporter::SubdirFetchReporter;
 use crate::{
-    fetch::FetchRepoDataError,
+    fetch::{CacheAction, FetchRepoDataError},
     gateway,
     gateway::{
         error::SubdirNotFoundError,
@@ -21,12 +24,21 @@ pub struct SubdirBuilder<'g> {
     channel: Channel,
     platform: Platform,
     reporter: Option<Arc<dyn Reporter>>,
-    gateway: &'g GatewayInner,
+    gateway: &'g Arc<GatewayInner>,
+}
+
+/// The data of a subdirectory that was built from a remote channel.
+struct RemoteSubdirData {
+    data: SubdirData,
+
+    /// Whether new repodata was downloaded, as opposed to read from the cache.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    downloaded: bool,
 }
 
 impl<'g> SubdirBuilder<'g> {
     pub fn new(
-        gateway: &'g GatewayInner,
+        gateway: &'g Arc<GatewayInner>,
         channel: Channel,
         platform: Platform,
         reporter: Option<Arc<dyn Reporter>>,
@@ -39,7 +51,8 @@ impl<'g> SubdirBuilder<'g> {
         }
     }
 
-    pub async fn build(self) -> Result<Subdir, GatewayError> {
+    #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+    pub async fn build(mut self) -> Result<Subdir, GatewayError> {
         let url = self.channel.platform_url(self.platform);
 
         let subdir_data = if url.scheme() == "file" {
@@ -53,35 +66,34 @@ impl<'g> SubdirBuilder<'g> {
         } else if url.scheme() == "http"
             || url.scheme() == "https"
             || url.scheme() == "gcs"
+            || url.scheme() == "gs"
             || url.scheme() == "oci"
             || url.scheme() == "s3"
+            || url.scheme() == "az"
         {
             let source_config = self.gateway.channel_config.get(&self.channel.base_url);
 
-            // Use sharded repodata if enabled
-            let subdir_data = if source_config.sharded_enabled
-                || gateway::force_sharded_repodata(&url)
-            {
-                match self.build_sharded(source_config).await {
-                    Ok(client) => Some(client),
-                    Err(GatewayError::SubdirNotFoundError(_)) => {
-                        tracing::info!(
-                            "sharded repodata seems to be missing for {url}, falling back to repodata.json files",
-                        );
-                        None
-                    }
-                    Err(err) => return Err(err),
-                }
-            } else {
-                None
-            };
+            // Report the progress of the subdirectory as a whole on top of the
+            // individual downloads.
+            #[cfg(not(target_arch = "wasm32"))]
+            let fetch_reporter = self
+                .reporter
+                .clone()
+                .and_then(|reporter| SubdirFetchReporter::start(reporter, &url));
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(fetch_reporter) = &fetch_reporter {
+                let reporter: Arc<dyn Reporter> = fetch_reporter.clone();
+                self.reporter = Some(reporter);
+            }
 
-            // Otherwise fall back to repodata.json files
-            if let Some(subdir_data) = subdir_data {
-                Ok(subdir_data)
-            } else {
-                self.build_generic(source_config).await
+            let subdir_data = self.build_from_source(&url, source_config).await;
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(fetch_reporter) = fetch_reporter {
+                fetch_reporter.finish();
             }
+
+            subdir_data
         } else {
             return Err(GatewayError::UnsupportedUrl(format!(
                 "'{}' is not a supported scheme",
@@ -90,7 +102,11 @@ impl<'g> SubdirBuilder<'g> {
         };
 
         match subdir_data {
-            Ok(client) => Ok(Subdir::Found(client)),
+            Ok(client) => Ok(Subdir::Found(
+                client
+                    .with_patches(self.patch_instructions())
+                    .with_exclusions(self.exclusions()),
+            )),
             Err(GatewayError::SubdirNotFoundError(err)) if self.platform != Platform::NoArch => {
                 // If the subdir was not found and the platform is not `noarch` we assume its
                 // just empty.
@@ -113,10 +129,148 @@ impl<'g> SubdirBuilder<'g> {
         }
     }
 
-    async fn build_generic(
+    /// Returns the patch instructions that were registered for this
+    /// subdirectory of the channel.
+    fn patch_instructions(&self) -> Vec<PatchInstructions> {
+        self.gateway
+            .repodata_patches
+            .get(&self.channel.base_url)
+            .into_iter()
+            .flatten()
+            .filter_map(|patch| patch.subdirs.get(self.platform.as_str()).cloned())
+            .collect()
+    }
+
+    /// Returns the specs of the records that are excluded from this channel.
+    fn exclusions(&self) -> Vec<MatchSpec> {
+        self.gateway
+            .excluded_packages
+            .get(&self.channel.base_url)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn build_from_source(
         &self,
+        url: &Url,
         source_config: &SourceConfig,
     ) -> Result<SubdirData, GatewayError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if source_config.stale_while_revalidate
+            && source_config.cache_action == CacheAction::CacheOrFetch
+        {
+            return self.build_stale_while_revalidate(url, source_config).await;
+        }
+
+        self.build_remote(url, source_config, source_config.cache_action)
+            .await
+            .map(|remote| remote.data)
+    }
+
+    /// Builds a subdirectory from a remote channel. The `index_cache_action`
+    /// determines how the cache of the `repodata.json` or the shard index is
+    /// used, the shards themselves are cached according to the
+    /// `source_config`.
+    async fn build_remote(
+        &self,
+        url: &Url,
+        source_config: &SourceConfig,
+        index_cache_action: CacheAction,
+    ) -> Result<RemoteSubdirData, GatewayError> {
+        // Use sharded repodata if enabled
+        if source_config.sharded_enabled || gateway::force_sharded_repodata(url) {
+            match self.build_sharded(source_config, index_cache_action).await {
+                Ok(client) => return Ok(client),
+                Err(GatewayError::Cancelled) => return Err(GatewayError::Cancelled),
+                Err(GatewayError::SubdirNotFoundError(_)) => {
+                    tracing::info!(
+                        "sharded repodata seems to be missing for {url}, falling back to repodata.json files",
+                    );
+                }
+                Err(err) => {
+                    // Channels without sharded repodata do not always respond
+                    // with a 404 (e.g. S3 buckets respond with a 403), and in
+                    // cache-only mode the channel might simply never have
+                    // provided shards. Try the repodata.json files instead.
+                    tracing::info!(
+                        "failed to load sharded repodata for {url}, falling back to repodata.json files: {err}",
+                    );
+                }
+            }
+        }
+
+        // Otherwise fall back to repodata.json files
+        self.build_generic(&SourceConfig {
+            cache_action: index_cache_action,
+            ..source_config.clone()
+        })
+        .await
+    }
+
+    /// Builds the subdirectory from the cached repodata. If the cache is out
+    /// of date it is still used, but refreshed in the background. Once the
+    /// refresh has downloaded new repodata the stale subdirectory is evicted
+    /// from memory so subsequent queries use the refreshed repodata. If
+    /// nothing is cached the repodata is fetched as usual.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn build_stale_while_revalidate(
+        &self,
+        url: &Url,
+        source_config: &SourceConfig,
+    ) -> Result<SubdirData, GatewayError> {
+        // If the cache is up to date there is nothing to refresh.
+        if let Ok(remote) = self
+            .build_remote(url, source_config, CacheAction::UseCacheOnly)
+            .await
+        {
+            return Ok(remote.data);
+        }
+
+        let subdir_data = match self
+            .build_remote(url, source_config, CacheAction::ForceCacheOnly)
+            .await
+        {
+            Ok(remote) => remote.data,
+            Err(err) => {
+                tracing::debug!("no cached repodata available for {url}: {err}");
+                return self
+                    .build_remote(url, source_config, source_config.cache_action)
+                    .await
+                    .map(|remote| remote.data);
+            }
+        };
+
+        let gateway = self.gateway.clone();
+        let channel = self.channel.clone();
+        let platform = self.platform;
+        let url = url.clone();
+        let source_config = source_config.clone();
+        let refresh = tokio::spawn(async move {
+            let builder = SubdirBuilder::new(&gateway, channel.clone(), platform, None);
+            match builder
+                .build_remote(&url, &source_config, source_config.cache_action)
+                .await
+            {
+                Ok(remote) if remote.downloaded => gateway
+                    .subdirs
+                    .retain(|key, _| key.0 != channel || key.1 != platform),
+                Ok(_) => tracing::debug!("cached repodata for {url} is still up to date"),
+                Err(err) => tracing::warn!("failed to refresh repodata for {url}: {err}"),
+            }
+        });
+        {
+            let mut refreshes = self.gateway.background_refreshes.lock();
+            refreshes.retain(|refresh| !refresh.is_finished());
+            refreshes.push(refresh);
+        }
+
+        Ok(subdir_data)
+    }
+
+    async fn build_generic(
+        &self,
+        source_config: &SourceConfig,
+    ) -> Result<RemoteSubdirData, GatewayError> {
         let client = remote_subdir::RemoteSubdirClient::new(
             self.channel.clone(),
             self.platform,
@@ -127,13 +281,23 @@ impl<'g> SubdirBuilder<'g> {
             self.reporter.clone(),
         )
         .await?;
-        Ok(SubdirData::from_client(client))
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let downloaded = client.downloaded();
+        #[cfg(target_arch = "wasm32")]
+        let downloaded = true;
+
+        Ok(RemoteSubdirData {
+            data: SubdirData::from_client(client),
+            downloaded,
+        })
     }
 
     async fn build_sharded(
         &self,
         _source_config: &SourceConfig,
-    ) -> Result<SubdirData, GatewayError> {
+        _index_cache_action: CacheAction,
+    ) -> Result<RemoteSubdirData, GatewayError> {
         let client = sharded_subdir::ShardedSubdir::new(
             self.channel.clone(),
             self.platform.to_string(),
@@ -141,21 +305,47 @@ impl<'g> SubdirBuilder<'g> {
             #[cfg(not(target_arch = "wasm32"))]
             self.gateway.cache.clone(),
             #[cfg(not(target_arch = "wasm32"))]
-            _source_config.cache_action,
+            sharded_subdir::ShardedSubdirCacheOptions {
+                cache_action: _source_config.cache_action,
+                index_cache_action: _index_cache_action,
+                max_stale: _source_config.max_stale,
+            },
             self.gateway.concurrent_requests_semaphore.clone(),
             self.reporter.as_deref(),
         )
         .await?;
 
-        Ok(SubdirData::from_client(client))
+        #[cfg(not(target_arch = "wasm32"))]
+        let downloaded = client.index_downloaded();
+        #[cfg(target_arch = "wasm32")]
+        let downloaded = true;
+
+        Ok(RemoteSubdirData {
+            data: SubdirData::from_client(client),
+            downloaded,
+        })
     }
 
     async fn build_local(&self, path: &Path) -> Result<SubdirData, GatewayError> {
         let channel = self.channel.clone();
         let platform = self.platform;
-        let path = path.join("repodata.json");
-        let build_client =
-            move || LocalSubdirClient::from_file(&path, channel.clone(), platform.as_str());
+        let subdir_path = path.to_path_buf();
+        #[cfg(all(feature = "indexing", not(target_arch = "wasm32")))]
+        let cache_dir = self.gateway.cache.join("indexed");
+        let build_client = move || {
+            let repodata_path = subdir_path.join("repodata.json");
+
+            // Index directories that only contain packages on the fly.
+            #[cfg(all(feature = "indexing", not(target_arch = "wasm32")))]
+            let repodata_path = if repodata_path.is_file() {
+                repodata_path
+            } else {
+                gateway::local_index::index_local_subdir(&subdir_path, platform, &cache_dir)?
+                    .unwrap_or(repodata_path)
+            };
+
+            LocalSubdirClient::from_file(&repodata_path, channel.clone(), platform.as_str())
+        };
 
         #[cfg(target_arch = "wasm32")]
         let client = build_client()?;
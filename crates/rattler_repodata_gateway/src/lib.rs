@@ -66,15 +66,17 @@ mod reporter;
 #[cfg(feature = "sparse")]
 pub mod sparse;
 mod utils;
-pub use reporter::{DownloadReporter, JLAPReporter, Reporter};
+pub use reporter::{
+    DownloadReporter, GatewayReporter, JLAPReporter, Reporter, SubdirFetchInfo, SubdirFetchSource,
+};
 
 #[cfg(feature = "gateway")]
 mod gateway;
 
 #[cfg(feature = "gateway")]
 pub use gateway::{
-    ChannelConfig, Gateway, GatewayBuilder, GatewayError, MaxConcurrency, RepoData, SourceConfig,
-    SubdirSelection,
+    ChannelConfig, Gateway, GatewayBuilder, GatewayError, MaxConcurrency, PlatformFallback,
+    RepoData, SourceConfig, SubdirSelection,
 };
 #[cfg(all(not(target_arch = "wasm32"), feature = "gateway"))]
 pub use gateway::{RunExportExtractorError, RunExportsReporter};
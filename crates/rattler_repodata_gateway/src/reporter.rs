@@ -1,4 +1,4 @@
-use std::future::Future;
+use std::{future::Future, time::Duration};
 
 use bytes::Bytes;
 use futures::{Stream, TryStreamExt};
@@ -69,6 +69,64 @@ pub trait JLAPReporter: Send + Sync {
     fn on_jlap_completed(&self, _index: usize) {}
 }
 
+/// Describes where the repodata of a subdirectory came from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubdirFetchSource {
+    /// The repodata was read from the cache without downloading anything. This
+    /// includes cached repodata that was revalidated with the server.
+    Cache,
+
+    /// (Part of) the repodata was downloaded.
+    Download,
+}
+
+/// Information about the repodata of a subdirectory that finished loading.
+#[derive(Debug, Clone)]
+pub struct SubdirFetchInfo {
+    /// Where the repodata came from.
+    pub source: SubdirFetchSource,
+
+    /// The number of bytes that were downloaded.
+    pub bytes_downloaded: usize,
+
+    /// The total time it took to load the subdirectory.
+    pub duration: Duration,
+
+    /// The time spent reading and parsing the repodata after all downloads
+    /// completed.
+    pub parse_duration: Duration,
+}
+
+/// A trait that enables being notified of the progress of loading the
+/// repodata of subdirectories with a [`crate::Gateway`].
+///
+/// Only the subdirectories that are fetched from a remote channel are
+/// reported.
+pub trait GatewayReporter: Send + Sync {
+    /// Called when the gateway starts loading the repodata of a subdirectory.
+    ///
+    /// Returns an index that can be used to identify the subdirectory in
+    /// subsequent calls.
+    fn on_subdir_fetch_start(&self, _url: &Url) -> usize {
+        0
+    }
+
+    /// Called when downloading the repodata of a subdirectory makes progress.
+    ///
+    /// The `total_bytes` parameter is `None` if the total size is unknown.
+    fn on_subdir_fetch_progress(
+        &self,
+        _index: usize,
+        _bytes_downloaded: usize,
+        _total_bytes: Option<usize>,
+    ) {
+    }
+
+    /// Called when the gateway finished loading the repodata of a
+    /// subdirectory, regardless of whether that succeeded.
+    fn on_subdir_fetch_complete(&self, _index: usize, _info: &SubdirFetchInfo) {}
+}
+
 /// A trait that enables being notified of repodata fetching progress.
 pub trait Reporter: Send + Sync {
     /// Returns a reporter for downloading files.
@@ -76,6 +134,11 @@ pub trait Reporter: Send + Sync {
 
     /// Returns a reporter for JLAP operations.
     fn jlap_reporter(&self) -> Option<&dyn JLAPReporter>;
+
+    /// Returns a reporter for loading the repodata of subdirectories.
+    fn gateway_reporter(&self) -> Option<&dyn GatewayReporter> {
+        None
+    }
 }
 
 #[allow(dead_code)]
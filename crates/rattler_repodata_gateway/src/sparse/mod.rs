@@ -221,6 +221,28 @@ impl SparseRepoData {
         }
     }
 
+    /// Returns true if the repodata contains any record for the package with
+    /// the given name. No records are parsed to determine this.
+    pub fn contains_package(
+        &self,
+        package_name: &PackageName,
+        package_format_selection: PackageFormatSelection,
+    ) -> bool {
+        let repo_data = self.inner.borrow_repo_data();
+        let contains = |slice: &[(PackageFilename<'_>, &RawValue)]| {
+            find_package_in_slice(slice, Some(package_name))
+                .next()
+                .is_some()
+        };
+        match package_format_selection {
+            PackageFormatSelection::Both | PackageFormatSelection::PreferConda => {
+                contains(&repo_data.packages) || contains(&repo_data.conda_packages)
+            }
+            PackageFormatSelection::OnlyTarBz2 => contains(&repo_data.packages),
+            PackageFormatSelection::OnlyConda => contains(&repo_data.conda_packages),
+        }
+    }
+
     /// Returns the number of records in this instance.
     pub fn record_count(&self, package_format_selection: PackageFormatSelection) -> usize {
         match package_format_selection {
@@ -1058,6 +1080,21 @@ mod test {
         assert_eq!(count, expected_count);
     }
 
+    #[test]
+    fn test_contains_package() {
+        let (channel, platform, path) = dummy_repo_data();
+        let sparse = SparseRepoData::from_file(channel, platform, path, None).unwrap();
+        let foo = PackageName::new_unchecked("foo");
+        let conda_only = PackageName::new_unchecked("conda-only");
+        let missing = PackageName::new_unchecked("does-not-exist");
+
+        assert!(sparse.contains_package(&foo, PackageFormatSelection::default()));
+        assert!(!sparse.contains_package(&foo, PackageFormatSelection::OnlyConda));
+        assert!(sparse.contains_package(&conda_only, PackageFormatSelection::OnlyConda));
+        assert!(!sparse.contains_package(&conda_only, PackageFormatSelection::OnlyTarBz2));
+        assert!(!sparse.contains_package(&missing, PackageFormatSelection::Both));
+    }
+
     #[test]
     fn test_query() {
         let (channel, platform, path) = dummy_repo_data();
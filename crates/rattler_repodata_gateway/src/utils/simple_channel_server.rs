@@ -33,6 +33,12 @@ impl SimpleChannelServer {
         // Create a router that will serve the static files from the channel.
         let app = axum::Router::new().fallback_service(service);
 
+        Self::from_router(app).await
+    }
+
+    /// Serves the given router instead of just the static files of a channel.
+    #[allow(dead_code)]
+    pub async fn from_router(app: axum::Router) -> Self {
         // Construct the server that will listen on localhost but with a *random port*. The random
         // port is very important because it enables creating multiple instances at the same time.
         // We need this to be able to run tests in parallel.
@@ -6,7 +6,7 @@
 #[cfg(target_family = "unix")]
 use std::io::Write;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     ffi::OsStr,
     path::{Path, PathBuf},
     process::ExitStatus,
@@ -18,15 +18,17 @@ use fs_err as fs;
 use indexmap::IndexMap;
 use itertools::Itertools;
 use rattler_conda_types::Platform;
+use rattler_digest::{digest::Digest, Sha256};
 #[cfg(target_family = "unix")]
 use rattler_pty::unix::PtySession;
+use serde::{Deserialize, Serialize};
 
 use crate::shell::{Shell, ShellError, ShellScript};
 
 const ENV_START_SEPARATOR: &str = "____RATTLER_ENV_START____";
 
 /// Type of modification done to the `PATH` variable
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Copy, Debug)]
 pub enum PathModificationBehavior {
     /// Replaces the complete path variable with specified paths.
     #[default]
@@ -74,6 +76,47 @@ impl ActivationVariables {
     }
 }
 
+/// Options that control how an environment is activated on top of the
+/// current environment.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ActivationOptions {
+    /// Activate the environment on top of the currently activated environment
+    /// (`ActivationVariables::conda_prefix`) instead of replacing it, like
+    /// `conda activate --stack`. The paths and the environment variables of the
+    /// current environment are kept and its deactivation scripts are not run.
+    pub stack: bool,
+
+    /// Where the paths of the environment are placed in the `PATH`. Overrides
+    /// [`ActivationVariables::path_modification_behavior`] if set.
+    pub path_modification_behavior: Option<PathModificationBehavior>,
+}
+
+impl ActivationOptions {
+    /// Sets whether the environment is stacked on top of the currently
+    /// activated environment.
+    pub fn with_stack(mut self, stack: bool) -> Self {
+        self.stack = stack;
+        self
+    }
+
+    /// Sets where the paths of the environment are placed in the `PATH`.
+    pub fn with_path_modification_behavior(mut self, behavior: PathModificationBehavior) -> Self {
+        self.path_modification_behavior = Some(behavior);
+        self
+    }
+
+    /// Returns where the paths of the environment are placed in the `PATH`,
+    /// falling back to the behavior of `variables`.
+    fn path_modification_behavior(
+        &self,
+        variables: &ActivationVariables,
+    ) -> PathModificationBehavior {
+        self.path_modification_behavior
+            .unwrap_or(variables.path_modification_behavior)
+    }
+}
+
 /// A struct that holds values for the activation and deactivation
 /// process of an environment, e.g. activation scripts to execute or environment
 /// variables to set.
@@ -241,7 +284,7 @@ fn collect_env_vars(prefix: &Path) -> Result<IndexMap<String, String>, Activatio
         for (env_var_json, env_var_file) in env_var_json_files.iter().zip(env_var_files.iter()) {
             let env_var_json = env_var_json.as_object().ok_or_else(|| {
                 ActivationError::InvalidEnvVarFileJsonNoObject {
-                    file: pkg_env_var_dir.clone(),
+                    file: env_var_file.clone(),
                 }
             })?;
 
@@ -258,28 +301,19 @@ fn collect_env_vars(prefix: &Path) -> Result<IndexMap<String, String>, Activatio
     }
 
     if state_file.exists() {
-        let state_json = fs::read_to_string(&state_file)?;
-
-        // load json but preserve the order of dicts - for this we use the serde
-        // preserve_order feature
-        let state_json: serde_json::Value = serde_json::from_str(&state_json)
-            .map_err(|e| ActivationError::InvalidEnvVarFileJson(e, state_file.clone()))?;
-
-        let state_env_vars = state_json["env_vars"].as_object().ok_or_else(|| {
-            ActivationError::InvalidEnvVarFileStateFile {
-                file: state_file.clone(),
-            }
-        })?;
+        let state_json = read_state_file(&state_file)?;
+        let state_env_vars = state_env_vars(&state_json, &state_file)?;
 
-        for (key, value) in state_env_vars {
-            if state_env_vars.contains_key(key) {
+        for (key, value) in state_env_vars.into_iter().flatten() {
+            let key = key.to_uppercase();
+            if env_vars.contains_key(&key) {
                 tracing::warn!(
                     "WARNING: environment variable {key} already defined in packages (path: {state_file:?})"
                 );
             }
 
             if let Some(value) = value.as_str() {
-                env_vars.insert(key.to_uppercase(), value.to_string());
+                env_vars.insert(key, value.to_string());
             } else {
                 tracing::warn!(
                     "WARNING: environment variable {key} has no string value (path: {state_file:?})"
@@ -290,6 +324,85 @@ fn collect_env_vars(prefix: &Path) -> Result<IndexMap<String, String>, Activatio
     Ok(env_vars)
 }
 
+/// Reads the `conda-meta/state` file of a prefix.
+fn read_state_file(
+    state_file: &Path,
+) -> Result<serde_json::Map<String, serde_json::Value>, ActivationError> {
+    let state_json = fs::read_to_string(state_file)?;
+
+    // load json but preserve the order of dicts - for this we use the serde
+    // preserve_order feature
+    serde_json::from_str(&state_json)
+        .map_err(|e| ActivationError::InvalidEnvVarFileJson(e, state_file.to_path_buf()))
+}
+
+/// Returns the `env_vars` of a state file. A state file without `env_vars` has
+/// no environment variables.
+fn state_env_vars<'a>(
+    state_json: &'a serde_json::Map<String, serde_json::Value>,
+    state_file: &Path,
+) -> Result<Option<&'a serde_json::Map<String, serde_json::Value>>, ActivationError> {
+    match state_json.get("env_vars") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::Object(env_vars)) => Ok(Some(env_vars)),
+        Some(_) => Err(ActivationError::InvalidEnvVarFileStateFile {
+            file: state_file.to_path_buf(),
+        }),
+    }
+}
+
+/// Modifies the environment variables in the `conda-meta/state` file of a
+/// prefix, like `conda env config vars set` and `conda env config vars unset`.
+/// The variables are set when the environment is activated and restored when
+/// it is deactivated.
+///
+/// The variables in `unset` are removed first, then the variables in `set` are
+/// added or overwritten. Other contents of the state file are preserved. The
+/// file is created if it does not exist yet.
+pub fn update_prefix_env_vars<'a>(
+    prefix: &Path,
+    set: impl IntoIterator<Item = (&'a str, &'a str)>,
+    unset: impl IntoIterator<Item = &'a str>,
+) -> Result<(), ActivationError> {
+    let state_file = prefix.join("conda-meta/state");
+    let mut state_json = if state_file.exists() {
+        read_state_file(&state_file)?
+    } else {
+        serde_json::Map::new()
+    };
+
+    // Validate the existing variables before modifying them.
+    state_env_vars(&state_json, &state_file)?;
+    let env_vars = state_json
+        .entry("env_vars")
+        .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+    if env_vars.is_null() {
+        *env_vars = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let env_vars = env_vars
+        .as_object_mut()
+        .expect("env_vars was validated to be an object");
+
+    for key in unset {
+        env_vars.retain(|existing, _| !existing.eq_ignore_ascii_case(key));
+    }
+    for (key, value) in set {
+        env_vars.retain(|existing, _| !existing.eq_ignore_ascii_case(key));
+        env_vars.insert(
+            key.to_string(),
+            serde_json::Value::String(value.to_string()),
+        );
+    }
+
+    fs::create_dir_all(prefix.join("conda-meta"))?;
+    fs::write(
+        &state_file,
+        serde_json::to_string_pretty(&state_json)
+            .map_err(|e| ActivationError::InvalidEnvVarFileJson(e, state_file.clone()))?,
+    )?;
+    Ok(())
+}
+
 /// Return a vector of path entries that are prefixed with the given path.
 ///
 /// # Arguments
@@ -328,6 +441,65 @@ pub struct ActivationResult<T: Shell + 'static> {
     pub path: Vec<PathBuf>,
 }
 
+/// The old and the new value of an environment variable that is modified by
+/// the activation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModifiedEnvVar {
+    /// The value before the activation.
+    pub old: String,
+
+    /// The value after the activation.
+    pub new: String,
+}
+
+/// The changes that activating an environment makes to the environment
+/// variables. In contrast to the activation script this can be inspected and
+/// applied programmatically, e.g. by applications that start processes in an
+/// environment without a shell.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivationDiff {
+    /// Environment variables that were not set before the activation.
+    pub set: BTreeMap<String, String>,
+
+    /// Environment variables whose value is changed by the activation.
+    pub modified: BTreeMap<String, ModifiedEnvVar>,
+
+    /// Environment variables that are removed by the activation, e.g. the
+    /// variables of a previously activated environment.
+    pub unset: BTreeSet<String>,
+
+    /// The entries that are added to the PATH.
+    pub path_entries_added: Vec<PathBuf>,
+
+    /// The entries that are removed from the PATH, e.g. the entries of a
+    /// previously activated environment.
+    pub path_entries_removed: Vec<PathBuf>,
+
+    /// The activation scripts of the environment. These are not evaluated
+    /// when computing the diff, applications have to run them in a shell if
+    /// they need their side effects.
+    pub activation_scripts: Vec<PathBuf>,
+}
+
+impl ActivationDiff {
+    /// Applies the changes to a set of environment variables.
+    pub fn apply(&self, env: &mut HashMap<String, String>) {
+        for key in &self.unset {
+            env.remove(key);
+        }
+        env.extend(
+            self.set
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        env.extend(
+            self.modified
+                .iter()
+                .map(|(key, value)| (key.clone(), value.new.clone())),
+        );
+    }
+}
+
 impl<T: Shell + Clone> Activator<T> {
     /// Return unique env var keys from both `env_vars` and `post_activation_env_vars` in insertion order.
     fn unique_env_keys(&self) -> impl Iterator<Item = &str> {
@@ -462,23 +634,37 @@ impl<T: Shell + Clone> Activator<T> {
     pub fn activation(
         &self,
         variables: ActivationVariables,
+    ) -> Result<ActivationResult<T>, ActivationError> {
+        self.activation_with_options(variables, &ActivationOptions::default())
+    }
+
+    /// Same as [`Self::activation`] but with additional [`ActivationOptions`],
+    /// e.g. to stack the environment on top of the current one.
+    pub fn activation_with_options(
+        &self,
+        variables: ActivationVariables,
+        options: &ActivationOptions,
     ) -> Result<ActivationResult<T>, ActivationError> {
         let mut script = ShellScript::new(self.shell_type.clone(), self.platform);
+        let path_modification_behavior = options.path_modification_behavior(&variables);
 
+        // Remove the paths of this environment so they are not added twice when
+        // it is activated again.
         let mut path = variables.path.clone().unwrap_or_default();
-        if let Some(conda_prefix) = variables.conda_prefix {
+        path.retain(|x| !self.paths.contains(x));
+        if let Some(conda_prefix) = variables.conda_prefix.filter(|_| !options.stack) {
             let deactivate = Activator::from_path(
                 Path::new(&conda_prefix),
                 self.shell_type.clone(),
                 self.platform,
             )?;
 
-            for (key, _) in &deactivate.env_vars {
-                script.unset_env_var(key)?;
+            for deactivation_script in deactivate.deactivation_scripts.iter().rev() {
+                script.run_script(deactivation_script)?;
             }
 
-            for deactivation_script in &deactivate.deactivation_scripts {
-                script.run_script(deactivation_script)?;
+            for (key, _) in &deactivate.env_vars {
+                script.unset_env_var(key)?;
             }
 
             path.retain(|x| !deactivate.paths.contains(x));
@@ -487,7 +673,7 @@ impl<T: Shell + Clone> Activator<T> {
         // prepend new paths
         let path = [self.paths.clone(), path].concat();
 
-        script.set_path(path.as_slice(), variables.path_modification_behavior)?;
+        script.set_path(path.as_slice(), path_modification_behavior)?;
 
         // Get the current shell level
         // For us, zero is the starting point, so we will increment it
@@ -530,15 +716,169 @@ impl<T: Shell + Clone> Activator<T> {
         Ok(ActivationResult { script, path })
     }
 
+    /// Returns the name of the PATH variable in `current_env` and its entries, if
+    /// it is set.
+    fn current_path(
+        &self,
+        current_env: &HashMap<String, String>,
+    ) -> (String, Option<Vec<PathBuf>>) {
+        let path_var = self.shell_type.path_var(&self.platform);
+        let key = current_env
+            .keys()
+            .find(|key| {
+                *key == path_var
+                    || (self.platform.is_windows() && key.eq_ignore_ascii_case(path_var))
+            })
+            .cloned()
+            .unwrap_or_else(|| path_var.to_string());
+        let path = current_env.get(&key).map(|path| {
+            path.split(self.shell_type.path_separator(&self.platform))
+                .filter(|entry| !entry.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        });
+        (key, path)
+    }
+
+    /// Computes the changes that [`Self::activation`] makes to the environment
+    /// variables in `variables.current_env` as an [`ActivationDiff`].
+    ///
+    /// The PATH is computed from `variables.path`, or from the PATH in
+    /// `variables.current_env` if that is not set. Activation scripts are not
+    /// evaluated, the variables that are set after the activation scripts are
+    /// part of the diff nonetheless.
+    pub fn activation_diff(
+        &self,
+        variables: ActivationVariables,
+    ) -> Result<ActivationDiff, ActivationError> {
+        self.activation_diff_with_options(variables, &ActivationOptions::default())
+    }
+
+    /// Same as [`Self::activation_diff`] but with additional
+    /// [`ActivationOptions`].
+    pub fn activation_diff_with_options(
+        &self,
+        variables: ActivationVariables,
+        options: &ActivationOptions,
+    ) -> Result<ActivationDiff, ActivationError> {
+        let current_env = &variables.current_env;
+
+        // The new values of all variables that are touched, `None` if the variable
+        // is unset.
+        let mut changes: IndexMap<String, Option<String>> = IndexMap::new();
+
+        let path_separator = self.shell_type.path_separator(&self.platform);
+        let (current_path_key, current_path) = self.current_path(current_env);
+        let mut path = variables.path.clone().or(current_path).unwrap_or_default();
+        path.retain(|entry| !self.paths.contains(entry));
+
+        let mut path_entries_removed = Vec::new();
+        if let Some(conda_prefix) = variables.conda_prefix.as_ref().filter(|_| !options.stack) {
+            let deactivate =
+                Activator::from_path(conda_prefix, self.shell_type.clone(), self.platform)?;
+            for key in deactivate.env_vars.keys() {
+                changes.insert(key.clone(), None);
+            }
+            path.retain(|entry| {
+                let removed = deactivate.paths.contains(entry);
+                if removed {
+                    path_entries_removed.push(entry.clone());
+                }
+                !removed
+            });
+        }
+
+        let path = match options.path_modification_behavior(&variables) {
+            PathModificationBehavior::Append => [path, self.paths.clone()].concat(),
+            PathModificationBehavior::Replace | PathModificationBehavior::Prepend => {
+                [self.paths.clone(), path].concat()
+            }
+        };
+        changes.insert(
+            current_path_key,
+            Some(
+                path.iter()
+                    .map(|entry| entry.to_string_lossy())
+                    .join(path_separator),
+            ),
+        );
+
+        let new_shlvl = current_env
+            .get("CONDA_SHLVL")
+            .and_then(|s| s.parse::<i32>().ok())
+            .unwrap_or(0)
+            + 1;
+        changes.insert(String::from("CONDA_SHLVL"), Some(new_shlvl.to_string()));
+        if let Some(existing_prefix) = current_env.get("CONDA_PREFIX") {
+            changes.insert(
+                format!("CONDA_ENV_SHLVL_{new_shlvl}_CONDA_PREFIX"),
+                Some(existing_prefix.clone()),
+            );
+        }
+        changes.insert(
+            String::from("CONDA_PREFIX"),
+            Some(self.target_prefix.to_string_lossy().into_owned()),
+        );
+
+        for (key, value) in self.env_vars.iter().chain(&self.post_activation_env_vars) {
+            if let Some(existing_value) = current_env.get(key) {
+                changes.insert(
+                    format!("CONDA_ENV_SHLVL_{new_shlvl}_{key}"),
+                    Some(existing_value.clone()),
+                );
+            }
+            changes.insert(key.clone(), Some(value.clone()));
+        }
+
+        let mut diff = ActivationDiff {
+            path_entries_added: self.paths.clone(),
+            path_entries_removed,
+            activation_scripts: self.activation_scripts.clone(),
+            ..ActivationDiff::default()
+        };
+        for (key, value) in changes {
+            match (current_env.get(&key), value) {
+                (None, Some(new)) => {
+                    diff.set.insert(key, new);
+                }
+                (Some(old), Some(new)) if *old != new => {
+                    diff.modified.insert(
+                        key,
+                        ModifiedEnvVar {
+                            old: old.clone(),
+                            new,
+                        },
+                    );
+                }
+                (Some(_), None) => {
+                    diff.unset.insert(key);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(diff)
+    }
+
     /// Create a deactivation script for the environment.
-    /// This returns the deactivation script that unsets environment variables
-    /// and runs deactivation scripts.
+    /// This returns the deactivation script that runs the deactivation scripts,
+    /// restores the environment variables that were overwritten by the
+    /// activation and removes the entries of the environment from the PATH.
+    ///
+    /// Like conda, the deactivation scripts run first, in reverse order, so
+    /// they can still access the variables of the environment. The PATH is
+    /// taken from `variables.path`, or from the PATH in
+    /// `variables.current_env`. If neither is set the PATH is left untouched.
     pub fn deactivation(
         &self,
         variables: ActivationVariables,
     ) -> Result<ActivationResult<T>, ActivationError> {
         let mut script = ShellScript::new(self.shell_type.clone(), self.platform);
 
+        for deactivation_script in self.deactivation_scripts.iter().rev() {
+            script.run_script(deactivation_script)?;
+        }
+
         // Get the current CONDA shell level from passed environment variables
         let current_conda_shlvl = variables
             .current_env
@@ -598,14 +938,19 @@ impl<T: Shell + Clone> Activator<T> {
             }
         }
 
-        // Run all deactivation scripts
-        for deactivation_script in &self.deactivation_scripts {
-            script.run_script(deactivation_script)?;
+        // Remove the entries of the environment from the PATH
+        let mut path = variables
+            .path
+            .clone()
+            .or_else(|| self.current_path(&variables.current_env).1);
+        if let Some(path) = &mut path {
+            path.retain(|entry| !self.paths.contains(entry));
+            script.set_path(path, PathModificationBehavior::Replace)?;
         }
 
         Ok(ActivationResult {
             script,
-            path: Vec::new(),
+            path: path.unwrap_or_default(),
         })
     }
 
@@ -641,9 +986,9 @@ impl<T: Shell + Clone> Activator<T> {
 
         // Write the activation script to the temporary file, closing the file
         // afterwards
-        fs::write(
-            &activation_script_path,
-            activation_detection_script.contents()?,
+        self.shell_type.write_script(
+            &mut fs::File::create(&activation_script_path)?,
+            &activation_detection_script.contents()?,
         )?;
         // Get only the path to the temporary file
         let mut activation_command = self
@@ -687,6 +1032,153 @@ impl<T: Shell + Clone> Activator<T> {
             .map(|(key, value)| (key.to_owned(), value.to_owned()))
             .collect())
     }
+
+    /// Same as [`Self::run_activation`] but caches the resulting environment
+    /// variables in `cache_dir`.
+    ///
+    /// Running the activation scripts can be slow. The first call captures the
+    /// changed environment variables and stores them in a cache file, later
+    /// calls replay them without running any script. The cache is invalidated
+    /// when the contents of the `conda-meta` directory of the prefix change
+    /// (e.g. when packages are installed or removed), or when the variables
+    /// that are read by the activation itself (`PATH`, `CONDA_PREFIX`,
+    /// `CONDA_SHLVL` and the variables set by the environment) or the
+    /// `environment` parameter change. Other variables that activation scripts
+    /// might read are not tracked.
+    ///
+    /// Failing to write the cache is not an error, the activation is simply
+    /// not cached.
+    pub fn run_activation_cached(
+        &self,
+        variables: ActivationVariables,
+        environment: Option<HashMap<&OsStr, &OsStr>>,
+        cache_dir: &Path,
+    ) -> Result<HashMap<String, String>, ActivationError> {
+        let key = self.activation_cache_key(&variables, environment.as_ref())?;
+        let cache_file = cache_dir.join(format!(
+            "{:x}.json",
+            Sha256::new()
+                .chain_update(self.target_prefix.to_string_lossy().as_bytes())
+                .chain_update(self.shell_type.executable())
+                .chain_update(self.platform.as_str())
+                .finalize()
+        ));
+
+        if let Some(cached) = fs::read_to_string(&cache_file)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<CachedActivation>(&contents).ok())
+        {
+            if cached.key == key {
+                return Ok(cached.environment);
+            }
+            tracing::debug!("activation cache {} is outdated", cache_file.display());
+        }
+
+        let environment = self.run_activation(variables, environment)?;
+        let cached = CachedActivation { key, environment };
+        if let Err(err) = fs::create_dir_all(cache_dir).and_then(|_| {
+            fs::write(
+                &cache_file,
+                serde_json::to_string(&cached).map_err(std::io::Error::other)?,
+            )
+        }) {
+            tracing::warn!(
+                "failed to write activation cache {}: {err}",
+                cache_file.display()
+            );
+        }
+
+        Ok(cached.environment)
+    }
+
+    /// Computes the key that identifies a cached activation. It hashes the
+    /// contents of the `conda-meta` directory and the inputs of the activation.
+    fn activation_cache_key(
+        &self,
+        variables: &ActivationVariables,
+        environment: Option<&HashMap<&OsStr, &OsStr>>,
+    ) -> Result<String, ActivationError> {
+        let mut hasher = Sha256::new();
+
+        // The names, sizes and modification times of the files in conda-meta
+        // change whenever packages are installed or removed, or the state file
+        // is modified.
+        let conda_meta = self.target_prefix.join("conda-meta");
+        if conda_meta.is_dir() {
+            let mut entries = fs::read_dir(&conda_meta)?
+                .map(|entry| {
+                    let entry = entry?;
+                    let metadata = entry.metadata()?;
+                    let modified = metadata
+                        .modified()
+                        .ok()
+                        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                        .unwrap_or_default();
+                    Ok((entry.file_name(), metadata.len(), modified.as_nanos()))
+                })
+                .collect::<Result<Vec<_>, std::io::Error>>()?;
+            entries.sort();
+            for (name, len, modified) in entries {
+                hasher.update(name.to_string_lossy().as_bytes());
+                hasher.update(len.to_le_bytes());
+                hasher.update(modified.to_le_bytes());
+            }
+        }
+
+        let mut inputs = BTreeMap::new();
+        for key in ["PATH", "CONDA_PREFIX", "CONDA_SHLVL"]
+            .into_iter()
+            .chain(self.unique_env_keys())
+        {
+            inputs.insert(
+                format!("env:{key}"),
+                variables.current_env.get(key).cloned(),
+            );
+        }
+        inputs.insert(
+            String::from("conda_prefix"),
+            variables
+                .conda_prefix
+                .as_ref()
+                .map(|prefix| prefix.to_string_lossy().into_owned()),
+        );
+        inputs.insert(
+            String::from("path"),
+            variables
+                .path
+                .as_ref()
+                .map(|path| path.iter().map(|entry| entry.to_string_lossy()).join("\0")),
+        );
+        inputs.insert(
+            String::from("path_modification_behavior"),
+            Some(String::from(match variables.path_modification_behavior {
+                PathModificationBehavior::Replace => "replace",
+                PathModificationBehavior::Append => "append",
+                PathModificationBehavior::Prepend => "prepend",
+            })),
+        );
+        if let Some(environment) = environment {
+            for (key, value) in environment {
+                inputs.insert(
+                    format!("environment:{}", key.to_string_lossy()),
+                    Some(value.to_string_lossy().into_owned()),
+                );
+            }
+        }
+        hasher.update(serde_json::to_string(&inputs).map_err(std::io::Error::other)?);
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+/// The contents of an activation cache file.
+#[derive(Serialize, Deserialize)]
+struct CachedActivation {
+    /// The key of the activation that produced the environment.
+    key: String,
+
+    /// The environment variables that were changed by the activation.
+    environment: HashMap<String, String>,
 }
 
 #[cfg(test)]
@@ -817,6 +1309,25 @@ mod tests {
         assert_eq!(env_vars["AAA"], "abcdef");
     }
 
+    #[test]
+    fn test_update_prefix_env_vars() {
+        let tdir = TempDir::new("test").unwrap();
+
+        update_prefix_env_vars(tdir.path(), [("FOO", "foo"), ("BAR", "bar")], []).unwrap();
+        let env_vars = collect_env_vars(tdir.path()).unwrap();
+        assert_eq!(env_vars["FOO"], "foo");
+        assert_eq!(env_vars["BAR"], "bar");
+
+        update_prefix_env_vars(tdir.path(), [("bar", "baz")], ["FOO"]).unwrap();
+        let env_vars = collect_env_vars(tdir.path()).unwrap();
+        assert_eq!(env_vars.len(), 1);
+        assert_eq!(env_vars["BAR"], "baz");
+
+        // A state file without environment variables is valid.
+        fs::write(tdir.path().join("conda-meta/state"), "{}").unwrap();
+        assert!(collect_env_vars(tdir.path()).unwrap().is_empty());
+    }
+
     #[test]
     fn test_collect_env_vars_with_directory() {
         let tdir = TempDir::new("test").unwrap();
@@ -976,6 +1487,168 @@ mod tests {
         insta::assert_snapshot!("test_activation_script_cmd_prepend", script);
     }
 
+    #[test]
+    fn test_deactivation_restores_path() {
+        let tdir = TempDir::new("test_deactivation_restores_path").unwrap();
+        let activator = Activator {
+            target_prefix: tdir.path().to_path_buf(),
+            shell_type: shell::Bash,
+            paths: vec![PathBuf::from("/prefix/bin")],
+            activation_scripts: vec![],
+            deactivation_scripts: vec![PathBuf::from("/a.sh"), PathBuf::from("/b.sh")],
+            env_vars: IndexMap::new(),
+            post_activation_env_vars: IndexMap::new(),
+            platform: Platform::Linux64,
+        };
+
+        let result = activator
+            .deactivation(ActivationVariables {
+                conda_prefix: None,
+                path: None,
+                path_modification_behavior: PathModificationBehavior::Prepend,
+                current_env: HashMap::from([
+                    (String::from("CONDA_SHLVL"), String::from("1")),
+                    (
+                        String::from("PATH"),
+                        String::from("/prefix/bin:/usr/bin:/bin"),
+                    ),
+                ]),
+            })
+            .unwrap();
+
+        assert_eq!(
+            result.path,
+            [PathBuf::from("/usr/bin"), PathBuf::from("/bin")]
+        );
+        let contents = result.script.contents().unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines[..2], [". /b.sh", ". /a.sh"]);
+        assert_eq!(lines.last(), Some(&"export PATH=\"/usr/bin:/bin\""));
+    }
+
+    #[test]
+    fn test_activation_diff() {
+        let tdir = TempDir::new("test_activation_diff").unwrap();
+        let mut activator =
+            Activator::from_path(tdir.path(), shell::Bash, Platform::Linux64).unwrap();
+        activator.env_vars = IndexMap::from_iter([
+            (String::from("FOO"), String::from("bar")),
+            (String::from("EXISTING"), String::from("new")),
+        ]);
+
+        let current_env = HashMap::from([
+            (String::from("PATH"), String::from("/usr/bin:/bin")),
+            (String::from("EXISTING"), String::from("old")),
+        ]);
+        let diff = activator
+            .activation_diff(ActivationVariables {
+                conda_prefix: None,
+                path: None,
+                path_modification_behavior: PathModificationBehavior::Prepend,
+                current_env: current_env.clone(),
+            })
+            .unwrap();
+
+        let prefix = tdir.path().to_string_lossy().into_owned();
+        assert_eq!(
+            diff.set,
+            BTreeMap::from([
+                (
+                    String::from("CONDA_ENV_SHLVL_1_EXISTING"),
+                    String::from("old")
+                ),
+                (String::from("CONDA_PREFIX"), prefix.clone()),
+                (String::from("CONDA_SHLVL"), String::from("1")),
+                (String::from("FOO"), String::from("bar")),
+            ])
+        );
+        assert_eq!(
+            diff.modified["PATH"],
+            ModifiedEnvVar {
+                old: String::from("/usr/bin:/bin"),
+                new: format!("{prefix}/bin:/usr/bin:/bin"),
+            }
+        );
+        assert_eq!(diff.modified["EXISTING"].new, "new");
+        assert!(diff.unset.is_empty());
+        assert_eq!(diff.path_entries_added, [tdir.path().join("bin")]);
+
+        let mut env = current_env;
+        diff.apply(&mut env);
+        assert_eq!(env["FOO"], "bar");
+        assert_eq!(env["EXISTING"], "new");
+
+        let json = serde_json::to_string(&diff).unwrap();
+        assert_eq!(serde_json::from_str::<ActivationDiff>(&json).unwrap(), diff);
+    }
+
+    #[test]
+    fn test_activation_options_path_modification_behavior() {
+        let tdir = TempDir::new("test_activation_options_path").unwrap();
+        let activator = Activator::from_path(tdir.path(), shell::Bash, Platform::Linux64).unwrap();
+
+        let variables = ActivationVariables {
+            conda_prefix: None,
+            path: Some(vec![PathBuf::from("/usr/bin")]),
+            path_modification_behavior: PathModificationBehavior::Prepend,
+            current_env: HashMap::new(),
+        };
+        let options = ActivationOptions::default()
+            .with_path_modification_behavior(PathModificationBehavior::Append);
+
+        // The options take precedence over the activation variables.
+        let diff = activator
+            .activation_diff_with_options(variables, &options)
+            .unwrap();
+        assert_eq!(
+            diff.set["PATH"],
+            format!("/usr/bin:{}", tdir.path().join("bin").display())
+        );
+    }
+
+    #[test]
+    fn test_activation_stacking() {
+        let base = TempDir::new("test_activation_stacking_base").unwrap();
+        let env = TempDir::new("test_activation_stacking_env").unwrap();
+        let activator = Activator::from_path(env.path(), shell::Bash, Platform::Linux64).unwrap();
+
+        let activate = |path: Vec<PathBuf>, stack: bool| {
+            activator
+                .activation_with_options(
+                    ActivationVariables {
+                        conda_prefix: Some(base.path().to_path_buf()),
+                        path: Some(path),
+                        path_modification_behavior: PathModificationBehavior::Prepend,
+                        current_env: HashMap::new(),
+                    },
+                    &ActivationOptions::default().with_stack(stack),
+                )
+                .unwrap()
+                .path
+        };
+
+        let path = vec![base.path().join("bin"), PathBuf::from("/usr/bin")];
+        assert_eq!(
+            activate(path.clone(), false),
+            [env.path().join("bin"), PathBuf::from("/usr/bin")]
+        );
+        assert_eq!(
+            activate(path, true),
+            [
+                env.path().join("bin"),
+                base.path().join("bin"),
+                PathBuf::from("/usr/bin")
+            ]
+        );
+
+        // Activating the same environment again doesn't add its paths twice.
+        let path = vec![env.path().join("bin"), PathBuf::from("/usr/bin")];
+        assert_eq!(
+            activate(path, true),
+            [env.path().join("bin"), PathBuf::from("/usr/bin")]
+        );
+    }
+
     #[test]
     #[cfg(unix)]
     fn test_activation_script_xonsh() {
@@ -1089,6 +1762,40 @@ mod tests {
         test_run_activation(crate::shell::Xonsh.into(), false);
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn test_run_activation_cached() {
+        let environment_dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let env = environment_dir.path();
+
+        let conda_meta = env.join("conda-meta");
+        fs::create_dir_all(&conda_meta).unwrap();
+        fs::write(conda_meta.join("foo-1.0-0.json"), "{}").unwrap();
+
+        let activation_script_dir = env.join("etc/conda/activate.d");
+        fs::create_dir_all(&activation_script_dir).unwrap();
+        let script_path = activation_script_dir.join("pkg1.sh");
+        fs::write(&script_path, "export SCRIPT_ENV=first\n").unwrap();
+
+        let activator = Activator::from_path(env, shell::Bash, Platform::current()).unwrap();
+        let run = || {
+            activator
+                .run_activation_cached(ActivationVariables::default(), None, cache_dir.path())
+                .unwrap()
+        };
+
+        assert_eq!(run()["SCRIPT_ENV"], "first");
+
+        // The cached environment is replayed without running the script.
+        fs::write(&script_path, "export SCRIPT_ENV=second\n").unwrap();
+        assert_eq!(run()["SCRIPT_ENV"], "first");
+
+        // Changing conda-meta invalidates the cache.
+        fs::write(conda_meta.join("bar-1.0-0.json"), "{}").unwrap();
+        assert_eq!(run()["SCRIPT_ENV"], "second");
+    }
+
     #[test]
     fn test_deactivation() {
         let tmp_dir = TempDir::new("test_deactivation").unwrap();
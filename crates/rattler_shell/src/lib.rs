@@ -4,4 +4,7 @@
 pub mod activation;
 pub mod run;
 pub mod shell;
-pub use run::run_in_environment;
+pub use run::{
+    command_in_environment, prepare_in_environment, run_in_environment, run_script_in_environment,
+    RunOptions,
+};
@@ -1,7 +1,9 @@
 //! Helpers to run commands in an activated environment.
 
 use rattler_conda_types::Platform;
-use std::process::{Command, Output};
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Output};
 use std::{collections::HashMap, path::Path};
 
 use crate::activation::{ActivationError, PathModificationBehavior};
@@ -24,13 +26,35 @@ pub enum RunError {
     IoError(#[from] std::io::Error),
 }
 
-/// Execute a script in an activated environment.
-pub fn run_in_environment(
+/// A script that has been written to a temporary file together with the
+/// activation of an environment. The temporary file is removed when this
+/// instance is dropped.
+pub struct ActivatedScript {
+    file: tempfile::NamedTempFile,
+    shell: ShellEnum,
+}
+
+impl ActivatedScript {
+    /// Returns the path of the generated script.
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Constructs a [`Command`] that executes the generated script. The script
+    /// must outlive the process that is spawned from the command.
+    pub fn command(&self) -> Command {
+        self.shell.create_run_script_command(self.file.path())
+    }
+}
+
+/// Writes a script that activates the environment at `prefix` and then runs
+/// `script`. Use [`ActivatedScript::command`] to execute it.
+pub fn prepare_in_environment(
     prefix: &Path,
     script: &Path,
     shell: ShellEnum,
     env_vars: &HashMap<String, String>,
-) -> Result<Output, RunError> {
+) -> Result<ActivatedScript, RunError> {
     let mut shell_script = shell::ShellScript::new(shell.clone(), Platform::current());
 
     for (k, v) in env_vars.iter() {
@@ -63,17 +87,232 @@ pub fn run_in_environment(
     let file = tempfile::Builder::new()
         .suffix(&format!(".{}", shell.extension()))
         .tempfile()?;
-    fs_err::write(
-        file.path(),
-        shell_script.contents().map_err(ActivationError::from)?,
+    shell.write_script(
+        &mut fs_err::File::create(file.path())?,
+        &shell_script.contents().map_err(ActivationError::from)?,
     )?;
 
-    match shell {
-        ShellEnum::Bash(_) => Ok(Command::new(shell.executable()).arg(file.path()).output()?),
-        ShellEnum::CmdExe(_) => Ok(Command::new(shell.executable())
-            .arg("/c")
-            .arg(file.path())
-            .output()?),
-        _ => unimplemented!("Unsupported shell: {:?}", shell),
+    Ok(ActivatedScript { file, shell })
+}
+
+/// Execute a script in an activated environment.
+pub fn run_script_in_environment(
+    prefix: &Path,
+    script: &Path,
+    shell: ShellEnum,
+    env_vars: &HashMap<String, String>,
+) -> Result<Output, RunError> {
+    let script = prepare_in_environment(prefix, script, shell, env_vars)?;
+    Ok(script.command().output()?)
+}
+
+/// Options for [`run_in_environment`].
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// The shell that is used to run the activation scripts of the
+    /// environment. Defaults to the default shell of the current platform.
+    pub shell: Option<ShellEnum>,
+
+    /// Additional environment variables that are set before the environment
+    /// is activated.
+    pub env_vars: HashMap<String, String>,
+
+    /// The working directory of the command. Defaults to the current
+    /// directory.
+    pub cwd: Option<PathBuf>,
+
+    /// If set, the activated environment is cached in this directory, see
+    /// [`Activator::run_activation_cached`].
+    pub activation_cache_dir: Option<PathBuf>,
+}
+
+impl RunOptions {
+    /// Sets the shell that is used to run the activation scripts.
+    pub fn with_shell(self, shell: impl Into<ShellEnum>) -> Self {
+        Self {
+            shell: Some(shell.into()),
+            ..self
+        }
+    }
+
+    /// Adds an environment variable that is set before the environment is
+    /// activated.
+    pub fn with_env_var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env_vars.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the working directory of the command.
+    pub fn with_cwd(self, cwd: impl Into<PathBuf>) -> Self {
+        Self {
+            cwd: Some(cwd.into()),
+            ..self
+        }
+    }
+
+    /// Caches the activated environment in the given directory.
+    pub fn with_activation_cache_dir(self, cache_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            activation_cache_dir: Some(cache_dir.into()),
+            ..self
+        }
+    }
+}
+
+/// Constructs a [`Command`] that runs `cmd` with `args` in the activated
+/// environment at `prefix`.
+///
+/// The activation scripts of the environment are executed once with the
+/// shell from the options to capture the activated environment variables
+/// (including `PATH`). The command itself is spawned directly, without a
+/// shell, so the arguments don't have to be quoted for any shell. The
+/// executable is looked up in the activated `PATH`.
+pub fn command_in_environment(
+    prefix: &Path,
+    cmd: impl AsRef<OsStr>,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    options: &RunOptions,
+) -> Result<Command, RunError> {
+    let shell = options.shell.clone().unwrap_or_default();
+    let activator = Activator::from_path(prefix, shell, Platform::current())?;
+
+    let mut current_env: HashMap<String, String> = std::env::vars().collect();
+    current_env.extend(options.env_vars.clone());
+    let variables = ActivationVariables {
+        conda_prefix: current_env.get("CONDA_PREFIX").map(PathBuf::from),
+        path: None,
+        path_modification_behavior: PathModificationBehavior::Prepend,
+        current_env,
+    };
+
+    // Run the activation with the additional environment variables applied so
+    // that activation scripts can observe them.
+    let environment = std::env::vars_os()
+        .chain(
+            options
+                .env_vars
+                .iter()
+                .map(|(key, value)| (OsString::from(key), OsString::from(value))),
+        )
+        .collect::<HashMap<_, _>>();
+    let environment = environment
+        .iter()
+        .map(|(key, value)| (key.as_os_str(), value.as_os_str()))
+        .collect();
+    let activated_env = match &options.activation_cache_dir {
+        Some(cache_dir) => {
+            activator.run_activation_cached(variables, Some(environment), cache_dir)?
+        }
+        None => activator.run_activation(variables, Some(environment))?,
+    };
+
+    let path_var = activator.shell_type.path_var(&activator.platform);
+    let mut command = Command::new(resolve_executable(
+        cmd.as_ref(),
+        activated_env
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(path_var))
+            .map(|(_, value)| value.as_str()),
+    ));
+    command
+        .args(args)
+        .envs(&options.env_vars)
+        .envs(&activated_env);
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+
+    Ok(command)
+}
+
+/// Runs `cmd` with `args` in the activated environment at `prefix` and waits
+/// for it to finish.
+///
+/// The standard input, output and error streams are inherited from the
+/// current process, so the output of the command is streamed. The exit status
+/// of the command is returned, a tool can propagate it with e.g.
+/// `std::process::exit(status.code().unwrap_or(1))`.
+pub fn run_in_environment(
+    prefix: &Path,
+    cmd: impl AsRef<OsStr>,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    options: &RunOptions,
+) -> Result<ExitStatus, RunError> {
+    Ok(command_in_environment(prefix, cmd, args, options)?.status()?)
+}
+
+/// Looks up `cmd` in the given `PATH`. The lookup of [`Command`] uses the
+/// `PATH` of the current process on some platforms, so the executable is
+/// resolved explicitly. If it can't be found `cmd` is returned unchanged.
+fn resolve_executable(cmd: &OsStr, path: Option<&str>) -> PathBuf {
+    let cmd_path = Path::new(cmd);
+    let Some(path) = path else {
+        return cmd_path.to_path_buf();
+    };
+    if cmd_path.components().count() != 1 {
+        return cmd_path.to_path_buf();
+    }
+
+    let extensions = if cfg!(windows) && cmd_path.extension().is_none() {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| String::from(".COM;.EXE;.BAT;.CMD"))
+            .split(';')
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+    } else {
+        vec![String::new()]
+    };
+
+    std::env::split_paths(path)
+        .flat_map(|dir| {
+            extensions.iter().map(move |ext| {
+                let mut file_name = cmd.to_os_string();
+                file_name.push(ext);
+                dir.join(file_name)
+            })
+        })
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| cmd_path.to_path_buf())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run_in_environment, RunOptions};
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_in_environment() {
+        let prefix = tempfile::TempDir::new().unwrap();
+        let activate_dir = prefix.path().join("etc/conda/activate.d");
+        fs_err::create_dir_all(&activate_dir).unwrap();
+        fs_err::write(
+            activate_dir.join("pkg.sh"),
+            "export FROM_SCRIPT=activated\n",
+        )
+        .unwrap();
+
+        let bin = prefix.path().join("bin");
+        fs_err::create_dir_all(&bin).unwrap();
+        let tool = bin.join("tool");
+        fs_err::write(
+            &tool,
+            "#!/bin/sh\n[ \"$FROM_SCRIPT-$FROM_OPTIONS\" = activated-set ] || exit 1\nexit 3\n",
+        )
+        .unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs_err::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let status = run_in_environment(
+            prefix.path(),
+            "tool",
+            Vec::<&str>::new(),
+            &RunOptions::default()
+                .with_shell(crate::shell::Bash)
+                .with_env_var("FROM_OPTIONS", "set"),
+        )
+        .unwrap();
+        assert_eq!(status.code(), Some(3));
     }
 }
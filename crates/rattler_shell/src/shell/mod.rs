@@ -43,6 +43,12 @@ pub trait Shell {
         Ok(())
     }
 
+    /// Write a command to the end of the script that restores the encoding
+    /// that was active before [`Self::force_utf8`].
+    fn reset_encoding(&self, _f: &mut impl Write) -> ShellResult {
+        Ok(())
+    }
+
     /// Set an env var by `export`-ing it.
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> ShellResult;
 
@@ -185,6 +191,15 @@ pub trait Shell {
         None
     }
 
+    /// Prefixes the prompt of the shell with the given text, e.g. the name of
+    /// the activated environment.
+    ///
+    /// Shells for which the prompt cannot be modified from a script ignore
+    /// this.
+    fn set_prompt_prefix(&self, _f: &mut impl Write, _prefix: &str) -> ShellResult {
+        Ok(())
+    }
+
     /// Restores an environment variable from its backup if it exists, otherwise
     /// unsets it.
     ///
@@ -485,7 +500,7 @@ pub struct Xonsh;
 impl Shell for Xonsh {
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> ShellResult {
         validate_env_var_name(env_var)?;
-        Ok(writeln!(f, "${env_var} = \"{value}\"")?)
+        Ok(writeln!(f, "${env_var} = {}", python_string(value))?)
     }
 
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> ShellResult {
@@ -510,6 +525,41 @@ impl Shell for Xonsh {
                 .is_some_and(|ext| ext == "xsh" || ext == "sh")
     }
 
+    fn set_path(
+        &self,
+        f: &mut impl Write,
+        paths: &[PathBuf],
+        modification_behavior: PathModificationBehavior,
+        platform: &Platform,
+    ) -> ShellResult {
+        // Xonsh stores the path as a list and does not expand variables in strings.
+        let path = paths
+            .iter()
+            .map(|path| python_string(&path.to_string_lossy()))
+            .join(", ");
+
+        let path_var = self.path_var(platform);
+        match modification_behavior {
+            PathModificationBehavior::Replace => Ok(writeln!(f, "${path_var} = [{path}]")?),
+            PathModificationBehavior::Prepend => {
+                Ok(writeln!(f, "${path_var} = [{path}] + list(${path_var})")?)
+            }
+            PathModificationBehavior::Append => {
+                Ok(writeln!(f, "${path_var} = list(${path_var}) + [{path}]")?)
+            }
+        }
+    }
+
+    fn set_prompt_prefix(&self, f: &mut impl Write, prefix: &str) -> ShellResult {
+        // A prompt function cannot be prefixed without evaluating it.
+        writeln!(f, "if not callable($PROMPT):")?;
+        Ok(writeln!(
+            f,
+            "    $PROMPT = {} + $PROMPT",
+            python_string(prefix)
+        )?)
+    }
+
     fn extension(&self) -> &str {
         "xsh"
     }
@@ -531,26 +581,42 @@ impl Shell for Xonsh {
     fn restore_env_var(&self, f: &mut impl Write, key: &str, backup_key: &str) -> ShellResult {
         validate_env_var_name(key)?;
         validate_env_var_name(backup_key)?;
-        Ok(writeln!(
-            f,
-            r#"if {backup_key} in $env:
-                $env[{key}] = $env[{backup_key}]
-                del $env[{backup_key}]
-            else:
-                del $env[{key}]"#
-        )?)
+        writeln!(f, "if \"{backup_key}\" in ${{...}}:")?;
+        writeln!(f, "    ${key} = ${backup_key}")?;
+        writeln!(f, "    del ${backup_key}")?;
+        writeln!(f, "elif \"{key}\" in ${{...}}:")?;
+        Ok(writeln!(f, "    del ${key}")?)
     }
 }
 
+/// Quotes a string as a Python string literal.
+fn python_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 /// A [`Shell`] implementation for the cmd.exe shell.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct CmdExe;
 
 impl Shell for CmdExe {
     fn force_utf8(&self, f: &mut impl Write) -> ShellResult {
+        // Remember the active code page so it can be restored at the end of the
+        // script. The output of `chcp` is localized, e.g. "Aktive Codepage: 850."
+        writeln!(
+            f,
+            "@FOR /F \"tokens=2 delims=:.\" %%A IN ('chcp') DO @SET \"_RATTLER_OLD_CODEPAGE=%%A\""
+        )?;
         Ok(writeln!(f, "@chcp 65001 > nul")?)
     }
 
+    fn reset_encoding(&self, f: &mut impl Write) -> ShellResult {
+        writeln!(
+            f,
+            "@IF DEFINED _RATTLER_OLD_CODEPAGE @chcp %_RATTLER_OLD_CODEPAGE% > nul"
+        )?;
+        Ok(writeln!(f, "@SET _RATTLER_OLD_CODEPAGE=")?)
+    }
+
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> ShellResult {
         validate_env_var_name(env_var)?;
         Ok(writeln!(f, "@SET \"{env_var}={value}\"")?)
@@ -604,7 +670,7 @@ impl Shell for CmdExe {
     }
 
     fn write_script(&self, f: &mut impl std::io::Write, script: &str) -> std::io::Result<()> {
-        let script = script.replace('\n', "\r\n");
+        let script = script.replace("\r\n", "\n").replace('\n', "\r\n");
         f.write_all(script.as_bytes())
     }
 
@@ -616,12 +682,35 @@ impl Shell for CmdExe {
         "\r\n"
     }
 
+    fn can_run_script(&self, path: &Path) -> bool {
+        path.is_file()
+            && path
+                .extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(self.extension()))
+    }
+
+    fn set_prompt_prefix(&self, f: &mut impl Write, prefix: &str) -> ShellResult {
+        // `$` starts a special code in the prompt and `%` would be expanded.
+        let prefix = prefix.replace('$', "$$").replace('%', "%%");
+        writeln!(f, "@IF NOT DEFINED PROMPT @SET \"PROMPT=$P$G\"")?;
+        // Keep the original prompt so activating again doesn't stack prefixes.
+        writeln!(
+            f,
+            "@IF NOT DEFINED _RATTLER_OLD_PROMPT @SET \"_RATTLER_OLD_PROMPT=%PROMPT%\""
+        )?;
+        Ok(writeln!(
+            f,
+            "@SET \"PROMPT={prefix}%_RATTLER_OLD_PROMPT%\""
+        )?)
+    }
+
     fn restore_env_var(&self, f: &mut impl Write, key: &str, backup_key: &str) -> ShellResult {
         validate_env_var_name(key)?;
         validate_env_var_name(backup_key)?;
         Ok(writeln!(
             f,
-            r#"if defined {backup_key} (
+            r#"@if defined {backup_key} (
                 set "{key}=%{backup_key}%"
                 set "{backup_key}="
             ) else (
@@ -656,10 +745,16 @@ impl Default for PowerShell {
 
 impl Shell for PowerShell {
     fn force_utf8(&self, f: &mut impl Write) -> ShellResult {
-        // Taken from https://stackoverflow.com/a/49481797
+        // Taken from https://stackoverflow.com/a/49481797. Changing the console
+        // encoding fails if the process has no console attached, which is fine.
+        writeln!(f, "$OutputEncoding = [System.Text.Encoding]::UTF8")?;
+        writeln!(
+            f,
+            "try {{ [System.Console]::OutputEncoding = [System.Text.Encoding]::UTF8 }} catch {{ }}"
+        )?;
         Ok(writeln!(
             f,
-            "$OutputEncoding = [System.Console]::OutputEncoding = [System.Console]::InputEncoding = [System.Text.Encoding]::UTF8"
+            "try {{ [System.Console]::InputEncoding = [System.Text.Encoding]::UTF8 }} catch {{ }}"
         )?)
     }
 
@@ -687,7 +782,13 @@ impl Shell for PowerShell {
 
     fn create_run_script_command(&self, path: &Path) -> Command {
         let mut cmd = Command::new(self.executable());
-        cmd.arg(path);
+        // Windows PowerShell interprets a bare argument as a command, and the
+        // default execution policy does not allow running scripts.
+        cmd.arg("-NoProfile")
+            .arg("-ExecutionPolicy")
+            .arg("Bypass")
+            .arg("-File")
+            .arg(path);
         cmd
     }
 
@@ -700,6 +801,36 @@ impl Shell for PowerShell {
         writeln!(f, r##"dir env: | %{{"{{0}}={{1}}" -f $_.Name,$_.Value}}"##)
     }
 
+    /// Windows `PowerShell` reads scripts without a byte order mark in the
+    /// legacy code page, so the script is written with one.
+    fn write_script(&self, f: &mut impl std::io::Write, script: &str) -> std::io::Result<()> {
+        f.write_all("\u{feff}".as_bytes())?;
+        f.write_all(script.as_bytes())
+    }
+
+    fn can_run_script(&self, path: &Path) -> bool {
+        path.is_file()
+            && path
+                .extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(self.extension()))
+    }
+
+    fn set_prompt_prefix(&self, f: &mut impl Write, prefix: &str) -> ShellResult {
+        // Keep the original prompt so activating again doesn't stack prefixes.
+        writeln!(f, "if (-not (Test-Path function:__rattler_old_prompt)) {{")?;
+        writeln!(
+            f,
+            "    Set-Item -Path function:global:__rattler_old_prompt -Value $function:prompt"
+        )?;
+        writeln!(f, "}}")?;
+        Ok(writeln!(
+            f,
+            "function global:prompt {{ '{}' + (__rattler_old_prompt) }}",
+            prefix.replace('\'', "''")
+        )?)
+    }
+
     fn restore_env_var(&self, f: &mut impl Write, key: &str, backup_key: &str) -> ShellResult {
         validate_env_var_name(key)?;
         validate_env_var_name(backup_key)?;
@@ -719,10 +850,85 @@ impl Shell for PowerShell {
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Fish;
 
+impl Fish {
+    /// Writes a fish function called `name` that runs `body` when it is
+    /// invoked.
+    ///
+    /// Activation and deactivation scripts only use global (`-g`) variables,
+    /// so their effects outlive the function call. This makes it possible to
+    /// install them as functions (e.g. in `conf.d`) that are called on demand
+    /// instead of being sourced. Note that scripts sourced from within the
+    /// function that set variables without an explicit scope will set them
+    /// local to the function.
+    pub fn write_function(
+        &self,
+        f: &mut impl Write,
+        name: &str,
+        description: &str,
+        body: &str,
+    ) -> ShellResult {
+        if name.is_empty()
+            || name.starts_with('-')
+            || name
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || c == '/' || c == '"')
+        {
+            return Err(ShellError::InvalidName(
+                name.to_string(),
+                "not a valid fish function name",
+            ));
+        }
+
+        writeln!(
+            f,
+            "function {name} --description \"{}\"",
+            escape_fish_string(description)
+        )?;
+        for line in body.lines() {
+            if line.is_empty() {
+                writeln!(f)?;
+            } else {
+                writeln!(f, "    {line}")?;
+            }
+        }
+        Ok(writeln!(f, "end")?)
+    }
+}
+
 impl Shell for Fish {
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> ShellResult {
         validate_env_var_name(env_var)?;
-        Ok(writeln!(f, "set -gx {env_var} \"{value}\"")?)
+        Ok(writeln!(
+            f,
+            "set -gx {env_var} \"{}\"",
+            escape_fish_string(value)
+        )?)
+    }
+
+    fn set_path(
+        &self,
+        f: &mut impl Write,
+        paths: &[PathBuf],
+        modification_behavior: PathModificationBehavior,
+        platform: &Platform,
+    ) -> ShellResult {
+        // Fish stores the path as a list, so every entry is a separate quoted
+        // element instead of a single joined string.
+        let paths = paths
+            .iter()
+            .map(|path| format!("\"{}\"", escape_fish_string(&path.to_string_lossy())))
+            .join(" ");
+
+        let path_var = self.path_var(platform);
+        match modification_behavior {
+            PathModificationBehavior::Replace => Ok(writeln!(f, "set -gx {path_var} {paths}")?),
+            PathModificationBehavior::Prepend => {
+                Ok(writeln!(f, "set -gx {path_var} {paths} ${path_var}")?)
+            }
+            PathModificationBehavior::Append => {
+                Ok(writeln!(f, "set -gx {path_var} ${path_var} {paths}")?)
+            }
+        }
     }
 
     fn format_env_var(&self, var_name: &str) -> String {
@@ -759,10 +965,15 @@ impl Shell for Fish {
 
     fn source_completions(&self, f: &mut impl Write, completions_dir: &Path) -> ShellResult {
         if completions_dir.exists() {
-            // glob all files in the completions directory using fish
-            let completions_glob = completions_dir.join("*");
-            writeln!(f, "for file in {}", completions_glob.to_string_lossy())?;
-            writeln!(f, "    source $file")?;
+            // Register the directory with fish's completion loader instead of
+            // sourcing every file, so completions are loaded lazily and
+            // non-completion code in the directory is never executed.
+            let dir = escape_fish_string(&completions_dir.to_string_lossy());
+            writeln!(f, "if not contains -- \"{dir}\" $fish_complete_path")?;
+            writeln!(
+                f,
+                "    set -g fish_complete_path \"{dir}\" $fish_complete_path"
+            )?;
             writeln!(f, "end")?;
         }
         Ok(())
@@ -786,6 +997,14 @@ impl Shell for Fish {
 fn escape_backslashes(s: &str) -> String {
     s.replace('\\', "\\\\")
 }
+fn escape_fish_string(s: &str) -> String {
+    escape_backslashes(s)
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+}
+fn escape_nu_string(s: &str) -> String {
+    escape_backslashes(s).replace('"', "\\\"")
+}
 fn quote_if_required(s: &str) -> Cow<'_, str> {
     if s.contains(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-') {
         Cow::Owned(format!("\"{s}\""))
@@ -794,7 +1013,7 @@ fn quote_if_required(s: &str) -> Cow<'_, str> {
     }
 }
 
-/// A [`Shell`] implementation for the Bash shell.
+/// A [`Shell`] implementation for the Nushell shell.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct NuShell;
 
@@ -806,7 +1025,7 @@ impl Shell for NuShell {
             f,
             "$env.{} = \"{}\"",
             quote_if_required(env_var),
-            escape_backslashes(value)
+            escape_nu_string(value)
         )?)
     }
 
@@ -846,6 +1065,19 @@ impl Shell for NuShell {
         }
     }
 
+    fn set_prompt_prefix(&self, f: &mut impl Write, prefix: &str) -> ShellResult {
+        // The prompt command is either a closure or a plain string.
+        writeln!(
+            f,
+            "let __rattler_prompt = ($env.PROMPT_COMMAND? | default \"\")"
+        )?;
+        Ok(writeln!(
+            f,
+            "$env.PROMPT_COMMAND = {{|| \"{}\" + (if ($__rattler_prompt | describe) =~ \"closure\" {{ do $__rattler_prompt }} else {{ $__rattler_prompt }}) }}",
+            escape_nu_string(prefix)
+        )?)
+    }
+
     fn extension(&self) -> &str {
         "nu"
     }
@@ -867,15 +1099,12 @@ impl Shell for NuShell {
     fn restore_env_var(&self, f: &mut impl Write, key: &str, backup_key: &str) -> ShellResult {
         validate_env_var_name(key)?;
         validate_env_var_name(backup_key)?;
-        Ok(writeln!(
-            f,
-            r#"if ($env | get {backup_key}?) {{
-                $env.{key} = $env.{backup_key}
-                $env = $env | reject {backup_key}
-            }} else {{
-                $env = $env | reject {key}
-            }}"#
-        )?)
+        writeln!(f, "if ($env.{backup_key}? != null) {{")?;
+        writeln!(f, "    $env.{key} = $env.{backup_key}")?;
+        writeln!(f, "    hide-env {backup_key}")?;
+        writeln!(f, "}} else {{")?;
+        writeln!(f, "    hide-env --ignore-errors {key}")?;
+        Ok(writeln!(f, "}}")?)
     }
 }
 
@@ -927,7 +1156,13 @@ impl ShellEnum {
         }
     }
 
-    /// Guesses the current shell by checking the name of the parent process.
+    /// Guesses the current shell by checking the names of the parent processes.
+    ///
+    /// The parent processes are walked up until a process is found that is a
+    /// known shell. This skips intermediate processes like `sudo` or the
+    /// launcher of a package manager. On Windows process ids are reused, a
+    /// parent that started after its child is therefore not the actual parent
+    /// and stops the search.
     #[cfg(feature = "sysinfo")]
     pub fn from_parent_process() -> Option<Self> {
         use sysinfo::get_current_pid;
@@ -938,54 +1173,32 @@ impl ShellEnum {
         let mut current_pid = get_current_pid().ok()?;
         system_info.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[current_pid]), true);
 
-        while let Some(parent_process_id) = system_info
-            .process(current_pid)
-            .and_then(sysinfo::Process::parent)
-        {
+        while let Some(current_process) = system_info.process(current_pid) {
+            let Some(parent_process_id) = current_process.parent() else {
+                break;
+            };
+            let start_time = current_process.start_time();
+
             // Get the name of the parent process
             system_info
                 .refresh_processes(sysinfo::ProcessesToUpdate::Some(&[parent_process_id]), true);
             let parent_process = system_info.process(parent_process_id)?;
-            let parent_process_name = parent_process.name().to_string_lossy().to_lowercase();
-
-            let shell: Option<ShellEnum> = if parent_process_name.contains("bash") {
-                Some(Bash.into())
-            } else if parent_process_name.contains("zsh") {
-                Some(Zsh.into())
-            } else if parent_process_name.contains("xonsh")
-                // xonsh is a python shell, so we need to check if the parent process is python and if it
-                // contains xonsh in the arguments.
-                || (parent_process_name.contains("python")
-                && parent_process
-                .cmd().iter()
-                .any(|arg| arg.to_string_lossy().contains("xonsh")))
-            {
-                Some(Xonsh.into())
-            } else if parent_process_name.contains("fish") {
-                Some(Fish.into())
-            } else if parent_process_name.contains("nu") {
-                Some(NuShell.into())
-            } else if parent_process_name.contains("powershell")
-                || parent_process_name.contains("pwsh")
-            {
-                Some(
-                    PowerShell {
-                        executable_path: parent_process_name.clone(),
-                    }
-                    .into(),
-                )
-            } else if parent_process_name.contains("cmd.exe") {
-                Some(CmdExe.into())
-            } else {
-                None
-            };
+            if parent_process.start_time() > start_time {
+                break;
+            }
 
-            if let Some(shell) = shell {
+            let parent_process_name = parent_process.name().to_string_lossy();
+            let args = parent_process
+                .cmd()
+                .iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>();
+            if let Some(shell) = shell_from_process(&parent_process_name, &args) {
                 tracing::debug!(
                     "Guessing the current shell is {}. Parent process name: {} and args: {:?}",
                     &shell.executable(),
                     &parent_process_name,
-                    &parent_process.cmd()
+                    &args
                 );
                 return Some(shell);
             }
@@ -995,6 +1208,20 @@ impl ShellEnum {
 
         None
     }
+
+    /// Determines the shell that should be used by default, e.g. as the
+    /// default of a `--shell` argument of a CLI.
+    ///
+    /// This first checks the parent processes for a known shell, see
+    /// [`Self::from_parent_process`], then falls back to the `SHELL`
+    /// environment variable, see [`Self::from_env`], and finally to the
+    /// default shell of the platform.
+    #[cfg(feature = "sysinfo")]
+    pub fn detect() -> Self {
+        Self::from_parent_process()
+            .or_else(Self::from_env)
+            .unwrap_or_default()
+    }
 }
 
 /// Parsing of a shell was not possible. The shell mostlikely is not supported.
@@ -1013,6 +1240,10 @@ impl FromStr for ShellEnum {
             "fish" => Ok(Fish.into()),
             "cmd" => Ok(CmdExe.into()),
             "nu" | "nushell" => Ok(NuShell.into()),
+            "pwsh" => Ok(PowerShell {
+                executable_path: String::from("pwsh"),
+            }
+            .into()),
             "powershell" | "powershell_ise" => Ok(PowerShell::default().into()),
             _ => Err(ParseShellEnumError(format!(
                 "'{s}' is an unknown shell variant"
@@ -1021,6 +1252,44 @@ impl FromStr for ShellEnum {
     }
 }
 
+/// Determines the shell from the name and the arguments of a process. The
+/// name must match a known shell exactly (ignoring case and the `.exe`
+/// extension), so that e.g. `runner` is not mistaken for `nu`.
+#[cfg_attr(not(feature = "sysinfo"), allow(dead_code))]
+fn shell_from_process(name: &str, args: &[impl AsRef<str>]) -> Option<ShellEnum> {
+    let name = name.to_lowercase();
+    // Login shells are started with a leading dash, e.g. `-bash`.
+    let name = name.trim_start_matches('-');
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+
+    match name {
+        "bash" => Some(Bash.into()),
+        "zsh" => Some(Zsh.into()),
+        "fish" => Some(Fish.into()),
+        "nu" | "nushell" => Some(NuShell.into()),
+        "xonsh" => Some(Xonsh.into()),
+        "cmd" => Some(CmdExe.into()),
+        "pwsh" | "powershell" | "powershell_ise" => Some(
+            PowerShell {
+                executable_path: name.to_string(),
+            }
+            .into(),
+        ),
+        // xonsh is a python shell, so we need to check if the process is python and if
+        // it runs xonsh.
+        name if name.starts_with("python")
+            && args.iter().any(|arg| {
+                Path::new(arg.as_ref())
+                    .file_stem()
+                    .is_some_and(|stem| stem == "xonsh")
+            }) =>
+        {
+            Some(Xonsh.into())
+        }
+        _ => None,
+    }
+}
+
 /// Determine the shell from a path to a shell.
 fn parse_shell_from_path(path: &Path) -> Option<ShellEnum> {
     let name = path.file_stem()?.to_str()?;
@@ -1121,6 +1390,7 @@ impl<T: Shell + 'static> ShellScript<T> {
         let mut final_contents = String::new();
         self.shell.force_utf8(&mut final_contents)?;
         final_contents.push_str(&self.contents);
+        self.shell.reset_encoding(&mut final_contents)?;
 
         if self.shell.line_ending() == "\n" {
             Ok(final_contents)
@@ -1152,6 +1422,13 @@ impl<T: Shell + 'static> ShellScript<T> {
             .restore_env_var(&mut self.contents, key, backup_key)?;
         Ok(self)
     }
+
+    /// Prefixes the prompt of the shell with the given text. See
+    /// [`Shell::set_prompt_prefix`].
+    pub fn set_prompt_prefix(&mut self, prefix: &str) -> Result<&mut Self, ShellError> {
+        self.shell.set_prompt_prefix(&mut self.contents, prefix)?;
+        Ok(self)
+    }
 }
 
 #[cfg(test)]
@@ -1206,6 +1483,79 @@ mod tests {
         insta::assert_snapshot!(script.contents);
     }
 
+    #[test]
+    fn test_fish_path_and_escaping() {
+        let mut script = ShellScript::new(Fish, Platform::Linux64);
+
+        let paths = vec![PathBuf::from("bar"), PathBuf::from("a/b")];
+
+        script
+            .set_env_var("FOO", "a \"b\" $c \\d")
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Append)
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Prepend)
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Replace)
+            .unwrap();
+
+        insta::assert_snapshot!(script.contents);
+    }
+
+    // Requires `fish` to be installed, CI runs it in a dedicated step.
+    #[test]
+    #[cfg(unix)]
+    #[ignore]
+    fn test_fish_function_activation() {
+        let completions = tempfile::TempDir::new().unwrap();
+
+        let mut body = ShellScript::new(Fish, Platform::current());
+        body.set_env_var("RATTLER_FISH", "a \"b\" $c \\d")
+            .unwrap()
+            .set_path(
+                &[PathBuf::from("/rattler test/bin")],
+                PathModificationBehavior::Prepend,
+            )
+            .unwrap()
+            .source_completions(completions.path())
+            .unwrap();
+
+        let mut script = ShellScript::new(Fish, Platform::current());
+        Fish.write_function(
+            &mut script.contents,
+            "__rattler_activate",
+            "Activate the \"test\" environment",
+            &body.contents,
+        )
+        .unwrap();
+        writeln!(script.contents, "__rattler_activate").unwrap();
+        writeln!(script.contents, "echo $RATTLER_FISH").unwrap();
+        writeln!(script.contents, "echo $PATH[1]").unwrap();
+        writeln!(script.contents, "echo $fish_complete_path[1]").unwrap();
+        writeln!(script.contents, "env | grep '^RATTLER_FISH='").unwrap();
+
+        let output = execute_script(&script);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "a \"b\" $c \\d",
+                "/rattler test/bin",
+                completions.path().to_str().unwrap(),
+                "RATTLER_FISH=a \"b\" $c \\d",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fish_function_invalid_name() {
+        let mut contents = String::new();
+        assert!(Fish
+            .write_function(&mut contents, "-bad name", "", "")
+            .is_err());
+        assert!(contents.is_empty());
+    }
+
     #[test]
     fn test_xonsh_bash() {
         let mut script = ShellScript::new(Xonsh, Platform::Linux64);
@@ -1231,6 +1581,139 @@ mod tests {
         insta::assert_snapshot!(script.contents);
     }
 
+    #[test]
+    fn test_xonsh_path_and_prompt() {
+        let mut script = ShellScript::new(Xonsh, Platform::Linux64);
+        let paths = vec![PathBuf::from("bar"), PathBuf::from("a\\b")];
+        script
+            .set_env_var("FOO", "a \"quoted\" \\value")
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Append)
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Prepend)
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Replace)
+            .unwrap()
+            .set_prompt_prefix("(env) ")
+            .unwrap()
+            .restore_env_var("FOO", "CONDA_ENV_SHLVL_1_FOO")
+            .unwrap();
+
+        insta::assert_snapshot!(script.contents);
+    }
+
+    #[test]
+    fn test_nushell_path_and_prompt() {
+        let mut script = ShellScript::new(NuShell, Platform::Linux64);
+        let paths = vec![PathBuf::from("bar"), PathBuf::from("a\\b")];
+        script
+            .set_env_var("FOO", "a \"quoted\" \\value")
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Append)
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Prepend)
+            .unwrap()
+            .set_path(&paths, PathModificationBehavior::Replace)
+            .unwrap()
+            .set_prompt_prefix("(env) ")
+            .unwrap()
+            .restore_env_var("FOO", "CONDA_ENV_SHLVL_1_FOO")
+            .unwrap();
+
+        insta::assert_snapshot!(script.contents);
+    }
+
+    /// Writes the script to a temporary file and executes it with the shell.
+    fn execute_script<T: Shell + Clone + 'static>(script: &ShellScript<T>) -> String {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir
+            .path()
+            .join(format!("script.{}", script.shell.extension()));
+        script
+            .shell
+            .write_script(
+                &mut fs_err::File::create(&path).unwrap(),
+                &script.contents().unwrap(),
+            )
+            .unwrap();
+        let output = script
+            .shell
+            .create_run_script_command(&path)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_cmd_prompt_and_encoding() {
+        let mut script = ShellScript::new(CmdExe, Platform::current());
+        script
+            .set_env_var("UNICODE", "🦀")
+            .unwrap()
+            .set_prompt_prefix("(env) ")
+            .unwrap()
+            .set_prompt_prefix("(env) ")
+            .unwrap()
+            .print_env()
+            .unwrap();
+
+        let stdout = execute_script(&script);
+        let env = CmdExe.parse_env(&stdout);
+        let prompt = std::env::var("PROMPT").unwrap_or_else(|_| String::from("$P$G"));
+        assert_eq!(env["PROMPT"], format!("(env) {prompt}"));
+        assert_eq!(env["UNICODE"], "🦀");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_powershell_prompt_and_encoding() {
+        let shell = PowerShell::default();
+        let mut script = ShellScript::new(shell.clone(), Platform::current());
+        script
+            .set_env_var("UNICODE", "🦀")
+            .unwrap()
+            .set_prompt_prefix("(env) ")
+            .unwrap()
+            .set_prompt_prefix("(env) ")
+            .unwrap();
+        shell.run_command(&mut script.contents, ["prompt"]).unwrap();
+        shell
+            .run_command(&mut script.contents, ["$Env:UNICODE"])
+            .unwrap();
+
+        let stdout = execute_script(&script);
+        let mut lines = stdout.lines();
+        let prompt = lines.next().unwrap();
+        assert!(prompt.starts_with("(env) ") && !prompt.starts_with("(env) (env) "));
+        assert_eq!(lines.next(), Some("🦀"));
+    }
+
+    #[test]
+    fn test_shell_from_process() {
+        let no_args: &[&str] = &[];
+        let shell = |name: &str, args: &[&str]| {
+            shell_from_process(name, args).map(|shell| shell.executable().to_string())
+        };
+        assert_eq!(shell("bash", no_args).as_deref(), Some("bash"));
+        assert_eq!(shell("-zsh", no_args).as_deref(), Some("zsh"));
+        assert_eq!(shell("PWSH.EXE", no_args).as_deref(), Some("pwsh"));
+        assert_eq!(shell("cmd.exe", no_args).as_deref(), Some("cmd.exe"));
+        assert_eq!(shell("nu", no_args).as_deref(), Some("nu"));
+        assert_eq!(
+            shell("python3.12", &["/usr/bin/python3.12", "/usr/bin/xonsh"]).as_deref(),
+            Some("xonsh")
+        );
+        assert_eq!(shell("python3.12", &["script.py"]), None);
+        assert_eq!(shell("runner", no_args), None);
+        assert_eq!(shell("sudo", no_args), None);
+    }
+
     #[cfg(feature = "sysinfo")]
     #[test]
     fn test_from_parent_process_doesnt_crash() {
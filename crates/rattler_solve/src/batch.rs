@@ -0,0 +1,42 @@
+//! Solving the same environment for multiple platforms at once.
+
+use rattler_conda_types::{Platform, SolverResult};
+
+use crate::{IntoRepoData, SolveError, SolverImpl, SolverTask};
+
+/// Solves a task for each of the given platforms concurrently and returns the
+/// result for every platform, in the same order as the tasks.
+///
+/// Every task is solved on its own thread with a new instance of the solver
+/// `S`. This is useful when generating a lock-file for multiple platforms.
+/// The available packages of a task are usually references to records, so
+/// records that are shared between platforms (e.g. `noarch` packages) only
+/// have to be parsed once and can be passed to every task.
+pub fn solve_for_platforms<'a, S, R, TAvailablePackagesIterator>(
+    tasks: impl IntoIterator<Item = (Platform, SolverTask<TAvailablePackagesIterator>)>,
+) -> Vec<(Platform, Result<SolverResult, SolveError>)>
+where
+    S: SolverImpl + Default,
+    R: IntoRepoData<'a, S::RepoData<'a>>,
+    TAvailablePackagesIterator: IntoIterator<Item = R> + Send,
+{
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = tasks
+            .into_iter()
+            .map(|(platform, task)| {
+                let handle = scope.spawn(move || S::default().solve(task));
+                (platform, handle)
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|(platform, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                (platform, result)
+            })
+            .collect()
+    })
+}
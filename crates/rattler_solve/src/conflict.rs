@@ -0,0 +1,212 @@
+//! A structured representation of the reasons why a solve failed.
+
+use std::fmt::{Display, Formatter};
+
+use rattler_conda_types::{MatchSpec, PackageName, Version};
+
+/// A node in the explanation of why the requested specs could not be solved.
+///
+/// The explanation is a tree that is built from the problem the solver
+/// reported. The children of a node explain their parent, e.g. a
+/// [`ConflictKind::Requirement`] has a child for every candidate that was
+/// considered for it and a [`ConflictKind::Package`] has a child for every
+/// reason why that candidate cannot be installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConflictNode {
+    /// What this part of the conflict is about.
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub kind: ConflictKind,
+
+    /// The nodes that explain this node.
+    pub children: Vec<ConflictNode>,
+}
+
+/// Describes what a [`ConflictNode`] represents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum ConflictKind {
+    /// A spec that is requested, either by the user or by the package of the
+    /// parent node. The children are the candidates that were considered, a
+    /// requirement without children could not be matched by any package.
+    Requirement(MatchSpec),
+
+    /// A candidate package. The children explain why it cannot be installed.
+    Package(ConflictPackage),
+
+    /// The package of the parent node was excluded by the solver, e.g.
+    /// because of strict channel priority.
+    Excluded {
+        /// The reason why the package was excluded.
+        reason: String,
+    },
+
+    /// The package of the parent node cannot be installed because another
+    /// version of it is locked.
+    Locked(ConflictPackage),
+
+    /// The package of the parent node does not satisfy a constraint.
+    Constrained {
+        /// The constraint that is not satisfied.
+        spec: MatchSpec,
+
+        /// The package that introduced the constraint, or `None` if the
+        /// constraint was passed to the solver directly.
+        by: Option<ConflictPackage>,
+    },
+
+    /// The package of the parent node cannot be installed together with
+    /// another version of the same package.
+    ConflictsWith(ConflictPackage),
+}
+
+/// A package that is involved in a conflict.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConflictPackage {
+    /// The name of the package.
+    pub name: PackageName,
+
+    /// The version of the package, if the package has one.
+    pub version: Option<Version>,
+
+    /// The build string of the package, if the package has one.
+    pub build: Option<String>,
+}
+
+// The nodes are only constructed by the resolvo backend.
+#[cfg_attr(not(feature = "resolvo"), allow(dead_code))]
+impl ConflictNode {
+    /// Constructs a node without children.
+    pub(crate) fn new(kind: ConflictKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    /// Adds the given children to this node.
+    pub(crate) fn with_children(mut self, children: impl IntoIterator<Item = Self>) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    /// Returns the spec this node is about, if any.
+    pub fn spec(&self) -> Option<&MatchSpec> {
+        match &self.kind {
+            ConflictKind::Requirement(spec) | ConflictKind::Constrained { spec, .. } => Some(spec),
+            _ => None,
+        }
+    }
+
+    /// Returns the package this node is about, if any.
+    pub fn package(&self) -> Option<&ConflictPackage> {
+        match &self.kind {
+            ConflictKind::Package(package)
+            | ConflictKind::Locked(package)
+            | ConflictKind::ConflictsWith(package) => Some(package),
+            _ => None,
+        }
+    }
+
+    /// Returns an iterator over this node and all its descendants, depth
+    /// first.
+    pub fn iter(&self) -> impl Iterator<Item = &ConflictNode> + '_ {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+}
+
+impl Display for ConflictNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Display for ConflictKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictKind::Requirement(spec) => write!(f, "{spec}"),
+            ConflictKind::Package(package) => write!(f, "{package}"),
+            ConflictKind::Excluded { reason } => write!(f, "excluded because {reason}"),
+            ConflictKind::Locked(package) => write!(f, "conflicts with locked package {package}"),
+            ConflictKind::Constrained { spec, by: Some(by) } => {
+                write!(f, "constrained by {spec} from {by}")
+            }
+            ConflictKind::Constrained { spec, by: None } => write!(f, "constrained by {spec}"),
+            ConflictKind::ConflictsWith(package) => write!(f, "conflicts with {package}"),
+        }
+    }
+}
+
+impl Display for ConflictPackage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name.as_source())?;
+        if let Some(version) = &self.version {
+            write!(f, " {version}")?;
+        }
+        if let Some(build) = self.build.as_deref().filter(|build| !build.is_empty()) {
+            write!(f, " {build}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{MatchSpec, PackageName, ParseStrictness, Version};
+
+    use super::{ConflictKind, ConflictNode, ConflictPackage};
+
+    fn package(name: &str, version: &str, build: &str) -> ConflictPackage {
+        ConflictPackage {
+            name: PackageName::new_unchecked(name),
+            version: Some(Version::from_str(version).unwrap()),
+            build: Some(build.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_conflict_tree() {
+        let spec = MatchSpec::from_str("bors >=2", ParseStrictness::Strict).unwrap();
+        let root = ConflictNode::new(ConflictKind::Requirement(spec.clone())).with_children([
+            ConflictNode::new(ConflictKind::Package(package("bors", "2.0", "bla_1")))
+                .with_children([ConflictNode::new(ConflictKind::Locked(package(
+                    "bors", "1.0", "bla_1",
+                )))]),
+            ConflictNode::new(ConflictKind::Package(package("bors", "2.1", "bla_1"))),
+        ]);
+
+        assert_eq!(root.spec(), Some(&spec));
+        assert_eq!(root.to_string(), "bors >=2");
+        assert_eq!(root.children[0].to_string(), "bors 2.0 bla_1");
+        assert_eq!(
+            root.children[0].children[0].to_string(),
+            "conflicts with locked package bors 1.0 bla_1"
+        );
+        assert_eq!(
+            root.children[0].children[0]
+                .package()
+                .and_then(|p| p.version.as_ref())
+                .map(ToString::to_string)
+                .as_deref(),
+            Some("1.0")
+        );
+        assert_eq!(
+            root.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "bors >=2",
+                "bors 2.0 bla_1",
+                "conflicts with locked package bors 1.0 bla_1",
+                "bors 2.1 bla_1",
+            ]
+        );
+    }
+}
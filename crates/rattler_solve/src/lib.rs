@@ -4,15 +4,31 @@
 
 #![deny(missing_docs)]
 
+#[cfg(not(target_arch = "wasm32"))]
+mod batch;
+mod conflict;
 #[cfg(feature = "libsolv_c")]
 pub mod libsolv_c;
+mod removal;
+mod reporter;
 #[cfg(feature = "resolvo")]
 pub mod resolvo;
 
-use std::fmt;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use batch::solve_for_platforms;
 use chrono::{DateTime, Utc};
+pub use conflict::{ConflictKind, ConflictNode, ConflictPackage};
 use rattler_conda_types::{GenericVirtualPackage, MatchSpec, RepoDataRecord, SolverResult};
+pub use removal::{find_orphans, RemovalResult, RemovalTask};
+pub use reporter::{SolvePhase, SolveReporter};
 
 /// Represents a solver implementation, capable of solving [`SolverTask`]s
 pub trait SolverImpl {
@@ -21,6 +37,10 @@ pub trait SolverImpl {
 
     /// Resolve the dependencies and return the [`RepoDataRecord`]s that should
     /// be present in the environment.
+    ///
+    /// Solving the same task twice results in the same records in the same
+    /// order: the records are sorted by package name and the extras of each
+    /// package are sorted alphabetically.
     fn solve<
         'a,
         R: IntoRepoData<'a, Self::RepoData<'a>>,
@@ -34,7 +54,12 @@ pub trait SolverImpl {
 /// Represents an error when solving the dependencies for a given environment
 #[derive(thiserror::Error, Debug)]
 pub enum SolveError {
-    /// There is no set of dependencies that satisfies the requirements
+    /// There is no set of dependencies that satisfies the requirements. Each
+    /// string is a human-readable explanation as reported by the solver
+    /// backend.
+    ///
+    /// The same explanation is passed to
+    /// [`SolveReporter::on_unsolvable`] as a tree of [`ConflictNode`]s.
     Unsolvable(Vec<String>),
 
     /// The solver backend returned operations that we dont know how to install.
@@ -49,7 +74,11 @@ pub enum SolveError {
     /// Encountered duplicate records in the available packages.
     DuplicateRecords(String),
 
-    /// To support Resolvo cancellation
+    /// The solve was aborted because the [`SolverTask::timeout`] was reached
+    /// or the [`SolverTask::cancellation_token`] was cancelled.
+    ///
+    /// An explanation of how far the solver got is passed to
+    /// [`SolveReporter::on_cancelled`].
     Cancelled,
 }
 
@@ -89,10 +118,12 @@ pub enum ChannelPriority {
     #[default]
     Strict,
 
-    // Conda also has "Flexible" as an option, where packages present in multiple channels
-    // are only taken from lower-priority channels when this prevents unsatisfiable environment
-    // errors, but this would need implementation in the solvers.
-    // Flexible,
+    /// Packages from higher-priority channels are preferred over packages
+    /// from lower-priority channels, regardless of their version. Packages
+    /// present in multiple channels are only taken from a lower-priority
+    /// channel if that is required to find a solution.
+    Flexible,
+
     /// Packages can be retrieved from any channel as package version takes
     /// precedence.
     Disabled,
@@ -101,6 +132,11 @@ pub enum ChannelPriority {
 /// Represents a dependency resolution task, to be solved by one of the backends
 pub struct SolverTask<TAvailablePackagesIterator> {
     /// An iterator over all available packages
+    ///
+    /// The order of the packages is used to break ties between candidates.
+    /// Candidates are ordered by channel priority, track features, version
+    /// and build number, in that order. If all of these are equal, the
+    /// candidate that was passed first is preferred.
     pub available_packages: TAvailablePackagesIterator,
 
     /// Records of packages that are previously selected.
@@ -112,7 +148,9 @@ pub struct SolverTask<TAvailablePackagesIterator> {
     /// packages that are updated when installing new packages.
     ///
     /// Usually you add the currently installed packages or packages from a
-    /// lock-file here.
+    /// lock-file here. A locked record is only replaced if it is incompatible
+    /// with the [`Self::specs`] or the other selected packages, which keeps
+    /// incremental updates of an environment as small as possible.
     pub locked_packages: Vec<RepoDataRecord>,
 
     /// Records of packages that are previously selected and CANNOT be changed.
@@ -125,29 +163,67 @@ pub struct SolverTask<TAvailablePackagesIterator> {
     pub pinned_packages: Vec<RepoDataRecord>,
 
     /// Virtual packages considered active
+    ///
+    /// The solver never detects virtual packages itself, so these do not
+    /// have to match the host system. Use
+    /// [`Self::override_virtual_packages`] to override individual packages,
+    /// e.g. to solve for a machine with a different CUDA version.
     pub virtual_packages: Vec<GenericVirtualPackage>,
 
     /// The specs we want to solve
+    ///
+    /// Multiple specs for the same package are all required to hold, e.g.
+    /// `numpy >=1.26` and `numpy <2` select a `numpy` that satisfies both.
+    /// This makes it possible to combine specs from different sources, like
+    /// global pins and project specs, without merging them first.
     pub specs: Vec<MatchSpec>,
 
     /// Additional constraints that should be satisfied by the solver.
     /// Packages included in the `constraints` are not necessarily
     /// installed, but they must be satisfied by the solution.
+    ///
+    /// This is where pins like `python 3.11.*` should be added: unlike
+    /// [`Self::pinned_packages`] they do not fix a single record, but the
+    /// solve fails if the pin cannot be satisfied.
+    ///
+    /// Constraints behave like the `run_constrained` (or `constrains`) field
+    /// of a package: they restrict the versions of a package if it is pulled
+    /// in by the specs, but never cause it to be installed on their own.
+    /// Every constraint must have a package name.
     pub constraints: Vec<MatchSpec>,
 
     /// The timeout after which the solver should stop
     pub timeout: Option<std::time::Duration>,
 
-    /// The channel priority to solve with, either [`ChannelPriority::Strict`]
-    /// or [`ChannelPriority::Disabled`]
+    /// A token that can be used to abort the solve from another thread. The
+    /// solver periodically checks the token and returns
+    /// [`SolveError::Cancelled`] once it has been cancelled.
+    ///
+    /// Only the `resolvo` backend supports this.
+    pub cancellation_token: Option<CancellationToken>,
+
+    /// The channel priority to solve with. The priority of a channel is
+    /// determined by the order in which its records are passed in
+    /// [`Self::available_packages`].
     pub channel_priority: ChannelPriority,
 
     /// Exclude any package that has a timestamp newer than the specified
-    /// timestamp.
+    /// timestamp. This makes it possible to reproduce a solve as it would
+    /// have been at a certain point in time.
+    ///
+    /// Records without a timestamp are never excluded. Old packages
+    /// frequently lack a timestamp, so they are assumed to predate the
+    /// cutoff.
     pub exclude_newer: Option<DateTime<Utc>>,
 
     /// The solve strategy.
     pub strategy: SolveStrategy,
+
+    /// How packages with `track_features` are treated.
+    pub track_features_policy: TrackFeaturesPolicy,
+
+    /// A reporter that is notified of the progress of the solve.
+    pub reporter: Option<Arc<dyn SolveReporter>>,
 }
 
 impl<'r, I: IntoIterator<Item = &'r RepoDataRecord>> FromIterator<I>
@@ -162,13 +238,97 @@ impl<'r, I: IntoIterator<Item = &'r RepoDataRecord>> FromIterator<I>
             specs: Vec::new(),
             constraints: Vec::new(),
             timeout: None,
+            cancellation_token: None,
             channel_priority: ChannelPriority::default(),
             exclude_newer: None,
             strategy: SolveStrategy::default(),
+            track_features_policy: TrackFeaturesPolicy::default(),
+            reporter: None,
+        }
+    }
+}
+
+impl<TAvailablePackagesIterator> SolverTask<TAvailablePackagesIterator> {
+    /// Adds the given virtual packages to [`Self::virtual_packages`].
+    /// Existing virtual packages with the same name are replaced.
+    pub fn override_virtual_packages(
+        &mut self,
+        overrides: impl IntoIterator<Item = GenericVirtualPackage>,
+    ) {
+        for package in overrides {
+            match self
+                .virtual_packages
+                .iter_mut()
+                .find(|existing| existing.name == package.name)
+            {
+                Some(existing) => *existing = package,
+                None => self.virtual_packages.push(package),
+            }
         }
     }
 }
 
+/// A handle that can be used to cancel a running solve.
+///
+/// Clones of a token share their state, so a clone can be moved to another
+/// thread and cancelled from there while the solver holds on to the original.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Constructs a new token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests the cancellation of every solve that uses this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if [`Self::cancel`] has been called on this token or one
+    /// of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+/// Sorts the result of a solve so that it does not depend on the order in
+/// which the backend selected the packages.
+#[cfg(any(feature = "libsolv_c", feature = "resolvo"))]
+pub(crate) fn sort_solver_result(result: &mut SolverResult) {
+    result.records.sort_by(|a, b| {
+        a.package_record
+            .name
+            .cmp(&b.package_record.name)
+            .then_with(|| a.file_name.cmp(&b.file_name))
+    });
+    for extras in result.extras.values_mut() {
+        extras.sort();
+    }
+}
+
+/// Returns true if the record was uploaded after the `exclude_newer` cutoff.
+/// Records without a timestamp are never excluded.
+#[cfg(any(feature = "libsolv_c", feature = "resolvo"))]
+pub(crate) fn is_newer_than_cutoff(
+    record: &RepoDataRecord,
+    exclude_newer: Option<&DateTime<Utc>>,
+) -> bool {
+    match (exclude_newer, record.package_record.timestamp.as_ref()) {
+        (Some(exclude_newer), Some(timestamp)) => timestamp > exclude_newer,
+        _ => false,
+    }
+}
+
 /// Represents the strategy to use when solving dependencies
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -189,6 +349,38 @@ pub enum SolveStrategy {
     /// highest for transitive dependencies. This is similar to `LowestVersion`
     /// but only for direct dependencies.
     LowestVersionDirect,
+
+    /// Minimize the difference with the [`SolverTask::locked_packages`].
+    ///
+    /// Locked packages are kept if possible. If a locked package has to
+    /// change, the smallest upgrade is preferred, followed by the closest
+    /// downgrade. Packages that are not locked resolve to the highest
+    /// compatible version. This is useful to update a single package in an
+    /// existing environment.
+    MinimalUpdate,
+}
+
+/// Represents how packages with `track_features` are treated by the solver.
+///
+/// Conda uses `track_features` to mark variants of a package that should only
+/// be installed when explicitly requested, e.g. the `mkl` or `openblas`
+/// variants of a mutex package.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum TrackFeaturesPolicy {
+    /// Packages with track features are only selected if no package without
+    /// track features satisfies the requirements. This matches the behavior
+    /// of conda.
+    #[default]
+    Deprioritize,
+
+    /// Track features are ignored, packages with track features are ordered
+    /// the same as any other package.
+    Ignore,
+
+    /// Packages with track features are never selected.
+    Exclude,
 }
 
 /// A representation of a collection of [`RepoDataRecord`] usable by a
@@ -240,3 +432,35 @@ impl<'a, T: IntoIterator<Item = &'a RepoDataRecord>, S: SolverRepoData<'a>> Into
         self.0.into_iter().collect()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use rattler_conda_types::{GenericVirtualPackage, RepoDataRecord};
+
+    use super::SolverTask;
+
+    #[test]
+    fn test_override_virtual_packages() {
+        let records: Vec<RepoDataRecord> = Vec::new();
+        let mut task = SolverTask::from_iter([&records]);
+        task.virtual_packages = vec![
+            "__cuda=11.8".parse().unwrap(),
+            "__unix=0=0".parse().unwrap(),
+        ];
+
+        task.override_virtual_packages([
+            "__cuda=12.2".parse::<GenericVirtualPackage>().unwrap(),
+            "__glibc=2.28".parse().unwrap(),
+        ]);
+
+        let virtual_packages: Vec<_> = task
+            .virtual_packages
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            virtual_packages,
+            ["__cuda=12.2", "__unix=0=0", "__glibc=2.28"]
+        );
+    }
+}
@@ -86,9 +86,8 @@ pub fn add_repodata_records<'a>(
     let mut solvable_ids = Vec::new();
     for (repo_data_index, repo_data) in repo_data.into_iter().enumerate() {
         // Skip packages that are newer than the specified timestamp
-        match (exclude_newer, repo_data.package_record.timestamp.as_ref()) {
-            (Some(exclude_newer), Some(timestamp)) if *timestamp > *exclude_newer => continue,
-            _ => {}
+        if crate::is_newer_than_cutoff(repo_data, exclude_newer) {
+            continue;
         }
 
         // Create a solvable for the package
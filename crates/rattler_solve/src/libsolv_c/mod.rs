@@ -9,7 +9,7 @@ use std::{
 pub use input::cache_repodata;
 use input::{add_repodata_records, add_solv_file, add_virtual_packages};
 pub use libc_byte_slice::LibcByteSlice;
-use output::get_required_packages;
+use output::{get_conflicts, get_required_packages};
 use rattler_conda_types::{MatchSpec, NamelessMatchSpec, RepoDataRecord, SolverResult};
 use wrapper::{
     flags::SolverFlag,
@@ -18,7 +18,10 @@ use wrapper::{
     solve_goal::SolveGoal,
 };
 
-use crate::{ChannelPriority, IntoRepoData, SolveError, SolveStrategy, SolverRepoData, SolverTask};
+use crate::{
+    ChannelPriority, IntoRepoData, SolveError, SolvePhase, SolveStrategy, SolverRepoData,
+    SolverTask, TrackFeaturesPolicy,
+};
 
 mod input;
 mod libc_byte_slice;
@@ -101,6 +104,18 @@ impl super::SolverImpl for Solver {
             ]));
         }
 
+        if task.cancellation_token.is_some() {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "cancellation".to_string()
+            ]));
+        }
+
+        if task.track_features_policy != TrackFeaturesPolicy::Deprioritize {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "track_features_policy".to_string(),
+            ]));
+        }
+
         if task.strategy != SolveStrategy::Highest {
             return Err(SolveError::UnsupportedOperations(vec![
                 "strategy".to_string()
@@ -131,11 +146,11 @@ impl super::SolverImpl for Solver {
         // Determine the channel priority for each channel in the repodata in the order
         // in which the repodatas are passed, where the first channel will have
         // the highest priority value and each successive channel will descend
-        // in priority value. If not strict, the highest priority value will be
-        // 0 and the channel priority map will not be populated as it will
-        // not be used.
+        // in priority value. If channel priority is disabled, the highest priority
+        // value will be 0 and the channel priority map will not be populated as it
+        // will not be used.
         let mut highest_priority: i32 = 0;
-        let channel_priority = if task.channel_priority == ChannelPriority::Strict {
+        let channel_priority = if task.channel_priority != ChannelPriority::Disabled {
             let mut seen_channels = HashSet::new();
             let mut channel_order = Vec::new();
             for channel in repodatas
@@ -178,7 +193,7 @@ impl super::SolverImpl for Solver {
             let channel_name = &repodata.records[0].channel;
 
             // We dont want to drop the Repo, its stored in the pool anyway.
-            let priority: i32 = if task.channel_priority == ChannelPriority::Strict {
+            let priority: i32 = if task.channel_priority != ChannelPriority::Disabled {
                 *channel_priority.get(channel_name).unwrap()
             } else {
                 0
@@ -268,7 +283,24 @@ impl super::SolverImpl for Solver {
             task.channel_priority == ChannelPriority::Strict,
         );
 
-        let transaction = solver.solve(&mut goal).map_err(SolveError::Unsolvable)?;
+        if let Some(reporter) = &task.reporter {
+            reporter.on_phase_start(SolvePhase::Resolving);
+        }
+        let transaction = solver.solve(&mut goal);
+        if let Some(reporter) = &task.reporter {
+            reporter.on_phase_complete(SolvePhase::Resolving);
+        }
+        let transaction = transaction.map_err(|(explanations, problems)| {
+            if let Some(reporter) = &task.reporter {
+                reporter.on_unsolvable(&get_conflicts(
+                    &pool,
+                    &repo_mapping,
+                    &problems,
+                    all_repodata_records.as_slice(),
+                ));
+            }
+            SolveError::Unsolvable(explanations)
+        })?;
 
         let required_records = get_required_packages(
             &pool,
@@ -285,10 +317,12 @@ impl super::SolverImpl for Solver {
             )
         })?;
 
-        Ok(SolverResult {
+        let mut result = SolverResult {
             records: required_records,
             extras: HashMap::new(),
-        })
+        };
+        crate::sort_solver_result(&mut result);
+        Ok(result)
     }
 }
 
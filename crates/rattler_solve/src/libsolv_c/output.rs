@@ -5,11 +5,13 @@ use super::{
     wrapper::pool::{Pool, StringId},
     wrapper::repo::RepoId,
     wrapper::solvable::SolvableId,
+    wrapper::solve_problem::SolveProblem,
     wrapper::transaction::Transaction,
     wrapper::{ffi, solvable},
 };
-use rattler_conda_types::RepoDataRecord;
-use std::collections::HashMap;
+use crate::{ConflictKind, ConflictNode, ConflictPackage};
+use rattler_conda_types::{MatchSpec, PackageName, ParseStrictness, RepoDataRecord, Version};
+use std::{collections::HashMap, str::FromStr};
 
 /// Returns which packages should be installed in the environment
 ///
@@ -71,7 +73,128 @@ fn get_solvable_indexes(
     // Safe because there are no active mutable borrows of any solvable at this stage
     let repo_id = RepoId::from_ffi_solvable(unsafe { solvable.as_ref() });
 
-    let repo_index = repo_mapping[&repo_id];
+    let repo_index = *repo_mapping.get(&repo_id)?;
 
     Some((repo_index, solvable_index))
 }
+
+/// Converts the rules of the problems that libsolv reported into a tree of [`ConflictNode`]s.
+///
+/// The rules of a problem that refer to the same package are grouped into a single package node.
+/// Package nodes are nested below the requested spec with the same name, if there is one.
+pub fn get_conflicts(
+    pool: &Pool,
+    repo_mapping: &HashMap<RepoId, usize>,
+    problems: &[Vec<SolveProblem>],
+    repodata_records: &[Vec<&RepoDataRecord>],
+) -> Vec<ConflictNode> {
+    let solvable_index_id = pool
+        .find_interned_str("solvable:repodata_record_index")
+        .unwrap();
+    let package = |id: SolvableId| {
+        conflict_package(pool, repo_mapping, solvable_index_id, repodata_records, id)
+    };
+    let parse_spec = |dep: &str| MatchSpec::from_str(dep, ParseStrictness::Lenient).ok();
+
+    let mut roots = Vec::new();
+    for rules in problems {
+        let mut requirements = Vec::new();
+        let mut packages: Vec<(ffi::Id, ConflictNode)> = Vec::new();
+        for rule in rules {
+            let (source, kind) = match rule {
+                SolveProblem::Job { dep }
+                | SolveProblem::JobNothingProvidesDep { dep }
+                | SolveProblem::JobUnknownPackage { dep }
+                | SolveProblem::Pkg { dep } => {
+                    requirements.extend(
+                        parse_spec(dep)
+                            .map(|spec| ConflictNode::new(ConflictKind::Requirement(spec))),
+                    );
+                    continue;
+                }
+                SolveProblem::PkgRequires { source, dep }
+                | SolveProblem::PkgNothingProvidesDep { source, dep } => {
+                    (*source, parse_spec(dep).map(ConflictKind::Requirement))
+                }
+                SolveProblem::PkgConflicts { source, target }
+                | SolveProblem::PkgSameName { source, target } => {
+                    (*source, Some(ConflictKind::ConflictsWith(package(*target))))
+                }
+                SolveProblem::PkgConstrains {
+                    source,
+                    target,
+                    dep,
+                } => (
+                    *target,
+                    parse_spec(dep).map(|spec| ConflictKind::Constrained {
+                        spec,
+                        by: Some(package(*source)),
+                    }),
+                ),
+                SolveProblem::StrictRepoPriority { source } => (
+                    *source,
+                    Some(ConflictKind::Excluded {
+                        reason: "of strict channel priority".to_string(),
+                    }),
+                ),
+                SolveProblem::Update => continue,
+            };
+
+            let id = ffi::Id::from(source);
+            let index = match packages.iter().position(|(other, _)| *other == id) {
+                Some(index) => index,
+                None => {
+                    let node = ConflictNode::new(ConflictKind::Package(package(source)));
+                    packages.push((id, node));
+                    packages.len() - 1
+                }
+            };
+            packages[index]
+                .1
+                .children
+                .extend(kind.map(ConflictNode::new));
+        }
+
+        let mut unattached = Vec::new();
+        for (_, node) in packages {
+            let name = node.package().map(|package| &package.name);
+            let parent = requirements
+                .iter_mut()
+                .find(|requirement| requirement.spec().and_then(|spec| spec.name.as_ref()) == name);
+            match parent {
+                Some(parent) => parent.children.push(node),
+                None => unattached.push(node),
+            }
+        }
+        roots.extend(requirements);
+        roots.extend(unattached);
+    }
+    roots
+}
+
+fn conflict_package(
+    pool: &Pool,
+    repo_mapping: &HashMap<RepoId, usize>,
+    solvable_index_id: StringId,
+    repodata_records: &[Vec<&RepoDataRecord>],
+    id: SolvableId,
+) -> ConflictPackage {
+    if let Some((repo_index, solvable_index)) =
+        get_solvable_indexes(pool, repo_mapping, solvable_index_id, id)
+    {
+        let record = &repodata_records[repo_index][solvable_index].package_record;
+        return ConflictPackage {
+            name: record.name.clone(),
+            version: Some(record.version.version().clone()),
+            build: Some(record.build.clone()),
+        };
+    }
+
+    // Solvables without a record, e.g. virtual packages.
+    let (name, version) = id.name_and_version(pool);
+    ConflictPackage {
+        name: PackageName::new_unchecked(name.unwrap_or_default()),
+        version: version.and_then(|version| Version::from_str(version).ok()),
+        build: None,
+    }
+}
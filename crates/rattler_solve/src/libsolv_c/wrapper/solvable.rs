@@ -30,6 +30,18 @@ impl SolvableId {
             panic!("invalid solvable id!")
         }
     }
+
+    /// Returns the name and the version of the solvable
+    ///
+    /// Panics if the solvable is not found in the pool
+    pub fn name_and_version(self, pool: &Pool) -> (Option<&str>, Option<&str>) {
+        // Safe because there are no active mutable borrows of any solvable at this stage
+        let solvable = unsafe { self.resolve_raw(pool).as_ref() };
+        (
+            StringId(solvable.name).resolve(pool),
+            StringId(solvable.evr).resolve(pool),
+        )
+    }
 }
 
 /// Gets a number associated to this solvable
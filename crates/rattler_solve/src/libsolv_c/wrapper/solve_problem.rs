@@ -11,6 +11,7 @@ use ffi::{
     SolverRuleinfo_SOLVER_RULE_PKG_NOTHING_PROVIDES_DEP as SOLVER_RULE_SOLVER_RULE_PKG_NOTHING_PROVIDES_DEP,
     SolverRuleinfo_SOLVER_RULE_PKG_REQUIRES as SOLVER_RULE_PKG_REQUIRES,
     SolverRuleinfo_SOLVER_RULE_PKG_SAME_NAME as SOLVER_RULE_SOLVER_RULE_PKG_SAME_NAME,
+    SolverRuleinfo_SOLVER_RULE_STRICT_REPO_PRIORITY as SOLVER_RULE_STRICT_REPO_PRIORITY,
     SolverRuleinfo_SOLVER_RULE_UPDATE as SOLVER_RULE_SOLVER_RULE_UPDATE,
 };
 
@@ -61,48 +62,53 @@ pub enum SolveProblem {
         source: SolvableId,
         target: SolvableId,
     },
+    /// The source is not considered because it is available from a channel
+    /// with a higher priority.
+    StrictRepoPriority { source: SolvableId },
     /// Encountered in the problems list from libsolv but unknown.
     /// Explicitly ignored until we do something with it.
     Update,
 }
 
 impl SolveProblem {
+    /// Constructs a problem from the information of a libsolv rule. Returns
+    /// `None` for rules that are not represented by a [`SolveProblem`].
     pub fn from_raw(
         problem_type: ffi::SolverRuleinfo,
         dep: Option<String>,
         source: Option<SolvableId>,
         target: Option<SolvableId>,
-    ) -> Self {
-        match problem_type {
-            SOLVER_RULE_JOB => Self::Job { dep: dep.unwrap() },
-            SOLVER_RULE_JOB_NOTHING_PROVIDES_DEP => {
-                Self::JobNothingProvidesDep { dep: dep.unwrap() }
-            }
-            SOLVER_RULE_JOB_UNKNOWN_PACKAGE => Self::JobUnknownPackage { dep: dep.unwrap() },
-            SOLVER_RULE_PKG => Self::Pkg { dep: dep.unwrap() },
+    ) -> Option<Self> {
+        let problem = match problem_type {
+            SOLVER_RULE_JOB => Self::Job { dep: dep? },
+            SOLVER_RULE_JOB_NOTHING_PROVIDES_DEP => Self::JobNothingProvidesDep { dep: dep? },
+            SOLVER_RULE_JOB_UNKNOWN_PACKAGE => Self::JobUnknownPackage { dep: dep? },
+            SOLVER_RULE_PKG => Self::Pkg { dep: dep? },
             SOLVER_RULE_SOLVER_RULE_PKG_CONFLICTS => Self::PkgConflicts {
-                source: source.unwrap(),
-                target: target.unwrap(),
+                source: source?,
+                target: target?,
             },
             SOLVER_RULE_PKG_CONSTRAINS => Self::PkgConstrains {
-                source: source.unwrap(),
-                target: target.unwrap(),
-                dep: dep.unwrap(),
+                source: source?,
+                target: target?,
+                dep: dep?,
             },
             SOLVER_RULE_SOLVER_RULE_PKG_NOTHING_PROVIDES_DEP => Self::PkgNothingProvidesDep {
-                source: source.unwrap(),
-                dep: dep.unwrap(),
+                source: source?,
+                dep: dep?,
             },
             SOLVER_RULE_PKG_REQUIRES => Self::PkgRequires {
-                source: source.unwrap(),
-                dep: dep.unwrap(),
+                source: source?,
+                dep: dep?,
             },
             SOLVER_RULE_SOLVER_RULE_PKG_SAME_NAME => Self::PkgSameName {
-                source: source.unwrap(),
-                target: target.unwrap(),
+                source: source?,
+                target: target?,
             },
+            SOLVER_RULE_STRICT_REPO_PRIORITY => Self::StrictRepoPriority { source: source? },
             SOLVER_RULE_SOLVER_RULE_UPDATE => Self::Update,
-            _ => panic!("Unknown problem type: {problem_type}"),
-        }
+            _ => return None,
+        };
+        Some(problem)
     }
 }
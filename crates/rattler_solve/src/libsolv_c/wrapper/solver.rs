@@ -63,12 +63,14 @@ impl Solver<'_> {
         output
     }
 
-    pub fn all_solver_problems(&self) -> Vec<SolveProblem> {
+    /// Returns the rules that are involved in each of the problems that the solver still has.
+    fn all_solver_problems(&self) -> Vec<Vec<SolveProblem>> {
         let mut problems = Vec::new();
         let mut problem_rules = Queue::<ffi::Id>::default();
 
         let count = self.problem_count();
         for i in 1..=count {
+            let mut rules = Vec::new();
             unsafe {
                 ffi::solver_findallproblemrules(
                     self.raw_ptr(),
@@ -105,7 +107,7 @@ impl Solver<'_> {
                     let source = if source_id < 0 || source_id >= nsolvables {
                         None
                     } else {
-                        Some(SolvableId(target_id))
+                        Some(SolvableId(source_id))
                     };
 
                     let dep = if dep_id == 0 {
@@ -117,9 +119,10 @@ impl Solver<'_> {
                         Some(dep)
                     };
 
-                    problems.push(SolveProblem::from_raw(problem_type, dep, source, target));
+                    rules.extend(SolveProblem::from_raw(problem_type, dep, source, target));
                 }
             }
+            problems.push(rules);
         }
         problems
     }
@@ -130,8 +133,12 @@ impl Solver<'_> {
     }
 
     /// Solves all the problems in the `queue` and returns a transaction from the found solution.
-    /// Returns an error if problems remain unsolved.
-    pub fn solve(&mut self, queue: &mut SolveGoal) -> Result<Transaction<'_>, Vec<String>> {
+    /// Returns an error if problems remain unsolved, containing a user-friendly representation of
+    /// each problem and the rules involved in it.
+    pub fn solve(
+        &mut self,
+        queue: &mut SolveGoal,
+    ) -> Result<Transaction<'_>, (Vec<String>, Vec<Vec<SolveProblem>>)> {
         let result = unsafe {
             // Run the solve method
             ffi::solver_solve(self.raw_ptr(), queue.raw_ptr());
@@ -146,7 +153,7 @@ impl Solver<'_> {
             // Safe because we know the `transaction` ptr is valid
             Ok(unsafe { Transaction::new(self, transaction) })
         } else {
-            Err(self.solver_problems())
+            Err((self.solver_problems(), self.all_solver_problems()))
         }
     }
 }
@@ -0,0 +1,251 @@
+//! Computes the environment that remains after removing packages from it.
+
+use std::collections::{HashMap, HashSet};
+
+use rattler_conda_types::{MatchSpec, PackageName, PackageRecord, ParseStrictness, PrefixRecord};
+
+/// Describes which packages to remove from an environment.
+///
+/// Unlike a [`crate::SolverTask`] this does not require any repodata, the
+/// result is computed from the dependencies of the installed packages. Every
+/// installed package that (transitively) depends on a removed package is
+/// removed as well, which is how `conda remove` behaves.
+#[derive(Debug, Clone)]
+pub struct RemovalTask<T> {
+    /// The packages that are currently installed in the environment.
+    pub installed_packages: Vec<T>,
+
+    /// The names of the packages to remove.
+    pub remove: Vec<PackageName>,
+
+    /// The names of the packages that were explicitly requested by the user.
+    /// Only used if [`Self::prune_orphans`] is set.
+    pub requested: Vec<PackageName>,
+
+    /// If true, also remove the packages that are no longer required by any
+    /// of the [`Self::requested`] packages.
+    pub prune_orphans: bool,
+}
+
+/// The result of a [`RemovalTask`].
+#[derive(Debug, Clone)]
+pub struct RemovalResult<T> {
+    /// The packages that remain in the environment.
+    pub kept: Vec<T>,
+
+    /// The packages that have to be removed from the environment, including
+    /// the dependents of the removed packages and the pruned orphans.
+    pub removed: Vec<T>,
+}
+
+impl<T: AsRef<PackageRecord>> RemovalTask<T> {
+    /// Constructs a task that removes the given packages from the
+    /// environment.
+    pub fn new(installed_packages: Vec<T>, remove: Vec<PackageName>) -> Self {
+        Self {
+            installed_packages,
+            remove,
+            requested: Vec::new(),
+            prune_orphans: false,
+        }
+    }
+
+    /// Also removes the packages that are no longer required by any of the
+    /// `requested` packages.
+    pub fn with_pruned_orphans(self, requested: Vec<PackageName>) -> Self {
+        Self {
+            requested,
+            prune_orphans: true,
+            ..self
+        }
+    }
+
+    /// Computes which packages remain in the environment.
+    pub fn solve(self) -> RemovalResult<T> {
+        let dependencies: HashMap<&PackageName, Vec<PackageName>> = self
+            .installed_packages
+            .iter()
+            .map(|record| {
+                let record = record.as_ref();
+                (&record.name, dependency_names(record))
+            })
+            .collect();
+
+        // Remove the packages and everything that depends on them until
+        // nothing changes anymore.
+        let mut removed: HashSet<&PackageName> = self.remove.iter().collect();
+        loop {
+            let dependents: Vec<_> = dependencies
+                .iter()
+                .filter(|(name, depends)| {
+                    !removed.contains(*name) && depends.iter().any(|dep| removed.contains(&dep))
+                })
+                .map(|(name, _)| *name)
+                .collect();
+            if dependents.is_empty() {
+                break;
+            }
+            removed.extend(dependents);
+        }
+
+        // Only keep the packages that are reachable from the requested
+        // packages.
+        if self.prune_orphans {
+            let mut required = HashSet::new();
+            let mut queue: Vec<&PackageName> = self
+                .requested
+                .iter()
+                .filter(|name| !removed.contains(name))
+                .collect();
+            while let Some(name) = queue.pop() {
+                if !required.insert(name) {
+                    continue;
+                }
+                if let Some(depends) = dependencies.get(name) {
+                    queue.extend(depends.iter().filter(|dep| !removed.contains(dep)));
+                }
+            }
+            removed.extend(dependencies.keys().filter(|name| !required.contains(*name)));
+        }
+
+        let removed: HashSet<PackageName> = removed.into_iter().cloned().collect();
+        let (removed, kept) = self
+            .installed_packages
+            .into_iter()
+            .partition(|record| removed.contains(&record.as_ref().name));
+        RemovalResult { kept, removed }
+    }
+}
+
+/// Returns the installed packages that are not (transitively) required by any
+/// of the packages the user requested. These packages can be removed without
+/// affecting the requested packages, e.g. to implement `autoremove`.
+///
+/// The requested packages are the packages that match one of the
+/// `requested_specs` (usually read from the history of the environment) and
+/// the packages whose [`PrefixRecord::requested_specs`] are not empty.
+pub fn find_orphans<'r>(
+    installed: &'r [PrefixRecord],
+    requested_specs: &[MatchSpec],
+) -> Vec<&'r PrefixRecord> {
+    let requested = installed
+        .iter()
+        .filter(|record| {
+            !record.requested_specs.is_empty()
+                || requested_specs.iter().any(|spec| {
+                    spec.name.as_ref() == Some(&record.repodata_record.package_record.name)
+                })
+        })
+        .map(|record| record.repodata_record.package_record.name.clone())
+        .collect();
+
+    RemovalTask::new(installed.iter().collect(), Vec::new())
+        .with_pruned_orphans(requested)
+        .solve()
+        .removed
+}
+
+/// Returns the names of the packages the record depends on.
+fn dependency_names(record: &PackageRecord) -> Vec<PackageName> {
+    record
+        .depends
+        .iter()
+        .filter_map(|spec| {
+            MatchSpec::from_str(spec, ParseStrictness::Lenient)
+                .ok()?
+                .name
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{
+        MatchSpec, PackageName, PackageRecord, ParseStrictness, PrefixRecord, RepoDataRecord,
+        Version,
+    };
+    use url::Url;
+
+    use super::{find_orphans, RemovalTask};
+
+    fn record(name: &str, depends: &[&str]) -> PackageRecord {
+        let mut record = PackageRecord::new(
+            PackageName::from_str(name).unwrap(),
+            Version::from_str("1.0").unwrap(),
+            "0".to_string(),
+        );
+        record.depends = depends.iter().map(ToString::to_string).collect();
+        record
+    }
+
+    fn environment() -> Vec<PackageRecord> {
+        vec![
+            record("python", &["openssl >=3", "__unix"]),
+            record("openssl", &[]),
+            record("numpy", &["python >=3.8", "libblas"]),
+            record("libblas", &[]),
+            record("pandas", &["numpy", "python"]),
+            record("requests", &["python"]),
+        ]
+    }
+
+    fn names(records: &[PackageRecord]) -> Vec<&str> {
+        let mut names: Vec<_> = records.iter().map(|r| r.name.as_normalized()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn test_remove_dependents() {
+        let result =
+            RemovalTask::new(environment(), vec![PackageName::from_str("numpy").unwrap()]).solve();
+        assert_eq!(names(&result.removed), ["numpy", "pandas"]);
+        assert_eq!(
+            names(&result.kept),
+            ["libblas", "openssl", "python", "requests"]
+        );
+    }
+
+    #[test]
+    fn test_prune_orphans() {
+        let result = RemovalTask::new(environment(), vec![PackageName::from_str("numpy").unwrap()])
+            .with_pruned_orphans(vec![
+                PackageName::from_str("pandas").unwrap(),
+                PackageName::from_str("requests").unwrap(),
+            ])
+            .solve();
+        assert_eq!(names(&result.removed), ["libblas", "numpy", "pandas"]);
+        assert_eq!(names(&result.kept), ["openssl", "python", "requests"]);
+    }
+
+    #[test]
+    fn test_find_orphans() {
+        let installed: Vec<PrefixRecord> = environment()
+            .into_iter()
+            .map(|package_record| {
+                let mut record = PrefixRecord::from_repodata_record(
+                    RepoDataRecord {
+                        url: Url::parse("https://example.com/package.conda").unwrap(),
+                        channel: None,
+                        file_name: "package.conda".to_string(),
+                        package_record,
+                    },
+                    Vec::new(),
+                );
+                if record.repodata_record.package_record.name.as_normalized() == "requests" {
+                    record.requested_specs = vec!["requests".to_string()];
+                }
+                record
+            })
+            .collect();
+
+        let requested = [MatchSpec::from_str("numpy >=1", ParseStrictness::Lenient).unwrap()];
+        let orphans: Vec<_> = find_orphans(&installed, &requested)
+            .into_iter()
+            .map(|record| record.repodata_record.package_record.clone())
+            .collect();
+        assert_eq!(names(&orphans), ["pandas"]);
+    }
+}
@@ -0,0 +1,65 @@
+//! Reporting the progress of a solve.
+
+use std::fmt;
+
+use crate::ConflictNode;
+
+/// The phases of a solve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolvePhase {
+    /// The available packages are loaded into the solver.
+    ///
+    /// Only the `resolvo` backend reports this phase.
+    LoadingPackages,
+
+    /// The solver searches for a set of packages that satisfies the specs.
+    Resolving,
+}
+
+/// A trait that enables being notified of the progress of a solve.
+///
+/// The solver backends do not report how often they backtrack, instead the
+/// number of packages that have been considered can be used as an indication
+/// of the progress.
+pub trait SolveReporter: Send + Sync {
+    /// Called when a phase of the solve started.
+    fn on_phase_start(&self, _phase: SolvePhase) {}
+
+    /// Called when a phase of the solve finished, regardless of whether it
+    /// succeeded.
+    fn on_phase_complete(&self, _phase: SolvePhase) {}
+
+    /// Called when the solver collected the candidates of a package for the
+    /// first time. `count` is the number of candidates that were found.
+    ///
+    /// Only the `resolvo` backend reports this.
+    fn on_candidates_collected(&self, _package: &str, _count: usize) {}
+
+    /// Called when the requirements cannot be satisfied, right before
+    /// [`crate::SolveError::Unsolvable`] is returned. `conflicts` contains the same
+    /// explanation as the error, but as a tree that can be rendered
+    /// interactively.
+    fn on_unsolvable(&self, _conflicts: &[ConflictNode]) {}
+
+    /// Called when the solve was aborted because of its timeout or
+    /// cancellation token, right before [`crate::SolveError::Cancelled`] is returned.
+    /// `explanations` describe how far the solver got.
+    ///
+    /// Only the `resolvo` backend reports this.
+    fn on_cancelled(&self, _explanations: &[String]) {}
+}
+
+impl fmt::Debug for dyn SolveReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SolveReporter")
+    }
+}
+
+/// Two reporters are equal if they are the same instance.
+impl PartialEq for dyn SolveReporter {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+impl Eq for dyn SolveReporter {}
@@ -14,9 +14,13 @@ use super::{NameType, SolverMatchSpec, SolverPackageRecord};
 use crate::resolvo::CondaDependencyProvider;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub(super) enum CompareStrategy {
+pub(super) enum CompareStrategy<'v> {
     Default,
     LowestVersion,
+    /// Prefer the versions that are closest to the given version: the
+    /// version itself, then higher versions in ascending order, then lower
+    /// versions in descending order.
+    ClosestTo(&'v Version),
 }
 
 /// Sort the candidates based on the dependencies.
@@ -26,15 +30,15 @@ pub(super) enum CompareStrategy {
 ///    the shared set of dependencies
 pub struct SolvableSorter<'a, 'repo> {
     solver: &'a SolverCache<CondaDependencyProvider<'repo>>,
-    strategy: CompareStrategy,
-    dependency_strategy: CompareStrategy,
+    strategy: CompareStrategy<'repo>,
+    dependency_strategy: CompareStrategy<'repo>,
 }
 
 impl<'a, 'repo> SolvableSorter<'a, 'repo> {
     pub fn new(
         solver: &'a SolverCache<CondaDependencyProvider<'repo>>,
-        strategy: CompareStrategy,
-        dependency_strategy: CompareStrategy,
+        strategy: CompareStrategy<'repo>,
+        dependency_strategy: CompareStrategy<'repo>,
     ) -> Self {
         Self {
             solver,
@@ -76,17 +80,31 @@ impl<'a, 'repo> SolvableSorter<'a, 'repo> {
     }
 
     /// Sort the candidates based on:
-    /// 1. Whether the package has tracked features
-    /// 2. The version of the package
-    /// 3. The build number of the package
+    /// 1. The priority of the channel of the package (only with flexible
+    ///    channel priority)
+    /// 2. Whether the package has tracked features
+    /// 3. The version of the package
+    /// 4. The build number of the package
     fn simple_compare(&self, a: SolvableId, b: SolvableId) -> Ordering {
         let a_record = &self.solvable_record(a);
         let b_record = &self.solvable_record(b);
 
+        // Prefer packages from channels with a higher priority.
+        let provider = self.solver.provider();
+        if let (Some(a_rank), Some(b_rank)) = (
+            provider.channel_rank(a_record),
+            provider.channel_rank(b_record),
+        ) {
+            match a_rank.cmp(&b_rank) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+        }
+
         // First compare by "tracked_features". If one of the packages has a tracked
         // feature it is sorted below the one that doesn't have the tracked feature.
-        let a_has_tracked_features = !a_record.track_features().is_empty();
-        let b_has_tracked_features = !b_record.track_features().is_empty();
+        let a_has_tracked_features = provider.is_deprioritized(a_record);
+        let b_has_tracked_features = provider.is_deprioritized(b_record);
         match (a_has_tracked_features, b_has_tracked_features) {
             (true, false) => return Ordering::Greater,
             (false, true) => return Ordering::Less,
@@ -94,13 +112,21 @@ impl<'a, 'repo> SolvableSorter<'a, 'repo> {
         };
 
         // Otherwise, select the variant with the highest version
-        match (self.strategy, a_record.version().cmp(&b_record.version())) {
-            (CompareStrategy::Default, Ordering::Greater)
-            | (CompareStrategy::LowestVersion, Ordering::Less) => return Ordering::Less,
-            (CompareStrategy::Default, Ordering::Less)
-            | (CompareStrategy::LowestVersion, Ordering::Greater) => return Ordering::Greater,
-            (_, Ordering::Equal) => {}
+        let version_ordering = match (self.strategy, a_record.version(), b_record.version()) {
+            (CompareStrategy::LowestVersion, a_version, b_version) => a_version.cmp(&b_version),
+            (CompareStrategy::ClosestTo(target), Some(a_version), Some(b_version)) => {
+                match (a_version >= target, b_version >= target) {
+                    (true, true) => a_version.cmp(b_version),
+                    (false, false) => b_version.cmp(a_version),
+                    (true, false) => Ordering::Less,
+                    (false, true) => Ordering::Greater,
+                }
+            }
+            (_, a_version, b_version) => b_version.cmp(&a_version),
         };
+        if version_ordering != Ordering::Equal {
+            return version_ordering;
+        }
 
         // Otherwise, select the variant with the highest build number first
         b_record.build_number().cmp(&a_record.build_number())
@@ -325,14 +351,20 @@ impl TrackedFeatureVersion {
         }
     }
 
-    fn compare_with_strategy(&self, other: &Self, compare_strategy: CompareStrategy) -> Ordering {
+    fn compare_with_strategy(
+        &self,
+        other: &Self,
+        compare_strategy: CompareStrategy<'_>,
+    ) -> Ordering {
         // First compare by "tracked_features". If one of the packages has a tracked
         // feature it is sorted below the one that doesn't have the tracked feature.
         match (self.tracked_features, other.tracked_features) {
             (true, false) => Ordering::Greater,
             (false, true) => Ordering::Less,
-            _ if compare_strategy == CompareStrategy::Default => other.version.cmp(&self.version),
-            _ => self.version.cmp(&other.version),
+            _ if compare_strategy == CompareStrategy::LowestVersion => {
+                self.version.cmp(&other.version)
+            }
+            _ => other.version.cmp(&self.version),
         }
     }
 }
@@ -357,21 +389,17 @@ pub(super) fn find_highest_version(
                 return None;
             };
 
-            let pool = &solver.provider().pool;
+            let provider = solver.provider();
 
             let mut highest_version = None;
             for record in candidates
                 .iter()
-                .map(|id| &pool.resolve_solvable(*id).record)
+                .map(|id| &provider.pool.resolve_solvable(*id).record)
             {
-                let (version, has_tracked_features) = match record {
-                    SolverPackageRecord::Record(record) => (
-                        record.package_record.version.version(),
-                        !record.package_record.track_features.is_empty(),
-                    ),
-                    SolverPackageRecord::VirtualPackage(record) => (&record.version, false),
-                    SolverPackageRecord::Extra { .. } => continue,
+                let Some(version) = record.version() else {
+                    continue;
                 };
+                let has_tracked_features = provider.is_deprioritized(record);
                 highest_version = highest_version.map_or_else(
                     || Some((version.clone(), has_tracked_features)),
                     |(highest_version, current_has_tracked_features)| {
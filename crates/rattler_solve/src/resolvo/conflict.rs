@@ -0,0 +1,216 @@
+//! Converts the conflict graph of [`resolvo`] into [`ConflictNode`]s.
+
+use std::collections::HashSet;
+
+use petgraph::{graph::NodeIndex, visit::EdgeRef, Direction};
+use rattler_conda_types::{MatchSpec, PackageName};
+use resolvo::{
+    conflict::{ConflictCause, ConflictEdge, ConflictGraph, ConflictNode as GraphNode},
+    Interner, Requirement, SolvableId, VersionSetId,
+};
+
+use super::{CondaDependencyProvider, NameType, SolverMatchSpec, SolverPackageRecord};
+use crate::{ConflictKind, ConflictNode, ConflictPackage};
+
+/// Builds a tree of [`ConflictNode`]s from the conflict graph reported by the
+/// solver. The roots of the tree are the requirements of the problem that are
+/// involved in the conflict.
+pub(super) fn conflict_tree(
+    graph: &ConflictGraph,
+    provider: &CondaDependencyProvider<'_>,
+) -> Vec<ConflictNode> {
+    let mut builder = TreeBuilder {
+        graph,
+        provider,
+        visited: HashSet::new(),
+    };
+    builder.visited.insert(graph.root_node);
+    builder.requirements(graph.root_node)
+}
+
+struct TreeBuilder<'a, 'p> {
+    graph: &'a ConflictGraph,
+    provider: &'a CondaDependencyProvider<'p>,
+
+    /// The nodes that have already been expanded. The graph can contain
+    /// cycles and nodes that are shared between multiple parents, these are
+    /// only explained the first time they are encountered.
+    visited: HashSet<NodeIndex>,
+}
+
+impl TreeBuilder<'_, '_> {
+    /// Returns a node for every requirement of the given node that is
+    /// involved in the conflict.
+    fn requirements(&mut self, node: NodeIndex) -> Vec<ConflictNode> {
+        // Group the candidates by the requirement they were considered for,
+        // in the order in which the requirements were encountered.
+        let mut requirements: Vec<(Requirement, Vec<NodeIndex>)> = Vec::new();
+        for (target, weight) in self.edges(node, Direction::Outgoing) {
+            let ConflictEdge::Requires(requirement) = weight else {
+                continue;
+            };
+            match requirements.iter_mut().find(|(r, _)| *r == requirement) {
+                Some((_, candidates)) => candidates.push(target),
+                None => requirements.push((requirement, vec![target])),
+            }
+        }
+
+        let mut result = Vec::new();
+        for (requirement, candidates) in requirements {
+            let version_sets: Vec<_> = match requirement {
+                Requirement::Single(version_set) => vec![version_set],
+                Requirement::Union(union) => self.provider.version_sets_in_union(union).collect(),
+            };
+            for version_set in version_sets {
+                // A requirement without candidates points to the unresolved
+                // node, which results in a requirement without children.
+                let name = self.provider.version_set_name(version_set);
+                let matching: Vec<_> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|&candidate| match self.graph.graph[candidate] {
+                        GraphNode::Solvable(solvable) => solvable
+                            .solvable()
+                            .is_some_and(|id| self.provider.solvable_name(id) == name),
+                        GraphNode::UnresolvedDependency | GraphNode::Excluded(_) => false,
+                    })
+                    .collect();
+                let children: Vec<_> = matching
+                    .into_iter()
+                    .filter_map(|candidate| self.package(candidate))
+                    .collect();
+                result.push(
+                    ConflictNode::new(ConflictKind::Requirement(self.spec(version_set)))
+                        .with_children(children),
+                );
+            }
+        }
+        result
+    }
+
+    /// Returns a node for the package of the given graph node together with
+    /// the reasons why it cannot be installed.
+    fn package(&mut self, node: NodeIndex) -> Option<ConflictNode> {
+        let GraphNode::Solvable(solvable) = self.graph.graph[node] else {
+            return None;
+        };
+        let package = ConflictNode::new(ConflictKind::Package(
+            self.conflict_package(solvable.solvable()?),
+        ));
+        if !self.visited.insert(node) {
+            return Some(package);
+        }
+
+        let mut children = Vec::new();
+        for (source, weight) in self.edges(node, Direction::Incoming) {
+            let ConflictEdge::Conflict(cause) = weight else {
+                continue;
+            };
+            let source = self.graph.graph[source];
+            let kind = match cause {
+                ConflictCause::Locked(locked) => {
+                    ConflictKind::Locked(self.conflict_package(locked))
+                }
+                ConflictCause::Constrains(version_set) => ConflictKind::Constrained {
+                    spec: self.spec(version_set),
+                    by: self.graph_package(source),
+                },
+                ConflictCause::ForbidMultipleInstances => match self.graph_package(source) {
+                    Some(other) => ConflictKind::ConflictsWith(other),
+                    None => continue,
+                },
+                ConflictCause::Excluded => continue,
+            };
+            children.push(ConflictNode::new(kind));
+        }
+        for (target, weight) in self.edges(node, Direction::Outgoing) {
+            let kind = match (weight, self.graph.graph[target]) {
+                (ConflictEdge::Conflict(ConflictCause::Excluded), GraphNode::Excluded(reason)) => {
+                    ConflictKind::Excluded {
+                        reason: self.provider.pool.resolve_string(reason).to_string(),
+                    }
+                }
+                (ConflictEdge::Conflict(ConflictCause::ForbidMultipleInstances), other) => {
+                    match self.graph_package(other) {
+                        Some(other) => ConflictKind::ConflictsWith(other),
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+            children.push(ConflictNode::new(kind));
+        }
+        children.extend(self.requirements(node));
+
+        Some(package.with_children(children))
+    }
+
+    /// Returns the other endpoint and the weight of every edge of a node in
+    /// the given direction, in the order in which the edges were added.
+    fn edges(&self, node: NodeIndex, direction: Direction) -> Vec<(NodeIndex, ConflictEdge)> {
+        let mut edges: Vec<_> = self
+            .graph
+            .graph
+            .edges_directed(node, direction)
+            .map(|edge| {
+                let other = match direction {
+                    Direction::Outgoing => edge.target(),
+                    Direction::Incoming => edge.source(),
+                };
+                (other, *edge.weight())
+            })
+            .collect();
+        // petgraph iterates the edges of a node from newest to oldest.
+        edges.reverse();
+        edges
+    }
+
+    /// Returns the package of a graph node, or `None` if the node does not
+    /// represent a package.
+    fn graph_package(&self, node: GraphNode) -> Option<ConflictPackage> {
+        match node {
+            GraphNode::Solvable(solvable) => Some(self.conflict_package(solvable.solvable()?)),
+            GraphNode::UnresolvedDependency | GraphNode::Excluded(_) => None,
+        }
+    }
+
+    fn conflict_package(&self, solvable: SolvableId) -> ConflictPackage {
+        match &self.provider.pool.resolve_solvable(solvable).record {
+            SolverPackageRecord::Record(rec) => ConflictPackage {
+                name: rec.package_record.name.clone(),
+                version: Some(rec.package_record.version.version().clone()),
+                build: Some(rec.package_record.build.clone()),
+            },
+            SolverPackageRecord::VirtualPackage(rec) => ConflictPackage {
+                name: rec.name.clone(),
+                version: Some(rec.version.clone()),
+                build: Some(rec.build_string.clone()),
+            },
+            SolverPackageRecord::Extra { package, .. } => ConflictPackage {
+                name: package.clone(),
+                version: None,
+                build: None,
+            },
+        }
+    }
+
+    /// Reconstructs the [`MatchSpec`] from which a version set was created.
+    fn spec(&self, version_set: VersionSetId) -> MatchSpec {
+        let pool = &self.provider.pool;
+        let name = pool.resolve_package_name(pool.resolve_version_set_package_name(version_set));
+        match (name, pool.resolve_version_set(version_set)) {
+            (NameType::Base(name), SolverMatchSpec::MatchSpec(spec)) => {
+                MatchSpec::from_nameless(spec.clone(), Some(PackageName::new_unchecked(name)))
+            }
+            (NameType::Base(name), _) => MatchSpec {
+                name: Some(PackageName::new_unchecked(name)),
+                ..MatchSpec::default()
+            },
+            (NameType::Extra { package, extra }, _) => MatchSpec {
+                name: Some(PackageName::new_unchecked(package)),
+                extras: Some(vec![extra.clone()]),
+                ..MatchSpec::default()
+            },
+        }
+    }
+}
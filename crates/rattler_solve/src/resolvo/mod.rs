@@ -6,6 +6,7 @@ use std::{
     collections::{HashMap, HashSet},
     fmt::{Display, Formatter},
     marker::PhantomData,
+    sync::Arc,
 };
 
 use chrono::{DateTime, Utc};
@@ -24,11 +25,13 @@ use resolvo::{
 };
 
 use crate::{
-    resolvo::conda_sorting::CompareStrategy, ChannelPriority, IntoRepoData, SolveError,
-    SolveStrategy, SolverRepoData, SolverTask,
+    resolvo::conda_sorting::CompareStrategy, CancellationToken, ChannelPriority, IntoRepoData,
+    SolveError, SolvePhase, SolveReporter, SolveStrategy, SolverRepoData, SolverTask,
+    TrackFeaturesPolicy,
 };
 
 mod conda_sorting;
+mod conflict;
 
 /// Represents the information required to load available packages into libsolv
 /// for a single channel and platform combination
@@ -263,9 +266,27 @@ pub struct CondaDependencyProvider<'a> {
 
     stop_time: Option<std::time::SystemTime>,
 
+    cancellation_token: Option<CancellationToken>,
+
     strategy: SolveStrategy,
 
+    track_features_policy: TrackFeaturesPolicy,
+
+    reporter: Option<Arc<dyn SolveReporter>>,
+
+    /// The packages whose candidates were requested by the solver, in order.
+    /// Used to explain how far the solver got when it is cancelled.
+    considered_packages: RefCell<Vec<NameId>>,
+
     direct_dependencies: HashSet<NameId>,
+
+    /// The versions of the favored records, used by
+    /// [`SolveStrategy::MinimalUpdate`].
+    favored_versions: HashMap<NameId, &'a rattler_conda_types::Version>,
+
+    /// The rank of each channel when flexible channel priority is used. Lower
+    /// ranks have a higher priority. Empty for other channel priorities.
+    channel_ranks: HashMap<Option<String>, usize>,
 }
 
 impl<'a> CondaDependencyProvider<'a> {
@@ -309,6 +330,9 @@ impl<'a> CondaDependencyProvider<'a> {
         // Hashmap that maps the package name to the channel it was first found in.
         let mut package_name_found_in_channel = HashMap::<String, &Option<String>>::new();
 
+        // The channels in order of priority, only used with flexible channel priority.
+        let mut channel_ranks = HashMap::<Option<String>, usize>::new();
+
         // Add additional records
         for repo_data in repodata {
             // Iterate over all records and dedup records that refer to the same package
@@ -328,9 +352,7 @@ impl<'a> CondaDependencyProvider<'a> {
 
             for record in repo_data.records {
                 // Determine if this record will be excluded.
-                let excluded = matches!((&exclude_newer, &record.package_record.timestamp),
-                    (Some(exclude_newer), Some(record_timestamp))
-                        if record_timestamp > exclude_newer);
+                let excluded = crate::is_newer_than_cutoff(record, exclude_newer.as_ref());
 
                 let (file_name, archive_type) = ArchiveType::split_str(&record.file_name)
                     .unwrap_or((&record.file_name, ArchiveType::TarBz2));
@@ -379,6 +401,13 @@ impl<'a> CondaDependencyProvider<'a> {
             }
 
             for record in ordered_repodata {
+                if channel_priority == ChannelPriority::Flexible {
+                    let next_rank = channel_ranks.len();
+                    channel_ranks
+                        .entry(record.channel.clone())
+                        .or_insert(next_rank);
+                }
+
                 let package_name = pool.intern_package_name(&record.package_record.name);
                 let solvable_id =
                     pool.intern_solvable(package_name, SolverPackageRecord::Record(record));
@@ -388,16 +417,13 @@ impl<'a> CondaDependencyProvider<'a> {
                 candidates.candidates.push(solvable_id);
 
                 // Filter out any records that are newer than a specific date.
-                match (&exclude_newer, &record.package_record.timestamp) {
-                    (Some(exclude_newer), Some(record_timestamp))
-                        if record_timestamp > exclude_newer =>
-                    {
+                if let Some(exclude_newer) = &exclude_newer {
+                    if crate::is_newer_than_cutoff(record, Some(exclude_newer)) {
                         let reason = pool.intern_string(format!(
                             "the package is uploaded after the cutoff date of {exclude_newer}"
                         ));
                         candidates.excluded.push((solvable_id, reason));
                     }
-                    _ => {}
                 }
 
                 // Add to excluded when package is not in the specified channel.
@@ -475,12 +501,14 @@ impl<'a> CondaDependencyProvider<'a> {
         }
 
         // Add favored packages to the records
+        let mut favored_versions = HashMap::new();
         for favored_record in favored_records {
             let name = pool.intern_package_name(&favored_record.package_record.name);
             let solvable = pool.intern_solvable(name, SolverPackageRecord::Record(favored_record));
             let candidates = records.entry(name).or_default();
             candidates.candidates.push(solvable);
             candidates.favored = Some(solvable);
+            favored_versions.insert(name, favored_record.package_record.version.version());
         }
 
         for locked_record in locked_records {
@@ -503,11 +531,65 @@ impl<'a> CondaDependencyProvider<'a> {
             matchspec_to_highest_version: RefCell::default(),
             parse_match_spec_cache: RefCell::default(),
             stop_time,
+            cancellation_token: None,
             strategy,
+            track_features_policy: TrackFeaturesPolicy::default(),
+            reporter: None,
+            considered_packages: RefCell::default(),
             direct_dependencies,
+            favored_versions,
+            channel_ranks,
         })
     }
 
+    /// Sets a token that aborts the solve once it has been cancelled.
+    pub fn with_cancellation_token(self, cancellation_token: Option<CancellationToken>) -> Self {
+        Self {
+            cancellation_token,
+            ..self
+        }
+    }
+
+    /// Sets a reporter that is notified of the progress of the solve.
+    pub fn with_reporter(self, reporter: Option<Arc<dyn SolveReporter>>) -> Self {
+        Self { reporter, ..self }
+    }
+
+    /// Sets how packages with `track_features` are treated.
+    pub fn with_track_features_policy(mut self, policy: TrackFeaturesPolicy) -> Self {
+        if policy == TrackFeaturesPolicy::Exclude {
+            let reason = self.pool.intern_string("the package has track features");
+            for candidates in self.records.values_mut() {
+                for &solvable_id in &candidates.candidates {
+                    let record = &self.pool.resolve_solvable(solvable_id).record;
+                    if !record.track_features().is_empty() {
+                        candidates.excluded.push((solvable_id, reason));
+                    }
+                }
+            }
+        }
+        Self {
+            track_features_policy: policy,
+            ..self
+        }
+    }
+
+    /// Returns true if the record should be sorted below records without
+    /// track features.
+    fn is_deprioritized(&self, record: &SolverPackageRecord<'_>) -> bool {
+        self.track_features_policy == TrackFeaturesPolicy::Deprioritize
+            && !record.track_features().is_empty()
+    }
+
+    /// Returns the rank of the channel of a record if flexible channel
+    /// priority is used. Records from channels with a lower rank are preferred.
+    fn channel_rank(&self, record: &SolverPackageRecord<'_>) -> Option<usize> {
+        match record {
+            SolverPackageRecord::Record(rec) => self.channel_ranks.get(&rec.channel).copied(),
+            SolverPackageRecord::VirtualPackage(_) | SolverPackageRecord::Extra { .. } => None,
+        }
+    }
+
     /// Returns all package names
     pub fn package_names(&self) -> impl Iterator<Item = NameId> + use<'_, 'a> {
         self.records.keys().copied()
@@ -527,10 +609,45 @@ impl<'a> CondaDependencyProvider<'a> {
     }
 }
 
+/// The number of packages that is listed when explaining how far a cancelled
+/// solve got.
+const MAX_REPORTED_PACKAGES: usize = 10;
+
+impl CondaDependencyProvider<'_> {
+    /// Explains how far the solver got before it was cancelled for the given
+    /// reason.
+    fn cancellation_explanations(&self, reason: Option<&CancelReason>) -> Vec<String> {
+        let mut explanations = vec![match reason {
+            Some(CancelReason::Timeout) => {
+                "the solver did not finish before the timeout was reached".to_string()
+            }
+            Some(CancelReason::Cancelled) | None => "the solver was cancelled".to_string(),
+        }];
+
+        let considered = self.considered_packages.borrow();
+        if let Some(start) = considered.len().checked_sub(1) {
+            let start = start.saturating_sub(MAX_REPORTED_PACKAGES - 1);
+            let recent = considered[start..]
+                .iter()
+                .map(|&name| self.pool.resolve_package_name(name).to_string())
+                .join(", ");
+            explanations.push(format!(
+                "the solver considered {} package(s) so far, most recently: {recent}",
+                considered.len()
+            ));
+        }
+
+        explanations
+    }
+}
+
 /// The reason why the solver was cancelled
 pub enum CancelReason {
     /// The solver was cancelled because the timeout was reached
     Timeout,
+
+    /// The solver was cancelled through its [`CancellationToken`]
+    Cancelled,
 }
 
 impl Interner for CondaDependencyProvider<'_> {
@@ -611,6 +728,19 @@ impl DependencyProvider for CondaDependencyProvider<'_> {
                     (CompareStrategy::Default, CompareStrategy::Default)
                 }
             }
+            SolveStrategy::MinimalUpdate => {
+                match self
+                    .favored_versions
+                    .get(&self.pool.resolve_solvable(solvables[0]).name)
+                    .copied()
+                {
+                    Some(version) => (
+                        CompareStrategy::ClosestTo(version),
+                        CompareStrategy::Default,
+                    ),
+                    None => (CompareStrategy::Default, CompareStrategy::Default),
+                }
+            }
         };
 
         // Custom sorter that sorts by name, version, and build
@@ -622,7 +752,15 @@ impl DependencyProvider for CondaDependencyProvider<'_> {
 
     async fn get_candidates(&self, name: NameId) -> Option<Candidates> {
         match self.pool.resolve_package_name(name) {
-            NameType::Base(_) => self.records.get(&name).cloned(),
+            NameType::Base(package) => {
+                self.considered_packages.borrow_mut().push(name);
+                let candidates = self.records.get(&name).cloned();
+                if let Some(reporter) = &self.reporter {
+                    let count = candidates.as_ref().map_or(0, |c| c.candidates.len());
+                    reporter.on_candidates_collected(package, count);
+                }
+                candidates
+            }
             NameType::Extra { package, extra } => {
                 // For extras, we need to create a new candidates object
                 // that contains only the extra solvable.
@@ -779,6 +917,13 @@ impl DependencyProvider for CondaDependencyProvider<'_> {
     }
 
     fn should_cancel_with_value(&self) -> Option<Box<dyn std::any::Any>> {
+        if self
+            .cancellation_token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Some(Box::new(CancelReason::Cancelled));
+        }
         if let Some(stop_time) = self.stop_time {
             if std::time::SystemTime::now() > stop_time {
                 return Some(Box::new(CancelReason::Timeout));
@@ -804,10 +949,26 @@ impl super::SolverImpl for Solver {
         &mut self,
         task: SolverTask<TAvailablePackagesIterator>,
     ) -> Result<SolverResult, SolveError> {
+        if task
+            .specs
+            .iter()
+            .chain(&task.constraints)
+            .any(|spec| spec.name.is_none())
+        {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "matchspecs without a name".to_string(),
+            ]));
+        }
+
         let stop_time = task
             .timeout
             .map(|timeout| std::time::SystemTime::now() + timeout);
 
+        let reporter = task.reporter;
+        if let Some(reporter) = &reporter {
+            reporter.on_phase_start(SolvePhase::LoadingPackages);
+        }
+
         // Construct a provider that can serve the data.
         let provider = CondaDependencyProvider::new(
             task.available_packages.into_iter().map(|r| r.into()),
@@ -819,76 +980,211 @@ impl super::SolverImpl for Solver {
             task.channel_priority,
             task.exclude_newer,
             task.strategy,
-        )?;
-
-        // Construct the requirements that the solver needs to satisfy.
-        let virtual_package_requirements = task.virtual_packages.iter().map(|spec| {
-            let name_id = provider.pool.intern_package_name(&spec.name);
-            provider
-                .pool
-                .intern_version_set(name_id, NamelessMatchSpec::default().into())
-        });
-
-        let root_requirements = task
-            .specs
-            .into_iter()
-            .flat_map(|spec| version_sets_for_match_spec(&provider.pool, spec));
+        );
 
-        let all_requirements: Vec<_> = virtual_package_requirements
-            .chain(root_requirements)
-            .map(ConditionalRequirement::from)
-            .collect();
+        if let Some(reporter) = &reporter {
+            reporter.on_phase_complete(SolvePhase::LoadingPackages);
+        }
 
-        let root_constraints = task
-            .constraints
-            .iter()
-            .map(|spec| {
-                let (Some(name), spec) = spec.clone().into_nameless() else {
-                    unimplemented!("matchspecs without a name are not supported");
-                };
-                let name_id = provider.pool.intern_package_name(&name);
-                provider.pool.intern_version_set(name_id, spec.into())
-            })
-            .collect();
+        let provider = provider?
+            .with_cancellation_token(task.cancellation_token)
+            .with_track_features_policy(task.track_features_policy)
+            .with_reporter(reporter.clone());
 
-        let problem = Problem::new()
-            .requirements(all_requirements.clone())
-            .constraints(root_constraints);
+        if let Some(reporter) = &reporter {
+            reporter.on_phase_start(SolvePhase::Resolving);
+        }
 
         // Construct a solver and solve the problems in the queue
         let mut solver = LibSolvRsSolver::new(provider);
-        let solvables = solver.solve(problem).map_err(|unsolvable_or_cancelled| {
-            match unsolvable_or_cancelled {
-                UnsolvableOrCancelled::Unsolvable(problem) => {
-                    SolveError::Unsolvable(vec![problem.display_user_friendly(&solver).to_string()])
-                }
-                // We are not doing this as of yet
-                // put a generic message in here for now
-                UnsolvableOrCancelled::Cancelled(_) => SolveError::Cancelled,
-            }
-        })?;
+        let result = solve_problem(
+            &mut solver,
+            &task.virtual_packages,
+            task.specs,
+            &task.constraints,
+        );
+
+        if let Some(reporter) = &reporter {
+            reporter.on_phase_complete(SolvePhase::Resolving);
+        }
+
+        result
+    }
+}
+
+/// A solver that keeps its state alive between solves that share the same
+/// available packages.
+///
+/// Constructing the solver interns all available packages. Every call to
+/// [`IncrementalSolver::solve`] reuses those, together with the candidates and
+/// dependencies that previous solves have already sorted and parsed. This
+/// makes it cheap to repeatedly solve slightly different specs, e.g. in an
+/// interactive tool. Learned clauses are not reused because they depend on
+/// the specs that are solved for.
+pub struct IncrementalSolver<'a> {
+    solver: LibSolvRsSolver<CondaDependencyProvider<'a>>,
+    virtual_packages: &'a [GenericVirtualPackage],
+}
+
+impl<'a> IncrementalSolver<'a> {
+    /// Constructs a new solver from the packages that are shared by all
+    /// solves. These have the same meaning as the fields of a
+    /// [`SolverTask`].
+    ///
+    /// [`SolveStrategy::LowestVersionDirect`] is not supported because it
+    /// depends on the specs of every individual solve.
+    pub fn new<R: IntoRepoData<'a, RepoData<'a>>>(
+        available_packages: impl IntoIterator<Item = R>,
+        locked_packages: &'a [RepoDataRecord],
+        pinned_packages: &'a [RepoDataRecord],
+        virtual_packages: &'a [GenericVirtualPackage],
+        channel_priority: ChannelPriority,
+        exclude_newer: Option<DateTime<Utc>>,
+        strategy: SolveStrategy,
+    ) -> Result<Self, SolveError> {
+        if strategy == SolveStrategy::LowestVersionDirect {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "strategy".to_string()
+            ]));
+        }
+
+        let provider = CondaDependencyProvider::new(
+            available_packages.into_iter().map(IntoRepoData::into),
+            locked_packages,
+            pinned_packages,
+            virtual_packages,
+            &[],
+            None,
+            channel_priority,
+            exclude_newer,
+            strategy,
+        )?;
+
+        Ok(Self {
+            solver: LibSolvRsSolver::new(provider),
+            virtual_packages,
+        })
+    }
 
-        // Get the resulting packages from the solver.
-        let mut extras: HashMap<PackageName, Vec<String>> = HashMap::new();
-        let mut records = Vec::new();
+    /// Solves the given specs and constraints, reusing the state of previous
+    /// solves.
+    ///
+    /// Specs that select a specific channel are not supported because the
+    /// candidates of a package are filtered when the solver is constructed.
+    pub fn solve(
+        &mut self,
+        specs: Vec<MatchSpec>,
+        constraints: &[MatchSpec],
+    ) -> Result<SolverResult, SolveError> {
+        if specs
+            .iter()
+            .chain(constraints)
+            .any(|spec| spec.name.is_none())
+        {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "matchspecs without a name".to_string(),
+            ]));
+        }
+
+        if specs.iter().any(|spec| spec.channel.is_some()) {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "channel-specific matchspecs".to_string(),
+            ]));
+        }
 
-        for id in solvables {
-            match &solver.provider().pool.resolve_solvable(id).record {
-                SolverPackageRecord::Record(rec) => {
-                    records.push((*rec).clone());
+        solve_problem(&mut self.solver, self.virtual_packages, specs, constraints)
+    }
+}
+
+/// Solves the specs and constraints with the given solver and returns the
+/// selected records.
+fn solve_problem(
+    solver: &mut LibSolvRsSolver<CondaDependencyProvider<'_>>,
+    virtual_packages: &[GenericVirtualPackage],
+    specs: Vec<MatchSpec>,
+    constraints: &[MatchSpec],
+) -> Result<SolverResult, SolveError> {
+    let provider = solver.provider();
+
+    // Construct the requirements that the solver needs to satisfy.
+    let virtual_package_requirements = virtual_packages.iter().map(|spec| {
+        let name_id = provider.pool.intern_package_name(&spec.name);
+        provider
+            .pool
+            .intern_version_set(name_id, NamelessMatchSpec::default().into())
+    });
+
+    let root_requirements = specs
+        .into_iter()
+        .flat_map(|spec| version_sets_for_match_spec(&provider.pool, spec));
+
+    let all_requirements: Vec<_> = virtual_package_requirements
+        .chain(root_requirements)
+        .map(ConditionalRequirement::from)
+        .collect();
+
+    let root_constraints = constraints
+        .iter()
+        .map(|spec| {
+            let (Some(name), spec) = spec.clone().into_nameless() else {
+                unreachable!("matchspecs without a name are rejected before solving");
+            };
+            let name_id = provider.pool.intern_package_name(&name);
+            provider.pool.intern_version_set(name_id, spec.into())
+        })
+        .collect();
+
+    let problem = Problem::new()
+        .requirements(all_requirements)
+        .constraints(root_constraints);
+
+    let solvables = solver.solve(problem).map_err(|unsolvable_or_cancelled| {
+        let reporter = solver.provider().reporter.as_deref();
+        match unsolvable_or_cancelled {
+            UnsolvableOrCancelled::Unsolvable(problem) => {
+                if let Some(reporter) = reporter {
+                    reporter.on_unsolvable(&conflict::conflict_tree(
+                        &problem.graph(solver),
+                        solver.provider(),
+                    ));
                 }
-                SolverPackageRecord::Extra { package, extra } => {
-                    extras
-                        .entry(package.clone())
-                        .or_default()
-                        .push(extra.clone());
+                SolveError::Unsolvable(vec![problem.display_user_friendly(solver).to_string()])
+            }
+            UnsolvableOrCancelled::Cancelled(reason) => {
+                if let Some(reporter) = reporter {
+                    reporter.on_cancelled(
+                        &solver
+                            .provider()
+                            .cancellation_explanations(reason.downcast_ref::<CancelReason>()),
+                    );
                 }
-                SolverPackageRecord::VirtualPackage(_) => {}
+                SolveError::Cancelled
             }
         }
+    })?;
+
+    // Get the resulting packages from the solver.
+    let mut extras: HashMap<PackageName, Vec<String>> = HashMap::new();
+    let mut records = Vec::new();
 
-        Ok(SolverResult { records, extras })
+    for id in solvables {
+        match &solver.provider().pool.resolve_solvable(id).record {
+            SolverPackageRecord::Record(rec) => {
+                records.push((*rec).clone());
+            }
+            SolverPackageRecord::Extra { package, extra } => {
+                extras
+                    .entry(package.clone())
+                    .or_default()
+                    .push(extra.clone());
+            }
+            SolverPackageRecord::VirtualPackage(_) => {}
+        }
     }
+
+    let mut result = SolverResult { records, extras };
+    crate::sort_solver_result(&mut result);
+    Ok(result)
 }
 
 fn parse_match_spec(
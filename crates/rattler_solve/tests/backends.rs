@@ -280,6 +280,59 @@ macro_rules! solver_backend_tests {
             assert_eq!(result.records[0].package_record.to_string(), "bors=1.0=bla_1");
         }
 
+        #[test]
+        fn test_solve_favored_incompatible() {
+            // A locked record is replaced if it does not satisfy the specs.
+            let result = solve::<$T>(
+                &[dummy_channel_json_path()],
+                SimpleSolveTask {
+                    specs: &["bors >=2"],
+                    installed_packages: vec![installed_package(
+                        "conda-forge",
+                        "linux-64",
+                        "bors",
+                        "1.0",
+                        "bla_1",
+                        1,
+                    )],
+                    ..SimpleSolveTask::default()
+                },
+            )
+            .unwrap();
+
+            assert_eq!(result.records.len(), 1);
+            assert_eq!(result.records[0].package_record.to_string(), "bors=2.1=bla_1");
+        }
+
+        #[test]
+        fn test_solve_pinned_spec() {
+            // A pin restricts the version that is selected without fixing a
+            // specific record.
+            let result = solve::<$T>(
+                &[dummy_channel_json_path()],
+                SimpleSolveTask {
+                    specs: &["bors"],
+                    constraints: vec!["bors 1.*"],
+                    ..SimpleSolveTask::default()
+                },
+            )
+            .unwrap();
+
+            assert_eq!(result.records.len(), 1);
+            assert_eq!(result.records[0].package_record.to_string(), "bors=1.2.1=bla_1");
+
+            // The solve fails if the pin conflicts with the specs.
+            let result = solve::<$T>(
+                &[dummy_channel_json_path()],
+                SimpleSolveTask {
+                    specs: &["bors >=2"],
+                    constraints: vec!["bors 1.*"],
+                    ..SimpleSolveTask::default()
+                },
+            );
+            assert!(result.is_err());
+        }
+
         #[test]
         fn test_solve_with_error() {
             let result = solve::<$T>(
@@ -542,6 +595,35 @@ macro_rules! solver_backend_tests {
             assert_eq!(&info.file_name, "foo-3.0.2-py36h1af98f8_1.tar.bz2", "even though there is a conda version available we expect the tar.bz2 version because we exclude the .conda version based on the timestamp");
         }
 
+        #[test]
+        fn test_exclude_newer_without_timestamp() {
+            use rattler_solve::SolverImpl;
+
+            let date = "2021-12-12T12:12:12Z".parse::<DateTime<Utc>>().unwrap();
+
+            // Records without a timestamp are assumed to predate the cutoff.
+            let mut records = super::read_repodata(&dummy_channel_json_path());
+            for record in &mut records {
+                if record.file_name == "foo-4.0.2-py36h1af98f8_2.tar.bz2" {
+                    record.package_record.timestamp = None;
+                }
+            }
+
+            let task = rattler_solve::SolverTask {
+                specs: vec![rattler_conda_types::MatchSpec::from_str(
+                    "foo",
+                    rattler_conda_types::ParseStrictness::Lenient,
+                )
+                .unwrap()],
+                exclude_newer: Some(date),
+                ..rattler_solve::SolverTask::from_iter([&records])
+            };
+
+            let pkgs = <$T>::default().solve(task).unwrap();
+            assert_eq!(1, pkgs.records.len());
+            assert_eq!(&pkgs.records[0].file_name, "foo-4.0.2-py36h1af98f8_2.tar.bz2");
+        }
+
         #[test]
         fn test_duplicate_record() {
             use rattler_solve::SolverImpl;
@@ -558,6 +640,59 @@ macro_rules! solver_backend_tests {
             }
         }
 
+        #[test]
+        fn test_intersect_specs_for_same_package() {
+            let result = solve::<$T>(
+                &[dummy_channel_json_path()],
+                SimpleSolveTask {
+                    specs: &["bors >=1.1", "bors <2"],
+                    ..SimpleSolveTask::default()
+                },
+            )
+            .unwrap();
+
+            assert_eq!(result.records.len(), 1);
+            assert_eq!(result.records[0].file_name, "bors-1.2.1-bla_1.tar.bz2");
+
+            // Specs that cannot be satisfied together make the solve fail.
+            let result = solve::<$T>(
+                &[dummy_channel_json_path()],
+                SimpleSolveTask {
+                    specs: &["bors >=2", "bors <2"],
+                    ..SimpleSolveTask::default()
+                },
+            );
+            assert!(matches!(result, Err(SolveError::Unsolvable(_))));
+        }
+
+        #[test]
+        fn test_deterministic_output() {
+            let solve_task = || {
+                solve::<$T>(
+                    &[dummy_channel_json_path()],
+                    SimpleSolveTask {
+                        specs: &["foobar", "bors", "foo"],
+                        ..SimpleSolveTask::default()
+                    },
+                )
+                .unwrap()
+            };
+
+            let first = solve_task();
+            let names: Vec<_> = first
+                .records
+                .iter()
+                .map(|record| record.package_record.name.as_normalized())
+                .collect();
+            let mut sorted_names = names.clone();
+            sorted_names.sort_unstable();
+            assert_eq!(names, sorted_names);
+
+            for _ in 0..5 {
+                assert_eq!(solve_task(), first);
+            }
+        }
+
         #[test]
         fn test_constraints() {
             // There following package is provided as .tar.bz and as .conda in repodata.json
@@ -621,7 +756,7 @@ macro_rules! solver_backend_tests {
 mod libsolv_c {
     #![allow(unused_imports)] // For some reason windows thinks this is an unused import.
 
-    use rattler_solve::{ChannelPriority, SolveStrategy};
+    use rattler_solve::{ChannelPriority, SolveStrategy, TrackFeaturesPolicy};
 
     use super::{
         dummy_channel_json_path, installed_package, solve, solve_real_world, FromStr,
@@ -669,9 +804,12 @@ mod libsolv_c {
                 constraints: Vec::new(),
                 pinned_packages: Vec::new(),
                 timeout: None,
+                cancellation_token: None,
                 channel_priority: ChannelPriority::default(),
                 exclude_newer: None,
                 strategy: SolveStrategy::default(),
+                track_features_policy: TrackFeaturesPolicy::default(),
+                reporter: None,
             })
             .unwrap()
             .records;
@@ -719,9 +857,11 @@ mod libsolv_c {
 #[cfg(feature = "resolvo")]
 mod resolvo {
     use rattler_conda_types::{
-        MatchSpec, PackageRecord, ParseStrictness, RepoDataRecord, VersionWithSource,
+        MatchSpec, PackageRecord, ParseStrictness, Platform, RepoDataRecord, VersionWithSource,
+    };
+    use rattler_solve::{
+        ConflictKind, ConflictNode, SolveStrategy, SolverImpl, SolverTask, TrackFeaturesPolicy,
     };
-    use rattler_solve::{SolveStrategy, SolverImpl, SolverTask};
     use url::Url;
 
     #[cfg(feature = "experimental_extras")]
@@ -755,6 +895,338 @@ mod resolvo {
         insta::assert_snapshot!(result.unwrap_err());
     }
 
+    #[test]
+    fn test_solve_locked_reports_conflicts() {
+        #[derive(Default)]
+        struct Conflicts(std::sync::Mutex<Vec<ConflictNode>>);
+
+        impl rattler_solve::SolveReporter for Conflicts {
+            fn on_unsolvable(&self, conflicts: &[ConflictNode]) {
+                self.0.lock().unwrap().extend_from_slice(conflicts);
+            }
+        }
+
+        let records = super::read_repodata(&dummy_channel_json_path());
+        let reporter = std::sync::Arc::new(Conflicts::default());
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("bors >=2", ParseStrictness::Lenient).unwrap()],
+            pinned_packages: vec![installed_package(
+                "conda-forge",
+                "linux-64",
+                "bors",
+                "1.0",
+                "bla_1",
+                1,
+            )],
+            reporter: Some(reporter.clone()),
+            ..SolverTask::from_iter([&records])
+        };
+        let result = rattler_solve::resolvo::Solver.solve(task);
+        assert!(matches!(result, Err(SolveError::Unsolvable(_))));
+
+        // The conflict can also be inspected structurally.
+        let conflicts = reporter.0.lock().unwrap();
+        let requested = conflicts
+            .iter()
+            .find(|node| {
+                node.spec()
+                    .is_some_and(|spec| spec.to_string() == "bors >=2")
+            })
+            .expect("the requested spec is part of the conflict");
+        assert!(!requested.children.is_empty());
+        assert!(requested.children.iter().all(|node| {
+            node.package()
+                .is_some_and(|package| package.name.as_normalized() == "bors")
+        }));
+        assert!(conflicts
+            .iter()
+            .flat_map(ConflictNode::iter)
+            .any(|node| matches!(
+                &node.kind,
+                ConflictKind::Locked(package)
+                    if package.version.as_ref().is_some_and(|v| v.to_string() == "1.0")
+            )));
+    }
+
+    #[test]
+    fn test_constraint_without_name() {
+        let records = super::read_repodata(&dummy_channel_json_path());
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foo", ParseStrictness::Lenient).unwrap()],
+            constraints: vec![MatchSpec::from_nameless(
+                rattler_conda_types::NamelessMatchSpec::default(),
+                None,
+            )],
+            ..SolverTask::from_iter([&records])
+        };
+
+        let result = rattler_solve::resolvo::Solver.solve(task);
+        assert!(matches!(
+            result,
+            Err(rattler_solve::SolveError::UnsupportedOperations(_))
+        ));
+    }
+
+    #[test]
+    fn test_solve_for_platforms() {
+        let records = super::read_repodata(&dummy_channel_json_path());
+        let tasks = [Platform::Linux64, Platform::Osx64].map(|platform| {
+            let task = SolverTask {
+                specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+                ..SolverTask::from_iter([&records])
+            };
+            (platform, task)
+        });
+
+        let results =
+            rattler_solve::solve_for_platforms::<rattler_solve::resolvo::Solver, _, _>(tasks);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, Platform::Linux64);
+        assert_eq!(results[1].0, Platform::Osx64);
+        for (_, result) in results {
+            let records = result.unwrap().records;
+            assert!(records
+                .iter()
+                .any(|record| record.package_record.name.as_normalized() == "foobar"));
+        }
+    }
+
+    #[test]
+    fn test_incremental_solve() {
+        let records = super::read_repodata(&dummy_channel_json_path());
+        let mut solver = rattler_solve::resolvo::IncrementalSolver::new(
+            [&records],
+            &[],
+            &[],
+            &[],
+            rattler_solve::ChannelPriority::default(),
+            None,
+            SolveStrategy::default(),
+        )
+        .unwrap();
+
+        for specs in [&["foobar"][..], &["foobar 2.0.*"], &["foobar", "bors"]] {
+            let specs: Vec<MatchSpec> = specs
+                .iter()
+                .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Lenient).unwrap())
+                .collect();
+
+            let incremental = solver.solve(specs.clone(), &[]).unwrap();
+            let fresh = rattler_solve::resolvo::Solver
+                .solve(SolverTask {
+                    specs,
+                    ..SolverTask::from_iter([&records])
+                })
+                .unwrap();
+            let file_names = |records: Vec<RepoDataRecord>| {
+                let mut file_names: Vec<_> =
+                    records.into_iter().map(|record| record.file_name).collect();
+                file_names.sort();
+                file_names
+            };
+            assert_eq!(file_names(incremental.records), file_names(fresh.records));
+        }
+    }
+
+    #[test]
+    fn test_track_features_policy() {
+        let mut plain = installed_package("conda-forge", "linux-64", "blas", "1.0", "openblas", 0);
+        plain.file_name = "blas-1.0-openblas.tar.bz2".to_string();
+        let mut tracked = installed_package("conda-forge", "linux-64", "blas", "2.0", "mkl", 0);
+        tracked.file_name = "blas-2.0-mkl.tar.bz2".to_string();
+        tracked.package_record.track_features = vec!["mkl".to_string()];
+        let records = vec![plain, tracked];
+
+        let solve = |spec: &str, track_features_policy| {
+            rattler_solve::resolvo::Solver.solve(SolverTask {
+                specs: vec![MatchSpec::from_str(spec, ParseStrictness::Lenient).unwrap()],
+                track_features_policy,
+                ..SolverTask::from_iter([&records])
+            })
+        };
+        let solved_build = |spec: &str, track_features_policy| {
+            solve(spec, track_features_policy).unwrap().records[0]
+                .package_record
+                .build
+                .clone()
+        };
+
+        assert_eq!(
+            solved_build("blas", TrackFeaturesPolicy::Deprioritize),
+            "openblas"
+        );
+        assert_eq!(
+            solved_build("blas >=2", TrackFeaturesPolicy::Deprioritize),
+            "mkl"
+        );
+        assert_eq!(solved_build("blas", TrackFeaturesPolicy::Ignore), "mkl");
+        assert_eq!(
+            solved_build("blas", TrackFeaturesPolicy::Exclude),
+            "openblas"
+        );
+        assert!(matches!(
+            solve("blas >=2", TrackFeaturesPolicy::Exclude),
+            Err(SolveError::Unsolvable(_))
+        ));
+    }
+
+    #[test]
+    fn test_solve_reporter() {
+        #[derive(Default)]
+        struct Events(std::sync::Mutex<Vec<String>>);
+
+        impl rattler_solve::SolveReporter for Events {
+            fn on_phase_start(&self, phase: rattler_solve::SolvePhase) {
+                self.0.lock().unwrap().push(format!("start {phase:?}"));
+            }
+
+            fn on_phase_complete(&self, phase: rattler_solve::SolvePhase) {
+                self.0.lock().unwrap().push(format!("complete {phase:?}"));
+            }
+
+            fn on_candidates_collected(&self, package: &str, count: usize) {
+                self.0.lock().unwrap().push(format!("{package}: {count}"));
+            }
+        }
+
+        let records = super::read_repodata(&dummy_channel_json_path());
+        let events = std::sync::Arc::new(Events::default());
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+            reporter: Some(events.clone()),
+            ..SolverTask::from_iter([&records])
+        };
+        rattler_solve::resolvo::Solver.solve(task).unwrap();
+
+        let events = events.0.lock().unwrap();
+        assert_eq!(events.first().unwrap(), "start LoadingPackages");
+        assert_eq!(events[1], "complete LoadingPackages");
+        assert_eq!(events[2], "start Resolving");
+        assert_eq!(events.last().unwrap(), "complete Resolving");
+        assert!(events.contains(&"foobar: 2".to_string()));
+        assert!(events.contains(&"bors: 5".to_string()));
+    }
+
+    #[test]
+    fn test_cancelled_solve() {
+        let records = super::read_repodata(&dummy_channel_json_path());
+        let cancellation_token = rattler_solve::CancellationToken::new();
+        cancellation_token.clone().cancel();
+        assert!(cancellation_token.is_cancelled());
+
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+            cancellation_token: Some(cancellation_token),
+            ..SolverTask::from_iter([&records])
+        };
+
+        let result = rattler_solve::resolvo::Solver.solve(task);
+        assert!(matches!(result, Err(SolveError::Cancelled)));
+    }
+
+    #[test]
+    fn test_cancelled_solve_explains_progress() {
+        // Cancels the solve as soon as the candidates of `foobar` are known.
+        struct CancelOnCandidates(
+            rattler_solve::CancellationToken,
+            std::sync::Mutex<Vec<String>>,
+        );
+
+        impl rattler_solve::SolveReporter for CancelOnCandidates {
+            fn on_candidates_collected(&self, package: &str, _count: usize) {
+                if package == "foobar" {
+                    self.0.cancel();
+                }
+            }
+
+            fn on_cancelled(&self, explanations: &[String]) {
+                self.1.lock().unwrap().extend_from_slice(explanations);
+            }
+        }
+
+        let records = super::read_repodata(&dummy_channel_json_path());
+        let cancellation_token = rattler_solve::CancellationToken::new();
+        let reporter = std::sync::Arc::new(CancelOnCandidates(
+            cancellation_token.clone(),
+            std::sync::Mutex::default(),
+        ));
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+            cancellation_token: Some(cancellation_token),
+            reporter: Some(reporter.clone()),
+            ..SolverTask::from_iter([&records])
+        };
+
+        let result = rattler_solve::resolvo::Solver.solve(task);
+        assert!(matches!(result, Err(SolveError::Cancelled)));
+
+        let explanations = reporter.1.lock().unwrap();
+        assert_eq!(explanations[0], "the solver was cancelled");
+        assert!(explanations[1].contains("foobar"), "{explanations:?}");
+    }
+
+    #[test]
+    fn test_minimal_update_strategy() {
+        let installed = || {
+            vec![installed_package(
+                "conda-forge",
+                "linux-64",
+                "bors",
+                "1.0",
+                "bla_1",
+                1,
+            )]
+        };
+
+        // The installed version no longer satisfies the spec, the smallest
+        // possible upgrade is selected.
+        let result = solve::<rattler_solve::resolvo::Solver>(
+            &[dummy_channel_json_path()],
+            SimpleSolveTask {
+                specs: &["bors >1.0"],
+                installed_packages: installed(),
+                strategy: SolveStrategy::MinimalUpdate,
+                ..SimpleSolveTask::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(result.records.len(), 1);
+        assert_eq!(
+            result.records[0].package_record.to_string(),
+            "bors=1.1=bla_1"
+        );
+
+        // Without the strategy the highest version is selected.
+        let result = solve::<rattler_solve::resolvo::Solver>(
+            &[dummy_channel_json_path()],
+            SimpleSolveTask {
+                specs: &["bors >1.0"],
+                installed_packages: installed(),
+                ..SimpleSolveTask::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            result.records[0].package_record.to_string(),
+            "bors=2.1=bla_1"
+        );
+
+        // Packages that are not installed resolve to the highest version.
+        let result = solve::<rattler_solve::resolvo::Solver>(
+            &[dummy_channel_json_path()],
+            SimpleSolveTask {
+                specs: &["foobar"],
+                strategy: SolveStrategy::MinimalUpdate,
+                ..SimpleSolveTask::default()
+            },
+        )
+        .unwrap();
+        assert!(result
+            .records
+            .iter()
+            .any(|record| record.package_record.to_string() == "foobar=2.1=bla_1"));
+    }
+
     #[test]
     fn test_issue_717() {
         let result = solve::<rattler_solve::resolvo::Solver>(
@@ -821,23 +1293,24 @@ mod resolvo {
         )
         .unwrap();
 
+        // The records are sorted by name.
         assert_eq!(result.records.len(), 2);
         assert_eq!(
-            result.records[0].package_record.name.as_normalized(),
+            result.records[1].package_record.name.as_normalized(),
             "foobar"
         );
         assert_eq!(
-            result.records[0].package_record.version,
+            result.records[1].package_record.version,
             Version::from_str("2.0").unwrap(),
             "expected lowest version of foobar"
         );
 
         assert_eq!(
-            result.records[1].package_record.name.as_normalized(),
+            result.records[0].package_record.name.as_normalized(),
             "bors"
         );
         assert_eq!(
-            result.records[1].package_record.version,
+            result.records[0].package_record.version,
             Version::from_str("1.0").unwrap(),
             "expected lowest version of bors"
         );
@@ -855,23 +1328,24 @@ mod resolvo {
         )
         .unwrap();
 
+        // The records are sorted by name.
         assert_eq!(result.records.len(), 2);
         assert_eq!(
-            result.records[0].package_record.name.as_normalized(),
+            result.records[1].package_record.name.as_normalized(),
             "foobar"
         );
         assert_eq!(
-            result.records[0].package_record.version,
+            result.records[1].package_record.version,
             Version::from_str("2.0").unwrap(),
             "expected lowest version of foobar"
         );
 
         assert_eq!(
-            result.records[1].package_record.name.as_normalized(),
+            result.records[0].package_record.name.as_normalized(),
             "bors"
         );
         assert_eq!(
-            result.records[1].package_record.version,
+            result.records[0].package_record.version,
             Version::from_str("1.2.1").unwrap(),
             "expected highest compatible version of bors"
         );
@@ -1409,6 +1883,42 @@ fn channel_priority_disabled() {
     );
 }
 
+#[test]
+fn channel_priority_flexible() {
+    let repodata = vec![
+        read_conda_forge_sparse_repo_data(),
+        read_pytorch_sparse_repo_data(),
+    ];
+
+    // Packages from the channel with the highest priority are preferred...
+    solve_to_get_channel_of_spec::<rattler_solve::resolvo::Solver>(
+        "pytorch-cpu",
+        "https://conda.anaconda.org/conda-forge/",
+        repodata.clone(),
+        ChannelPriority::Flexible,
+    );
+
+    // ...but unlike strict channel priority, lower priority channels are used if
+    // that is required to find a solution.
+    solve_to_get_channel_of_spec::<rattler_solve::resolvo::Solver>(
+        "pytorch-cpu=0.4.1=py36_cpu_1",
+        "https://conda.anaconda.org/pytorch/",
+        repodata,
+        ChannelPriority::Flexible,
+    );
+
+    let repodata = vec![
+        read_pytorch_sparse_repo_data(),
+        read_conda_forge_sparse_repo_data(),
+    ];
+    solve_to_get_channel_of_spec::<rattler_solve::resolvo::Solver>(
+        "pytorch-cpu",
+        "https://conda.anaconda.org/pytorch/",
+        repodata,
+        ChannelPriority::Flexible,
+    );
+}
+
 #[cfg(feature = "libsolv_c")]
 #[test]
 #[should_panic(
@@ -1444,3 +1954,25 @@ fn channel_priority_disabled_libsolv_c() {
         ChannelPriority::Disabled,
     );
 }
+
+#[cfg(feature = "libsolv_c")]
+#[test]
+fn channel_priority_flexible_libsolv_c() {
+    let repodata = vec![
+        read_conda_forge_sparse_repo_data(),
+        read_pytorch_sparse_repo_data(),
+    ];
+
+    solve_to_get_channel_of_spec::<rattler_solve::libsolv_c::Solver>(
+        "pytorch-cpu",
+        "https://conda.anaconda.org/conda-forge/",
+        repodata.clone(),
+        ChannelPriority::Flexible,
+    );
+    solve_to_get_channel_of_spec::<rattler_solve::libsolv_c::Solver>(
+        "pytorch-cpu=0.4.1=py36_cpu_1",
+        "https://conda.anaconda.org/pytorch/",
+        repodata,
+        ChannelPriority::Flexible,
+    );
+}
@@ -94,6 +94,7 @@ fn test_ordering(#[case] spec: &str, #[case] solve_strategy: SolveStrategy) {
                 SolveStrategy::Highest => "highest",
                 SolveStrategy::LowestVersion => "lowest",
                 SolveStrategy::LowestVersionDirect => "lowest_direct",
+                SolveStrategy::MinimalUpdate => "minimal_update",
             }
         ),
         create_sorting_snapshot(spec, solve_strategy)
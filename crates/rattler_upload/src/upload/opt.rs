@@ -30,10 +30,15 @@ impl FromStr for ChannelPriorityWrapper {
             "strict" => Ok(ChannelPriorityWrapper {
                 value: ChannelPriority::Strict,
             }),
+            "flexible" => Ok(ChannelPriorityWrapper {
+                value: ChannelPriority::Flexible,
+            }),
             "disabled" => Ok(ChannelPriorityWrapper {
                 value: ChannelPriority::Disabled,
             }),
-            _ => Err("Channel priority must be either 'strict' or 'disabled'".to_string()),
+            _ => Err(
+                "Channel priority must be either 'strict', 'flexible' or 'disabled'".to_string(),
+            ),
         }
     }
 }
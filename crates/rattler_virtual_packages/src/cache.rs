@@ -0,0 +1,267 @@
+//! Caching of the virtual packages detected on the host system.
+//!
+//! Detecting virtual packages shells out to external commands and probes the
+//! hardware, so the results are memoized in-process. Use [`clear`] to
+//! invalidate them, e.g. after installing a new driver.
+//!
+//! The host detection results can also be persisted to disk with
+//! [`save_to_file`] and restored in another process with [`load_from_file`].
+//! Overrides are never stored in the cache, they are always applied on top of
+//! the (cached) host detection results.
+
+use std::{
+    io::Write,
+    path::Path,
+    sync::{PoisonError, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use rattler_conda_types::{Platform, Version};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cuda::{self, CudaDetection, CudaDetectionSource},
+    libc, linux, osx, win, Archspec, DetectVirtualPackageError,
+};
+
+/// A resettable in-process cache for a single detection result.
+pub(crate) struct DetectionCache<T> {
+    value: RwLock<Option<T>>,
+}
+
+impl<T: Clone> DetectionCache<T> {
+    /// Constructs a new empty cache.
+    pub(crate) const fn new() -> Self {
+        Self {
+            value: RwLock::new(None),
+        }
+    }
+
+    /// Returns the cached value or computes and stores it with `f`. Errors
+    /// are not cached.
+    pub(crate) fn get_or_try_init<E>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        if let Some(value) = self
+            .value
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            return Ok(value.clone());
+        }
+
+        let value = f()?;
+        *self.value.write().unwrap_or_else(PoisonError::into_inner) = Some(value.clone());
+        Ok(value)
+    }
+
+    /// Returns the cached value or computes and stores it with `f`.
+    pub(crate) fn get_or_init(&self, f: impl FnOnce() -> T) -> T {
+        self.get_or_try_init(|| Ok::<_, std::convert::Infallible>(f()))
+            .unwrap_or_else(|never| match never {})
+    }
+
+    /// Replaces the cached value.
+    pub(crate) fn set(&self, value: T) {
+        *self.value.write().unwrap_or_else(PoisonError::into_inner) = Some(value);
+    }
+
+    /// Removes the cached value so it is detected again on the next access.
+    pub(crate) fn clear(&self) {
+        *self.value.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Invalidates all in-process detection results. The next detection will
+/// probe the system again.
+pub fn clear() {
+    linux::DETECTED_LINUX_VERSION.clear();
+    osx::DETECTED_OSX_VERSION.clear();
+    win::DETECTED_WINDOWS_VERSION.clear();
+    libc::DETECTED_LIBC_VERSION.clear();
+    cuda::DETECTED_CUDA.clear();
+    crate::DETECTED_ARCHSPEC.clear();
+}
+
+/// An error that can occur when reading or writing the on-disk cache.
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    /// The cache file could not be read or written.
+    #[error("failed to access the virtual package cache")]
+    Io(#[from] std::io::Error),
+
+    /// The detection results could not be serialized.
+    #[error("failed to serialize the virtual package cache")]
+    Json(#[from] serde_json::Error),
+
+    /// Detecting the virtual packages of the host failed.
+    #[error(transparent)]
+    Detect(#[from] DetectVirtualPackageError),
+}
+
+/// The host detection results as stored on disk.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct CachedHostDetection {
+    /// The version of this crate that wrote the cache. Detection logic may
+    /// differ between versions.
+    crate_version: String,
+    platform: Platform,
+    linux: Option<Version>,
+    osx: Option<Version>,
+    windows: Option<Version>,
+    libc: Option<(String, Version)>,
+    cuda: Option<(Version, CudaDetectionSource)>,
+    archspec: Archspec,
+}
+
+/// Detects the virtual packages of the host (or takes them from the
+/// in-process cache) and writes them to `path`.
+///
+/// Nothing is written if the CUDA version could not be determined because of
+/// an error, so that the detection is retried by the next process.
+pub fn save_to_file(path: &Path) -> Result<(), CacheError> {
+    let cuda = match cuda::cuda_detection() {
+        CudaDetection::Detected { version, source } => Some((version, source)),
+        CudaDetection::NotFound => None,
+        CudaDetection::Failed(_) => {
+            tracing::debug!("not caching virtual packages because CUDA detection failed");
+            return Ok(());
+        }
+    };
+
+    let cached = CachedHostDetection {
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        platform: Platform::current(),
+        linux: linux::linux_version().map_err(DetectVirtualPackageError::from)?,
+        osx: osx::osx_version().map_err(DetectVirtualPackageError::from)?,
+        windows: win::windows_version(),
+        libc: libc::libc_family_and_version().map_err(DetectVirtualPackageError::from)?,
+        cuda,
+        archspec: Archspec::current(),
+    };
+
+    // Write to a temporary file first so that concurrent readers never observe
+    // a partially written cache.
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(parent)?;
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    file.write_all(&serde_json::to_vec(&cached)?)?;
+    file.persist(path).map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+/// Populates the in-process cache from a file written by [`save_to_file`].
+///
+/// Returns `false` without touching the in-process cache if the file does not
+/// exist, cannot be parsed, is older than `max_age`, or was written by a
+/// different version of this crate or for a different platform.
+pub fn load_from_file(path: &Path, max_age: Duration) -> Result<bool, CacheError> {
+    let Some(cached) = read_cache_file(path, max_age)? else {
+        return Ok(false);
+    };
+
+    linux::DETECTED_LINUX_VERSION.set(cached.linux);
+    osx::DETECTED_OSX_VERSION.set(cached.osx);
+    win::DETECTED_WINDOWS_VERSION.set(cached.windows);
+    libc::DETECTED_LIBC_VERSION.set(cached.libc);
+    cuda::DETECTED_CUDA.set(match cached.cuda {
+        Some((version, source)) => CudaDetection::Detected { version, source },
+        None => CudaDetection::NotFound,
+    });
+    crate::DETECTED_ARCHSPEC.set(cached.archspec);
+    Ok(true)
+}
+
+/// Reads the detection results from `path` if they are still valid.
+fn read_cache_file(
+    path: &Path,
+    max_age: Duration,
+) -> Result<Option<CachedHostDetection>, CacheError> {
+    let modified = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    if age > max_age {
+        return Ok(None);
+    }
+
+    let cached: CachedHostDetection = match serde_json::from_slice(&std::fs::read(path)?) {
+        Ok(cached) => cached,
+        Err(e) => {
+            tracing::debug!(
+                "ignoring invalid virtual package cache {}: {e}",
+                path.display()
+            );
+            return Ok(None);
+        }
+    };
+    if cached.crate_version != env!("CARGO_PKG_VERSION") || cached.platform != Platform::current() {
+        return Ok(None);
+    }
+    Ok(Some(cached))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_cache_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache/virtual-packages.json");
+
+        assert!(read_cache_file(&path, Duration::from_secs(60))
+            .unwrap()
+            .is_none());
+
+        save_to_file(&path).unwrap();
+        if !path.exists() {
+            // CUDA detection failed on this machine, nothing was cached.
+            return;
+        }
+
+        // Only the file is inspected here, the in-process caches are shared
+        // with the other tests.
+        let cached = read_cache_file(&path, Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached.platform, Platform::current());
+        assert_eq!(cached.archspec, Archspec::current());
+        assert_eq!(
+            cached.linux,
+            linux::linux_version()
+                .map_err(DetectVirtualPackageError::from)
+                .unwrap()
+        );
+        assert!(std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .all(|entry| entry.unwrap().path() == path));
+    }
+
+    #[test]
+    pub fn test_invalid_cache_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("virtual-packages.json");
+        std::fs::write(&path, "{ not json").unwrap();
+
+        assert!(!load_from_file(&path, Duration::from_secs(60)).unwrap());
+    }
+
+    #[test]
+    pub fn test_detection_cache() {
+        let cache = DetectionCache::new();
+        assert_eq!(cache.get_or_init(|| 1), 1);
+        assert_eq!(cache.get_or_init(|| 2), 1);
+        assert_eq!(cache.get_or_try_init(|| Err::<i32, ()>(())), Ok(1));
+
+        cache.clear();
+        assert_eq!(cache.get_or_try_init(|| Err::<i32, ()>(())), Err(()));
+        assert_eq!(cache.get_or_init(|| 3), 3);
+    }
+}
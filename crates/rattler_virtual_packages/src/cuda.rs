@@ -1,16 +1,18 @@
 //! Provides functionality to detect the CUDA version present on the current system.
 //!
-//! Two methods are provided:
+//! Three methods are provided:
 //!
 //! * [`detect_cuda_version_via_nvml`]
 //! * [`detect_cuda_version_via_libcuda`]
+//! * [`try_detect_cuda_version_via_nvidia_smi`]
 //!
-//! Both will detect the current supported CUDA version but the first method has less edge cases.
-//! See the function documentation for more information.
+//! All will detect the current supported CUDA version but the first method has less edge cases.
+//! See the function documentation for more information. [`detect_cuda`] tries them in order and
+//! reports which method succeeded, or whether no driver was found at all.
 
 use libloading::Symbol;
-use once_cell::sync::OnceCell;
 use rattler_conda_types::Version;
+use serde::{Deserialize, Serialize};
 use std::process::Command;
 use std::{
     mem::MaybeUninit,
@@ -18,26 +20,175 @@ use std::{
     str::FromStr,
 };
 
+use crate::cache::DetectionCache;
+
+/// The method that was used to detect the CUDA version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CudaDetectionSource {
+    /// The NVIDIA Management Library, see [`detect_cuda_version_via_nvml`].
+    Nvml,
+
+    /// The CUDA driver library, see [`detect_cuda_version_via_libcuda`].
+    LibCuda,
+
+    /// The `nvidia-smi` command, see [`try_detect_cuda_version_via_nvidia_smi`].
+    NvidiaSmi,
+}
+
+impl std::fmt::Display for CudaDetectionSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CudaDetectionSource::Nvml => write!(f, "nvml"),
+            CudaDetectionSource::LibCuda => write!(f, "libcuda"),
+            CudaDetectionSource::NvidiaSmi => write!(f, "nvidia-smi"),
+        }
+    }
+}
+
+/// An error that occurred while querying a CUDA driver that is present on the system.
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq)]
+pub enum DetectCudaError {
+    /// A required function could not be found in the loaded library.
+    #[error("the library does not provide `{0}`")]
+    MissingSymbol(&'static str),
+
+    /// A call into the driver returned an error code.
+    #[error("`{function}` failed with error code {code}")]
+    CallFailed {
+        /// The function that failed.
+        function: &'static str,
+        /// The error code returned by the function.
+        code: i64,
+    },
+
+    /// Executing `nvidia-smi` failed.
+    #[error("failed to execute nvidia-smi: {0}")]
+    NvidiaSmi(String),
+
+    /// The driver reported a version that could not be parsed.
+    #[error("could not parse the CUDA version '{0}'")]
+    InvalidVersion(String),
+}
+
+/// The outcome of detecting the CUDA version of the system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CudaDetection {
+    /// A CUDA driver was found and reported its version.
+    Detected {
+        /// The maximum CUDA version supported by the driver.
+        version: Version,
+        /// The method that detected the version.
+        source: CudaDetectionSource,
+    },
+
+    /// None of the detection methods found a CUDA driver, the system has no (usable) GPU.
+    NotFound,
+
+    /// A CUDA driver seems to be present but none of the detection methods could determine its
+    /// version.
+    Failed(Vec<(CudaDetectionSource, DetectCudaError)>),
+}
+
+impl CudaDetection {
+    /// Returns the detected version, if any.
+    pub fn version(&self) -> Option<&Version> {
+        match self {
+            CudaDetection::Detected { version, .. } => Some(version),
+            CudaDetection::NotFound | CudaDetection::Failed(_) => None,
+        }
+    }
+
+    /// Returns the method that detected the version, if any.
+    pub fn source(&self) -> Option<CudaDetectionSource> {
+        match self {
+            CudaDetection::Detected { source, .. } => Some(*source),
+            CudaDetection::NotFound | CudaDetection::Failed(_) => None,
+        }
+    }
+}
+
 /// Returns the maximum Cuda version available on the current platform.
 pub fn cuda_version() -> Option<Version> {
-    static DETECTED_CUDA_VERSION: OnceCell<Option<Version>> = OnceCell::new();
-    DETECTED_CUDA_VERSION
-        .get_or_init(detect_cuda_version)
-        .clone()
+    cuda_detection().version().cloned()
+}
+
+pub(crate) static DETECTED_CUDA: DetectionCache<CudaDetection> = DetectionCache::new();
+
+/// Returns the memoized result of [`detect_cuda`]. Use [`crate::cache::clear`]
+/// to detect the version again.
+pub fn cuda_detection() -> CudaDetection {
+    DETECTED_CUDA.get_or_init(detect_cuda)
 }
 
 /// Attempts to detect the version of CUDA present in the current operating system by employing the
 /// best technique available for the current environment.
 pub fn detect_cuda_version() -> Option<Version> {
-    if cfg!(target_env = "musl") {
+    detect_cuda().version().cloned()
+}
+
+/// Attempts to detect the version of CUDA present in the current operating system by trying NVML,
+/// `libcuda` and `nvidia-smi` in that order.
+///
+/// Methods that cannot find a driver are skipped silently, methods that find a driver but fail to
+/// query it are recorded so that [`CudaDetection::Failed`] can be distinguished from
+/// [`CudaDetection::NotFound`].
+pub fn detect_cuda() -> CudaDetection {
+    type Detector = fn() -> Result<Option<Version>, DetectCudaError>;
+    let detectors: &[(CudaDetectionSource, Detector)] = if cfg!(target_env = "musl") {
         // Dynamically loading a library is not supported on musl so we have to fall-back to using
         // the nvidia-smi command.
-        detect_cuda_version_via_nvidia_smi()
+        &[(
+            CudaDetectionSource::NvidiaSmi,
+            try_detect_cuda_version_via_nvidia_smi as Detector,
+        )]
+    } else {
+        &[
+            (
+                CudaDetectionSource::Nvml,
+                try_detect_cuda_version_via_nvml as Detector,
+            ),
+            (
+                CudaDetectionSource::LibCuda,
+                try_detect_cuda_version_via_libcuda as Detector,
+            ),
+            (
+                CudaDetectionSource::NvidiaSmi,
+                try_detect_cuda_version_via_nvidia_smi as Detector,
+            ),
+        ]
+    };
+
+    let mut errors = Vec::new();
+    for (source, detect) in detectors {
+        match detect() {
+            Ok(Some(version)) => {
+                return CudaDetection::Detected {
+                    version,
+                    source: *source,
+                }
+            }
+            Ok(None) => {}
+            Err(err) => {
+                tracing::debug!("failed to detect the CUDA version using {source}: {err}");
+                errors.push((*source, err));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        CudaDetection::NotFound
     } else {
-        detect_cuda_version_via_nvml()
+        CudaDetection::Failed(errors)
     }
 }
 
+/// Converts the integer version reported by the CUDA driver (e.g. `12020`) to a [`Version`].
+fn cuda_version_from_int(version: c_int) -> Result<Version, DetectCudaError> {
+    let version = format!("{}.{}", version / 1000, (version % 1000) / 10);
+    Version::from_str(&version).map_err(|_err| DetectCudaError::InvalidVersion(version))
+}
+
 /// Attempts to detect the version of CUDA present in the current operating system by loading the
 /// NVIDIA Management Library and querying the CUDA driver version. The method is preferred over
 /// [`detect_cuda_version_via_libcuda`] because that method might fail base on environment
@@ -47,10 +198,22 @@ pub fn detect_cuda_version() -> Option<Version> {
 /// considered old enough to be usable for our use case. Since Conda doesnt provide old versions of
 /// the CUDA SDK anyway this is considered a non-issue.
 pub fn detect_cuda_version_via_nvml() -> Option<Version> {
+    try_detect_cuda_version_via_nvml().ok().flatten()
+}
+
+/// Like [`detect_cuda_version_via_nvml`] but distinguishes between the library not being available
+/// (`Ok(None)`) and the library failing to report a version (`Err`).
+pub fn try_detect_cuda_version_via_nvml() -> Result<Option<Version>, DetectCudaError> {
+    /// Returned by `nvmlInit` if the NVIDIA driver is not running.
+    const NVML_ERROR_DRIVER_NOT_LOADED: c_int = 9;
+
     // Try to open the library
-    let library = nvml_library_paths()
+    let Some(library) = nvml_library_paths()
         .iter()
-        .find_map(|path| unsafe { libloading::Library::new(*path).ok() })?;
+        .find_map(|path| unsafe { libloading::Library::new(*path).ok() })
+    else {
+        return Ok(None);
+    };
 
     // Get the initialization function. We first try to get `nvmlInit_v2` but if we can't find that
     // we use the `nvmlInit` function.
@@ -59,11 +222,12 @@ pub fn detect_cuda_version_via_nvml() -> Option<Version> {
             .get(b"nvmlInit_v2\0")
             .or_else(|_| library.get(b"nvmlInit\0"))
     }
-    .ok()?;
+    .map_err(|_err| DetectCudaError::MissingSymbol("nvmlInit"))?;
 
     // Find the shutdown function
     let nvml_shutdown: Symbol<'_, unsafe extern "C" fn() -> c_int> =
-        unsafe { library.get(b"nvmlShutdown\0") }.ok()?;
+        unsafe { library.get(b"nvmlShutdown\0") }
+            .map_err(|_err| DetectCudaError::MissingSymbol("nvmlShutdown"))?;
 
     // Find the `nvmlSystemGetCudaDriverVersion_v2` function. If that function cannot be found, fall
     // back to the `nvmlSystemGetCudaDriverVersion` function instead.
@@ -73,11 +237,18 @@ pub fn detect_cuda_version_via_nvml() -> Option<Version> {
                 .get(b"nvmlSystemGetCudaDriverVersion_v2\0")
                 .or_else(|_| library.get(b"nvmlSystemGetCudaDriverVersion\0"))
         }
-        .ok()?;
+        .map_err(|_err| DetectCudaError::MissingSymbol("nvmlSystemGetCudaDriverVersion"))?;
 
-    // Call the initialization function
-    if unsafe { nvml_init() } != 0 {
-        return None;
+    // Call the initialization function. If the driver is not loaded there is no GPU to query.
+    match unsafe { nvml_init() } {
+        0 => {}
+        NVML_ERROR_DRIVER_NOT_LOADED => return Ok(None),
+        code => {
+            return Err(DetectCudaError::CallFailed {
+                function: "nvmlInit",
+                code: code.into(),
+            })
+        }
     }
 
     // Get the version
@@ -90,14 +261,17 @@ pub fn detect_cuda_version_via_nvml() -> Option<Version> {
 
     // If the call failed we dont have a version
     if result != 0 {
-        return None;
+        return Err(DetectCudaError::CallFailed {
+            function: "nvmlSystemGetCudaDriverVersion",
+            code: result.into(),
+        });
     }
 
     // We can assume the value is initialized by the `nvmlSystemGetCudaDriverVersion` function.
     let version = unsafe { cuda_driver_version.assume_init() };
 
     // Convert the version integer to a version string
-    Version::from_str(&format!("{}.{}", version / 1000, (version % 1000) / 10)).ok()
+    cuda_version_from_int(version).map(Some)
 }
 
 /// Returns platform specific set of search paths for the CUDA library.
@@ -144,31 +318,56 @@ fn nvml_library_paths() -> &'static [&'static str] {
 /// Therefore you should use the function [`detect_cuda_version_via_nvml`] instead which does not
 /// have this limitation.
 pub fn detect_cuda_version_via_libcuda() -> Option<Version> {
+    try_detect_cuda_version_via_libcuda().ok().flatten()
+}
+
+/// Like [`detect_cuda_version_via_libcuda`] but distinguishes between the library not being
+/// available (`Ok(None)`) and the library failing to report a version (`Err`).
+pub fn try_detect_cuda_version_via_libcuda() -> Result<Option<Version>, DetectCudaError> {
+    /// Returned by `cuInit` if no CUDA capable device is available.
+    const CUDA_ERROR_NO_DEVICE: c_ulong = 100;
+
     // Try to open the library
-    let cuda_library = cuda_library_paths()
+    let Some(cuda_library) = cuda_library_paths()
         .iter()
-        .find_map(|path| unsafe { libloading::Library::new(*path).ok() })?;
+        .find_map(|path| unsafe { libloading::Library::new(*path).ok() })
+    else {
+        return Ok(None);
+    };
 
     // Get entry points from the library
     let cu_init: Symbol<'_, unsafe extern "C" fn(c_uint) -> c_ulong> =
-        unsafe { cuda_library.get(b"cuInit\0") }.ok()?;
+        unsafe { cuda_library.get(b"cuInit\0") }
+            .map_err(|_err| DetectCudaError::MissingSymbol("cuInit"))?;
     let cu_driver_get_version: Symbol<'_, unsafe extern "C" fn(*mut c_int) -> c_ulong> =
-        unsafe { cuda_library.get(b"cuDriverGetVersion\0") }.ok()?;
+        unsafe { cuda_library.get(b"cuDriverGetVersion\0") }
+            .map_err(|_err| DetectCudaError::MissingSymbol("cuDriverGetVersion"))?;
 
     // Initialize the CUDA library
-    if unsafe { cu_init(0) } != 0 {
-        return None;
+    match unsafe { cu_init(0) } {
+        0 => {}
+        CUDA_ERROR_NO_DEVICE => return Ok(None),
+        code => {
+            return Err(DetectCudaError::CallFailed {
+                function: "cuInit",
+                code: i64::try_from(code).unwrap_or(i64::MAX),
+            })
+        }
     }
 
     // Get the version from the library
     let mut version_int = MaybeUninit::uninit();
-    if unsafe { cu_driver_get_version(version_int.as_mut_ptr()) != 0 } {
-        return None;
+    let result = unsafe { cu_driver_get_version(version_int.as_mut_ptr()) };
+    if result != 0 {
+        return Err(DetectCudaError::CallFailed {
+            function: "cuDriverGetVersion",
+            code: i64::try_from(result).unwrap_or(i64::MAX),
+        });
     }
     let version = unsafe { version_int.assume_init() };
 
     // Convert the version integer to a version string
-    Version::from_str(&format!("{}.{}", version / 1000, (version % 1000) / 10)).ok()
+    cuda_version_from_int(version).map(Some)
 }
 
 /// Returns platform specific set of search paths for the CUDA library.
@@ -216,7 +415,9 @@ fn cuda_library_paths() -> &'static [&'static str] {
 /// The upside of using this detection function over any of the others is that this method does not
 /// dynamically load a library which might not be supported on all systems. The downside is that
 /// executing a subprocess is generally slower and more prone to errors.
-fn detect_cuda_version_via_nvidia_smi() -> Option<Version> {
+///
+/// Returns `Ok(None)` if `nvidia-smi` is not installed.
+pub fn try_detect_cuda_version_via_nvidia_smi() -> Result<Option<Version>, DetectCudaError> {
     static CUDA_VERSION_RE: once_cell::sync::Lazy<regex::Regex> =
         once_cell::sync::Lazy::new(|| {
             regex::Regex::new("<cuda_version>(.*)<\\/cuda_version>").unwrap()
@@ -238,20 +439,40 @@ fn detect_cuda_version_via_nvidia_smi() -> Option<Version> {
         // TODO: Is this really the proper way to do it? Should we maybe clear the entire
         // environment.
         .env_remove("CUDA_VISIBLE_DEVICES")
-        .output()
-        .ok()?;
+        .output();
+    let nvidia_smi_output = match nvidia_smi_output {
+        Ok(output) => output,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(DetectCudaError::NvidiaSmi(err.to_string())),
+    };
+    if !nvidia_smi_output.status.success() {
+        return Err(DetectCudaError::NvidiaSmi(format!(
+            "{}: {}",
+            nvidia_smi_output.status,
+            String::from_utf8_lossy(&nvidia_smi_output.stderr).trim()
+        )));
+    }
 
     // Convert the output to Utf8. The conversion is lossy so it might contain some illegal
     // characters. If thats the case we simply assume the version in the file also wont make sense
     // during parsing.
     let output = String::from_utf8_lossy(&nvidia_smi_output.stdout);
 
-    // Extract the version from the XML
-    let version_match = CUDA_VERSION_RE.captures(&output)?;
-    let version_str = version_match.get(1)?.as_str();
+    // Extract the version from the XML. A driver without a supported CUDA version reports
+    // `N/A`, treat that the same as a missing version.
+    let Some(version_str) = CUDA_VERSION_RE
+        .captures(&output)
+        .and_then(|captures| captures.get(1))
+        .map(|version_match| version_match.as_str().trim())
+        .filter(|version| !version.is_empty() && *version != "N/A")
+    else {
+        return Ok(None);
+    };
 
     // Parse and return
-    Version::from_str(version_str).ok()
+    Version::from_str(version_str)
+        .map(Some)
+        .map_err(|_err| DetectCudaError::InvalidVersion(version_str.to_string()))
 }
 
 #[cfg(test)]
@@ -266,7 +487,35 @@ mod test {
 
     #[test]
     pub fn doesnt_crash_nvidia_smi() {
-        let version = detect_cuda_version_via_nvidia_smi();
+        let version = try_detect_cuda_version_via_nvidia_smi();
         println!("Cuda {version:?}");
     }
+
+    #[test]
+    pub fn detection_is_consistent() {
+        let detection = detect_cuda();
+        println!("Cuda {detection:?}");
+        match &detection {
+            CudaDetection::Detected { version, .. } => {
+                assert_eq!(detection.version(), Some(version));
+                assert!(detection.source().is_some());
+            }
+            CudaDetection::NotFound | CudaDetection::Failed(_) => {
+                assert_eq!(detection.version(), None);
+                assert_eq!(detection.source(), None);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_cuda_version_from_int() {
+        assert_eq!(
+            cuda_version_from_int(12020).unwrap(),
+            Version::from_str("12.2").unwrap()
+        );
+        assert_eq!(
+            cuda_version_from_int(11080).unwrap(),
+            Version::from_str("11.8").unwrap()
+        );
+    }
 }
@@ -27,14 +27,19 @@
 //! [`Linux::current`] method returns an instance of `Linux` which contains the
 //! current Linux version. It also provides conversions to the higher level API.
 //!
+//! Detection results of the host are cached in-process, see the [`cache`]
+//! module for how to invalidate or persist them.
+//!
 //! Finally at the core of the library are detection functions to perform
 //! specific capability detections that are not tied to anything related to
 //! virtual packages. See [`cuda::detect_cuda_version_via_libcuda`] as an
 //! example.
 
+pub mod cache;
 pub mod cuda;
 pub mod libc;
 pub mod linux;
+pub mod microarch;
 pub mod osx;
 pub mod win;
 
@@ -55,7 +60,9 @@ use rattler_conda_types::{
 };
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::osx::ParseOsxVersionError;
+use crate::{cache::DetectionCache, osx::ParseOsxVersionError};
+
+static DETECTED_ARCHSPEC: DetectionCache<Archspec> = DetectionCache::new();
 
 /// Configure the overrides used in in this crate.
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -164,6 +171,9 @@ pub enum VirtualPackage {
 
     /// The CPU architecture
     Archspec(Archspec),
+
+    /// A user-defined virtual package, e.g. `__slurm`.
+    Custom(GenericVirtualPackage),
 }
 
 /// A struct that represents all virtual packages provided by this library.
@@ -189,6 +199,10 @@ pub struct VirtualPackages {
 
     /// The CPU architecture
     pub archspec: Option<Archspec>,
+
+    /// User-defined virtual packages. These take precedence over detected
+    /// virtual packages with the same name.
+    pub custom: Vec<GenericVirtualPackage>,
 }
 
 impl VirtualPackages {
@@ -202,8 +216,14 @@ impl VirtualPackages {
             libc,
             cuda,
             archspec,
+            custom,
         } = self;
 
+        let custom_names = custom
+            .iter()
+            .map(|package| package.name.clone())
+            .collect::<Vec<_>>();
+
         [
             win.map(VirtualPackage::Win),
             unix.then_some(VirtualPackage::Unix),
@@ -215,6 +235,10 @@ impl VirtualPackages {
         ]
         .into_iter()
         .flatten()
+        .filter(move |package| {
+            !custom_names.contains(&GenericVirtualPackage::from(package.clone()).name)
+        })
+        .chain(custom.into_iter().map(VirtualPackage::Custom))
     }
 
     /// Convert this struct into an iterator of [`GenericVirtualPackage`].
@@ -222,9 +246,28 @@ impl VirtualPackages {
         self.into_virtual_packages().map(Into::into)
     }
 
+    /// Detect the virtual packages of the current system, honoring the
+    /// `CONDA_OVERRIDE_*` environment variables.
+    ///
+    /// Use [`VirtualPackages::detect`] with [`VirtualPackageOverrides::none`]
+    /// to ignore the environment and only detect the host capabilities.
+    pub fn current() -> Result<Self, DetectVirtualPackageError> {
+        Self::detect(&VirtualPackageOverrides::from_env())
+    }
+
     /// Detect the virtual packages of the current system with the given
     /// overrides.
     pub fn detect(overrides: &VirtualPackageOverrides) -> Result<Self, DetectVirtualPackageError> {
+        if let Some(package) = overrides
+            .custom
+            .iter()
+            .find(|package| !package.name.as_normalized().starts_with("__"))
+        {
+            return Err(DetectVirtualPackageError::InvalidCustomVirtualPackage(
+                package.name.as_source().to_string(),
+            ));
+        }
+
         Ok(Self {
             win: Windows::detect(overrides.win.as_ref())?,
             unix: Platform::current().is_unix(),
@@ -233,8 +276,18 @@ impl VirtualPackages {
             libc: LibC::detect(overrides.libc.as_ref())?,
             cuda: Cuda::detect(overrides.cuda.as_ref())?,
             archspec: Archspec::detect(overrides.archspec.as_ref())?,
+            custom: overrides.custom.clone(),
         })
     }
+
+    /// Detect the virtual packages of the current system on a blocking
+    /// thread, so that probing the system does not block the async runtime.
+    #[cfg(feature = "tokio")]
+    pub async fn detect_async(
+        overrides: VirtualPackageOverrides,
+    ) -> Result<Self, DetectVirtualPackageError> {
+        simple_spawn_blocking::tokio::run_blocking_task(move || Self::detect(&overrides)).await
+    }
 }
 
 impl From<VirtualPackage> for GenericVirtualPackage {
@@ -251,19 +304,21 @@ impl From<VirtualPackage> for GenericVirtualPackage {
             VirtualPackage::LibC(libc) => libc.into(),
             VirtualPackage::Cuda(cuda) => cuda.into(),
             VirtualPackage::Archspec(spec) => spec.into(),
+            VirtualPackage::Custom(package) => package,
         }
     }
 }
 
 impl VirtualPackage {
     /// Returns virtual packages detected for the current system or an error if
-    /// the versions could not be properly detected.
+    /// the versions could not be properly detected. The `CONDA_OVERRIDE_*`
+    /// environment variables are honored.
     #[deprecated(
         since = "1.1.0",
-        note = "Use `VirtualPackage::detect(&VirtualPackageOverrides::default())` instead."
+        note = "Use `VirtualPackage::detect(&VirtualPackageOverrides::from_env())` instead."
     )]
     pub fn current() -> Result<Vec<Self>, DetectVirtualPackageError> {
-        Self::detect(&VirtualPackageOverrides::default())
+        Self::detect(&VirtualPackageOverrides::from_env())
     }
 
     /// Detect the virtual packages of the current system with the given
@@ -295,6 +350,19 @@ pub enum DetectVirtualPackageError {
 
     #[error(transparent)]
     VersionParseError(#[from] ParseVersionError),
+
+    #[error("the name of the custom virtual package '{0}' does not start with '__'")]
+    InvalidCustomVirtualPackage(String),
+
+    #[error("the detection of virtual packages was cancelled")]
+    Cancelled,
+}
+
+#[cfg(feature = "tokio")]
+impl From<simple_spawn_blocking::Cancelled> for DetectVirtualPackageError {
+    fn from(_: simple_spawn_blocking::Cancelled) -> Self {
+        DetectVirtualPackageError::Cancelled
+    }
 }
 /// Configure the overrides used in this crate.
 ///
@@ -317,6 +385,10 @@ pub struct VirtualPackageOverrides {
     pub cuda: Option<Override>,
     /// The override for the archspec virtual package
     pub archspec: Option<Override>,
+    /// Additional user-defined virtual packages (e.g. `__slurm`) that are
+    /// added to the detected virtual packages. Their names must start with
+    /// `__`.
+    pub custom: Vec<GenericVirtualPackage>,
 }
 
 impl VirtualPackageOverrides {
@@ -330,14 +402,34 @@ impl VirtualPackageOverrides {
             libc: Some(ov.clone()),
             cuda: Some(ov.clone()),
             archspec: Some(ov),
+            custom: Vec::new(),
         }
     }
 
+    /// Adds user-defined virtual packages that are reported in addition to
+    /// the detected virtual packages.
+    #[must_use]
+    pub fn with_custom(
+        mut self,
+        packages: impl IntoIterator<Item = GenericVirtualPackage>,
+    ) -> Self {
+        self.custom.extend(packages);
+        self
+    }
+
     /// Returns an instance of `VirtualPackageOverrides` where all overrides are
-    /// taken from default environment variables.
+    /// taken from default environment variables (e.g. `CONDA_OVERRIDE_CUDA`,
+    /// `CONDA_OVERRIDE_GLIBC` or `CONDA_OVERRIDE_OSX`).
     pub fn from_env() -> Self {
         Self::all(Override::DefaultEnvVar)
     }
+
+    /// Returns an instance of `VirtualPackageOverrides` without any overrides.
+    /// Environment variables are not read and all virtual packages are
+    /// detected from the host system.
+    pub fn none() -> Self {
+        Self::default()
+    }
 }
 
 /// Linux virtual package description
@@ -571,12 +663,25 @@ impl Archspec {
 
     /// Returns the current CPU architecture or `Archspec::Unknown` if the
     /// architecture could not be determined.
+    ///
+    /// If the archspec database does not recognize the exact processor model
+    /// the microarchitecture level is derived from the CPU feature flags
+    /// instead (e.g. `x86_64_v3` or `m2`), see
+    /// [`microarch::microarchitecture_from_features`].
     pub fn current() -> Self {
-        archspec::cpu::host()
-            .ok()
-            .map(Into::into)
-            .or_else(|| Self::from_platform(Platform::current()))
-            .unwrap_or(Archspec::Unknown)
+        DETECTED_ARCHSPEC.get_or_init(Self::detect_current)
+    }
+
+    fn detect_current() -> Self {
+        let family = Self::from_platform(Platform::current());
+        match archspec::cpu::host().ok().map(Self::from) {
+            Some(host) if Some(&host) != family.as_ref() => host,
+            host => microarch::microarchitecture_from_features()
+                .map(|name| Self::from_name(&name))
+                .or(host)
+                .or(family)
+                .unwrap_or(Archspec::Unknown),
+        }
     }
 
     /// Returns the minimal supported archspec architecture for the given
@@ -778,6 +883,89 @@ mod test {
             VirtualPackages::detect(&VirtualPackageOverrides::default()).unwrap();
         println!("{virtual_packages:#?}");
     }
+    #[test]
+    fn detect_from_env() {
+        // `VirtualPackages::current` reads the default environment variables.
+        assert_eq!(
+            VirtualPackageOverrides::from_env().cuda,
+            Some(Override::DefaultEnvVar)
+        );
+
+        // Use a variable that is only read by this test so that other tests are
+        // not affected.
+        const ENV_NAME: &str = "RATTLER_TEST_DETECT_FROM_ENV_CUDA";
+        let overrides = VirtualPackageOverrides {
+            cuda: Some(Override::EnvVar(ENV_NAME.to_string())),
+            ..VirtualPackageOverrides::none()
+        };
+
+        env::set_var(ENV_NAME, "12.3");
+        assert_eq!(
+            VirtualPackages::detect(&overrides).unwrap().cuda,
+            Some(Cuda {
+                version: Version::from_str("12.3").unwrap(),
+            })
+        );
+
+        env::set_var(ENV_NAME, "");
+        assert_eq!(VirtualPackages::detect(&overrides).unwrap().cuda, None);
+
+        env::remove_var(ENV_NAME);
+        assert_eq!(
+            VirtualPackages::detect(&overrides).unwrap().cuda,
+            Cuda::current()
+        );
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn archspec_is_detected() {
+        let archspec = Archspec::current();
+        println!("{archspec}");
+        assert_ne!(archspec, Archspec::Unknown);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn detect_async() {
+        let detected = VirtualPackages::detect_async(VirtualPackageOverrides::none())
+            .await
+            .unwrap();
+        assert_eq!(
+            detected.archspec,
+            VirtualPackages::detect(&VirtualPackageOverrides::none())
+                .unwrap()
+                .archspec
+        );
+    }
+
+    #[test]
+    fn custom_virtual_packages() {
+        let slurm = GenericVirtualPackage::from_str("__slurm=23.02").unwrap();
+        let cuda = GenericVirtualPackage::from_str("__cuda=12.4").unwrap();
+        let overrides = VirtualPackageOverrides::none().with_custom([slurm.clone(), cuda.clone()]);
+
+        let packages = VirtualPackages::detect(&overrides)
+            .unwrap()
+            .into_generic_virtual_packages()
+            .collect::<Vec<_>>();
+        assert!(packages.contains(&slurm));
+        assert_eq!(
+            packages
+                .iter()
+                .filter(|package| package.name.as_normalized() == "__cuda")
+                .collect::<Vec<_>>(),
+            [&cuda]
+        );
+
+        let invalid = VirtualPackageOverrides::none()
+            .with_custom([GenericVirtualPackage::from_str("slurm").unwrap()]);
+        assert!(matches!(
+            VirtualPackages::detect(&invalid),
+            Err(DetectVirtualPackageError::InvalidCustomVirtualPackage(_))
+        ));
+    }
+
     #[test]
     fn parse_libc() {
         let v = "1.23";
@@ -813,6 +1001,17 @@ mod test {
         );
     }
 
+    #[test]
+    fn musl_generic_virtual_package() {
+        let libc = LibC {
+            family: "musl".into(),
+            version: Version::from_str("1.2.4").unwrap(),
+        };
+        let generic = GenericVirtualPackage::from(libc);
+        assert_eq!(generic.name.as_normalized(), "__musl");
+        assert_eq!(generic.version, Version::from_str("1.2.4").unwrap());
+    }
+
     #[test]
     fn parse_cuda() {
         let v = "1.234";
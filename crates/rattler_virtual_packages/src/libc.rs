@@ -1,19 +1,20 @@
 //! Low-level functions to detect the `LibC` family and version. See
 //! [`libc_family_and_version`].
 
-use once_cell::sync::OnceCell;
 use rattler_conda_types::{ParseVersionError, Version};
 
+use crate::cache::DetectionCache;
+
+pub(crate) static DETECTED_LIBC_VERSION: DetectionCache<Option<(String, Version)>> =
+    DetectionCache::new();
+
 /// Returns the `LibC` version and family of the current platform.
 ///
 /// Returns an error if determining the `LibC` family and version resulted in an
 /// error. Returns `None` if the current platform does not provide a version of
 /// `LibC`.
 pub fn libc_family_and_version() -> Result<Option<(String, Version)>, DetectLibCError> {
-    static DETECTED_LIBC_VERSION: OnceCell<Option<(String, Version)>> = OnceCell::new();
-    DETECTED_LIBC_VERSION
-        .get_or_try_init(try_detect_libc_version)
-        .cloned()
+    DETECTED_LIBC_VERSION.get_or_try_init(try_detect_libc_version)
 }
 
 /// An error that could occur when trying to detect to libc version
@@ -32,30 +33,46 @@ pub enum DetectLibCError {
 /// binary can still run on a glibc based system. For environments we are
 /// interested in the libc family that is available on the *system*.
 ///
-/// Currently this code is only able to detect glibc properly. We can add more
+/// Currently this code is able to detect glibc and musl. We can add more
 /// detection methods in the future.
 #[cfg(unix)]
 fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCError> {
     // Run `ldd --version` to detect the libc version and family on the system.
-    // `ldd` is shipped with libc so if an error occurred during its execution we
-    // can assume no libc is available on the system.
+    // `ldd` is shipped with libc so if an error occurred during its execution and
+    // no musl loader can be found we can assume no libc is available on the
+    // system.
 
     #[cfg(target_os = "linux")]
     {
-        let output = match std::process::Command::new("ldd").arg("--version").output() {
+        match std::process::Command::new("ldd").arg("--version").output() {
             Err(e) => {
-                tracing::info!(
-                    "failed to execute `ldd --version`: {e}. Assuming libc is not available."
-                );
-                return Ok(None);
+                tracing::info!("failed to execute `ldd --version`: {e}.");
             }
-            Ok(output) => output,
-        };
+            Ok(output) => {
+                if let Some(version) =
+                    parse_glibc_ldd_version(&String::from_utf8_lossy(&output.stdout))?
+                {
+                    return Ok(Some((String::from("glibc"), version)));
+                }
+
+                // musl's `ldd` does not know `--version`, it prints its version to stderr
+                // and exits with a non-zero exit code.
+                if let Some(version) =
+                    parse_musl_ldd_version(&String::from_utf8_lossy(&output.stderr))?
+                {
+                    return Ok(Some((String::from("musl"), version)));
+                }
+            }
+        }
+
+        // Minimal musl based images do not always ship `ldd`, but the dynamic loader
+        // itself reports the version when it is invoked without arguments.
+        if let Some(version) = detect_musl_version_via_loader()? {
+            return Ok(Some((String::from("musl"), version)));
+        }
 
-        Ok(
-            parse_glibc_ldd_version(&String::from_utf8_lossy(&output.stdout))?
-                .map(|version| (String::from("glibc"), version)),
-        )
+        tracing::info!("could not determine the libc family. Assuming libc is not available.");
+        Ok(None)
     }
 
     #[cfg(not(target_os = "linux"))]
@@ -64,6 +81,38 @@ fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCErro
     }
 }
 
+/// Runs the musl dynamic loader (e.g. `/lib/ld-musl-x86_64.so.1`) to determine
+/// the version of musl installed on the system.
+#[cfg(target_os = "linux")]
+fn detect_musl_version_via_loader() -> Result<Option<Version>, DetectLibCError> {
+    let Ok(entries) = std::fs::read_dir("/lib") else {
+        return Ok(None);
+    };
+
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if !(file_name.starts_with("ld-musl-") && file_name.ends_with(".so.1")) {
+            continue;
+        }
+
+        match std::process::Command::new(entry.path()).output() {
+            Ok(output) => {
+                if let Some(version) =
+                    parse_musl_ldd_version(&String::from_utf8_lossy(&output.stderr))?
+                {
+                    return Ok(Some(version));
+                }
+            }
+            Err(e) => {
+                tracing::info!("failed to execute `{}`: {e}.", entry.path().display());
+            }
+        }
+    }
+
+    Ok(None)
+}
+
 #[cfg(any(test, unix))]
 #[allow(dead_code)] // not used on macOS
 fn parse_glibc_ldd_version(input: &str) -> Result<Option<Version>, DetectLibCError> {
@@ -83,6 +132,25 @@ fn parse_glibc_ldd_version(input: &str) -> Result<Option<Version>, DetectLibCErr
     Ok(None)
 }
 
+#[cfg(any(test, unix))]
+#[allow(dead_code)] // not used on macOS
+fn parse_musl_ldd_version(input: &str) -> Result<Option<Version>, DetectLibCError> {
+    static MUSL_LIBC_RE: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+        regex::Regex::new(r"(?i)musl libc[^\n]*\n\s*Version\s+([0-9]+(?:\.[0-9]+)*)").unwrap()
+    });
+
+    if let Some(version_match) = MUSL_LIBC_RE
+        .captures(input)
+        .and_then(|captures| captures.get(1))
+        .map(|version_match| version_match.as_str())
+    {
+        let version = std::str::FromStr::from_str(version_match)?;
+        return Ok(Some(version));
+    }
+
+    Ok(None)
+}
+
 #[cfg(not(unix))]
 const fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCError> {
     Ok(None)
@@ -120,4 +188,14 @@ mod test {
             Some(Version::from_str("2.39").unwrap())
         );
     }
+    #[test]
+    pub fn test_parse_musl_ldd_version() {
+        let output = "musl libc (x86_64)\nVersion 1.2.4\nDynamic Program Loader\nUsage: ldd [options] [--] pathname\n";
+        assert_eq!(
+            parse_musl_ldd_version(output).unwrap(),
+            Some(Version::from_str("1.2.4").unwrap())
+        );
+        assert_eq!(parse_musl_ldd_version("ldd (GNU libc) 2.31").unwrap(), None);
+        assert_eq!(parse_glibc_ldd_version(output).unwrap(), None);
+    }
 }
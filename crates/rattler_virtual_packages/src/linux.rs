@@ -1,18 +1,18 @@
 //! Low-level functions to detect the linux version on the system. See [`linux_version`].
 
-use once_cell::sync::OnceCell;
 use rattler_conda_types::{ParseVersionError, Version};
+
+use crate::cache::DetectionCache;
 use std::str::FromStr;
 
+pub(crate) static DETECTED_LINUX_VERSION: DetectionCache<Option<Version>> = DetectionCache::new();
+
 /// Returns the Linux version of the current platform.
 ///
 /// Returns an error if determining the Linux version resulted in an error. Returns `None` if
 /// the current platform is not a Linux platform.
 pub fn linux_version() -> Result<Option<Version>, ParseLinuxVersionError> {
-    static DETECTED_LINUX_VERSION: OnceCell<Option<Version>> = OnceCell::new();
-    DETECTED_LINUX_VERSION
-        .get_or_try_init(try_detect_linux_version)
-        .cloned()
+    DETECTED_LINUX_VERSION.get_or_try_init(try_detect_linux_version)
 }
 
 /// Detects the current linux version.
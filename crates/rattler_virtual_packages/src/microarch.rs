@@ -0,0 +1,99 @@
+//! Low-level functions to detect the CPU microarchitecture from the features
+//! reported by the processor. See [`microarchitecture_from_features`].
+
+/// Returns the name of the archspec microarchitecture that best describes the
+/// host based on the features reported by the CPU, e.g. `x86_64_v3` or `m2`.
+///
+/// This is used when the archspec database does not know the exact model of
+/// the host processor. Returns `None` if the features of the current
+/// architecture cannot be inspected.
+pub fn microarchitecture_from_features() -> Option<String> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        Some(x86_64_microarchitecture_level().to_string())
+    }
+
+    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+    {
+        let output = std::process::Command::new("sysctl")
+            .args(["-n", "machdep.cpu.brand_string"])
+            .output()
+            .ok()?;
+        parse_apple_silicon_brand(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        all(target_os = "macos", target_arch = "aarch64")
+    )))]
+    {
+        None
+    }
+}
+
+/// Returns the x86-64 microarchitecture level (as defined by the x86-64 psABI)
+/// supported by the current CPU.
+#[cfg(target_arch = "x86_64")]
+pub fn x86_64_microarchitecture_level() -> &'static str {
+    macro_rules! has_features {
+        ($($feature:tt),+) => { $(std::arch::is_x86_feature_detected!($feature))&&+ };
+    }
+
+    let v2 = has_features!("cmpxchg16b", "popcnt", "sse3", "ssse3", "sse4.1", "sse4.2");
+    let v3 = v2 && has_features!("avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "xsave");
+    let v4 = v3 && has_features!("avx512f", "avx512bw", "avx512cd", "avx512dq", "avx512vl");
+
+    if v4 {
+        "x86_64_v4"
+    } else if v3 {
+        "x86_64_v3"
+    } else if v2 {
+        "x86_64_v2"
+    } else {
+        "x86_64"
+    }
+}
+
+/// Parses the CPU brand string of an Apple Silicon Mac (e.g. `Apple M2 Pro`)
+/// into the name of the archspec microarchitecture (e.g. `m2`).
+#[cfg(any(test, all(target_os = "macos", target_arch = "aarch64")))]
+fn parse_apple_silicon_brand(brand: &str) -> Option<String> {
+    let generation = brand.trim().strip_prefix("Apple M")?;
+    let generation = generation
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .filter(|generation| !generation.is_empty())?;
+    Some(format!("m{generation}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn doesnt_crash() {
+        let microarch = microarchitecture_from_features();
+        println!("Microarchitecture {microarch:?}");
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    pub fn test_x86_64_microarchitecture_level() {
+        assert!(x86_64_microarchitecture_level().starts_with("x86_64"));
+    }
+
+    #[test]
+    pub fn test_parse_apple_silicon_brand() {
+        assert_eq!(parse_apple_silicon_brand("Apple M1"), Some("m1".into()));
+        assert_eq!(
+            parse_apple_silicon_brand("Apple M2 Pro\n"),
+            Some("m2".into())
+        );
+        assert_eq!(
+            parse_apple_silicon_brand("Apple M10 Max"),
+            Some("m10".into())
+        );
+        assert_eq!(parse_apple_silicon_brand("Intel(R) Core(TM) i7"), None);
+        assert_eq!(parse_apple_silicon_brand("Apple Mx"), None);
+    }
+}
@@ -1,17 +1,17 @@
 //! Low-level functions to detect the OSX version of the system. See [`osx_version`].
 
-use once_cell::sync::OnceCell;
 use rattler_conda_types::{ParseVersionError, Version};
 
+use crate::cache::DetectionCache;
+
+pub(crate) static DETECTED_OSX_VERSION: DetectionCache<Option<Version>> = DetectionCache::new();
+
 /// Returns the OSX version of the current platform.
 ///
 /// Returns an error if determining the version resulted in an error. Returns `None` if
 /// the current platform is not a OSX platform.
 pub fn osx_version() -> Result<Option<Version>, ParseOsxVersionError> {
-    static DETECTED_OSX_VERSION: OnceCell<Option<Version>> = OnceCell::new();
-    DETECTED_OSX_VERSION
-        .get_or_try_init(try_detect_osx_version)
-        .cloned()
+    DETECTED_OSX_VERSION.get_or_try_init(try_detect_osx_version)
 }
 
 /// Detects the current linux version.
@@ -1,19 +1,19 @@
 //! Low-level functions to detect the Windows version on the system. See
 //! [`windows_version`].
 
-use once_cell::sync::OnceCell;
 use rattler_conda_types::Version;
 
+use crate::cache::DetectionCache;
+
+pub(crate) static DETECTED_WINDOWS_VERSION: DetectionCache<Option<Version>> = DetectionCache::new();
+
 /// Returns the Windows version of the current platform.
 ///
 /// Returns an error if determining the Windows version resulted in an error.
 /// Returns `None` if the Windows version could not be determined. Note that
 /// this does not mean the current platform is not Windows.
 pub fn windows_version() -> Option<Version> {
-    static DETECTED_WINDOWS_VERSION: OnceCell<Option<Version>> = OnceCell::new();
-    DETECTED_WINDOWS_VERSION
-        .get_or_init(detect_windows_version)
-        .clone()
+    DETECTED_WINDOWS_VERSION.get_or_init(detect_windows_version)
 }
 
 #[cfg(target_os = "windows")]
@@ -36,6 +36,8 @@ pub enum JsError {
     InvalidHexMd5(String),
     #[error("{0} is not a valid hex encoded SHA256 hash")]
     InvalidHexSha256(String),
+    #[error("solve strategy must be one of 'highest', 'lowest' or 'lowest-direct', got {0}")]
+    InvalidSolveStrategy(String),
 }
 
 pub type JsResult<T> = Result<T, JsError>;
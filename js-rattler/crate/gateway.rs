@@ -99,6 +99,8 @@ impl From<JsSourceConfig> for SourceConfig {
             bz2_enabled: value.bz2_enabled,
             sharded_enabled: value.sharded_enabled,
             cache_action: CacheAction::default(),
+            max_stale: None,
+            stale_while_revalidate: false,
         }
     }
 }
@@ -7,7 +7,7 @@ use rattler_conda_types::{
     ParseStrictness::Lenient, RepoDataRecord, Version,
 };
 use rattler_repodata_gateway::{Gateway, SourceConfig};
-use rattler_solve::{SolverImpl, SolverTask};
+use rattler_solve::{SolveStrategy, SolverImpl, SolverTask};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::collections::HashMap;
@@ -43,10 +43,21 @@ pub async fn simple_solve(
     channels: Vec<String>,
     #[wasm_bindgen(param_description = "The platforms to solve for")] platforms: Vec<JsPlatform>,
     #[wasm_bindgen(param_description = "Installed packages")] locked_packages: JsValue,
+    #[wasm_bindgen(
+        param_description = "The strategy to select versions: 'highest' (default), 'lowest' or 'lowest-direct'"
+    )]
+    strategy: Option<String>,
 ) -> Result<Vec<SolvedPackage>, JsError> {
     // TODO: Dont hardcode
     let channel_config = ChannelConfig::default_with_root_dir(PathBuf::from(""));
 
+    let strategy = match strategy.as_deref() {
+        None | Some("highest") => SolveStrategy::Highest,
+        Some("lowest") => SolveStrategy::LowestVersion,
+        Some("lowest-direct") => SolveStrategy::LowestVersionDirect,
+        Some(other) => return Err(JsError::InvalidSolveStrategy(other.to_string())),
+    };
+
     // Convert types
     let specs = specs
         .into_iter()
@@ -156,6 +167,7 @@ pub async fn simple_solve(
     let task = SolverTask {
         specs,
         locked_packages: installed_packages,
+        strategy,
         ..repodata.iter().collect::<SolverTask<_>>()
     };
 
@@ -117,4 +117,16 @@ describe("solving", () => {
             expect(numpy?.version).toBe("2.2.6");
         });
     });
+
+    it("should reject an unknown solve strategy", () => {
+        return expect(
+            simpleSolve(
+                ["python"],
+                ["https://prefix.dev/conda-forge"],
+                ["noarch"],
+                [],
+                "newest",
+            ),
+        ).rejects.toMatch("solve strategy must be one of");
+    });
 });
@@ -6,9 +6,11 @@ from rattler.rattler import PyChannelPriority
 class ChannelPriority(Enum):
     """
     Defines how priority of channels functions during solves. If strict, the channel that the package is first
-    found in will be used as the only channel for that package. If disabled, then packages can be retrieved from
-    any channel as package version takes precedence.
+    found in will be used as the only channel for that package. If flexible, packages from higher priority channels
+    are preferred but packages from lower priority channels are used if that is required to find a solution. If
+    disabled, then packages can be retrieved from any channel as package version takes precedence.
     """
 
     Strict = PyChannelPriority.Strict
+    Flexible = PyChannelPriority.Flexible
     Disabled = PyChannelPriority.Disabled
@@ -12,7 +12,7 @@ if TYPE_CHECKING:
     from rattler.platform import Platform
 
 
-CacheAction = Literal["cache-or-fetch", "use-cache-only", "force-cache-only", "no-cache"]
+CacheAction = Literal["cache-or-fetch", "use-cache-only", "force-cache-only", "no-cache", "force-refresh"]
 Variant = Literal["after-patches", "from-packages", "current"]
 
 
@@ -25,6 +25,7 @@ class FetchRepoDataOptions:
     * `'use-cache-only'`: Only use the cache, but error out if the cache is not up to date
     * `'force-cache-only'`: Only use the cache, ignore whether or not it is up to date.
     * `'no-cache'`: Do not use the cache even if there is an up to date entry
+    * `'force-refresh'`: Use the cache, but always check with the server whether it is still up to date.
     """
 
     variant: Variant = "after-patches"
@@ -32,8 +32,8 @@ class SourceConfig:
     bz2_enabled: bool = True
     """Whether the BZ2 compression is enabled or not."""
 
-    sharded_enabled: bool = False
-    """Whether sharded repodata is enabled or not."""
+    sharded_enabled: bool = True
+    """Whether sharded repodata is enabled or not. If the shard index of a channel cannot be fetched, for instance because the channel does not provide it or access to it is denied, `repodata.json` is used instead."""
 
     cache_action: CacheAction = "cache-or-fetch"
     """How to interact with the cache.
@@ -42,6 +42,7 @@ class SourceConfig:
     * `'use-cache-only'`: Only use the cache, but error out if the cache is not up to date
     * `'force-cache-only'`: Only use the cache, ignore whether or not it is up to date.
     * `'no-cache'`: Do not use the cache even if there is an up to date entry
+    * `'force-refresh'`: Use the cache, but always check with the server whether it is still up to date.
     """
 
     def _into_py(self) -> PySourceConfig:
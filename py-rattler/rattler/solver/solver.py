@@ -13,7 +13,7 @@ from rattler.repo_data.gateway import Gateway
 from rattler.repo_data.record import RepoDataRecord
 from rattler.virtual_package.generic import GenericVirtualPackage
 
-SolveStrategy = Literal["highest", "lowest", "lowest-direct"]
+SolveStrategy = Literal["highest", "lowest", "lowest-direct", "minimal-update"]
 """Defines the strategy to use when multiple versions of a package are available during solving."""
 
 
@@ -70,6 +70,8 @@ async def solve(
             * `"lowest-direct"`: Select the lowest compatible version for all
               direct dependencies but the highest compatible version of transitive
               dependencies.
+            * `"minimal-update"`: Keep the locked packages if possible and
+              otherwise select the versions closest to the locked versions.
         constraints: Additional constraints that should be satisfied by the solver.
             Packages included in the `constraints` are not necessarily installed,
             but they must be satisfied by the solution.
@@ -169,6 +171,8 @@ async def solve_with_sparse_repodata(
             * `"lowest-direct"`: Select the lowest compatible version for all
               direct dependencies but the highest compatible version of transitive
               dependencies.
+            * `"minimal-update"`: Keep the locked packages if possible and
+              otherwise select the versions closest to the locked versions.
         constraints: Additional constraints that should be satisfied by the solver.
             Packages included in the `constraints` are not necessarily installed,
             but they must be satisfied by the solution.
@@ -42,7 +42,8 @@ impl PyClientWithMiddleware {
             client_builder = client_builder.user_agent(RATTLER_USER_AGENT);
         }
 
-        let mut client = reqwest_middleware::ClientBuilder::new(client_builder.build().unwrap());
+        let base_client = client_builder.build().unwrap();
+        let mut client = reqwest_middleware::ClientBuilder::new(base_client.clone());
 
         for middleware in middlewares {
             match middleware {
@@ -56,7 +57,14 @@ impl PyClientWithMiddleware {
                     );
                 }
                 PyMiddleware::Oci(middleware) => {
-                    client = client.with(OciMiddleware::from(middleware));
+                    client = client.with(
+                        OciMiddleware::from(middleware)
+                            .with_authentication_storage(
+                                AuthenticationStorage::from_env_and_defaults()
+                                    .map_err(PyRattlerError::from)?,
+                            )
+                            .with_client(base_client.clone()),
+                    );
                 }
                 PyMiddleware::Gcs(middleware) => {
                     client = client.with(GCSMiddleware::from(middleware));
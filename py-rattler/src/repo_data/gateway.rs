@@ -178,9 +178,10 @@ impl<'py> FromPyObject<'py> for Wrap<CacheAction> {
             "use-cache-only" => CacheAction::UseCacheOnly,
             "force-cache-only" => CacheAction::ForceCacheOnly,
             "no-cache" => CacheAction::NoCache,
+            "force-refresh" => CacheAction::ForceRefresh,
             v => {
                 return Err(PyValueError::new_err(format!(
-                    "cache action must be one of {{'cache-or-fetch', 'use-cache-only', 'force-cache-only', 'no-cache'}}, got {v}",
+                    "cache action must be one of {{'cache-or-fetch', 'use-cache-only', 'force-cache-only', 'no-cache', 'force-refresh'}}, got {v}",
                 )))
             }
         };
@@ -206,6 +207,8 @@ impl PySourceConfig {
                 bz2_enabled,
                 sharded_enabled,
                 cache_action: cache_action.0,
+                max_stale: None,
+                stale_while_revalidate: false,
             },
         }
     }
@@ -265,6 +268,7 @@ impl PyFetchRepoDataOptions {
                 jlap_enabled,
                 zstd_enabled,
                 bz2_enabled,
+                max_stale: None,
                 retry_policy: None,
             },
         }
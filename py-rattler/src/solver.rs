@@ -5,7 +5,9 @@ use pyo3::{
 };
 use pyo3_async_runtimes::tokio::future_into_py;
 use rattler_repodata_gateway::sparse::SparseRepoData;
-use rattler_solve::{resolvo::Solver, RepoDataIter, SolveStrategy, SolverImpl, SolverTask};
+use rattler_solve::{
+    resolvo::Solver, RepoDataIter, SolveStrategy, SolverImpl, SolverTask, TrackFeaturesPolicy,
+};
 use tokio::task::JoinError;
 
 use crate::{
@@ -26,9 +28,10 @@ impl<'py> FromPyObject<'py> for Wrap<SolveStrategy> {
             "highest" => SolveStrategy::Highest,
             "lowest" => SolveStrategy::LowestVersion,
             "lowest-direct" => SolveStrategy::LowestVersionDirect,
+            "minimal-update" => SolveStrategy::MinimalUpdate,
             v => {
                 return Err(PyValueError::new_err(format!(
-                    "cache action must be one of {{'highest', 'lowest', 'lowest-direct'}}, got {v}",
+                    "cache action must be one of {{'highest', 'lowest', 'lowest-direct', 'minimal-update'}}, got {v}",
                 )))
             }
         };
@@ -88,9 +91,12 @@ pub fn py_solve(
                 specs: specs.into_iter().map(Into::into).collect(),
                 constraints: constraints.into_iter().map(Into::into).collect(),
                 timeout: timeout.map(std::time::Duration::from_micros),
+                cancellation_token: None,
                 channel_priority: channel_priority.into(),
                 exclude_newer,
                 strategy: strategy.map_or_else(Default::default, |v| v.0),
+                track_features_policy: TrackFeaturesPolicy::default(),
+                reporter: None,
             };
 
             Ok::<_, PyErr>(
@@ -187,9 +193,12 @@ pub fn py_solve_with_sparse_repodata<'py>(
                 specs: specs.into_iter().map(Into::into).collect(),
                 constraints: constraints.into_iter().map(Into::into).collect(),
                 timeout: timeout.map(std::time::Duration::from_micros),
+                cancellation_token: None,
                 channel_priority: channel_priority.into(),
                 exclude_newer,
                 strategy: strategy.map_or_else(Default::default, |v| v.0),
+                track_features_policy: TrackFeaturesPolicy::default(),
+                reporter: None,
             };
 
             Ok::<_, PyErr>(
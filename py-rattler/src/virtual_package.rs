@@ -85,7 +85,7 @@ impl PyVirtualPackageOverrides {
     #[staticmethod]
     pub fn none() -> Self {
         Self {
-            inner: VirtualPackageOverrides::default(),
+            inner: VirtualPackageOverrides::none(),
         }
     }
 